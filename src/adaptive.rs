@@ -0,0 +1,143 @@
+use crate::mask::{self, Mask};
+use crate::{blur_passes, Direction, Kernel};
+use image::{Rgb, RgbImage};
+
+/// Sigma factors of the blurs [`blur_variable`] interpolates between, from the sharp image to
+/// twice the requested blur
+const LEVELS: [f32; 5] = [0.0, 0.25, 0.5, 1.0, 2.0];
+
+/// Sigma factor of the flattest regions of an adaptive blur
+const FLAT_FACTOR: f32 = 2.0;
+
+/// Sigma factor of the most detailed regions of an adaptive blur
+const DETAIL_FACTOR: f32 = 0.25;
+
+/// Radius of the window the local detail is measured over
+const DETAIL_WINDOW: u32 = 3;
+
+/// Standard deviation of the luma, in levels from 0 to 255, above which a window counts as
+/// fully detailed
+const FULL_DETAIL: f32 = 24.0;
+
+/// Local detail of `img`: the standard deviation of the luma over the `(2 * window + 1)²`
+/// pixels around each one, from 0 for flat regions to 1 for textures and edges
+///
+/// # Examples
+/// ```
+/// use vincent_blur::adaptive::detail_map;
+///
+/// let img = image::RgbImage::from_fn(32, 16, |x, y| match x < 16 {
+///     true => image::Rgb([90; 3]),
+///     false => image::Rgb([((x + y) % 2 * 255) as u8; 3]),
+/// });
+/// let detail = detail_map(&img, 2);
+///
+/// assert_eq!(detail.get(4, 8), 0.0);
+/// assert_eq!(detail.get(28, 8), 1.0);
+/// ```
+pub fn detail_map(img: &RgbImage, window: u32) -> Mask {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let stride = width + 1;
+
+    // Summed-area tables of the luma and its square, one row and column of zeros first
+    let mut sum = vec![0.0f64; stride * (height + 1)];
+    let mut sum_sq = vec![0.0f64; stride * (height + 1)];
+
+    for y in 0..height {
+        for x in 0..width {
+            let l = mask::luma(img.get_pixel(x as u32, y as u32)) as f64;
+            let i = (y + 1) * stride + x + 1;
+
+            sum[i] = l + sum[i - 1] + sum[i - stride] - sum[i - stride - 1];
+            sum_sq[i] = l * l + sum_sq[i - 1] + sum_sq[i - stride] - sum_sq[i - stride - 1];
+        }
+    }
+
+    let window = window as usize;
+
+    Mask::from_fn(img.width(), img.height(), |x, y| {
+        let (x0, x1) = (
+            (x as usize).saturating_sub(window),
+            (x as usize + window + 1).min(width),
+        );
+        let (y0, y1) = (
+            (y as usize).saturating_sub(window),
+            (y as usize + window + 1).min(height),
+        );
+
+        let area = |t: &[f64]| {
+            t[y1 * stride + x1] - t[y0 * stride + x1] - t[y1 * stride + x0] + t[y0 * stride + x0]
+        };
+
+        let n = ((x1 - x0) * (y1 - y0)) as f64;
+        let mean = area(&sum) / n;
+        let variance = (area(&sum_sq) / n - mean * mean).max(0.0);
+
+        variance.sqrt() as f32 / FULL_DETAIL
+    })
+}
+
+/// Blurs every pixel with its own sigma, `strength` times twice `sigma` (0 keeps the pixel
+/// sharp). The image is blurred once per level of [`LEVELS`] and every pixel interpolated
+/// between the two levels around its sigma, so the cost doesn't depend on the image content.
+///
+/// # Panics
+/// - If `img` and `strength` don't have the same dimensions
+pub fn blur_variable(kernel: Kernel, img: &RgbImage, strength: &Mask) -> RgbImage {
+    assert_eq!(img.dimensions(), (strength.width(), strength.height()));
+
+    let max = LEVELS[LEVELS.len() - 1];
+
+    let levels: Vec<RgbImage> = LEVELS
+        .iter()
+        .map(|&f| match f == 0.0 {
+            true => img.clone(),
+            false => {
+                let level = Kernel {
+                    radius: (kernel.radius as f32 * f).ceil().clamp(1.0, 255.0) as u8,
+                    sigma: kernel.sigma * f as f64,
+                    faded: kernel.faded,
+                };
+                blur_passes(level, 1, Direction::Both, img)
+            }
+        })
+        .collect();
+
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let f = strength.get(x, y) * max;
+        let i = LEVELS[1..]
+            .iter()
+            .position(|&l| f <= l)
+            .unwrap_or(LEVELS.len() - 2);
+        let t = (f - LEVELS[i]) / (LEVELS[i + 1] - LEVELS[i]);
+
+        let (a, b) = (levels[i].get_pixel(x, y), levels[i + 1].get_pixel(x, y));
+
+        Rgb([0, 1, 2].map(|c| (a[c] as f32 * (1.0 - t) + b[c] as f32 * t).round() as u8))
+    })
+}
+
+/// Blurs flat regions like skies up to twice as much as `sigma` and detailed ones down to a
+/// quarter of it, following [`detail_map`], so smooth gradients lose their noise and banding
+/// while textures stay readable
+///
+/// # Examples
+/// ```
+/// use vincent_blur::adaptive::blur_adaptive;
+/// use vincent_blur::Kernel;
+///
+/// let img = image::RgbImage::from_fn(48, 48, |x, y| image::Rgb([(x * 5) as u8, (y * 5) as u8, 0]));
+///
+/// assert_eq!(blur_adaptive(Kernel::new(4, 2.0), &img).dimensions(), (48, 48));
+/// ```
+pub fn blur_adaptive(kernel: Kernel, img: &RgbImage) -> RgbImage {
+    let detail = detail_map(img, DETAIL_WINDOW).soften(DETAIL_WINDOW as u8, DETAIL_WINDOW as f64);
+    let max = LEVELS[LEVELS.len() - 1];
+
+    let strength = Mask::from_fn(img.width(), img.height(), |x, y| {
+        let d = detail.get(x, y);
+        (FLAT_FACTOR * (1.0 - d) + DETAIL_FACTOR * d) / max
+    });
+
+    blur_variable(kernel, img, &strength)
+}
@@ -0,0 +1,141 @@
+use crate::locale::Message;
+use crate::tr;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+
+/// Where `--affinity` places the workers of the threadpool backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Affinity {
+    /// Let the OS schedule the workers
+    #[default]
+    None,
+    /// Pin worker `i` to the `i`-th core
+    Cores,
+    /// Pin the workers to the cores of one NUMA node after another, so neighbouring bands of
+    /// rows and the memory they first touch stay on the same node
+    Numa,
+}
+
+impl FromStr for Affinity {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Affinity::None),
+            "cores" => Ok(Affinity::Cores),
+            "numa" => Ok(Affinity::Numa),
+            _ => Err(tr!("unknown-affinity", s)),
+        }
+    }
+}
+
+impl Affinity {
+    /// Cores to pin the workers to in order, empty when they're left to the OS or the cores
+    /// can't be listed
+    pub fn cores(self) -> Vec<usize> {
+        match self {
+            Affinity::None => Vec::new(),
+            Affinity::Cores => available_cores(),
+            Affinity::Numa => {
+                let nodes = numa_nodes();
+                let mut cores = available_cores();
+                cores.sort_by_key(|id| (nodes.get(id).copied().unwrap_or(0), *id));
+                cores
+            }
+        }
+    }
+}
+
+#[cfg(feature = "affinity")]
+fn available_cores() -> Vec<usize> {
+    core_affinity::get_core_ids()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|c| c.id)
+        .collect()
+}
+
+#[cfg(not(feature = "affinity"))]
+fn available_cores() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Pins the calling thread to `core`. Does nothing if the OS refuses or without the `affinity`
+/// feature.
+pub fn pin(core: usize) {
+    #[cfg(feature = "affinity")]
+    core_affinity::set_for_current(core_affinity::CoreId { id: core });
+
+    #[cfg(not(feature = "affinity"))]
+    let _ = core;
+}
+
+/// Niceness `--nice` gives the blur, the middle of the range of unprivileged priorities
+pub const NICENESS: i32 = 10;
+
+/// Lowers the priority of the calling thread, and of the threads it starts afterwards, to
+/// `niceness` like `nice -n`. Does nothing on platforms without niceness.
+///
+/// # Errors
+/// - If the OS refuses, e.g. when asked for a higher priority without the privilege
+pub fn lower_priority(niceness: i32) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        // SAFETY: setpriority only reads its arguments. On Linux, `0` is the calling thread,
+        // whose niceness new threads inherit.
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = niceness;
+
+    Ok(())
+}
+
+/// NUMA node of every CPU, from Linux's sysfs. Empty elsewhere.
+fn numa_nodes() -> HashMap<usize, usize> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else {
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let node = e.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpus = fs::read_to_string(e.path().join("cpulist")).ok()?;
+
+            Some(
+                parse_cpu_list(cpus.trim())?
+                    .into_iter()
+                    .map(move |c| (c, node)),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+/// Parses a Linux CPU list like `0-3,8,10-11`
+///
+/// # Examples
+/// ```
+/// use vincent_blur::affinity::parse_cpu_list;
+///
+/// assert_eq!(parse_cpu_list("0-3,8,10-11"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+/// assert_eq!(parse_cpu_list(""), Some(vec![]));
+/// assert_eq!(parse_cpu_list("3-"), None);
+/// ```
+pub fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+
+    for part in s.split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((lo, hi)) => cpus.extend(lo.parse::<usize>().ok()?..=hi.parse().ok()?),
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+
+    Some(cpus)
+}
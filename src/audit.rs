@@ -0,0 +1,307 @@
+use crate::cli::{self, error::ArgError};
+use crate::events::{self, Event};
+use crate::mask::luma;
+use crate::metrics::radius_for;
+use crate::region::Rect;
+use crate::tr;
+use crate::{codec, separable, Error};
+use image::{imageops, RgbImage};
+use std::env;
+use std::path::PathBuf;
+
+/// Sigmas of the Gaussian blurs [`recover`] tries to undo
+const DECONVOLUTION_SIGMAS: [f64; 5] = [1.0, 2.0, 3.0, 5.0, 8.0];
+
+/// Richardson-Lucy iterations per sigma
+const DECONVOLUTION_ITERATIONS: usize = 15;
+
+/// Sigma of the blur subtracted to keep only the stroke-sized detail of a region
+const DETAIL_SIGMA: f64 = 2.0;
+
+/// Correlation of the recovered detail with the original's above which a region counts as
+/// readable
+pub const RECOVERABLE: f32 = 0.4;
+
+/// How much of a redacted region's original detail an attacker can get back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recovery {
+    pub rect: Rect,
+    /// Correlation from -1 to 1 of the stroke-sized detail of the best recovery with the
+    /// original's. Text is readable well before it reaches 1.
+    pub score: f32,
+    /// Sigma of the deconvolution that recovered the most, or `None` if the redacted pixels
+    /// themselves were closest
+    pub sigma: Option<f64>,
+}
+
+impl Recovery {
+    /// Whether the original detail is still recognizable, see [`RECOVERABLE`]
+    pub fn is_recoverable(&self) -> bool {
+        self.score > RECOVERABLE
+    }
+}
+
+/// Bounding box of the pixels that differ between two images of the same size, or `None` if
+/// they are identical
+///
+/// # Panics
+/// - If the images don't have the same dimensions
+pub fn changed_region(original: &RgbImage, redacted: &RgbImage) -> Option<Rect> {
+    assert_eq!(original.dimensions(), redacted.dimensions());
+
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for ((x, y, a), b) in original.enumerate_pixels().zip(redacted.pixels()) {
+        if a != b {
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+    }
+
+    bounds.map(|(x0, y0, x1, y1)| Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+}
+
+/// Tries to read `rect` of `redacted` back: the redacted pixels as they are, which also
+/// stands for any contrast stretch since the score ignores contrast, and Richardson-Lucy
+/// deconvolutions of Gaussian blurs of several sizes. Each is compared with the same region
+/// of `original`.
+///
+/// # Panics
+/// - If the images don't have the same dimensions or `rect` isn't inside them
+///
+/// # Examples
+/// ```
+/// use vincent_blur::audit::recover;
+/// use vincent_blur::region::Rect;
+/// use vincent_blur::{blur_passes, Direction, Kernel};
+///
+/// // Stripes a few pixels wide, like the strokes of text
+/// let text = image::RgbImage::from_fn(64, 32, |x, y| {
+///     image::Rgb([if (x / 3 + y / 5) % 2 == 0 { 30 } else { 220 }; 3])
+/// });
+/// let rect = Rect::new(0, 0, 64, 32);
+///
+/// let light = blur_passes(Kernel::new(2, 0.8), 1, Direction::Both, &text);
+/// assert!(recover(&text, &light, rect).is_recoverable());
+///
+/// let heavy = blur_passes(Kernel::new(40, 14.0), 1, Direction::Both, &text);
+/// assert!(!recover(&text, &heavy, rect).is_recoverable());
+/// ```
+pub fn recover(original: &RgbImage, redacted: &RgbImage, rect: Rect) -> Recovery {
+    assert_eq!(original.dimensions(), redacted.dimensions());
+
+    let plane = |img: &RgbImage| -> Vec<f32> {
+        imageops::crop_imm(img, rect.x, rect.y, rect.width, rect.height)
+            .to_image()
+            .pixels()
+            .map(|p| luma(p) / 255.0)
+            .collect()
+    };
+
+    let width = rect.width as usize;
+    let original = detail(&plane(original), width);
+    let observed = plane(redacted);
+
+    let mut best = Recovery {
+        rect,
+        score: correlation(&detail(&observed, width), &original),
+        sigma: None,
+    };
+
+    for sigma in DECONVOLUTION_SIGMAS {
+        let restored = richardson_lucy(&observed, width, sigma, DECONVOLUTION_ITERATIONS);
+        let score = correlation(&detail(&restored, width), &original);
+
+        if score > best.score {
+            best = Recovery {
+                rect,
+                score,
+                sigma: Some(sigma),
+            };
+        }
+    }
+
+    best
+}
+
+/// Blurs a single-channel plane `width` values wide
+fn gaussian(plane: &[f32], width: usize, sigma: f64) -> Vec<f32> {
+    let weights = separable::gaussian_weights(radius_for(sigma) as usize, sigma, true);
+    let mut tmp = vec![0.0; plane.len()];
+    let mut out = vec![0.0; plane.len()];
+
+    separable::horizontal(plane, &mut tmp, width, 1, &weights);
+    separable::vertical(&tmp, &mut out, width, 1, &weights);
+    out
+}
+
+/// The plane minus its blur, which keeps edges and strokes and drops shading
+fn detail(plane: &[f32], width: usize) -> Vec<f32> {
+    let smooth = gaussian(plane, width, DETAIL_SIGMA);
+    plane.iter().zip(&smooth).map(|(v, s)| v - s).collect()
+}
+
+/// Estimates the plane before a Gaussian blur of `sigma`
+fn richardson_lucy(observed: &[f32], width: usize, sigma: f64, iterations: usize) -> Vec<f32> {
+    let observed: Vec<f32> = observed.iter().map(|v| v.max(1e-3)).collect();
+    let mut estimate = observed.clone();
+
+    for _ in 0..iterations {
+        let reblurred = gaussian(&estimate, width, sigma);
+        let ratio: Vec<f32> = observed
+            .iter()
+            .zip(&reblurred)
+            .map(|(o, r)| o / r.max(1e-6))
+            .collect();
+        let correction = gaussian(&ratio, width, sigma);
+
+        for (e, c) in estimate.iter_mut().zip(&correction) {
+            *e *= c;
+        }
+    }
+
+    estimate
+}
+
+/// Normalized cross-correlation of two zero-mean planes
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let (mut ab, mut aa, mut bb) = (0.0f64, 0.0f64, 0.0f64);
+
+    for (&x, &y) in a.iter().zip(b) {
+        ab += x as f64 * y as f64;
+        aa += x as f64 * x as f64;
+        bb += y as f64 * y as f64;
+    }
+
+    match aa > 0.0 && bb > 0.0 {
+        true => (ab / (aa * bb).sqrt()) as f32,
+        false => 0.0,
+    }
+}
+
+/// CLI options of the `verify-redaction` subcommand
+pub struct VerifyOpts {
+    pub original: PathBuf,
+    pub redacted: PathBuf,
+    /// Regions to check, or every changed pixel when empty, see [`changed_region`]
+    pub regions: Vec<Rect>,
+}
+
+impl VerifyOpts {
+    /// Constructs a new VerifyOpts from CLI options, skipping the program name and the
+    /// `verify-redaction` subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If the original and redacted images aren't both given
+    pub fn new(cli_opts: env::Args) -> Result<VerifyOpts, ArgError> {
+        let mut paths = Vec::new();
+        let mut regions = Vec::new();
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--region" => {
+                    regions.push(
+                        cli_opts
+                            .next()
+                            .ok_or_else(|| {
+                                cli_opts.invalid_value(
+                                    "--region",
+                                    "Expected x,y,width,height after --region",
+                                )
+                            })?
+                            .parse()
+                            .map_err(|e| cli_opts.invalid_value("--region", e))?,
+                    );
+                }
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur verify-redaction [--region <x>,<y>,<w>,<h>]... <original> <redacted> [--help|-h]\n",
+                        "   <original>          Path of the image before redaction.",
+                        "   <redacted>          Path of the redacted image.",
+                        "   --region            Region to check, can be repeated. Default is the box",
+                        "                       around every pixel that changed.",
+                        "   -h, --help          Print help.\n",
+                        "Tries to undo the blur of each region by deconvolution and reports how much",
+                        "of the original detail comes back. Exits with code 10 if a region is still",
+                        "readable, in which case blur it with a larger radius or pixelate it.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [original, redacted]: [PathBuf; 2] = paths
+            .try_into()
+            .map_err(|_| "Expected the original and the redacted image".to_string())?;
+
+        Ok(VerifyOpts {
+            original,
+            redacted,
+            regions,
+        })
+    }
+}
+
+/// Checks every region of the redacted image, reporting each as an [`Event::RegionChecked`]
+///
+/// # Errors
+/// - [`Error::Decode`] if an image can't be read
+/// - [`Error::InvalidArgs`] if the images differ in size or a region is outside them
+/// - [`Error::Recoverable`] if a region can still be read
+pub fn run(opts: &VerifyOpts) -> Result<(), Error> {
+    let decode_opts = codec::DecodeOptions::default();
+    let original = codec::open(&opts.original, &decode_opts).map_err(Error::Decode)?;
+    let redacted = codec::open(&opts.redacted, &decode_opts).map_err(Error::Decode)?;
+
+    if original.dimensions() != redacted.dimensions() {
+        return Err(Error::InvalidArgs(
+            "The original and redacted images have different sizes".into(),
+        ));
+    }
+
+    let regions = match opts.regions.is_empty() {
+        true => match changed_region(&original, &redacted) {
+            Some(rect) => vec![rect],
+            None => {
+                return Err(Error::Recoverable(
+                    "The redacted image is identical to the original".into(),
+                ))
+            }
+        },
+        false => opts.regions.clone(),
+    };
+
+    let mut readable = 0;
+
+    for rect in regions {
+        if rect.clamp_to(original.width(), original.height()) != Some(rect) {
+            return Err(Error::InvalidArgs(
+                tr!("region-outside", rect, original.width(), original.height()).into(),
+            ));
+        }
+
+        let recovery = recover(&original, &redacted, rect);
+
+        if recovery.is_recoverable() {
+            readable += 1;
+        }
+
+        events::emit(Event::RegionChecked(recovery));
+    }
+
+    match readable {
+        0 => Ok(()),
+        n => Err(Error::Recoverable(tr!("regions-readable", n))),
+    }
+}
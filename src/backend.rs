@@ -0,0 +1,492 @@
+use crate::affinity::Affinity;
+use crate::kernel::Kernel;
+use crate::locale::Message;
+use crate::timings::{self, Stage};
+use crate::tr;
+use crate::{blur_async_pinned, blur_sync_kernel};
+use crate::{fixed, logging, separable, throttle};
+use image::{Rgb, RgbImage};
+
+/// Names accepted by [`from_name`], including backends missing from this build
+pub const NAMES: [&str; 7] = [
+    "auto",
+    "sync",
+    "threadpool",
+    "rayon",
+    "simd",
+    "fixed",
+    "fft",
+];
+
+/// An execution strategy for the Gaussian blur. Every backend produces the same image up to
+/// rounding, and each one produces the same bytes whatever its number of workers.
+pub trait Backend: Send + Sync {
+    /// Name used to select the backend with `--backend`
+    fn name(&self) -> &'static str;
+
+    /// Blurs `img` with the Gaussian `kernel`
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage;
+}
+
+impl dyn Backend {
+    /// Picks a backend from the kernel radius, image size and available cores.
+    ///
+    /// The full 2D kernel costs `(2r + 1)²` taps per pixel spread over the workers, while the
+    /// separable SIMD path costs `2(2r + 1)` taps on one core, so the 2D pool only wins when
+    /// there are more cores than half the kernel width. Very wide kernels go to the FFT
+    /// backend when it is compiled in.
+    ///
+    /// The pick depends on the cores of the machine but not on `n_threads`, which only sets
+    /// the workers of the 2D pool, so the output is the same whatever `--threads` is.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::backend::Backend;
+    ///
+    /// let names: Vec<_> = [1, 2, 64]
+    ///     .map(|n| <dyn Backend>::auto(3, 1920, 1080, n).name())
+    ///     .into();
+    ///
+    /// assert!(names.iter().all(|n| *n == names[0]));
+    /// ```
+    pub fn auto(radius: u8, width: u32, height: u32, n_threads: usize) -> Box<dyn Backend> {
+        let backend = Self::pick(radius, width, height, n_threads);
+
+        logging::debug!(
+            "picked backend={} radius={radius} width={width} height={height} threads={n_threads}",
+            backend.name()
+        );
+
+        backend
+    }
+
+    // Rayon sizes its own pool, so only the threadpool fallback uses `n_threads`
+    #[cfg_attr(feature = "rayon", allow(unused_variables))]
+    fn pick(radius: u8, width: u32, height: u32, n_threads: usize) -> Box<dyn Backend> {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let width_taps = radius as usize * 2 + 1;
+
+        if width as u64 * height as u64 <= 64 * 64 && radius <= 1 {
+            return Box::new(SyncCpu);
+        }
+
+        #[cfg(feature = "fft")]
+        if radius >= 48 {
+            return Box::new(Fft);
+        }
+
+        if cores * 2 > width_taps {
+            #[cfg(feature = "rayon")]
+            return Box::new(Rayon);
+
+            #[cfg(not(feature = "rayon"))]
+            return Box::new(ThreadPoolCpu {
+                n_threads: n_threads.clamp(1, cores),
+                affinity: Affinity::None,
+                adaptive: false,
+            });
+        }
+
+        Box::new(Simd)
+    }
+}
+
+/// Kernel taps each worker should have to compute before another thread is worth starting
+pub const TAPS_PER_THREAD: u64 = 1 << 22;
+
+/// Number of workers for `pixels` pixels of `taps` kernel taps each, at most `max_threads`.
+/// Below [`TAPS_PER_THREAD`] of work in total, the blur runs on a single thread.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::backend::threads_for_work;
+///
+/// // A 64x64 icon with a 7x7 kernel
+/// assert_eq!(threads_for_work(64 * 64, 49, 10), 1);
+/// // A 4K frame with a 21x21 kernel
+/// assert_eq!(threads_for_work(3840 * 2160, 441, 10), 10);
+/// ```
+pub fn threads_for_work(pixels: u64, taps: u64, max_threads: usize) -> usize {
+    let work = pixels.saturating_mul(taps);
+    let threads = (work / TAPS_PER_THREAD).clamp(1, max_threads.max(1) as u64) as usize;
+
+    logging::debug!(
+        "scheduled pixels={pixels} taps={taps} threads={threads} max_threads={max_threads}"
+    );
+
+    threads
+}
+
+/// Looks up a backend by name. `auto` is resolved per image with [`<dyn Backend>::auto`], so
+/// it returns `None` here.
+///
+/// # Errors
+/// - If the name is unknown or the backend isn't available in this build
+pub fn from_name(name: &str, n_threads: usize) -> Result<Option<Box<dyn Backend>>, Message> {
+    let backend: Box<dyn Backend> = match name {
+        "auto" => return Ok(None),
+        "sync" => Box::new(SyncCpu),
+        "threadpool" => Box::new(ThreadPoolCpu {
+            n_threads,
+            affinity: Affinity::None,
+            adaptive: false,
+        }),
+        #[cfg(feature = "rayon")]
+        "rayon" => Box::new(Rayon),
+        #[cfg(not(feature = "rayon"))]
+        "rayon" => return Err("The rayon backend requires the `rayon` feature".into()),
+        "simd" => Box::new(Simd),
+        "fixed" => Box::new(Fixed),
+        #[cfg(feature = "fft")]
+        "fft" => Box::new(Fft),
+        #[cfg(not(feature = "fft"))]
+        "fft" => return Err("The fft backend requires the `fft` feature".into()),
+        _ => return Err(tr!("unknown-backend", name)),
+    };
+
+    Ok(Some(backend))
+}
+
+/// Every backend compiled into this build, [`SyncCpu`] first
+pub fn all(n_threads: usize) -> Vec<Box<dyn Backend>> {
+    NAMES
+        .iter()
+        .filter_map(|name| from_name(name, n_threads).ok().flatten())
+        .collect()
+}
+
+/// Largest difference of a channel value [`compare_all`] tolerates between two backends
+pub const TOLERANCE: u8 = 1;
+
+/// How far one backend's blur strays from [`SyncCpu`]'s
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub backend: &'static str,
+    /// Largest difference of a channel value
+    pub max_diff: u8,
+    /// A pixel where `max_diff` was found
+    pub at: (u32, u32),
+}
+
+impl Comparison {
+    pub fn is_match(&self) -> bool {
+        self.max_diff <= TOLERANCE
+    }
+}
+
+/// Blurs `img` with every other backend of this build and compares each result to [`SyncCpu`],
+/// the plain 2D convolution the faster paths are meant to reproduce
+///
+/// # Examples
+/// ```
+/// use image::{Rgb, RgbImage};
+/// use vincent_blur::backend::compare_all;
+///
+/// let img = RgbImage::from_fn(20, 12, |x, y| Rgb([(x * 12) as u8, (y * 20) as u8, 90]));
+///
+/// assert!(compare_all(2, 1.5, &img, 4).iter().all(|c| c.is_match()));
+/// ```
+pub fn compare_all(radius: u8, sigma: f64, img: &RgbImage, n_threads: usize) -> Vec<Comparison> {
+    let kernel = Kernel::new(radius, sigma);
+    let expected = SyncCpu.blur(kernel, img.clone());
+
+    all(n_threads)
+        .iter()
+        .filter(|b| b.name() != SyncCpu.name())
+        .map(|backend| {
+            let blurred = backend.blur(kernel, img.clone());
+
+            let (i, max_diff) = blurred
+                .as_raw()
+                .iter()
+                .zip(expected.as_raw())
+                .map(|(a, b)| a.abs_diff(*b))
+                .enumerate()
+                .max_by_key(|&(_, d)| d)
+                .unwrap_or((0, 0));
+
+            let pixel = (i / 3) as u32;
+
+            Comparison {
+                backend: backend.name(),
+                max_diff,
+                at: (pixel % img.width().max(1), pixel / img.width().max(1)),
+            }
+        })
+        .collect()
+}
+
+/// Radii and sigmas [`self_test`] blurs with, from the smallest kernel to one wider than its
+/// image
+const SELF_TEST_KERNELS: [(u8, f64); 3] = [(1, 0.8), (4, 2.0), (40, 12.0)];
+
+/// What [`self_test`] found for one backend and kernel
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    /// The backend's blur is within [`TOLERANCE`] of [`SyncCpu`]'s
+    Matches {
+        backend: &'static str,
+        radius: u8,
+        sigma: f64,
+    },
+    /// The backend's blur strays further than [`TOLERANCE`] from [`SyncCpu`]'s
+    Differs {
+        comparison: Comparison,
+        radius: u8,
+        sigma: f64,
+    },
+    /// The backend's impulse response isn't the kernel, see [`Kernel::verify`]
+    WrongKernel {
+        backend: &'static str,
+        radius: u8,
+        sigma: f64,
+        error: Message,
+    },
+}
+
+impl Check {
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Check::Matches { .. })
+    }
+}
+
+/// Runs [`compare_all`] on a synthetic image with gradients, hard edges and noise and
+/// [`Kernel::verify`] on every backend for a few kernels, returning every check for the caller
+/// to report
+pub fn self_test(n_threads: usize) -> Vec<Check> {
+    let img = RgbImage::from_fn(67, 45, |x, y| {
+        let edge = match (x / 8 + y / 8) % 2 {
+            0 => 0,
+            _ => 160,
+        };
+        let noise = (x * 7919 + y * 104_729) % 61;
+
+        Rgb([(x * 3) as u8, edge + noise as u8, (y * 5) as u8])
+    });
+
+    let mut checks = Vec::new();
+
+    for (radius, sigma) in SELF_TEST_KERNELS {
+        for comparison in compare_all(radius, sigma, &img, n_threads) {
+            checks.push(match comparison.is_match() {
+                true => Check::Matches {
+                    backend: comparison.backend,
+                    radius,
+                    sigma,
+                },
+                false => Check::Differs {
+                    comparison,
+                    radius,
+                    sigma,
+                },
+            });
+        }
+
+        let kernel = Kernel::new(radius, sigma);
+
+        for backend in all(n_threads) {
+            if let Err(error) = kernel.verify(|k, img| backend.blur(k, img)) {
+                checks.push(Check::WrongKernel {
+                    backend: backend.name(),
+                    radius,
+                    sigma,
+                    error,
+                });
+            }
+        }
+    }
+
+    checks
+}
+
+/// One pixel at a time on the calling thread, like [`blur_sync`]
+pub struct SyncCpu;
+
+impl Backend for SyncCpu {
+    fn name(&self) -> &'static str {
+        "sync"
+    }
+
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage {
+        timings::time(Stage::Convolve, || blur_sync_kernel(kernel, img))
+    }
+}
+
+/// Bands of rows on `n_threads` threads, like [`blur_async`](crate::blur_async)
+pub struct ThreadPoolCpu {
+    pub n_threads: usize,
+    pub affinity: Affinity,
+    /// Retire workers as their throughput drops, see [`throttle::blur_adaptive`]
+    pub adaptive: bool,
+}
+
+impl Backend for ThreadPoolCpu {
+    fn name(&self) -> &'static str {
+        "threadpool"
+    }
+
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage {
+        let cores = self.affinity.cores();
+
+        timings::time(Stage::Convolve, || match self.adaptive {
+            true => throttle::blur_adaptive(kernel, self.n_threads, &cores, img),
+            false => blur_async_pinned(kernel, self.n_threads, &cores, img, 100, |_, _| {}),
+        })
+    }
+}
+
+/// Rows of the 2D kernel spread over rayon's global pool
+#[cfg(feature = "rayon")]
+pub struct Rayon;
+
+#[cfg(feature = "rayon")]
+impl Backend for Rayon {
+    fn name(&self) -> &'static str {
+        "rayon"
+    }
+
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage {
+        use rayon::prelude::*;
+
+        let m = kernel.matrix();
+        let width = img.width() as usize;
+        let mut out = RgbImage::new(img.width(), img.height());
+
+        timings::time(Stage::Convolve, || {
+            out.par_chunks_mut(width * 3)
+                .enumerate()
+                .for_each(|(y, row)| {
+                    for (x, px) in row.chunks_exact_mut(3).enumerate() {
+                        let p = crate::calculate_new_pixel(x as i32, y as i32, &m, &img);
+                        px.copy_from_slice(&p.0);
+                    }
+                });
+        });
+
+        out
+    }
+}
+
+/// Separable horizontal and vertical passes over an f32 buffer, with inner loops the compiler
+/// turns into SIMD multiply-adds
+pub struct Simd;
+
+impl Backend for Simd {
+    fn name(&self) -> &'static str {
+        "simd"
+    }
+
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage {
+        separable::blur(&img, &kernel.weights())
+    }
+}
+
+/// Separable passes in 16.16 fixed point, for CPUs without fast floating point SIMD and for
+/// WebAssembly. Within 1 of [`Simd`].
+pub struct Fixed;
+
+impl Backend for Fixed {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage {
+        fixed::blur(&img, &fixed::weights(&kernel.weights()))
+    }
+}
+
+/// Separable passes where each row and column is convolved through an FFT, so the cost no
+/// longer grows with the radius
+#[cfg(feature = "fft")]
+pub struct Fft;
+
+#[cfg(feature = "fft")]
+impl Backend for Fft {
+    fn name(&self) -> &'static str {
+        "fft"
+    }
+
+    fn blur(&self, kernel: Kernel, img: RgbImage) -> RgbImage {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let weights = kernel.weights();
+
+        let rows = timings::time(Stage::Horizontal, || {
+            fft_rows(&separable::to_f32(&img), width, height, &weights)
+        });
+        let cols = timings::time(Stage::Vertical, || {
+            fft_rows(&transpose(&rows, width, height), height, width, &weights)
+        });
+
+        separable::to_rgb(img.width(), img.height(), &transpose(&cols, height, width))
+    }
+}
+
+/// Convolves every row of an interleaved RGB buffer with `weights` through an FFT of the
+/// zero-padded row
+#[cfg(feature = "fft")]
+fn fft_rows(data: &[f32], width: usize, height: usize, weights: &[f32]) -> Vec<f32> {
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    let r = weights.len() / 2;
+    let len = width + 2 * r;
+
+    let mut planner = FftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(len);
+    let inverse = planner.plan_fft_inverse(len);
+
+    let mut kernel = vec![Complex::new(0.0, 0.0); len];
+    for (k, &w) in weights.iter().enumerate() {
+        kernel[(k + len - r) % len] = Complex::new(w, 0.0);
+    }
+    forward.process(&mut kernel);
+
+    let norms: Vec<f32> = (0..width)
+        .map(|x| {
+            let lo = r.saturating_sub(x);
+            let hi = (width + r - x).min(weights.len());
+            weights[lo..hi].iter().sum()
+        })
+        .collect();
+
+    let mut out = vec![0.0; data.len()];
+    let mut buf = vec![Complex::new(0.0, 0.0); len];
+
+    for y in 0..height {
+        for c in 0..3 {
+            for (x, v) in buf.iter_mut().enumerate() {
+                *v = match x < width {
+                    true => Complex::new(data[(y * width + x) * 3 + c], 0.0),
+                    false => Complex::new(0.0, 0.0),
+                };
+            }
+
+            forward.process(&mut buf);
+            for (v, k) in buf.iter_mut().zip(&kernel) {
+                *v *= k;
+            }
+            inverse.process(&mut buf);
+
+            for x in 0..width {
+                out[(y * width + x) * 3 + c] = buf[x].re / len as f32 / norms[x];
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(feature = "fft")]
+fn transpose(data: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut out = vec![0.0; data.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            let src = (y * width + x) * 3;
+            let dst = (x * height + y) * 3;
+            out[dst..dst + 3].copy_from_slice(&data[src..src + 3]);
+        }
+    }
+
+    out
+}
@@ -0,0 +1,301 @@
+use crate::events::{self, Event};
+use crate::locale::Message;
+use crate::logging;
+use crate::threadpool::ThreadPool;
+use crate::tr;
+use crate::Error;
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+
+/// Outcome of blurring several images with `--batch`
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    #[serde(serialize_with = "lossy_paths")]
+    pub succeeded: Vec<PathBuf>,
+    #[serde(serialize_with = "failures")]
+    pub failed: Vec<(PathBuf, Error)>,
+    /// Sources left alone because [`cache`](crate::cache) had them as already blurred
+    #[serde(serialize_with = "lossy_paths")]
+    pub skipped: Vec<PathBuf>,
+}
+
+impl Report {
+    /// Number of files of the batch
+    pub fn total(&self) -> usize {
+        self.succeeded.len() + self.failed.len() + self.skipped.len()
+    }
+
+    /// Turns the report into the result of the whole run
+    ///
+    /// # Errors
+    /// - [`Error::PartialBatch`] if any file failed
+    pub fn result(&self) -> Result<(), Error> {
+        if self.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::PartialBatch {
+                failed: self.failed.len(),
+                total: self.total(),
+            })
+        }
+    }
+
+    /// The report in the language of the current catalog, see [`Message::translate`]. It
+    /// displays in English.
+    pub fn translate(&self) -> String {
+        self.render(Message::translate)
+    }
+
+    fn render(&self, text: impl Fn(&Message) -> String) -> String {
+        let mut out = text(&tr!(
+            "batch-summary",
+            self.succeeded.len(),
+            self.failed.len()
+        ));
+
+        if !self.skipped.is_empty() {
+            out += &format!(", {}", text(&tr!("already-blurred", self.skipped.len())));
+        }
+
+        for (path, e) in &self.failed {
+            out += &format!("\n  {}: {}", path.display(), text(&e.message()));
+        }
+
+        out
+    }
+
+    /// Serializes the report as a single line of JSON, with the total number of files next to
+    /// the fields of the report
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Summary<'a> {
+            total: usize,
+            #[serde(flatten)]
+            report: &'a Report,
+        }
+
+        serde_json::to_string(&Summary {
+            total: self.total(),
+            report: self,
+        })
+        .expect("reports are always serializable")
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(|m| m.to_string()))
+    }
+}
+
+/// Blurs every source/destination pair with `blur_file`, running up to `jobs` files at once
+/// on a thread pool. Each file is decoded, blurred and encoded by the same job.
+///
+/// Without `keep_going`, files that haven't started yet are skipped after the first failure.
+///
+/// # Errors
+/// - The first error, in input order, if a file failed and `keep_going` is false
+pub fn run<F>(
+    files: &[(PathBuf, PathBuf)],
+    jobs: usize,
+    keep_going: bool,
+    blur_file: F,
+) -> Result<Report, Error>
+where
+    F: Fn(&Path, &Path) -> Result<(), Error> + Send + Sync + 'static,
+{
+    let blur_file = Arc::new(blur_file);
+    let failed = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let workers = jobs.min(files.len()).max(1);
+
+    logging::debug!("scheduled files={} workers={workers}", files.len());
+
+    {
+        let pool = ThreadPool::new(workers);
+
+        for (i, (original, blurred)) in files.iter().cloned().enumerate() {
+            let blur_file = Arc::clone(&blur_file);
+            let failed = Arc::clone(&failed);
+            let tx = tx.clone();
+
+            pool.execute(Box::new(move || {
+                if !keep_going && failed.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                let res = blur_file(&original, &blurred);
+
+                if let Err(e) = &res {
+                    if keep_going {
+                        events::emit(Event::FileFailed {
+                            path: original.clone(),
+                            error: e.message(),
+                        });
+                    }
+                    failed.store(true, Ordering::SeqCst);
+                }
+
+                tx.send((i, original, res)).unwrap();
+            }));
+        }
+    }
+
+    drop(tx);
+
+    collect(rx.iter().collect(), keep_going)
+}
+
+/// Blurs every source/destination pair in order as a three-stage pipeline: while `blur` runs on
+/// the calling thread, the next file is decoded and the previous one encoded on two workers, so
+/// reading and writing are hidden behind the blur. `decode` gets the source and destination, and
+/// `encode` the blurred image and the destination.
+///
+/// Without `keep_going`, no new file is decoded or blurred after the first failure.
+///
+/// # Errors
+/// - The first error, in input order, if a file failed and `keep_going` is false
+///
+/// # Examples
+/// ```
+/// use std::path::PathBuf;
+/// use vincent_blur::batch;
+///
+/// let files: Vec<_> = (1..=3)
+///     .map(|i| (PathBuf::from(format!("{i}")), PathBuf::from(format!("{i}.out"))))
+///     .collect();
+///
+/// let report = batch::pipelined(
+///     &files,
+///     false,
+///     |original, _| Ok(original.to_str().unwrap().parse::<u32>().unwrap()),
+///     |n| Ok(n * 10),
+///     |n, blurred| {
+///         assert_eq!(blurred, PathBuf::from(format!("{}.out", n / 10)));
+///         Ok(())
+///     },
+/// )
+/// .unwrap();
+///
+/// assert_eq!(report.succeeded.len(), 3);
+/// ```
+pub fn pipelined<T, D, B, E>(
+    files: &[(PathBuf, PathBuf)],
+    keep_going: bool,
+    decode: D,
+    mut blur: B,
+    encode: E,
+) -> Result<Report, Error>
+where
+    T: Send + 'static,
+    D: Fn(&Path, &Path) -> Result<T, Error> + Send + 'static,
+    B: FnMut(T) -> Result<T, Error>,
+    E: Fn(T, &Path) -> Result<(), Error> + Send + 'static,
+{
+    let failed = Arc::new(AtomicBool::new(false));
+    let (decoded_tx, decoded_rx) = mpsc::sync_channel(1);
+
+    logging::debug!("scheduled files={} workers=pipelined", files.len());
+    let (blurred_tx, blurred_rx) = mpsc::sync_channel::<(usize, Result<T, Error>)>(1);
+    let (tx, rx) = mpsc::channel();
+
+    {
+        let pool = ThreadPool::new(2);
+
+        let decoder_files = files.to_vec();
+        let decoder_failed = Arc::clone(&failed);
+
+        pool.execute(Box::new(move || {
+            for (i, (original, blurred)) in decoder_files.iter().enumerate() {
+                if !keep_going && decoder_failed.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if decoded_tx.send((i, decode(original, blurred))).is_err() {
+                    break;
+                }
+            }
+        }));
+
+        let encoder_files = files.to_vec();
+        let encoder_failed = Arc::clone(&failed);
+
+        pool.execute(Box::new(move || {
+            for (i, res) in blurred_rx {
+                let (original, blurred) = &encoder_files[i];
+                let res = res.and_then(|img| encode(img, blurred));
+
+                if let Err(e) = &res {
+                    if keep_going {
+                        events::emit(Event::FileFailed {
+                            path: original.clone(),
+                            error: e.message(),
+                        });
+                    }
+                    encoder_failed.store(true, Ordering::SeqCst);
+                }
+
+                tx.send((i, original.clone(), res)).unwrap();
+            }
+        }));
+
+        for (i, res) in decoded_rx {
+            if !keep_going && failed.load(Ordering::SeqCst) {
+                break;
+            }
+
+            if blurred_tx.send((i, res.and_then(&mut blur))).is_err() {
+                break;
+            }
+        }
+
+        drop(blurred_tx);
+    }
+
+    collect(rx.iter().collect(), keep_going)
+}
+
+/// Sorts the results of the jobs back into input order and builds the report
+fn collect(
+    mut results: Vec<(usize, PathBuf, Result<(), Error>)>,
+    keep_going: bool,
+) -> Result<Report, Error> {
+    results.sort_by_key(|(i, _, _)| *i);
+
+    let mut report = Report::default();
+
+    for (_, original, res) in results {
+        match res {
+            Ok(()) => report.succeeded.push(original),
+            Err(e) if !keep_going => return Err(e),
+            Err(e) => report.failed.push((original, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Paths as strings, lossily for the ones that aren't UTF-8
+fn lossy_paths<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|p| p.to_string_lossy()))
+}
+
+/// Failed files as objects with their path, exit code and English error
+fn failures<S: Serializer>(failed: &[(PathBuf, Error)], serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Failure<'a> {
+        file: Cow<'a, str>,
+        exit_code: u8,
+        error: String,
+    }
+
+    serializer.collect_seq(failed.iter().map(|(path, e)| Failure {
+        file: path.to_string_lossy(),
+        exit_code: e.exit_code(),
+        error: e.to_string(),
+    }))
+}
@@ -0,0 +1,339 @@
+use crate::cli::{self, error::ArgError};
+use crate::testgen::{self, Pattern};
+use crate::tr;
+use crate::{backend, codec, parse_positive, parse_size, Error, Kernel};
+use serde::Serialize;
+use std::env;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// CLI options of the `bench` subcommand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchOpts {
+    pub sizes: Vec<(u32, u32)>,
+    pub radii: Vec<u8>,
+    /// Worker counts tried with the backends that take one
+    pub threads: Vec<usize>,
+    /// Backends to time, every one of the build when empty
+    pub backends: Vec<String>,
+    /// Blurs per measurement, of which the fastest is kept
+    pub runs: usize,
+    /// Where to write the results, as JSON for a `.json` file and as CSV otherwise
+    pub out: Option<PathBuf>,
+}
+
+impl Default for BenchOpts {
+    fn default() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        BenchOpts {
+            sizes: vec![(1024, 768)],
+            radii: vec![2, 8],
+            threads: thread_counts(cores),
+            backends: Vec::new(),
+            runs: 3,
+            out: None,
+        }
+    }
+}
+
+/// 1, 2, 4, ... up to `cores`, which is always included
+fn thread_counts(cores: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|n| *n < cores)
+        .collect();
+    counts.push(cores.max(1));
+    counts
+}
+
+/// `f` applied to every comma-separated item of `s`
+fn parse_list<T>(s: Option<String>, f: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    s?.split(',').map(|item| f(item.trim())).collect()
+}
+
+impl BenchOpts {
+    /// Constructs a new BenchOpts from CLI options, skipping the program name and the `bench`
+    /// subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If an argument isn't one of the options
+    pub fn new(cli_opts: env::Args) -> Result<BenchOpts, ArgError> {
+        let mut opts = BenchOpts::default();
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--sizes" => {
+                    opts.sizes = parse_list(cli_opts.next(), parse_size).ok_or_else(|| {
+                        cli_opts.invalid_value(
+                            "--sizes",
+                            "Expected sizes like 640x480,1920x1080 after --sizes",
+                        )
+                    })?;
+                }
+                "--radii" => {
+                    opts.radii = parse_list(cli_opts.next(), parse_positive).ok_or_else(|| {
+                        cli_opts
+                            .invalid_value("--radii", "Expected radii like 2,8,32 after --radii")
+                    })?;
+                }
+                "--threads" => {
+                    opts.threads =
+                        parse_list(cli_opts.next(), parse_positive).ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                "--threads",
+                                "Expected thread counts like 1,2,4 after --threads",
+                            )
+                        })?;
+                }
+                "--backends" => {
+                    opts.backends = parse_list(cli_opts.next(), |b| Some(b.to_string()))
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                "--backends",
+                                "Expected backends like simd,threadpool after --backends",
+                            )
+                        })?;
+                }
+                "--runs" => {
+                    opts.runs = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--runs", tr!("number-after", "--runs"))
+                        })?;
+                }
+                "--out" => {
+                    opts.out = Some(PathBuf::from(cli_opts.next().ok_or_else(|| {
+                        cli_opts.invalid_value("--out", "Expected a file after --out")
+                    })?));
+                }
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur bench [--sizes <sizes>] [--radii <radii>] [--threads <counts>] [--backends <names>] [--runs <runs>] [--out <file>] [--help|-h]\n",
+                        "   --sizes             Comma-separated image sizes. Default is 1024x768.",
+                        "   --radii             Comma-separated blur radii, each with a sigma of a",
+                        "                       third of it. Default is 2,8.",
+                        "   --threads           Comma-separated worker counts for the threadpool",
+                        "                       backend. Default is 1, 2, 4, ... up to the cores.",
+                        "   --backends          Comma-separated backends. Default is every backend",
+                        "                       of this build.",
+                        "   --runs              Blurs per measurement, the fastest being kept.",
+                        "                       Default is 3.",
+                        "   --out               Also write the results to this file, as JSON for a",
+                        "                       .json file and as CSV otherwise.",
+                        "   -h, --help          Print help.\n",
+                        "Times every backend on noise images of every size and radius, and prints",
+                        "the throughput of each in megapixels per second.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => return Err(cli_opts.unknown(tr!("unknown-option", arg))),
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+/// The fastest of the blurs of one backend on one image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+    pub width: u32,
+    pub height: u32,
+    pub backend: &'static str,
+    /// Workers the backend blurred with
+    pub threads: usize,
+    pub radius: u8,
+    pub seconds: f64,
+    pub megapixels_per_second: f64,
+}
+
+/// Blurs noise images with every combination of `opts`, keeping the fastest of `opts.runs`
+/// blurs of each. Backends that don't take a worker count are only timed once per image.
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if a backend is unknown or missing from this build
+///
+/// # Examples
+/// ```
+/// use vincent_blur::bench::{measure, BenchOpts};
+///
+/// let opts = BenchOpts {
+///     sizes: vec![(32, 24)],
+///     radii: vec![2],
+///     threads: vec![1, 2],
+///     backends: vec!["simd".to_string(), "threadpool".to_string()],
+///     runs: 1,
+///     out: None,
+/// };
+///
+/// let samples = measure(&opts).unwrap();
+/// let labels: Vec<_> = samples.iter().map(|s| (s.backend, s.threads)).collect();
+///
+/// assert_eq!(labels, [("simd", 1), ("threadpool", 1), ("threadpool", 2)]);
+/// assert!(samples.iter().all(|s| s.width == 32 && s.megapixels_per_second > 0.0));
+/// ```
+pub fn measure(opts: &BenchOpts) -> Result<Vec<Sample>, Error> {
+    let names: Vec<&str> = match opts.backends.is_empty() {
+        true => backend::all(1).iter().map(|b| b.name()).collect(),
+        false => opts.backends.iter().map(String::as_str).collect(),
+    };
+
+    let mut samples = Vec::new();
+
+    for &(width, height) in &opts.sizes {
+        let img = testgen::generate(Pattern::Noise, width, height, 0);
+
+        for &radius in &opts.radii {
+            let sigma = (radius as f64 / 3.0).max(0.5);
+
+            for name in &names {
+                let counts = match *name {
+                    "threadpool" => opts.threads.as_slice(),
+                    _ => &[1],
+                };
+
+                for &n_threads in counts {
+                    let backend = backend::from_name(name, n_threads)
+                        .map_err(|e| Error::InvalidArgs(e.into()))?
+                        .ok_or(Error::InvalidArgs(
+                            "The auto backend can't be benchmarked, name the backends instead"
+                                .into(),
+                        ))?;
+
+                    let seconds = (0..opts.runs)
+                        .map(|_| {
+                            let start = Instant::now();
+                            backend.blur(Kernel::new(radius, sigma), img.clone());
+                            start.elapsed()
+                        })
+                        .min()
+                        .unwrap_or(Duration::ZERO)
+                        .as_secs_f64();
+
+                    samples.push(Sample {
+                        width,
+                        height,
+                        backend: backend.name(),
+                        threads: threads_used(backend.name(), n_threads),
+                        radius,
+                        seconds,
+                        megapixels_per_second: width as f64 * height as f64
+                            / 1e6
+                            / seconds.max(f64::EPSILON),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Workers `backend` runs on when asked for `n_threads`
+fn threads_used(backend: &str, n_threads: usize) -> usize {
+    match backend {
+        "threadpool" => n_threads,
+        #[cfg(feature = "rayon")]
+        "rayon" => rayon::current_num_threads(),
+        _ => 1,
+    }
+}
+
+/// `samples` as CSV, one header line then one line per sample
+///
+/// # Examples
+/// ```
+/// use vincent_blur::bench::{to_csv, Sample};
+///
+/// let sample = Sample {
+///     width: 640,
+///     height: 480,
+///     backend: "simd",
+///     threads: 1,
+///     radius: 8,
+///     seconds: 0.01,
+///     megapixels_per_second: 30.72,
+/// };
+///
+/// assert_eq!(
+///     to_csv(&[sample]),
+///     "width,height,backend,threads,radius,seconds,megapixels_per_second\n\
+///      640,480,simd,1,8,0.01,30.72\n"
+/// );
+/// ```
+pub fn to_csv(samples: &[Sample]) -> String {
+    let mut csv = "width,height,backend,threads,radius,seconds,megapixels_per_second\n".to_string();
+
+    for s in samples {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            s.width, s.height, s.backend, s.threads, s.radius, s.seconds, s.megapixels_per_second
+        ));
+    }
+
+    csv
+}
+
+/// `samples` as a JSON array of objects with the fields of [`Sample`]
+pub fn to_json(samples: &[Sample]) -> String {
+    serde_json::to_string_pretty(samples).expect("samples are always serializable")
+}
+
+/// Prints the throughput of every combination of `opts` on stdout as it is measured, then
+/// writes them all to `opts.out`
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if a backend is unknown or missing from this build
+/// - [`Error::Encode`] if the results can't be written
+pub fn run(opts: &BenchOpts) -> Result<(), Error> {
+    println!(
+        "{:<11} {:<10} {:>7} {:>6} {:>10} {:>9}",
+        "size", "backend", "threads", "radius", "seconds", "MP/s"
+    );
+
+    let mut samples = Vec::new();
+
+    for &size in &opts.sizes {
+        for &radius in &opts.radii {
+            let one = BenchOpts {
+                sizes: vec![size],
+                radii: vec![radius],
+                ..opts.clone()
+            };
+
+            for s in measure(&one)? {
+                println!(
+                    "{:<11} {:<10} {:>7} {:>6} {:>10.4} {:>9.1}",
+                    format!("{}x{}", s.width, s.height),
+                    s.backend,
+                    s.threads,
+                    s.radius,
+                    s.seconds,
+                    s.megapixels_per_second
+                );
+                samples.push(s);
+            }
+        }
+    }
+
+    match &opts.out {
+        Some(out) => {
+            let contents = match out.extension().is_some_and(|e| e == "json") {
+                true => to_json(&samples),
+                false => to_csv(&samples),
+            };
+            codec::write(out, contents.as_bytes()).map_err(Error::Encode)
+        }
+        None => Ok(()),
+    }
+}
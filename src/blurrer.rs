@@ -0,0 +1,126 @@
+use crate::threadpool::{Priority, ThreadPool};
+use crate::{calculate_new_pixel, Kernel};
+use grid::Grid;
+use image::RgbImage;
+use std::sync::{mpsc, Arc};
+
+/// Blurs any number of images with the same kernel on one long-lived pool of workers. Video
+/// frames, watch mode and servers would otherwise start and stop the threads and rebuild the
+/// kernel for every image.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::blurrer::Blurrer;
+///
+/// let blurrer = Blurrer::new(3, 1.5, 4);
+///
+/// for frame in 0..3 {
+///     let img = image::RgbImage::from_fn(32, 24, |x, y| {
+///         image::Rgb([(x * 8) as u8, (y * 10) as u8, frame * 60])
+///     });
+///
+///     assert_eq!(blurrer.run(&img), vincent_blur::blur_sync(3, 1.5, img));
+/// }
+/// ```
+pub struct Blurrer {
+    radius: u8,
+    sigma: f64,
+    n_threads: usize,
+    kernel: Arc<Grid<f64>>,
+    pool: ThreadPool,
+}
+
+impl Blurrer {
+    /// Starts `n_threads` workers and builds the kernel
+    pub fn new(radius: u8, sigma: f64, n_threads: usize) -> Self {
+        let n_threads = n_threads.max(1);
+
+        Blurrer {
+            radius,
+            sigma,
+            n_threads,
+            kernel: Arc::new(Kernel::new(radius, sigma).matrix()),
+            pool: ThreadPool::new(n_threads),
+        }
+    }
+
+    pub fn radius(&self) -> u8 {
+        self.radius
+    }
+
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    /// Blurs `img` like [`blur_async`](crate::blur_async), on the workers started by
+    /// [`Blurrer::new`]. The image is copied once so the workers can share it.
+    pub fn run(&self, img: &RgbImage) -> RgbImage {
+        self.run_with_priority(img, Priority::Normal)
+    }
+
+    /// Like [`Blurrer::run`], but the bands of `img` are queued with `priority`, so when
+    /// several threads share the blurrer, e.g. an interactive preview next to background
+    /// renders, the more urgent image is finished first
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use vincent_blur::blurrer::Blurrer;
+    /// use vincent_blur::threadpool::Priority;
+    ///
+    /// let blurrer = Arc::new(Blurrer::new(2, 1.0, 2));
+    /// let img = image::RgbImage::from_fn(40, 30, |x, y| image::Rgb([(x * 6) as u8, (y * 8) as u8, 0]));
+    ///
+    /// let background = {
+    ///     let (blurrer, img) = (Arc::clone(&blurrer), img.clone());
+    ///     thread::spawn(move || blurrer.run_with_priority(&img, Priority::Background))
+    /// };
+    /// let preview = blurrer.run_with_priority(&img, Priority::Interactive);
+    ///
+    /// assert_eq!(preview, background.join().unwrap());
+    /// ```
+    pub fn run_with_priority(&self, img: &RgbImage, priority: Priority) -> RgbImage {
+        let (width, height) = img.dimensions();
+        let stride = width as usize * 3;
+        let band = height.div_ceil(self.n_threads as u32 * 4).max(1);
+
+        let img = Arc::new(img.clone());
+        let (tx, rx) = mpsc::channel();
+
+        for y0 in (0..height).step_by(band as usize) {
+            let kernel = Arc::clone(&self.kernel);
+            let img = Arc::clone(&img);
+            let tx = tx.clone();
+
+            self.pool.execute_with_priority(
+                priority,
+                Box::new(move || {
+                    let y1 = (y0 + band).min(height);
+                    let mut buf = vec![0; (y1 - y0) as usize * stride];
+
+                    for (y, row) in (y0..y1).zip(buf.chunks_exact_mut(stride)) {
+                        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+                            let p = calculate_new_pixel(x as i32, y as i32, &kernel, &img);
+                            px.copy_from_slice(&p.0);
+                        }
+                    }
+
+                    let _ = tx.send((y0, buf));
+                }),
+            );
+        }
+
+        drop(tx);
+
+        let mut out = RgbImage::new(width, height);
+
+        for (y0, buf) in rx {
+            let start = y0 as usize * stride;
+            let raw: &mut [u8] = &mut out;
+            raw[start..start + buf.len()].copy_from_slice(&buf);
+        }
+
+        out
+    }
+}
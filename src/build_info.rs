@@ -0,0 +1,141 @@
+use crate::{backend, codec};
+use image::ImageFormat;
+use std::fmt;
+
+/// Cargo features, with whether this build has them
+pub const FEATURES: [(&str, bool); 15] = [
+    ("affinity", cfg!(feature = "affinity")),
+    ("crossbeam", cfg!(feature = "crossbeam")),
+    ("fft", cfg!(feature = "fft")),
+    ("fuzz", cfg!(feature = "fuzz")),
+    ("gui", cfg!(feature = "gui")),
+    ("jxl", cfg!(feature = "jxl")),
+    ("log", cfg!(feature = "log")),
+    ("plugins", cfg!(feature = "plugins")),
+    ("portrait", cfg!(feature = "portrait")),
+    ("preview", cfg!(feature = "preview")),
+    ("rayon", cfg!(feature = "rayon")),
+    ("redact", cfg!(feature = "redact")),
+    ("svg", cfg!(feature = "svg")),
+    ("tokio", cfg!(feature = "tokio")),
+    ("wasm", cfg!(feature = "wasm")),
+];
+
+/// What this build of blur can do on this machine, as printed by `--version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Enabled cargo features
+    pub features: Vec<&'static str>,
+    /// Backends `--backend` accepts, besides `auto`
+    pub backends: Vec<&'static str>,
+    /// Extensions of the formats sources can be in
+    pub decodes: Vec<&'static str>,
+    /// Extensions `--format` accepts
+    pub encodes: Vec<&'static str>,
+    /// Threads the machine can run at once
+    pub threads: usize,
+    /// SIMD instruction sets the CPU supports
+    pub cpu_features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    /// Looks up the features compiled in and what the CPU and image codecs support
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::build_info::BuildInfo;
+    ///
+    /// let info = BuildInfo::detect();
+    ///
+    /// assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    /// assert!(info.backends.contains(&"sync"));
+    /// assert!(info.decodes.contains(&"png") && info.encodes.contains(&"pgm"));
+    /// assert_eq!(info.features.contains(&"fft"), info.backends.contains(&"fft"));
+    /// ```
+    pub fn detect() -> BuildInfo {
+        let extensions = |enabled: fn(&ImageFormat) -> bool| -> Vec<&'static str> {
+            ImageFormat::all()
+                .filter(enabled)
+                .flat_map(|f| f.extensions_str().iter().copied())
+                .collect()
+        };
+
+        let mut decodes = extensions(ImageFormat::reading_enabled);
+        if cfg!(feature = "svg") {
+            decodes.push("svg");
+        }
+        if cfg!(feature = "jxl") {
+            decodes.push("jxl");
+        }
+
+        let mut encodes = extensions(ImageFormat::writing_enabled);
+        encodes.retain(|e| codec::is_supported(e));
+        if cfg!(feature = "jxl") {
+            encodes.push("jxl");
+        }
+
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            features: FEATURES.iter().filter(|f| f.1).map(|f| f.0).collect(),
+            backends: backend::all(1).iter().map(|b| b.name()).collect(),
+            decodes,
+            encodes,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            cpu_features: cpu_features(),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpu_features() -> Vec<&'static str> {
+    let mut features = vec!["sse2"];
+
+    if is_x86_feature_detected!("sse4.1") {
+        features.push("sse4.1");
+    }
+    if is_x86_feature_detected!("avx2") {
+        features.push("avx2");
+    }
+    if is_x86_feature_detected!("fma") {
+        features.push("fma");
+    }
+    if is_x86_feature_detected!("avx512f") {
+        features.push("avx512f");
+    }
+
+    features
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cpu_features() -> Vec<&'static str> {
+    vec!["neon"]
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn cpu_features() -> Vec<&'static str> {
+    Vec::new()
+}
+
+impl fmt::Display for BuildInfo {
+    /// `blur <version>` followed by one `name: values` line per capability, for scripts to
+    /// grep
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |items: &[&str]| match items.is_empty() {
+            true => "none".to_string(),
+            false => items.join(", "),
+        };
+
+        writeln!(f, "blur {}", self.version)?;
+        writeln!(f, "features: {}", list(&self.features))?;
+        writeln!(f, "backends: {}", list(&self.backends))?;
+        writeln!(f, "decodes: {}", list(&self.decodes))?;
+        writeln!(f, "encodes: {}", list(&self.encodes))?;
+        write!(
+            f,
+            "cpu: {} threads, {}",
+            self.threads,
+            list(&self.cpu_features)
+        )
+    }
+}
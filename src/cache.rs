@@ -0,0 +1,220 @@
+use crate::cli::options::{self, Arg};
+use crate::codec;
+use crate::locale::Message;
+use crate::tr;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// State file a batch records its outputs in, in the current directory
+pub const FILE: &str = ".blur-cache.json";
+
+/// Options that change how or how fast files are blurred but not the pixels written
+const NOT_OUTPUT: &[&str] = &[
+    "--threads",
+    "--affinity",
+    "--adaptive-workers",
+    "--nice",
+    "--force",
+    "--emit-hash",
+    "--hash-sidecar",
+    "--no-cache",
+    "--batch",
+    "--keep-going",
+    "--jobs",
+    "--timeout",
+    "--max-memory",
+    "--summary",
+    "--timings",
+];
+
+/// Hex SHA-256 of `bytes`
+pub fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Hex SHA-256 of the contents of `path`
+///
+/// # Errors
+/// - If the file can't be read
+pub fn sha256_file(path: &Path) -> Result<String, Message> {
+    fs::read(path)
+        .map(|bytes| sha256(&bytes))
+        .map_err(|e| tr!("read-failed", path.display(), e))
+}
+
+/// The settings of the command line `args`, without the program name, that decide the pixels
+/// written: every option but those about speed, scheduling and reporting, the contents of
+/// the files they name, the variables that set their defaults, and the version of blur.
+/// Source and destination paths are left out, each file being recorded on its own.
+///
+/// `None` when two runs with the same settings can write different images, i.e. with
+/// `--grain` but no `--seed`, or when the settings are picked interactively.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cache::fingerprint;
+///
+/// let args = |a: &str| a.split(' ').map(String::from).collect::<Vec<_>>();
+///
+/// assert_eq!(
+///     fingerprint(args("a.png a_blur.png -r 4 --threads 8")),
+///     fingerprint(args("b.png b_blur.png --radius 4 -j 2 --force")),
+/// );
+/// assert_ne!(fingerprint(args("a.png -r 4")), fingerprint(args("a.png -r 5")));
+/// assert_eq!(fingerprint(args("a.png --grain 10")), None);
+/// assert!(fingerprint(args("a.png --grain 10 --seed 7")).is_some());
+/// ```
+pub fn fingerprint<I: IntoIterator<Item = String>>(args: I) -> Option<String> {
+    let mut settings = vec![format!("version={}", env!("CARGO_PKG_VERSION"))];
+    let mut given = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let Some(opt) = options::find(&arg) else {
+            continue;
+        };
+        let value = match opt.arg {
+            Arg::Flag => None,
+            _ => args.next(),
+        };
+        given.push(opt.long());
+
+        if NOT_OUTPUT.contains(&opt.long()) {
+            continue;
+        }
+
+        settings.push(match (&opt.arg, value) {
+            (Arg::Path(_), Some(path)) => match sha256_file(Path::new(&path)) {
+                Ok(hash) => format!("{}={path}@{hash}", opt.long()),
+                Err(_) => format!("{}={path}", opt.long()),
+            },
+            (_, Some(value)) => format!("{}={value}", opt.long()),
+            (_, None) => opt.long().to_string(),
+        });
+    }
+
+    let random = given.contains(&"--grain") && !given.contains(&"--seed");
+    let interactive = given.contains(&"--preview") || given.contains(&"--gui");
+    if random || interactive {
+        return None;
+    }
+
+    for opt in options::OPTIONS
+        .iter()
+        .filter(|o| !NOT_OUTPUT.contains(&o.long()))
+    {
+        if let Some((var, Ok(value))) = opt.env.map(|var| (var, std::env::var(var))) {
+            settings.push(format!("{var}={value}"));
+        }
+    }
+
+    Some(sha256(settings.join("\n").as_bytes()))
+}
+
+/// What a destination was blurred from and into
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    /// Hash of the source file
+    input: String,
+    /// [`fingerprint`] of the settings
+    params: String,
+    /// Hash of the destination file once written
+    output: String,
+}
+
+/// Record of the files a batch already blurred, so running it again only blurs the sources
+/// or settings that changed, and the destinations that were modified since.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cache::Cache;
+///
+/// let dir = std::env::temp_dir().join("blur_cache_doc");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let _ = std::fs::remove_file(dir.join("state.json"));
+/// let (original, blurred) = (dir.join("a.png"), dir.join("a_blur.png"));
+/// std::fs::write(&original, b"source").unwrap();
+/// std::fs::write(&blurred, b"blurred").unwrap();
+///
+/// let mut cache = Cache::load(&dir.join("state.json"), "radius 4");
+/// assert!(!cache.is_fresh(&original, &blurred));
+///
+/// cache.record(&original, &blurred).unwrap();
+/// cache.save().unwrap();
+///
+/// let cache = Cache::load(&dir.join("state.json"), "radius 4");
+/// assert!(cache.is_fresh(&original, &blurred));
+///
+/// // Other settings
+/// assert!(!Cache::load(&dir.join("state.json"), "radius 5").is_fresh(&original, &blurred));
+///
+/// // A modified source
+/// std::fs::write(&original, b"edited").unwrap();
+/// assert!(!cache.is_fresh(&original, &blurred));
+/// ```
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    params: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Cache {
+    /// The cache stored at `path`, for blurs with the settings `params`. A missing or
+    /// unreadable file is an empty cache, which the next [`save`](Cache::save) replaces.
+    pub fn load(path: &Path, params: &str) -> Cache {
+        let entries = fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Cache {
+            path: path.to_path_buf(),
+            params: params.to_string(),
+            entries,
+        }
+    }
+
+    /// Whether `blurred` was recorded as blurred from a file like `original` with the same
+    /// settings, and still holds what was written then
+    pub fn is_fresh(&self, original: &Path, blurred: &Path) -> bool {
+        let Some(entry) = self.entries.get(&key(blurred)) else {
+            return false;
+        };
+
+        entry.params == self.params
+            && sha256_file(original).is_ok_and(|hash| hash == entry.input)
+            && sha256_file(blurred).is_ok_and(|hash| hash == entry.output)
+    }
+
+    /// Records that `blurred` was just written from `original`
+    ///
+    /// # Errors
+    /// - If either file can't be read
+    pub fn record(&mut self, original: &Path, blurred: &Path) -> Result<(), Message> {
+        let entry = Entry {
+            input: sha256_file(original)?,
+            params: self.params.clone(),
+            output: sha256_file(blurred)?,
+        };
+        self.entries.insert(key(blurred), entry);
+
+        Ok(())
+    }
+
+    /// Writes the cache back to its file
+    ///
+    /// # Errors
+    /// - If the file can't be written
+    pub fn save(&self) -> Result<(), Message> {
+        let json = serde_json::to_string_pretty(&self.entries).expect("entries are serializable");
+        codec::write(&self.path, json.as_bytes())
+    }
+}
+
+fn key(blurred: &Path) -> String {
+    blurred.to_string_lossy().into_owned()
+}
@@ -0,0 +1,275 @@
+pub mod completions;
+pub mod error;
+pub mod options;
+
+use crate::locale::{tr, Message};
+use crate::tr;
+use error::{ArgError, Fault};
+use options::{Arg, OPTIONS, POSITIONALS};
+use std::env;
+
+/// A subcommand of `blur`, with its own options and help
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subcommand {
+    pub name: &'static str,
+    /// Arguments after the name in the synopsis
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+pub const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "temporal",
+        args: "<frames>... [--help|-h]",
+        help: "Average consecutive frames",
+    },
+    Subcommand {
+        name: "run",
+        args: "<job> [--help|-h]",
+        help: "Blur the images listed in a job file",
+    },
+    Subcommand {
+        name: "thumb",
+        args: "<sources>... [--help|-h]",
+        help: "Make square thumbnails",
+    },
+    Subcommand {
+        name: "denoise",
+        args: "<sources>... [--help|-h]",
+        help: "Remove noise, estimating how much",
+    },
+    Subcommand {
+        name: "verify-redaction",
+        args: "<original> <redacted> [--help|-h]",
+        help: "Check that redacted regions can't be read",
+    },
+    Subcommand {
+        name: "testgen",
+        args: "<pattern> <destination> [--help|-h]",
+        help: "Draw a synthetic test image",
+    },
+    Subcommand {
+        name: "bench",
+        args: "[--sizes <sizes>] [--radii <radii>] [--out <file>]",
+        help: "Time the backends",
+    },
+    Subcommand {
+        name: "list",
+        args: "[--algorithms] [--formats] [--backends] [--json]",
+        help: "List what this build can do",
+    },
+    Subcommand {
+        name: "completions",
+        args: "<bash|zsh|fish>",
+        help: "Print a shell completion script",
+    },
+    Subcommand {
+        name: "--self-test",
+        args: "",
+        help: "Check every backend against sync",
+    },
+];
+
+/// The arguments of the command line as the option parsers read them, counted so their errors
+/// can point at the arguments at fault
+pub struct Args {
+    args: env::Args,
+    /// Number of arguments asked for so far, including the ones past the end
+    read: usize,
+}
+
+impl Args {
+    pub fn new(args: env::Args) -> Args {
+        Args { args, read: 0 }
+    }
+
+    /// An error about the value just read for `option`, or about its lack of one
+    pub fn invalid_value(&self, option: &'static str, message: impl Into<Message>) -> ArgError {
+        let index = self.read.saturating_sub(2);
+
+        ArgError::new(Fault::Value { index, option }, message)
+    }
+
+    /// An error about the argument just read, e.g. one too many
+    pub fn invalid(&self, message: impl Into<Message>) -> ArgError {
+        let index = self.read.saturating_sub(1);
+
+        ArgError::new(Fault::Arg { index }, message)
+    }
+
+    /// An error about the argument just read, which looks like an option but isn't one
+    pub fn unknown(&self, message: impl Into<Message>) -> ArgError {
+        let index = self.read.saturating_sub(1);
+
+        ArgError::new(Fault::Unknown { index }, message)
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.read += 1;
+        self.args.next()
+    }
+}
+
+/// Width the help is wrapped to
+const WIDTH: usize = 80;
+/// Column the descriptions of the options start at
+const DESCRIPTION: usize = 23;
+/// Column the descriptions of the subcommands start at
+const SUBCOMMAND_DESCRIPTION: usize = 49;
+
+/// Synopsis of the main command, with every option of [`OPTIONS`] in brackets
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cli;
+///
+/// let usage = cli::usage();
+///
+/// assert!(usage.starts_with("blur [--radius|-r <radius>] [--sigma|-s <sigma>] "));
+/// assert!(usage.contains(" [--sigma-r|--sigma-g|--sigma-b <sigma>] "));
+/// assert!(usage.ends_with(" <source> [<destination>] [--help|-h]"));
+/// ```
+pub fn usage() -> String {
+    let mut words = vec!["blur".to_string()];
+
+    for opt in OPTIONS.iter().filter(|o| o.long() != "--help") {
+        words.push(format!("[{}]", synopsis(opt.names, opt.arg)));
+    }
+
+    words.push("<source> [<destination>] [--help|-h]".to_string());
+
+    words.join(" ")
+}
+
+/// `--radius|-r <radius>`
+fn synopsis(names: &[&str], arg: Arg) -> String {
+    match arg.placeholder() {
+        Some(p) => format!("{} {p}", names.join("|")),
+        None => names.join("|"),
+    }
+}
+
+/// The `--help` of the main command: its usage and the subcommands', then every argument and
+/// option with its default and environment variable, translated with the current
+/// [catalog](crate::locale)
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cli::{self, options::OPTIONS};
+///
+/// let help = cli::help();
+///
+/// assert!(help.starts_with(&format!("Usage: {}\n", cli::usage())));
+/// assert!(help.contains("\n   -r, --radius        Blur radius. Without it, a --sigma gets the radius\n"));
+/// assert!(help.contains("\n   --dither            How the blur is rounded to 8 bits: none, ordered or\n"));
+///
+/// for opt in OPTIONS {
+///     assert!(help.contains(opt.long()));
+/// }
+/// ```
+pub fn help() -> String {
+    let mut lines = vec![format!("{} {}", tr("Usage:"), usage())];
+
+    for sub in SUBCOMMANDS {
+        let synopsis = format!("       blur {} {}", sub.name, sub.args);
+        let synopsis = synopsis.trim_end();
+
+        match synopsis.len() < SUBCOMMAND_DESCRIPTION {
+            true => lines.push(format!("{synopsis:SUBCOMMAND_DESCRIPTION$}{}", sub.help)),
+            false => {
+                lines.push(synopsis.to_string());
+                lines.push(format!("{:SUBCOMMAND_DESCRIPTION$}{}", "", sub.help));
+            }
+        }
+    }
+
+    lines.push(String::new());
+
+    for arg in POSITIONALS {
+        describe(&mut lines, arg.name, &tr(arg.help));
+    }
+
+    lines.push(String::new());
+
+    for opt in OPTIONS {
+        let mut names: Vec<&str> = opt
+            .names
+            .iter()
+            .filter(|n| !n.starts_with("--"))
+            .copied()
+            .collect();
+        names.extend(opt.names.iter().filter(|n| n.starts_with("--")));
+
+        let default = match (opt.default, opt.env) {
+            (Some(default), Some(env)) => tr!("default-variable-or", env, tr(default)).translate(),
+            (Some(default), None) => tr!("default", tr(default)).translate(),
+            (None, Some(env)) => tr!("default-variable", env).translate(),
+            (None, None) => String::new(),
+        };
+        let help = format!("{} {default}", tr(opt.help));
+
+        describe(&mut lines, &names.join(", "), &help);
+    }
+
+    let variables: Vec<&str> = OPTIONS.iter().filter_map(|o| o.env).collect();
+    let variables = match variables.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        None => String::new(),
+    };
+
+    lines.push(String::new());
+    lines.push(tr("Environment:").into_owned());
+    wrap(&mut lines, "   ", &tr!("variables", variables).translate());
+
+    lines.push(String::new());
+    lines.push(tr("Exit codes:").into_owned());
+    wrap(
+        &mut lines,
+        "   ",
+        &tr("0 success, 2 invalid arguments, 3 decode failure, 4 encode failure, 5 cancelled, 6 some \
+         files of a batch failed, 7 a worker panicked, 8 timed out, 9 image too large, 10 a \
+         redacted region is still readable, 11 a backend failed the self-test."),
+    );
+
+    lines.join("\n")
+}
+
+/// Adds `name` and its wrapped `help` to `lines`, the help starting on the same line when the
+/// name fits before it
+fn describe(lines: &mut Vec<String>, name: &str, help: &str) {
+    let name = format!("   {name}");
+    let indent = " ".repeat(DESCRIPTION);
+    let first = lines.len();
+
+    wrap(lines, &indent, help);
+
+    if name.len() + 2 <= DESCRIPTION {
+        lines[first].replace_range(..name.len(), &name);
+    } else {
+        lines.insert(first, name);
+    }
+}
+
+/// Adds `text` to `lines`, wrapped to [`WIDTH`] with every line starting with `indent`
+fn wrap(lines: &mut Vec<String>, indent: &str, text: &str) {
+    let mut line = indent.to_string();
+
+    for word in text.split_whitespace() {
+        if line.len() > indent.len() && line.chars().count() + 1 + word.chars().count() > WIDTH {
+            lines.push(line);
+            line = indent.to_string();
+        }
+
+        if line.len() > indent.len() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    lines.push(line);
+}
@@ -0,0 +1,253 @@
+use super::error::ArgError;
+use super::options::{Arg, Opt, OPTIONS};
+use super::{Args, SUBCOMMANDS};
+use crate::locale::Message;
+use crate::tr;
+use crate::Error;
+use std::env;
+use std::str::FromStr;
+
+/// Shells [`generate`] writes completion scripts for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(tr!("unknown-shell", s)),
+        }
+    }
+}
+
+/// Completion script of `blur` for `shell`, completing the subcommands, every option of
+/// [`OPTIONS`], the words a choice accepts and paths
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cli::completions::{generate, Shell};
+///
+/// let bash = generate(Shell::Bash);
+/// assert!(bash.contains("--backend) COMPREPLY=($(compgen -W \"auto sync threadpool"));
+/// assert!(bash.ends_with("complete -o filenames -F _blur blur\n"));
+///
+/// assert!(generate(Shell::Zsh).contains("'*--radius[Blur radius.]:radius:'"));
+/// assert!(generate(Shell::Fish).contains("complete -c blur -l dither -x -a 'none ordered diffusion'"));
+/// ```
+pub fn generate(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    }
+}
+
+fn bash() -> String {
+    let subcommands: Vec<&str> = SUBCOMMANDS.iter().map(|s| s.name).collect();
+    let names: Vec<&str> = OPTIONS
+        .iter()
+        .flat_map(|o| o.names.iter().copied())
+        .collect();
+
+    let mut cases = Vec::new();
+    for opt in OPTIONS {
+        let action = match opt.arg {
+            Arg::Flag => continue,
+            Arg::Value(_) => "return".to_string(),
+            Arg::Choice(_, words) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return",
+                words.join(" ")
+            ),
+            Arg::Path(p) => format!(
+                "COMPREPLY=($(compgen -{} -- \"$cur\")); return",
+                if is_dir(p) { 'd' } else { 'f' }
+            ),
+        };
+        cases.push(format!("        {}) {action} ;;", opt.names.join("|")));
+    }
+
+    [
+        "_blur() {".to_string(),
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\""
+            .to_string(),
+        "    if [[ $COMP_CWORD -eq 1 && $cur != -* ]]; then".to_string(),
+        format!(
+            "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))",
+            subcommands.join(" ")
+        ),
+        "        return".to_string(),
+        "    fi".to_string(),
+        "    case \"$prev\" in".to_string(),
+        cases.join("\n"),
+        "    esac".to_string(),
+        "    if [[ $cur == -* ]]; then".to_string(),
+        format!(
+            "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            names.join(" ")
+        ),
+        "    else".to_string(),
+        "        COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+        "    fi".to_string(),
+        "}".to_string(),
+        "complete -o filenames -F _blur blur\n".to_string(),
+    ]
+    .join("\n")
+}
+
+fn zsh() -> String {
+    let mut specs = Vec::new();
+
+    for opt in OPTIONS {
+        let help = summary(opt).replace('\'', "'\\''");
+        let help = help.replace('[', "\\[").replace(']', "\\]");
+
+        let action = match opt.arg {
+            Arg::Flag => String::new(),
+            Arg::Value(p) => format!(":{}:", value_name(p)),
+            Arg::Choice(p, words) => format!(":{}:({})", value_name(p), words.join(" ")),
+            Arg::Path(p) if is_dir(p) => format!(":{}:_files -/", value_name(p)),
+            Arg::Path(p) => format!(":{}:_files", value_name(p)),
+        };
+
+        for name in opt.names {
+            specs.push(format!("    '*{name}[{help}]{action}' \\"));
+        }
+    }
+
+    let subcommands: Vec<String> = SUBCOMMANDS
+        .iter()
+        .filter(|s| !s.name.starts_with('-'))
+        .map(|s| format!("{}\\:'{}'", s.name, s.help.replace('\'', "'\\''")))
+        .collect();
+
+    [
+        "#compdef blur".to_string(),
+        String::new(),
+        "_arguments -s \\".to_string(),
+        specs.join("\n"),
+        format!(
+            "    '1:source or subcommand:(({}))' \\",
+            subcommands.join(" ")
+        ),
+        "    '*:file:_files'\n".to_string(),
+    ]
+    .join("\n")
+}
+
+fn fish() -> String {
+    let mut lines = vec![format!(
+        "complete -c blur -n __fish_use_subcommand -a '{}'",
+        SUBCOMMANDS
+            .iter()
+            .map(|s| s.name)
+            .filter(|n| !n.starts_with('-'))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )];
+
+    for opt in OPTIONS {
+        let mut line = "complete -c blur".to_string();
+
+        for name in opt.names {
+            match name.strip_prefix("--") {
+                Some(long) => line.push_str(&format!(" -l {long}")),
+                None => line.push_str(&format!(" -s {}", &name[1..])),
+            }
+        }
+
+        match opt.arg {
+            Arg::Flag => {}
+            Arg::Value(_) => line.push_str(" -x"),
+            Arg::Choice(_, words) => line.push_str(&format!(" -x -a '{}'", words.join(" "))),
+            Arg::Path(p) if is_dir(p) => line.push_str(" -x -a '(__fish_complete_directories)'"),
+            Arg::Path(_) => line.push_str(" -r -F"),
+        }
+
+        line.push_str(&format!(" -d '{}'", summary(opt).replace('\'', "\\'")));
+        lines.push(line);
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// First sentence of the option's help
+fn summary(opt: &Opt) -> &'static str {
+    match opt.help.find(". ") {
+        Some(i) => &opt.help[..=i],
+        None => opt.help,
+    }
+}
+
+/// `radius` for `<radius>`, and the whole placeholder when it has several parts
+fn value_name(placeholder: &str) -> String {
+    placeholder
+        .strip_prefix('<')
+        .and_then(|p| p.strip_suffix('>'))
+        .filter(|p| !p.contains(['<', '>']))
+        .unwrap_or(placeholder)
+        .replace(':', "\\:")
+}
+
+fn is_dir(placeholder: &str) -> bool {
+    placeholder == "<dir>"
+}
+
+/// CLI options of the `completions` subcommand
+pub struct CompletionsOpts {
+    pub shell: Shell,
+}
+
+impl CompletionsOpts {
+    /// Constructs a new CompletionsOpts from CLI options, skipping the program name and the
+    /// `completions` subcommand
+    ///
+    /// # Errors
+    /// - If the shell is missing or unknown
+    pub fn new(cli_opts: env::Args) -> Result<CompletionsOpts, ArgError> {
+        let mut shell = None;
+
+        let mut cli_opts = Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur completions <bash|zsh|fish> [--help|-h]\n",
+                        "   <shell>             bash, zsh or fish.",
+                        "   -h, --help          Print help.\n",
+                        "Prints a script that completes the options of blur, e.g.",
+                        "   blur completions bash > /etc/bash_completion.d/blur",
+                        "   blur completions zsh > ~/.zfunc/_blur",
+                        "   blur completions fish > ~/.config/fish/completions/blur.fish",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ if shell.is_none() => shell = Some(arg.parse().map_err(|e| cli_opts.invalid(e))?),
+                _ => return Err(cli_opts.invalid(tr!("unexpected-argument", arg))),
+            }
+        }
+
+        Ok(CompletionsOpts {
+            shell: shell.ok_or("Expected bash, zsh or fish")?,
+        })
+    }
+}
+
+/// Prints the completion script of `opts.shell` on stdout
+pub fn run(opts: &CompletionsOpts) -> Result<(), Error> {
+    print!("{}", generate(opts.shell));
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use super::options;
+use crate::locale::{tr, Message};
+use crate::{tr, Error};
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+
+/// Bad command line options or environment variables, with where the parser found them
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgError {
+    pub message: Message,
+    pub fault: Fault,
+}
+
+/// What an [`ArgError`] is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The value of the option at `index` in the arguments, or the lack of one, e.g. `x` in
+    /// `--sigma x`. `option` is its long name.
+    Value { index: usize, option: &'static str },
+    /// The argument at `index` looks like an option but isn't one
+    Unknown { index: usize },
+    /// The argument at `index`, e.g. one too many
+    Arg { index: usize },
+    /// An option that doesn't go with the others, wherever it is in the arguments
+    Option(&'static str),
+    /// An environment variable, e.g. `BLUR_RADIUS`
+    Variable(&'static str),
+    /// The arguments as a whole, e.g. a missing source
+    Args,
+}
+
+impl ArgError {
+    pub fn new(fault: Fault, message: impl Into<Message>) -> ArgError {
+        ArgError {
+            message: message.into(),
+            fault,
+        }
+    }
+}
+
+impl From<Message> for ArgError {
+    fn from(message: Message) -> ArgError {
+        ArgError::new(Fault::Args, message)
+    }
+}
+
+impl From<String> for ArgError {
+    fn from(message: String) -> ArgError {
+        ArgError::new(Fault::Args, message)
+    }
+}
+
+impl From<&'static str> for ArgError {
+    fn from(message: &'static str) -> ArgError {
+        ArgError::new(Fault::Args, message)
+    }
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+/// An invalid command line with the context to fix it: the arguments at fault, the usage of
+/// their option and a hint, in the language of the current [catalog](crate::locale)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// First and last index in the arguments of the ones at fault
+    pub span: Option<(usize, usize)>,
+    /// The option's part of the usage, e.g. `[--radius|-r <radius>]`
+    pub usage: Option<String>,
+    pub hint: String,
+}
+
+impl Diagnostic {
+    /// The context of `error`, from [`Opts::new`] or a subcommand, in `args`. `usage` is
+    /// searched for the bracketed usage of the option at fault.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::cli::{self, error::{ArgError, Diagnostic, Fault}};
+    ///
+    /// let args = ["blur", "--radius", "300", "photo.jpg"].map(String::from);
+    /// let error = ArgError::new(
+    ///     Fault::Value { index: 1, option: "--radius" },
+    ///     "Expected a number greater than 0 after --radius|-r",
+    /// );
+    /// let d = Diagnostic::new(&error, &args, &cli::usage());
+    ///
+    /// assert_eq!(d.span, Some((1, 2)));
+    /// assert_eq!(d.usage.as_deref(), Some("[--radius|-r <radius>]"));
+    ///
+    /// let args = ["blur", "--radus", "3", "photo.jpg"].map(String::from);
+    /// let error = ArgError::new(Fault::Unknown { index: 1 }, "Unknown option --radus");
+    /// let d = Diagnostic::new(&error, &args, &cli::usage());
+    ///
+    /// assert_eq!(d.span, Some((1, 1)));
+    /// assert_eq!(d.hint, "Did you mean --radius?");
+    /// ```
+    ///
+    /// [`Opts::new`]: crate::Opts::new
+    pub fn new(error: &ArgError, args: &[String], usage: &str) -> Diagnostic {
+        let last = args.len().saturating_sub(1);
+
+        let (span, option) = match error.fault {
+            Fault::Value { index, option } => (Some((index, (index + 1).min(last))), Some(option)),
+            Fault::Unknown { index } | Fault::Arg { index } => (Some((index, index)), None),
+            Fault::Option(option) => {
+                let index = args
+                    .iter()
+                    .skip(1)
+                    .rposition(|a| options::canonical(a) == Ok(Some(option)))
+                    .map(|i| i + 1);
+
+                (index.map(|i| (i, i)), Some(option))
+            }
+            Fault::Variable(_) | Fault::Args => (None, None),
+        };
+
+        let hint = match error.fault {
+            Fault::Variable(var) => tr!("fix-variable", var).translate(),
+            Fault::Unknown { index } => {
+                match args
+                    .get(index)
+                    .and_then(|a| closest(a, usage_options(usage)))
+                {
+                    Some(option) => tr!("did-you-mean", option).translate(),
+                    None => help_hint(args),
+                }
+            }
+            _ => help_hint(args),
+        };
+
+        Diagnostic {
+            message: error.message.translate(),
+            span: span.filter(|&(_, last)| last < args.len()),
+            usage: option.and_then(|o| group_of(o, usage)),
+            hint,
+        }
+    }
+
+    /// The diagnostic as shown on stderr, with the command line it is about and carets under
+    /// the arguments at fault. `color` adds ANSI colors.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::cli::error::{ArgError, Diagnostic, Fault};
+    ///
+    /// let args = ["/usr/bin/blur", "--sigma", "x", "photo.jpg"].map(String::from);
+    /// let error = ArgError::new(
+    ///     Fault::Value { index: 1, option: "--sigma" },
+    ///     "Expected a float greater than 0 after --sigma|-s",
+    /// );
+    /// let d = Diagnostic::new(&error, &args, "[--sigma|-s <sigma>]");
+    ///
+    /// assert_eq!(
+    ///     d.render(&args, false),
+    ///     [
+    ///         "Error: Expected a float greater than 0 after --sigma|-s",
+    ///         "  |",
+    ///         "  | blur --sigma x photo.jpg",
+    ///         "  |      ^^^^^^^^^",
+    ///         "  = usage: [--sigma|-s <sigma>]",
+    ///         "  = hint: Run `blur --help` to see every option",
+    ///     ]
+    ///     .join("\n")
+    /// );
+    /// ```
+    pub fn render(&self, args: &[String], color: bool) -> String {
+        let paint = |code: &str, s: &str| match color {
+            true => format!("\x1b[{code}m{s}\x1b[0m"),
+            false => s.to_string(),
+        };
+        let gutter = paint("1;34", "  |");
+
+        let mut lines = vec![format!("{} {}", paint("1;31", &tr("Error:")), self.message)];
+
+        if let Some((first, last)) = self.span {
+            let shown: Vec<String> = std::iter::once("blur".to_string())
+                .chain(args.iter().skip(1).map(|a| quote(a)))
+                .collect();
+
+            let start: usize = shown[..first].iter().map(|a| a.len() + 1).sum();
+            let end: usize = shown[..=last].iter().map(|a| a.len() + 1).sum::<usize>() - 1;
+
+            lines.push(gutter.clone());
+            lines.push(format!("{gutter} {}", shown.join(" ")));
+            lines.push(format!(
+                "{gutter} {}{}",
+                " ".repeat(start),
+                paint("1;33", &"^".repeat(end - start))
+            ));
+        }
+
+        if let Some(usage) = &self.usage {
+            lines.push(format!("  {} {usage}", paint("1;34", &tr("= usage:"))));
+        }
+
+        lines.push(format!("  {} {}", paint("1;34", &tr("= hint:")), self.hint));
+
+        lines.join("\n")
+    }
+}
+
+/// How the `blur` binary reports `e` on stderr: a [`Diagnostic`] of the command line for
+/// invalid arguments, and the bare message otherwise. Colored when stderr is a terminal and
+/// `NO_COLOR` isn't set. Translated with the current [catalog](crate::locale).
+pub fn report(e: &Error) -> String {
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let args: Vec<String> = env::args().collect();
+
+    match e {
+        Error::InvalidArgs(error) => {
+            let usage = match args.get(1).map(String::as_str) {
+                Some(sub) if is_subcommand(sub) => String::new(),
+                _ => super::usage(),
+            };
+
+            Diagnostic::new(error, &args, &usage).render(&args, color)
+        }
+        e => match color {
+            true => format!(
+                "\x1b[1;31m{}\x1b[0m {}",
+                tr("Error:"),
+                e.message().translate()
+            ),
+            false => format!("{} {}", tr("Error:"), e.message().translate()),
+        },
+    }
+}
+
+/// The bracketed group of `usage` naming `flag`, e.g. `[--radius|-r <radius>]` for `-r`
+fn group_of(flag: &str, usage: &str) -> Option<String> {
+    usage
+        .split('[')
+        .filter_map(|group| group.split_once(']').map(|(g, _)| g))
+        .find(|group| group.split([' ', '|']).any(|token| token == flag))
+        .map(|group| format!("[{group}]"))
+}
+
+/// Where to look for the options, `blur --help` or the subcommand's help
+fn help_hint(args: &[String]) -> String {
+    match args.get(1).map(String::as_str) {
+        Some(sub) if is_subcommand(sub) && sub != "--self-test" => {
+            tr!("subcommand-help", sub).translate()
+        }
+        _ => tr("Run `blur --help` to see every option").into_owned(),
+    }
+}
+
+/// Whether `arg` is one of the [`SUBCOMMANDS`](super::SUBCOMMANDS), which have their own
+/// usage
+fn is_subcommand(arg: &str) -> bool {
+    super::SUBCOMMANDS.iter().any(|s| s.name == arg)
+}
+
+/// Every long option in `usage`
+fn usage_options(usage: &str) -> impl Iterator<Item = &str> {
+    usage
+        .split(['[', ']', ' ', '|'])
+        .filter(|t| t.starts_with("--"))
+}
+
+/// The option closest to `option` by edit distance, if it is a likely typo
+fn closest<'a>(option: &str, options: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    options
+        .map(|o| (edit_distance(option, o), o))
+        .filter(|&(d, o)| d <= 2.max(o.len() / 4))
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, o)| o)
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + (ca != cb) as usize)
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// `arg` as it would be typed in a shell
+fn quote(arg: &str) -> String {
+    match arg.is_empty() || arg.contains([' ', ';', '\'', '"', '|', '&']) {
+        true => format!("'{arg}'"),
+        false => arg.to_string(),
+    }
+}
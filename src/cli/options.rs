@@ -0,0 +1,810 @@
+use crate::backend;
+use crate::locale::Message;
+use crate::tr;
+
+/// What follows an option on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    /// Nothing, the option is a switch
+    Flag,
+    /// A value described by its placeholder, e.g. `<x>,<y>`
+    Value(&'static str),
+    /// One of a fixed set of words
+    Choice(&'static str, &'static [&'static str]),
+    /// A file or directory, completed as a path
+    Path(&'static str),
+}
+
+impl Arg {
+    /// Placeholder of the value in the usage, if any
+    pub fn placeholder(&self) -> Option<&'static str> {
+        match self {
+            Arg::Flag => None,
+            Arg::Value(p) | Arg::Choice(p, _) | Arg::Path(p) => Some(p),
+        }
+    }
+}
+
+/// An option of the main command, as the parser accepts it and the help and completions
+/// describe it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opt {
+    /// Long names first, then the short ones, e.g. `["--radius", "-r"]`
+    pub names: &'static [&'static str],
+    pub arg: Arg,
+    pub help: &'static str,
+    /// Value used when the option isn't given, as shown in the help
+    pub default: Option<&'static str>,
+    /// Environment variable that sets the default
+    pub env: Option<&'static str>,
+}
+
+impl Opt {
+    /// First long name, which the parser matches on
+    pub fn long(&self) -> &'static str {
+        self.names[0]
+    }
+}
+
+/// A positional argument of the main command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Positional {
+    pub name: &'static str,
+    pub help: &'static str,
+}
+
+pub const POSITIONALS: &[Positional] = &[
+    Positional {
+        name: "<source>",
+        help: "Path to original image. Use - to read from stdin.",
+    },
+    Positional {
+        name: "<destination>",
+        help: "Path of the blurred image. Use - to write to stdout. Default is \
+               <source><suffix>, or stdout when reading from stdin. Existing files are never \
+               overwritten unless --force is given.",
+    },
+];
+
+const FILTERS: &[&str] = &["nearest", "triangle", "catmull-rom", "gaussian", "lanczos"];
+
+/// Every option of the main command, in the order of the usage and help
+pub const OPTIONS: &[Opt] = &[
+    Opt {
+        names: &["--radius", "-r"],
+        arg: Arg::Value("<radius>"),
+        help: "Blur radius. Without it, a --sigma gets the radius covering 3 sigma, with the \
+               outer taps faded in so fractional sigmas blur smoothly.",
+        default: Some("10px"),
+        env: Some("BLUR_RADIUS"),
+    },
+    Opt {
+        names: &["--sigma", "-s"],
+        arg: Arg::Value("<sigma>"),
+        help: "Gaussian blur standard deviation, e.g. 2.5.",
+        default: Some("10"),
+        env: Some("BLUR_SIGMA"),
+    },
+    Opt {
+        names: &["--threads", "-t"],
+        arg: Arg::Value("<n_threads>"),
+        help: "Number of thread workers. The output is the same whatever their number.",
+        default: Some("up to 10, fewer for small images and kernels"),
+        env: Some("BLUR_THREADS"),
+    },
+    Opt {
+        names: &["--backend"],
+        arg: Arg::Choice("<backend>", &backend::NAMES),
+        help: "Blur implementation: auto, sync, threadpool, rayon, simd, fixed or fft. \
+               auto picks one from the radius, image size and number of cores.",
+        default: Some("auto"),
+        env: Some("BLUR_BACKEND"),
+    },
+    Opt {
+        names: &["--affinity"],
+        arg: Arg::Choice("<affinity>", &["none", "cores", "numa"]),
+        help: "Pin the threadpool backend's workers to cores: none, cores, or numa to keep \
+               neighbouring rows on the same NUMA node. Requires the affinity feature.",
+        default: Some("none"),
+        env: None,
+    },
+    Opt {
+        names: &["--adaptive-workers"],
+        arg: Arg::Flag,
+        help: "Retire the threadpool backend's workers one by one when their throughput drops, \
+               as when a laptop throttles under a sustained all-core blur.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--nice"],
+        arg: Arg::Flag,
+        help: "Run the blur at a lower priority, so it yields the cores to interactive work.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--direction", "-d"],
+        arg: Arg::Choice("<direction>", &["horizontal", "vertical", "both"]),
+        help: "Blur only horizontally, only vertically, or both.",
+        default: Some("both"),
+        env: None,
+    },
+    Opt {
+        names: &["--passes", "-p"],
+        arg: Arg::Value("<passes>"),
+        help: "Number of times the blur is applied.",
+        default: Some("1"),
+        env: None,
+    },
+    Opt {
+        names: &["--algorithm", "-a"],
+        arg: Arg::Choice("<algorithm>", &["gaussian", "surface"]),
+        help: "gaussian, or surface to keep edges crisp by skipping samples that differ too \
+               much from the center pixel.",
+        default: Some("gaussian"),
+        env: None,
+    },
+    Opt {
+        names: &["--projection"],
+        arg: Arg::Choice("<projection>", &["flat", "equirect"]),
+        help: "flat, or equirect for 360° panoramas, which wrap around horizontally and blur \
+               wider toward the poles.",
+        default: Some("flat"),
+        env: None,
+    },
+    Opt {
+        names: &["--tileable"],
+        arg: Arg::Flag,
+        help: "Wrap around both axes so a tileable texture stays seamless after blurring.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--threshold"],
+        arg: Arg::Value("<threshold>"),
+        help: "Largest channel difference a surface blur sample may have from the center \
+               pixel, or with --bloom, the luma above which pixels glow.",
+        default: Some("25, or 200 with --bloom"),
+        env: None,
+    },
+    Opt {
+        names: &["--bloom"],
+        arg: Arg::Flag,
+        help: "Blur the bright parts of the image and screen them over the original so they \
+               glow.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--protect-edges"],
+        arg: Arg::Value("<strength>"),
+        help: "How much of the blur to remove on strong edges, from 0 to 1, keeping outlines \
+               readable.",
+        default: Some("0"),
+        env: None,
+    },
+    Opt {
+        names: &["--adaptive"],
+        arg: Arg::Flag,
+        help: "Blur flat regions like skies up to twice as much and detailed ones down to a \
+               quarter as much, following the local variance.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--target-ssim"],
+        arg: Arg::Value("<ssim>"),
+        help: "Pick the radius and sigma of each image so the blur is this similar to the \
+               source, from 0 to 1, e.g. 0.6 to anonymize photos of any size equally. \
+               Overrides --radius and --sigma.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--luma-range"],
+        arg: Arg::Value("<low>:<high>"),
+        help: "Only blur pixels whose luma is within <low>:<high> (0 to 255), feathered at \
+               both ends of the band.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--key-color"],
+        arg: Arg::Value("<rrggbb>"),
+        help: "Only blur pixels close to this hex color, e.g. 00ff00 for a green screen.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--key-tolerance"],
+        arg: Arg::Value("<distance>"),
+        help: "RGB distance from --key-color that still counts as the key.",
+        default: Some("30"),
+        env: None,
+    },
+    Opt {
+        names: &["--region"],
+        arg: Arg::Value("<x>,<y>,<w>,<h>"),
+        help: "Only blur the rectangle at <x>,<y> of size <w>x<h>. Can be repeated. Any of \
+               the numbers of this and the other region options can be a percentage of the \
+               image size instead, e.g. 10%,10%,30%,20%. Takes an ImageMagick geometry like \
+               300x200+10+20 too, as do --keep-region and --crop.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--region-circle"],
+        arg: Arg::Value("<cx>,<cy>,<r>"),
+        help: "Only blur the circle of radius <r> around <cx>,<cy>. Can be repeated, and \
+               combined with the other regions.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--region-ellipse"],
+        arg: Arg::Value("<cx>,<cy>,<rx>,<ry>"),
+        help: "Only blur the ellipse of radii <rx> and <ry> around <cx>,<cy>. Can be \
+               repeated.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--region-poly"],
+        arg: Arg::Value("<x1>,<y1>;<x2>,<y2>;..."),
+        help: "Only blur the polygon through these vertices, quoted for the shell, e.g. \
+               '10,10;80,20;40,90'. Can be repeated.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--regions-file"],
+        arg: Arg::Path("<file>"),
+        help: "Also blur the boxes in this JSON or CSV file, e.g. the output of an object \
+               detector. Can be repeated.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--keep-region"],
+        arg: Arg::Value("<x>,<y>,<w>,<h>"),
+        help: "Leave the rectangle at <x>,<y> of size <w>x<h> sharp. Can be repeated.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--blend"],
+        arg: Arg::Value("<opacity>"),
+        help: "Opacity of the blurred image over the original, e.g. 0.6 for a soft glow.",
+        default: Some("1"),
+        env: None,
+    },
+    Opt {
+        names: &["--preview"],
+        arg: Arg::Flag,
+        help: "Show a downscaled result in the terminal and adjust the radius with left/right \
+               and the sigma with up/down before rendering. Requires the preview feature and a \
+               terminal with kitty, iTerm2 or sixel graphics.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--gui"],
+        arg: Arg::Flag,
+        help: "Open a window to adjust the radius, sigma and algorithm on a preview before \
+               rendering. Requires the gui feature.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--ops"],
+        arg: Arg::Value("<stages>"),
+        help: "The whole pipeline as stages separated by |, e.g. \"linear | \
+               gaussian(r=8,s=3) | unsharp(1.2) | srgb\". Replaces the blur and the other \
+               stage options.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--plugin-dir"],
+        arg: Arg::Path("<dir>"),
+        help: "Load the plugin libraries in <dir> as --ops stages. Requires the plugins \
+               feature.",
+        default: None,
+        env: Some("BLUR_PLUGIN_DIR"),
+    },
+    Opt {
+        names: &["--rotate"],
+        arg: Arg::Choice("<degrees>", &["90", "180", "270"]),
+        help: "Turn the image clockwise by 90, 180 or 270 degrees before cropping and \
+               blurring.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--flip"],
+        arg: Arg::Choice("<h|v>", &["h", "v"]),
+        help: "Mirror the image horizontally (h) or vertically (v) after rotating.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--crop"],
+        arg: Arg::Value("<x>,<y>,<w>,<h>"),
+        help: "Only keep the rectangle at <x>,<y> of size <w>x<h> before blurring.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--crop-center"],
+        arg: Arg::Value("<size>"),
+        help: "Only keep a rectangle of <width>x<height> from the middle of the image before \
+               blurring.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--resize"],
+        arg: Arg::Value("<size>"),
+        help: "Resample the image to <width>x<height> before blurring.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--scale"],
+        arg: Arg::Value("<percent>"),
+        help: "Resample the image by a percentage, e.g. 50%, before blurring.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--filter"],
+        arg: Arg::Choice("<filter>", FILTERS),
+        help: "Resampling filter: nearest, triangle, catmull-rom, gaussian or lanczos.",
+        default: Some("triangle"),
+        env: None,
+    },
+    Opt {
+        names: &["--grayscale"],
+        arg: Arg::Flag,
+        help: "Desaturate the blurred image.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--duotone"],
+        arg: Arg::Value("<dark>,<light>"),
+        help: "Map the blurred image from <dark> to <light>, both rrggbb colors, by \
+               brightness.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--brightness"],
+        arg: Arg::Value("<percent>"),
+        help: "Scale the brightness of the blurred image, e.g. 70% to darken a lock screen.",
+        default: Some("100%"),
+        env: None,
+    },
+    Opt {
+        names: &["--contrast"],
+        arg: Arg::Value("<percent>"),
+        help: "Scale the contrast of the blurred image around mid-grey.",
+        default: Some("100%"),
+        env: None,
+    },
+    Opt {
+        names: &["--gamma"],
+        arg: Arg::Value("<gamma>"),
+        help: "Gamma correction of the blurred image, above 1 to lighten the midtones.",
+        default: Some("1"),
+        env: None,
+    },
+    Opt {
+        names: &["--tint"],
+        arg: Arg::Value("<rrggbb>@<opacity>"),
+        help: "Lay a color over the blurred image at an opacity from 0 to 1, e.g. \
+               000000@0.35 to dim it.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--dither"],
+        arg: Arg::Choice("<dither>", &["none", "ordered", "diffusion"]),
+        help: "How the blur is rounded to 8 bits: none, ordered or diffusion. Dithering hides \
+               banding in smooth gradients.",
+        default: Some("none"),
+        env: None,
+    },
+    Opt {
+        names: &["--grain"],
+        arg: Arg::Value("<amount>"),
+        help: "Add noise with this standard deviation after blurring to hide banding, e.g. \
+               1.5.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--seed"],
+        arg: Arg::Value("<seed>"),
+        help: "Seed of the --grain noise, for reproducible output.",
+        default: Some("random"),
+        env: None,
+    },
+    Opt {
+        names: &["--portrait"],
+        arg: Arg::Flag,
+        help: "Keep people sharp and only blur the background. Requires the portrait feature \
+               and a model.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--portrait-model"],
+        arg: Arg::Path("<model>"),
+        help: "ONNX person segmentation model used by --portrait.",
+        default: None,
+        env: Some("BLUR_PORTRAIT_MODEL"),
+    },
+    Opt {
+        names: &["--sigma-r", "--sigma-g", "--sigma-b"],
+        arg: Arg::Value("<sigma>"),
+        help: "Standard deviation of a single channel.",
+        default: Some("--sigma"),
+        env: None,
+    },
+    Opt {
+        names: &["--vignette-blur"],
+        arg: Arg::Flag,
+        help: "Keep the center sharp and blur toward the borders.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--vignette-shape"],
+        arg: Arg::Choice("<shape>", &["ellipse", "rect"]),
+        help: "Shape of the sharp center: ellipse or rect.",
+        default: Some("ellipse"),
+        env: None,
+    },
+    Opt {
+        names: &["--inner"],
+        arg: Arg::Value("<percent>"),
+        help: "Size of the sharp center as a percentage of the image.",
+        default: Some("60%"),
+        env: None,
+    },
+    Opt {
+        names: &["--feather"],
+        arg: Arg::Value("<percent>"),
+        help: "Width of the transition to the full blur as a percentage of the image.",
+        default: Some("15%"),
+        env: None,
+    },
+    Opt {
+        names: &["--mask-feather"],
+        arg: Arg::Value("<pixels>"),
+        help: "Pixels over which the edges of the regions and masks fade into the blur, e.g. 12.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--redact-text"],
+        arg: Arg::Flag,
+        help: "Only hide regions that look like text, e.g. to sanitize a screenshot. Requires \
+               the redact feature.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--redact-codes"],
+        arg: Arg::Flag,
+        help: "Only hide QR codes and barcodes. Requires the redact feature.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--redact-plates"],
+        arg: Arg::Flag,
+        help: "Only hide licence plates, found as short high-contrast lines of characters. \
+               Requires the redact feature.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--redact-style"],
+        arg: Arg::Choice("<style>", &["pixelate", "blur"]),
+        help: "How --redact-* hides regions: pixelate or blur.",
+        default: Some("pixelate"),
+        env: None,
+    },
+    Opt {
+        names: &["--pixel-size"],
+        arg: Arg::Value("<size>"),
+        help: "Block size used to pixelate.",
+        default: Some("16"),
+        env: None,
+    },
+    Opt {
+        names: &["--avatar"],
+        arg: Arg::Flag,
+        help: "Crop to a square and keep the subject sharp in a circle over the blurred \
+               image, like a profile picture.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--pad-to"],
+        arg: Arg::Value("<w>:<h>"),
+        help: "Place the image in the middle of a canvas with this aspect ratio, e.g. 16:9, \
+               instead of blurring it.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--pad-style"],
+        arg: Arg::Value("<style>"),
+        help: "What fills the rest of the canvas: blur, for a blurred copy of the image, or a \
+               hex color.",
+        default: Some("blur"),
+        env: None,
+    },
+    Opt {
+        names: &["--format", "-f"],
+        arg: Arg::Value("<format>"),
+        help: "Output format (png, jpg, qoi, ppm, pgm, ...).",
+        default: Some("taken from the destination extension"),
+        env: Some("BLUR_FORMAT"),
+    },
+    Opt {
+        names: &["--raster-size"],
+        arg: Arg::Value("<size>"),
+        help: "Box that SVG sources are rasterized into, as <width>x<height> or <size>.",
+        default: Some("the SVG size"),
+        env: None,
+    },
+    Opt {
+        names: &["--progressive"],
+        arg: Arg::Flag,
+        help: "Write JPEG output as a progressive JPEG.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--progressive-save"],
+        arg: Arg::Value("<percent>"),
+        help: "Write the unfinished blur to <stem>.partial.<ext> next to the destination \
+               every this many percent. Uses the threadpool backend, blurring from the center \
+               out, so it needs a single-pass Gaussian blur in both directions.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--focus"],
+        arg: Arg::Value("<x>,<y>"),
+        help: "Point <x>,<y> the progressive save blurs out from.",
+        default: Some("the center"),
+        env: None,
+    },
+    Opt {
+        names: &["--interlaced"],
+        arg: Arg::Flag,
+        help: "Write PNG output with Adam7 interlacing.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--srcset"],
+        arg: Arg::Value("<widths>"),
+        help: "Write a variant of the destination at each of these comma-separated widths \
+               instead, named <stem>-<width>w.<ext>. Widths above the source's are skipped.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--srcset-manifest"],
+        arg: Arg::Path("<file>"),
+        help: "Also write the variants' names and sizes to this file, as JSON for a .json \
+               file and as an HTML <img> tag otherwise.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--force"],
+        arg: Arg::Flag,
+        help: "Overwrite the destination if it already exists.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--preserve-times"],
+        arg: Arg::Flag,
+        help: "Give the destination the modification and access times of the source.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--preserve-mode"],
+        arg: Arg::Flag,
+        help: "Give the destination the permissions of the source.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--emit-hash"],
+        arg: Arg::Flag,
+        help: "Print the SHA-256 of the file written and the destination, on stdout or on \
+               stderr when the image goes to stdout.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--hash-sidecar"],
+        arg: Arg::Flag,
+        help: "Write the SHA-256 of the file written next to the destination, in \
+               <destination>.sha256.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--suffix"],
+        arg: Arg::Value("<suffix>"),
+        help: "Suffix of the default destination name.",
+        default: Some("_blurred_<radius>x<sigma>"),
+        env: Some("BLUR_SUFFIX"),
+    },
+    Opt {
+        names: &["--numbered"],
+        arg: Arg::Flag,
+        help: "Append _1, _2, ... to the destination name until it doesn't exist yet.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--output-dir", "-o"],
+        arg: Arg::Path("<dir>"),
+        help: "Directory of the default destination.",
+        default: Some("the source directory"),
+        env: Some("BLUR_OUTPUT_DIR"),
+    },
+    Opt {
+        names: &["--batch", "-b"],
+        arg: Arg::Flag,
+        help: "Treat every positional argument as a source and give each blurred image its \
+               default name.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--keep-going", "-k"],
+        arg: Arg::Flag,
+        help: "In batch mode, carry on with the other files when one fails and report the \
+               failures at the end.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--no-cache"],
+        arg: Arg::Flag,
+        help: "In batch mode, blur every file, even those already blurred with the same \
+               settings since their last change.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--jobs", "-j"],
+        arg: Arg::Value("<jobs>"),
+        help: "In batch mode, number of files blurred at once. Each file still uses --threads \
+               workers unless it is small. With 1, the next file is decoded and the previous \
+               one encoded while a file is blurred.",
+        default: Some("1"),
+        env: Some("BLUR_JOBS"),
+    },
+    Opt {
+        names: &["--timeout"],
+        arg: Arg::Value("<duration>"),
+        help: "Give up on a file whose blur takes longer than this, e.g. 30s, 500ms or 2m.",
+        default: Some("no limit"),
+        env: None,
+    },
+    Opt {
+        names: &["--max-memory"],
+        arg: Arg::Value("<size>"),
+        help: "Most memory a blur may use, e.g. 512M or 2G. Images that would need more, or \
+               more than the system has available, are blurred in strips of rows when the \
+               options allow it and refused otherwise. Also limits what the decoder may \
+               allocate.",
+        default: Some("no limit"),
+        env: None,
+    },
+    Opt {
+        names: &["--max-dimension"],
+        arg: Arg::Value("<pixels>"),
+        help: "Refuse sources wider or taller than this, checked before decoding to stop \
+               decompression bombs.",
+        default: Some("no limit"),
+        env: None,
+    },
+    Opt {
+        names: &["--max-pixels"],
+        arg: Arg::Value("<megapixels>"),
+        help: "Refuse sources with more megapixels than this.",
+        default: Some("no limit"),
+        env: None,
+    },
+    Opt {
+        names: &["--summary"],
+        arg: Arg::Choice("<text|json>", &["text", "json"]),
+        help: "Batch summary format, text (on stderr) or json (on stdout).",
+        default: Some("text"),
+        env: None,
+    },
+    Opt {
+        names: &["--timings"],
+        arg: Arg::Flag,
+        help: "Print the wall-clock and CPU time spent decoding, building the kernel, in the \
+               horizontal and vertical passes and encoding, on stderr.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--version", "-V"],
+        arg: Arg::Flag,
+        help: "Prints the version, the enabled features, and the backends and formats this \
+               build supports.",
+        default: None,
+        env: None,
+    },
+    Opt {
+        names: &["--help", "-h"],
+        arg: Arg::Flag,
+        help: "Prints this help.",
+        default: None,
+        env: None,
+    },
+];
+
+/// The option `arg` names, by any of its long or short names
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cli::options;
+///
+/// assert_eq!(options::find("-r").map(|o| o.long()), Some("--radius"));
+/// assert_eq!(options::find("--sigma-g").map(|o| o.long()), Some("--sigma-r"));
+/// assert!(options::find("--radus").is_none());
+/// ```
+pub fn find(arg: &str) -> Option<&'static Opt> {
+    OPTIONS.iter().find(|o| o.names.contains(&arg))
+}
+
+/// The long name the parser matches `arg` on: `arg` itself when it is a long name, the
+/// option's first long name for a short one, and `None` for a positional argument
+///
+/// # Errors
+/// - If `arg` looks like an option but isn't one
+///
+/// # Examples
+/// ```
+/// use vincent_blur::cli::options;
+///
+/// assert_eq!(options::canonical("-s"), Ok(Some("--sigma")));
+/// assert_eq!(options::canonical("--sigma-b"), Ok(Some("--sigma-b")));
+/// assert_eq!(options::canonical("photo.jpg"), Ok(None));
+/// assert_eq!(options::canonical("-"), Ok(None));
+/// assert!(options::canonical("--radus").is_err());
+/// ```
+pub fn canonical(arg: &str) -> Result<Option<&'static str>, Message> {
+    match find(arg) {
+        Some(opt) => Ok(Some(
+            opt.names
+                .iter()
+                .find(|&&n| n == arg && n.starts_with("--"))
+                .unwrap_or(&opt.names[0]),
+        )),
+        None if arg.starts_with('-') && arg != crate::codec::STDIO => {
+            Err(tr!("unknown-option", arg))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,617 @@
+use crate::locale::Message;
+use crate::logging;
+use crate::tr;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::{DynamicImage, ImageEncoder, ImageFormat, ImageReader, Rgb, RgbImage};
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+
+/// Path that stands for stdin when used as a source and stdout when used as a destination
+pub const STDIO: &str = "-";
+
+const JPEG_QUALITY: u8 = 75;
+
+/// Adam7 passes as (x offset, y offset, x step, y step)
+const ADAM7: [(u32, u32, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+const JXL_CODESTREAM: [u8; 2] = [0xff, 0x0a];
+const JXL_CONTAINER: [u8; 12] = [
+    0x00, 0x00, 0x00, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+];
+
+/// Returns whether `format` names an output format that can be encoded, e.g. `png` or `pgm`
+pub fn is_supported(format: &str) -> bool {
+    match format.to_lowercase().as_str() {
+        "jxl" => cfg!(feature = "jxl"),
+        f => ImageFormat::from_extension(f).is_some_and(|f| f.writing_enabled()),
+    }
+}
+
+/// Options that control how source images are decoded
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// Box that SVG sources are rasterized into, preserving their aspect ratio. Defaults to
+    /// the SVG's own size.
+    pub raster_size: Option<(u32, u32)>,
+    /// Largest width or height accepted, see [`check_limits`]
+    pub max_dimension: Option<u32>,
+    /// Most pixels accepted, see [`check_limits`]
+    pub max_pixels: Option<u64>,
+    /// Most bytes the decoder may allocate at once. Defaults to the 512 MiB of
+    /// [`image::Limits`].
+    pub max_alloc: Option<u64>,
+}
+
+/// Options that control how blurred images are encoded
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    /// Output format such as `png` or `pgm`. Defaults to the destination extension.
+    pub format: Option<String>,
+    /// Write JPEG output as a progressive JPEG
+    pub progressive: bool,
+    /// Write PNG output with Adam7 interlacing
+    pub interlaced: bool,
+}
+
+/// A CMYK image split into two RGB images so each ink can go through the RGB blur: `cmy`
+/// holds cyan, magenta and yellow, and `k` holds black repeated in all three channels
+pub struct CmykImage {
+    pub cmy: RgbImage,
+    pub k: RgbImage,
+}
+
+impl CmykImage {
+    /// Converts to RGB for destinations that can't hold CMYK
+    pub fn to_rgb(&self) -> RgbImage {
+        RgbImage::from_fn(self.cmy.width(), self.cmy.height(), |x, y| {
+            let cmy = self.cmy.get_pixel(x, y);
+            let k = 255 - self.k.get_pixel(x, y)[0] as u16;
+
+            Rgb(cmy.0.map(|c| ((255 - c as u16) * k / 255) as u8))
+        })
+    }
+}
+
+/// Opens a CMYK TIFF, keeping its ink separation. Returns `None` for stdin and for any other
+/// kind of image, which should be opened with [`open`].
+///
+/// # Errors
+/// - If the file can't be read or is a malformed TIFF
+pub fn open_cmyk(path: &Path) -> Result<Option<CmykImage>, Message> {
+    if path == Path::new(STDIO) {
+        return Ok(None);
+    }
+
+    decode_cmyk(&read(path)?, path)
+}
+
+/// Same as [`open_cmyk`] for an image already in memory. `path` is only used for error messages.
+///
+/// # Errors
+/// - If the image is a malformed TIFF
+pub fn decode_cmyk(bytes: &[u8], path: &Path) -> Result<Option<CmykImage>, Message> {
+    if !bytes.starts_with(b"II*\0") && !bytes.starts_with(b"MM\0*") {
+        return Ok(None);
+    }
+
+    let mut decoder = tiff::decoder::Decoder::new(Cursor::new(bytes))
+        .map_err(|e| tr!("decode-failed", path.display(), e))?;
+
+    if !matches!(decoder.colortype(), Ok(tiff::ColorType::CMYK(8))) {
+        return Ok(None);
+    }
+
+    let (width, height) = decoder
+        .dimensions()
+        .map_err(|e| tr!("decode-failed", path.display(), e))?;
+
+    let data = match decoder.read_image() {
+        Ok(tiff::decoder::DecodingResult::U8(data)) => data,
+        Ok(_) => return Ok(None),
+        Err(e) => return Err(tr!("decode-failed", path.display(), e)),
+    };
+
+    let mut cmy = RgbImage::new(width, height);
+    let mut k = RgbImage::new(width, height);
+
+    for ((ink, cmy), k) in data
+        .chunks_exact(4)
+        .zip(cmy.pixels_mut())
+        .zip(k.pixels_mut())
+    {
+        *cmy = Rgb([ink[0], ink[1], ink[2]]);
+        *k = Rgb([ink[3]; 3]);
+    }
+
+    Ok(Some(CmykImage { cmy, k }))
+}
+
+/// Saves a CMYK image as a CMYK TIFF, or converts it to RGB if the destination is another format
+///
+/// # Errors
+/// - If the encoder fails or the file can't be written
+pub fn save_cmyk(img: &CmykImage, path: &Path, opts: &EncodeOptions) -> Result<(), Message> {
+    let bytes = encode_cmyk(img, path, opts)?;
+
+    write(path, &bytes)
+}
+
+/// Encodes a CMYK image in memory for the destination `path`, like [`save_cmyk`]
+///
+/// # Errors
+/// - Same as [`save_cmyk`], except for write errors
+pub fn encode_cmyk(img: &CmykImage, path: &Path, opts: &EncodeOptions) -> Result<Vec<u8>, Message> {
+    let is_tiff = match &opts.format {
+        Some(f) => f.eq_ignore_ascii_case("tiff") || f.eq_ignore_ascii_case("tif"),
+        None => has_extension(path, "tiff") || has_extension(path, "tif"),
+    };
+
+    if !is_tiff {
+        return encode(&img.to_rgb(), path, opts);
+    }
+
+    let data: Vec<u8> = img
+        .cmy
+        .pixels()
+        .zip(img.k.pixels())
+        .flat_map(|(cmy, k)| [cmy[0], cmy[1], cmy[2], k[0]])
+        .collect();
+
+    let mut buf = Cursor::new(Vec::new());
+
+    tiff::encoder::TiffEncoder::new(&mut buf)
+        .and_then(|mut e| {
+            e.write_image::<tiff::encoder::colortype::CMYK8>(
+                img.cmy.width(),
+                img.cmy.height(),
+                &data,
+            )
+        })
+        .map_err(|e| tr!("encode-failed", path.display(), e))?;
+
+    Ok(buf.into_inner())
+}
+
+/// Opens an image and converts it to 8-bit RGB. A path of `-` reads from stdin.
+///
+/// SVG sources are rasterized over a white background when the `svg` feature is enabled, and
+/// JPEG XL sources are decoded when the `jxl` feature is.
+///
+/// # Errors
+/// - If the file can't be read or decoded
+/// - If the file is a JPEG XL image and the `jxl` feature is disabled
+/// - If the file is an SVG and the `svg` feature is disabled
+pub fn open(path: &Path, opts: &DecodeOptions) -> Result<RgbImage, Message> {
+    decode(read(path)?, path, opts)
+}
+
+/// Reads a whole file, or stdin for `-`
+///
+/// # Errors
+/// - If the file can't be read
+pub fn read(path: &Path) -> Result<Vec<u8>, Message> {
+    let bytes = match path != Path::new(STDIO) {
+        true => fs::read(path).map_err(|e| tr!("read-failed", path.display(), e))?,
+        false => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| tr!("stdin-read-failed", e))?;
+            bytes
+        }
+    };
+
+    logging::info!("opened path={} bytes={}", path.display(), bytes.len());
+
+    Ok(bytes)
+}
+
+/// Size of an encoded image read from its header without decoding it, or `None` if the format
+/// isn't recognized, e.g. for SVG sources whose size is only known once they are rasterized
+pub fn dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Rejects a `width`x`height` image that exceeds the size limits of `opts`, before a
+/// decompression bomb can be decoded
+///
+/// # Errors
+/// - A message naming the limit that is exceeded
+///
+/// # Examples
+/// ```
+/// use vincent_blur::codec::{check_limits, DecodeOptions};
+///
+/// let opts = DecodeOptions {
+///     max_dimension: Some(10_000),
+///     max_pixels: Some(50_000_000),
+///     ..Default::default()
+/// };
+///
+/// assert!(check_limits(6000, 4000, &opts).is_ok());
+/// assert!(check_limits(20_000, 100, &opts).is_err());
+/// assert!(check_limits(8000, 8000, &opts).is_err());
+/// ```
+pub fn check_limits(width: u32, height: u32, opts: &DecodeOptions) -> Result<(), Message> {
+    if let Some(max) = opts.max_dimension.filter(|&m| width.max(height) > m) {
+        return Err(tr!("too-large-side", width, height, max));
+    }
+
+    let pixels = width as u64 * height as u64;
+
+    if let Some(max) = opts.max_pixels.filter(|&m| pixels > m) {
+        return Err(tr!("too-many-pixels", width, height, max));
+    }
+
+    Ok(())
+}
+
+/// Decodes an in-memory image and converts it to 8-bit RGB. `path` is only used for error
+/// messages and as a format hint.
+///
+/// # Errors
+/// - Same as [`open`], except for read errors
+/// - If the image exceeds the limits of `opts`, see [`check_limits`]
+pub fn decode(bytes: Vec<u8>, path: &Path, opts: &DecodeOptions) -> Result<RgbImage, Message> {
+    if is_jxl(&bytes) || has_extension(path, "jxl") {
+        let img =
+            decode_jxl(bytes, opts).map_err(|e| tr!("decode-failed", path.display()).with(e))?;
+
+        logging::info!(
+            "decoded path={} format=jxl width={} height={}",
+            path.display(),
+            img.width(),
+            img.height()
+        );
+
+        return Ok(img);
+    }
+
+    if is_svg(&bytes) || has_extension(path, "svg") {
+        let img = rasterize_svg(&bytes, opts.raster_size)
+            .map_err(|e| tr!("decode-failed", path.display(), e))?;
+
+        logging::info!(
+            "rasterized path={} width={} height={}",
+            path.display(),
+            img.width(),
+            img.height()
+        );
+
+        return Ok(img);
+    }
+
+    if let Some((width, height)) = dimensions(&bytes) {
+        check_limits(width, height, opts)
+            .map_err(|e| tr!("decode-failed", path.display()).with(e))?;
+    }
+
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| tr!("read-failed", path.display(), e))?;
+
+    let mut limits = image::Limits::default();
+    limits.max_image_width = opts.max_dimension;
+    limits.max_image_height = opts.max_dimension;
+    if opts.max_alloc.is_some() {
+        limits.max_alloc = opts.max_alloc;
+    }
+    reader.limits(limits);
+
+    let format = reader
+        .format()
+        .and_then(|f| f.extensions_str().first().copied())
+        .unwrap_or("unknown");
+    let img = reader
+        .decode()
+        .map_err(|e| tr!("decode-failed", path.display(), e))?;
+
+    logging::info!(
+        "decoded path={} format={format} width={} height={}",
+        path.display(),
+        img.width(),
+        img.height()
+    );
+
+    Ok(img.to_rgb8())
+}
+
+/// Saves an image. The encoder is picked from `opts.format` if given, otherwise from the file
+/// extension. A path of `-` writes to stdout, in which case a format is required.
+///
+/// # Errors
+/// - If the format is unknown or the encoder fails
+/// - If the destination is a JPEG XL file and the `jxl` feature is disabled
+pub fn save(img: &RgbImage, path: &Path, opts: &EncodeOptions) -> Result<(), Message> {
+    let bytes = encode(img, path, opts)?;
+
+    write(path, &bytes)
+}
+
+/// Encodes an image in memory for the destination `path`, which picks the encoder like [`save`]
+///
+/// # Errors
+/// - Same as [`save`], except for write errors
+pub fn encode(img: &RgbImage, path: &Path, opts: &EncodeOptions) -> Result<Vec<u8>, Message> {
+    let to_stdout = path == Path::new(STDIO);
+
+    let ext = match &opts.format {
+        Some(f) => f.to_lowercase(),
+        None if to_stdout => return Err("Expected --format when writing to stdout".into()),
+        None => path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .ok_or_else(|| tr!("encode-failed", path.display(), "expected an extension"))?,
+    };
+
+    let bytes = encode_as(img, &ext, opts).map_err(|e| tr!("encode-failed", path.display(), e))?;
+
+    logging::info!(
+        "encoded path={} format={ext} width={} height={} bytes={}",
+        path.display(),
+        img.width(),
+        img.height(),
+        bytes.len()
+    );
+
+    Ok(bytes)
+}
+
+/// Writes encoded bytes to `path`, or stdout for `-`. Files are written to a hidden temporary
+/// file next to `path` and renamed into place, so an interrupted run or a watcher never sees a
+/// truncated image.
+///
+/// # Errors
+/// - If the file can't be written or renamed, in which case `path` is left as it was
+///
+/// # Examples
+/// ```
+/// use vincent_blur::codec;
+///
+/// let path = std::env::temp_dir().join("blur_codec_write.txt");
+/// codec::write(&path, b"first").unwrap();
+/// codec::write(&path, b"second").unwrap();
+///
+/// assert_eq!(std::fs::read(&path).unwrap(), b"second");
+/// ```
+pub fn write(path: &Path, bytes: &[u8]) -> Result<(), Message> {
+    if path == Path::new(STDIO) {
+        let mut stdout = io::stdout().lock();
+        return stdout
+            .write_all(bytes)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| tr!("stdout-write-failed", e));
+    }
+
+    let tmp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    ));
+
+    let written = fs::File::create(&tmp)
+        .and_then(|mut f| f.write_all(bytes).and_then(|()| f.sync_all()))
+        .and_then(|()| fs::rename(&tmp, path));
+
+    written.map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        tr!("write-failed", path.display(), e)
+    })?;
+
+    logging::info!("wrote path={} bytes={}", path.display(), bytes.len());
+
+    Ok(())
+}
+
+fn encode_as(img: &RgbImage, ext: &str, opts: &EncodeOptions) -> Result<Vec<u8>, String> {
+    if ext == "jxl" {
+        return encode_jxl(img);
+    }
+
+    let format = ImageFormat::from_extension(ext).ok_or(format!("unknown format {ext}"))?;
+    let mut buf = Cursor::new(Vec::new());
+
+    match (format, ext) {
+        (ImageFormat::Jpeg, _) if opts.progressive => return encode_progressive_jpeg(img),
+        (ImageFormat::Png, _) if opts.interlaced => return encode_interlaced_png(img),
+        (_, "pgm") => {
+            let gray = DynamicImage::ImageRgb8(img.clone()).to_luma8();
+
+            PnmEncoder::new(&mut buf)
+                .with_subtype(PnmSubtype::Graymap(SampleEncoding::Binary))
+                .write_image(
+                    gray.as_raw(),
+                    gray.width(),
+                    gray.height(),
+                    image::ExtendedColorType::L8,
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        _ => img.write_to(&mut buf, format).map_err(|e| e.to_string())?,
+    }
+
+    Ok(buf.into_inner())
+}
+
+fn encode_progressive_jpeg(img: &RgbImage) -> Result<Vec<u8>, String> {
+    let (width, height) = match (u16::try_from(img.width()), u16::try_from(img.height())) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => return Err("image is too large for JPEG".to_string()),
+    };
+
+    let mut buf = Vec::new();
+
+    let mut encoder = jpeg_encoder::Encoder::new(&mut buf, JPEG_QUALITY);
+    encoder.set_progressive(true);
+    encoder
+        .encode(img.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| e.to_string())?;
+
+    Ok(buf)
+}
+
+fn encode_interlaced_png(img: &RgbImage) -> Result<Vec<u8>, String> {
+    let (width, height) = img.dimensions();
+    let mut scanlines = Vec::with_capacity(img.as_raw().len() + height as usize * 7);
+
+    for (x0, y0, dx, dy) in ADAM7 {
+        if x0 >= width || y0 >= height {
+            continue;
+        }
+
+        for y in (y0..height).step_by(dy) {
+            scanlines.push(0);
+
+            for x in (x0..width).step_by(dx) {
+                scanlines.extend_from_slice(&img.get_pixel(x, y).0);
+            }
+        }
+    }
+
+    let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+    zlib.write_all(&scanlines).map_err(|e| e.to_string())?;
+    let data = zlib.finish().map_err(|e| e.to_string())?;
+
+    let mut info = png::Info::with_size(width, height);
+    info.color_type = png::ColorType::Rgb;
+    info.bit_depth = png::BitDepth::Eight;
+    info.interlaced = true;
+
+    let mut buf = Vec::new();
+
+    let mut writer = png::Encoder::with_info(&mut buf, info)
+        .and_then(|e| e.write_header())
+        .map_err(|e| e.to_string())?;
+    writer
+        .write_chunk(png::chunk::IDAT, &data)
+        .and_then(|_| writer.finish())
+        .map_err(|e| e.to_string())?;
+
+    Ok(buf)
+}
+
+#[cfg(feature = "svg")]
+fn rasterize_svg(bytes: &[u8], raster_size: Option<(u32, u32)>) -> Result<RgbImage, String> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree =
+        usvg::Tree::from_data(bytes, &usvg::Options::default()).map_err(|e| e.to_string())?;
+    let size = tree.size();
+
+    let scale = match raster_size {
+        Some((w, h)) => (w as f32 / size.width()).min(h as f32 / size.height()),
+        None => 1.0,
+    };
+
+    let width = (size.width() * scale).round().max(1.0) as u32;
+    let height = (size.height() * scale).round().max(1.0) as u32;
+
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or("raster size is too large".to_string())?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    let rgb = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let p = p.demultiply();
+            [p.red(), p.green(), p.blue()]
+        })
+        .collect();
+
+    Ok(RgbImage::from_raw(width, height, rgb).unwrap())
+}
+
+#[cfg(not(feature = "svg"))]
+fn rasterize_svg(_: &[u8], _: Option<(u32, u32)>) -> Result<RgbImage, String> {
+    Err("SVG input requires building with the `svg` feature".to_string())
+}
+
+/// Decodes a JPEG XL image, within the limits of `opts` like the other formats
+#[cfg(feature = "jxl")]
+fn decode_jxl(bytes: Vec<u8>, opts: &DecodeOptions) -> Result<RgbImage, Message> {
+    use image::ImageDecoder;
+    use jxl_oxide::integration::JxlDecoder;
+
+    let mut decoder = JxlDecoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions();
+    check_limits(width, height, opts)?;
+
+    let mut limits = image::Limits::default();
+    if opts.max_alloc.is_some() {
+        limits.max_alloc = opts.max_alloc;
+    }
+    decoder.set_limits(limits).map_err(|e| e.to_string())?;
+
+    let img = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+
+    Ok(img.to_rgb8())
+}
+
+#[cfg(not(feature = "jxl"))]
+fn decode_jxl(_: Vec<u8>, _: &DecodeOptions) -> Result<RgbImage, Message> {
+    Err("JPEG XL input requires building with the `jxl` feature".into())
+}
+
+/// Losslessly encodes a JPEG XL image
+#[cfg(feature = "jxl")]
+fn encode_jxl(img: &RgbImage) -> Result<Vec<u8>, String> {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::EncoderOptions;
+
+    let options = EncoderOptions::new(
+        img.width() as usize,
+        img.height() as usize,
+        ColorSpace::RGB,
+        BitDepth::Eight,
+    );
+
+    zune_jpegxl::JxlSimpleEncoder::new(img.as_raw(), options)
+        .encode()
+        .map_err(|e| format!("{e:?}").trim_end().to_string())
+}
+
+#[cfg(not(feature = "jxl"))]
+fn encode_jxl(_: &RgbImage) -> Result<Vec<u8>, String> {
+    Err("JPEG XL output requires building with the `jxl` feature".to_string())
+}
+
+fn is_svg(header: &[u8]) -> bool {
+    let header = String::from_utf8_lossy(&header[..header.len().min(256)]);
+    let header = header.trim_start();
+
+    header.starts_with("<svg") || (header.starts_with("<?xml") && header.contains("<svg"))
+}
+
+fn is_jxl(header: &[u8]) -> bool {
+    header.starts_with(&JXL_CODESTREAM) || header.starts_with(&JXL_CONTAINER)
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
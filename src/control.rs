@@ -0,0 +1,120 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// Steers a blur running in the background, see
+/// [`BlurTask::control`](crate::events::BlurTask::control). Clones control the same blur, so
+/// one can be handed to a UI thread while another thread waits for the result.
+///
+/// Workers check the handle between tiles, so a pause or a lower thread count takes effect
+/// once the tiles in progress are done, and no pixel is ever left half written.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::control::Control;
+///
+/// let control = Control::new(4);
+///
+/// control.pause();
+/// assert!(control.is_paused());
+///
+/// control.resume();
+/// control.set_threads(2);
+/// assert_eq!((control.is_paused(), control.threads()), (false, 2));
+///
+/// control.set_threads(0);
+/// assert_eq!(control.threads(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Control {
+    shared: Arc<(Mutex<Flow>, Condvar)>,
+}
+
+#[derive(Debug)]
+struct Flow {
+    paused: bool,
+    threads: usize,
+    /// Set once every tile is taken or the blur failed, which releases the parked workers
+    done: bool,
+}
+
+impl Control {
+    pub fn new(n_threads: usize) -> Control {
+        Control {
+            shared: Arc::new((
+                Mutex::new(Flow {
+                    paused: false,
+                    threads: n_threads.max(1),
+                    done: false,
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    fn flow(&self) -> MutexGuard<'_, Flow> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Flow)) {
+        f(&mut self.flow());
+        self.shared.1.notify_all();
+    }
+
+    /// Stops the workers once their current tile is done, freeing the CPU until [`resume`]
+    ///
+    /// [`resume`]: Control::resume
+    pub fn pause(&self) {
+        self.update(|f| f.paused = true);
+    }
+
+    /// Lets the workers carry on after [`pause`](Control::pause)
+    pub fn resume(&self) {
+        self.update(|f| f.paused = false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.flow().paused
+    }
+
+    /// Runs the rest of the blur on `n_threads` workers, at least 1. Extra workers stop after
+    /// their current tile, and missing ones are started.
+    pub fn set_threads(&self, n_threads: usize) {
+        self.update(|f| f.threads = n_threads.max(1));
+    }
+
+    /// Workers the blur runs on
+    pub fn threads(&self) -> usize {
+        self.flow().threads
+    }
+
+    /// Blocks worker `i` while the blur is paused or runs on `i` workers or fewer. Returns
+    /// whether it may take another tile, which is no longer the case once the blur is
+    /// [`finish`](Control::finish)ed.
+    pub(crate) fn wait_turn(&self, i: usize) -> bool {
+        let flow = self.flow();
+        let flow = self
+            .shared
+            .1
+            .wait_while(flow, |f| !f.done && (f.paused || i >= f.threads))
+            .unwrap_or_else(|e| e.into_inner());
+
+        !flow.done
+    }
+
+    /// Blocks until more than `running` workers are wanted, returning how many, or `None` once
+    /// the blur is [`finish`](Control::finish)ed
+    pub(crate) fn wait_for_more(&self, running: usize) -> Option<usize> {
+        let flow = self.flow();
+        let flow = self
+            .shared
+            .1
+            .wait_while(flow, |f| !f.done && f.threads <= running)
+            .unwrap_or_else(|e| e.into_inner());
+
+        (!flow.done).then_some(flow.threads)
+    }
+
+    /// Releases every parked worker for good, once no tile is left or a worker failed
+    pub(crate) fn finish(&self) {
+        self.update(|f| f.done = true);
+    }
+}
@@ -0,0 +1,25 @@
+use std::cell::Cell;
+use std::time::Instant;
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with `deadline` as the deadline of the blurs it starts on this thread. The
+/// long-running blurs hand it to their workers, which stop early once it has passed.
+pub(crate) fn with<T>(deadline: Option<Instant>, f: impl FnOnce() -> T) -> T {
+    let prev = DEADLINE.replace(deadline);
+    let res = f();
+    DEADLINE.set(prev);
+    res
+}
+
+/// Deadline set by [`with`] on this thread
+pub(crate) fn current() -> Option<Instant> {
+    DEADLINE.get()
+}
+
+/// Whether `deadline` is set and has passed
+pub(crate) fn passed(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|d| Instant::now() >= d)
+}
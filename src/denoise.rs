@@ -0,0 +1,509 @@
+use crate::cli::{self, error::ArgError};
+use crate::events::{self, Event};
+use crate::locale::Message;
+use crate::rows::map_rows;
+use crate::tr;
+use crate::{blur_passes, codec, naming, parse_positive, surface_blur, Direction, Error, Kernel};
+use image::{Rgb, RgbImage};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{fmt, thread};
+
+/// Ratio of the median absolute deviation to the standard deviation of Gaussian noise
+const MAD_TO_SIGMA: f32 = 0.6745;
+
+/// Noise levels of an image, as standard deviations in levels from 0 to 255
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseEstimate {
+    pub luma: f32,
+    pub chroma: f32,
+}
+
+/// Estimates the noise of `img` from the median absolute deviation of the diagonal detail of
+/// its 2x2 blocks, the finest high-pass there is. Edges and textures only make up a few of
+/// the blocks, so the median sees the noise.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::denoise::estimate_noise;
+///
+/// let flat = image::RgbImage::from_pixel(64, 64, image::Rgb([120; 3]));
+/// assert_eq!(estimate_noise(&flat).luma, 0.0);
+///
+/// let mut seed = 1u32;
+/// let noisy = image::RgbImage::from_fn(64, 64, |_, _| {
+///     seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+///     image::Rgb([(100 + (seed >> 16) % 41) as u8; 3])
+/// });
+/// assert!(estimate_noise(&noisy).luma > 5.0);
+/// ```
+pub fn estimate_noise(img: &RgbImage) -> NoiseEstimate {
+    let ycc = to_ycc(img);
+    let mut deviations: [Vec<f32>; 3] = Default::default();
+
+    for y in (0..img.height().saturating_sub(1)).step_by(2) {
+        for x in (0..img.width().saturating_sub(1)).step_by(2) {
+            let (a, b) = (ycc.get_pixel(x, y), ycc.get_pixel(x + 1, y));
+            let (c, d) = (ycc.get_pixel(x, y + 1), ycc.get_pixel(x + 1, y + 1));
+
+            for (ch, dev) in deviations.iter_mut().enumerate() {
+                let diagonal = (a[ch] as f32 - b[ch] as f32 - c[ch] as f32 + d[ch] as f32) / 2.0;
+                dev.push(diagonal.abs());
+            }
+        }
+    }
+
+    let [luma, cb, cr] = deviations.map(|mut d| median(&mut d) / MAD_TO_SIGMA);
+
+    NoiseEstimate {
+        luma,
+        chroma: (cb + cr) / 2.0,
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let mid = values.len() / 2;
+    *values.select_nth_unstable_by(mid, f32::total_cmp).1
+}
+
+/// Parameters of [`denoise`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DenoiseParams {
+    /// Radius and sigma of the edge-preserving luma blur, see [`surface_blur`]
+    pub radius: u8,
+    pub sigma: f64,
+    /// Largest difference from the center pixel still averaged in
+    pub threshold: u8,
+    /// Sigma of the extra chroma blur, or 0 for none
+    pub chroma_sigma: f64,
+}
+
+impl DenoiseParams {
+    /// Parameters that remove `noise` scaled by `strength`: samples within 3 standard deviations
+    /// of the center are averaged, over a radius that grows with the noise, and chroma noise,
+    /// which the eye forgives detail loss in, gets a wider plain blur
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::denoise::{DenoiseParams, NoiseEstimate};
+    ///
+    /// let mild = DenoiseParams::for_noise(NoiseEstimate { luma: 2.0, chroma: 1.0 }, 1.0);
+    /// let heavy = DenoiseParams::for_noise(NoiseEstimate { luma: 12.0, chroma: 8.0 }, 1.0);
+    ///
+    /// assert!(mild.threshold < heavy.threshold);
+    /// assert!(mild.radius <= heavy.radius);
+    /// ```
+    pub fn for_noise(noise: NoiseEstimate, strength: f32) -> Self {
+        let luma = noise.luma * strength;
+        let chroma = noise.chroma * strength;
+        let radius = (2.0 + luma / 4.0).round().clamp(2.0, 8.0) as u8;
+
+        DenoiseParams {
+            radius,
+            sigma: radius as f64 / 2.0,
+            threshold: (3.0 * luma).ceil().clamp(1.0, 255.0) as u8,
+            chroma_sigma: match chroma < 1.0 {
+                true => 0.0,
+                false => (chroma as f64 / 2.0).clamp(0.5, 8.0),
+            },
+        }
+    }
+}
+
+/// Removes noise with a surface blur of the pixels, then blurs the chroma on its own
+///
+/// # Examples
+/// ```
+/// use vincent_blur::denoise::{denoise, DenoiseParams};
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, _| image::Rgb([if x < 8 { 20 } else { 220 }; 3]));
+/// let params = DenoiseParams { radius: 2, sigma: 1.0, threshold: 10, chroma_sigma: 1.0 };
+///
+/// // The hard edge survives
+/// assert_eq!(denoise(&img, params, 2), img);
+/// ```
+pub fn denoise(img: &RgbImage, params: DenoiseParams, n_threads: usize) -> RgbImage {
+    let img = surface_blur(
+        Kernel::new(params.radius, params.sigma),
+        params.threshold,
+        n_threads,
+        img.clone(),
+    );
+
+    if params.chroma_sigma == 0.0 {
+        return img;
+    }
+
+    let ycc = to_ycc(&img);
+    let radius = (params.chroma_sigma * 3.0).ceil().min(255.0) as u8;
+    let chroma = Kernel::new(radius, params.chroma_sigma);
+    let blurred = blur_passes(chroma, 1, Direction::Both, &ycc);
+
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let (l, c) = (ycc.get_pixel(x, y), blurred.get_pixel(x, y));
+        from_ycc(Rgb([l[0], c[1], c[2]]))
+    })
+}
+
+/// Parameters of [`nl_means`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NlMeansParams {
+    /// Radius of the patches compared around each pixel
+    pub patch_radius: u8,
+    /// Radius of the window searched for similar patches
+    pub search_radius: u8,
+    /// Standard deviation of the noise, in levels from 0 to 255
+    pub noise: f32,
+    /// How quickly the weight of a patch falls with its distance, in levels
+    pub h: f32,
+}
+
+impl NlMeansParams {
+    /// Parameters for `noise` scaled by `strength`, after the table of Buades et al. for color
+    /// images: 3x3 patches and `h` at 0.55 times the noise up to a noise of 25, then 5x5
+    /// patches and 0.4 times the noise. The search windows are smaller than theirs to keep the
+    /// cost down.
+    pub fn for_noise(noise: NoiseEstimate, strength: f32) -> Self {
+        let noise = noise.luma.max(noise.chroma) * strength;
+
+        let (patch_radius, search_radius, factor) = match noise <= 25.0 {
+            true => (1, 7, 0.55),
+            false => (2, 10, 0.4),
+        };
+
+        NlMeansParams {
+            patch_radius,
+            search_radius,
+            noise,
+            h: (noise * factor).max(1.0),
+        }
+    }
+}
+
+/// Non-local means denoiser. Every pixel becomes the average of the pixels around it whose
+/// surrounding patch looks like its own, so repeated textures and edges are averaged along
+/// themselves instead of across. Slower than [`denoise`] but keeps much more detail. Rows are
+/// spread over `n_threads` workers.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::denoise::{nl_means, NlMeansParams};
+///
+/// let stripes = image::RgbImage::from_fn(24, 24, |x, y| {
+///     let base = if (x / 4) % 2 == 0 { 40 } else { 200 };
+///     image::Rgb([base + ((x * 7 + y * 13) % 9) as u8; 3])
+/// });
+/// let params = NlMeansParams { patch_radius: 1, search_radius: 5, noise: 3.0, h: 4.0 };
+/// let denoised = nl_means(&stripes, params, 2);
+///
+/// // The stripes stay, the ripple on them is smoothed
+/// let px = |x, y| denoised.get_pixel(x, y)[0] as i32;
+/// assert!(px(1, 12) < 60 && px(5, 12) > 180);
+/// assert!((px(5, 12) - px(6, 13)).abs() <= 4);
+/// ```
+pub fn nl_means(img: &RgbImage, params: NlMeansParams, n_threads: usize) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let img = Arc::new(img.clone());
+
+    map_rows(width, height, n_threads, move |y, row| {
+        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+            px.copy_from_slice(&nl_means_pixel(x as i64, y as i64, &img, &params));
+        }
+    })
+}
+
+fn nl_means_pixel(x: i64, y: i64, img: &RgbImage, params: &NlMeansParams) -> [u8; 3] {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let at =
+        |x: i64, y: i64| img.get_pixel(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+
+    let p = params.patch_radius as i64;
+    let s = params.search_radius as i64;
+    let patch_len = ((2 * p + 1) * (2 * p + 1) * 3) as f32;
+    let bias = 2.0 * params.noise * params.noise;
+    let h2 = params.h * params.h;
+
+    let mut sum = [0.0f32; 3];
+    let mut total = 0.0;
+
+    for qy in (y - s).max(0)..=(y + s).min(height - 1) {
+        for qx in (x - s).max(0)..=(x + s).min(width - 1) {
+            let mut distance = 0.0;
+
+            for dy in -p..=p {
+                for dx in -p..=p {
+                    let (a, b) = (at(x + dx, y + dy), at(qx + dx, qy + dy));
+
+                    for c in 0..3 {
+                        let d = a[c] as f32 - b[c] as f32;
+                        distance += d * d;
+                    }
+                }
+            }
+
+            let weight = (-(distance / patch_len - bias).max(0.0) / h2).exp();
+            let q = img.get_pixel(qx as u32, qy as u32);
+
+            for c in 0..3 {
+                sum[c] += q[c] as f32 * weight;
+            }
+            total += weight;
+        }
+    }
+
+    sum.map(|v| (v / total).round() as u8)
+}
+
+/// Full range BT.601 YCbCr, stored in the channels of an RgbImage
+fn to_ycc(img: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b] = img.get_pixel(x, y).0.map(|v| v as f32);
+
+        Rgb([
+            0.299 * r + 0.587 * g + 0.114 * b,
+            128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b,
+            128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b,
+        ]
+        .map(|v| v.round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+fn from_ycc(p: Rgb<u8>) -> Rgb<u8> {
+    let [y, cb, cr] = p.0.map(|v| v as f32);
+    let (cb, cr) = (cb - 128.0, cr - 128.0);
+
+    Rgb([
+        y + 1.402 * cr,
+        y - 0.344136 * cb - 0.714136 * cr,
+        y + 1.772 * cb,
+    ]
+    .map(|v| v.round().clamp(0.0, 255.0) as u8))
+}
+
+/// Parameters picked for a source by [`run`], with the method they belong to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DenoiseSettings {
+    Surface(DenoiseParams),
+    NlMeans(NlMeansParams),
+}
+
+/// Denoisers selectable with the `--method` of the `denoise` subcommand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Surface blur and chroma blur, see [`denoise`]
+    Surface,
+    /// See [`nl_means`]
+    NlMeans,
+}
+
+impl FromStr for Method {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "surface" => Ok(Method::Surface),
+            "nlmeans" => Ok(Method::NlMeans),
+            _ => Err(tr!("unknown-denoise-method", s)),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Method::Surface => "surface",
+            Method::NlMeans => "nlmeans",
+        })
+    }
+}
+
+/// CLI options of the `denoise` subcommand
+pub struct DenoiseOpts {
+    pub sources: Vec<PathBuf>,
+    pub method: Method,
+    /// Scales the estimated noise, above 1 to smooth harder
+    pub strength: f32,
+    pub format: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub force: bool,
+}
+
+impl DenoiseOpts {
+    /// Constructs a new DenoiseOpts from CLI options, skipping the program name and the
+    /// `denoise` subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If no sources are given
+    pub fn new(cli_opts: env::Args) -> Result<DenoiseOpts, ArgError> {
+        let mut sources = Vec::new();
+        let mut method = Method::Surface;
+        let mut strength = 1.0;
+        let mut format: Option<String> = None;
+        let mut output_dir: Option<PathBuf> = None;
+        let mut force = false;
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--method" | "-m" => {
+                    method = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                "--method",
+                                "Expected surface or nlmeans after --method|-m",
+                            )
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value("--method", e))?;
+                }
+                "--strength" => {
+                    strength = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--strength", tr!("number-after", "--strength"))
+                        })?;
+                }
+                "--format" | "-f" => {
+                    format = match cli_opts.next() {
+                        Some(f) if codec::is_supported(&f) => Some(f.to_lowercase()),
+                        Some(f) => {
+                            return Err(
+                                cli_opts.invalid_value("--format", tr!("unsupported-format", f))
+                            )
+                        }
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value("--format", "Expected a format after --format|-f"))
+                        }
+                    };
+                }
+                "--output-dir" | "-o" => {
+                    output_dir = match cli_opts.next() {
+                        Some(d) => Some(PathBuf::from(d)),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                "--output-dir",
+                                "Expected a directory after --output-dir|-o",
+                            ))
+                        }
+                    };
+                }
+                "--force" => force = true,
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur denoise [--method|-m <method>] [--strength <factor>] [--format|-f <format>] [--output-dir|-o <dir>] [--force] <sources>... [--help|-h]\n",
+                        "   <sources>           Paths of the images to denoise. The noise of each is",
+                        "                       estimated and the blur parameters picked from it.",
+                        "   -m, --method        surface, an edge-preserving blur with extra chroma",
+                        "                       smoothing, or nlmeans, slower but keeping more",
+                        "                       texture. Default is surface.",
+                        "   --strength          Scale of the estimated noise, above 1 to smooth",
+                        "                       harder. Default is 1.",
+                        "   -f, --format        Output format. Default is the source's format.",
+                        "   -o, --output-dir    Directory to write the images to. Default is next to",
+                        "                       the sources, as <stem>_denoised.<ext>.",
+                        "   --force             Overwrite existing files.",
+                        "   -h, --help          Print help.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => sources.push(PathBuf::from(arg)),
+            }
+        }
+
+        if sources.is_empty() {
+            return Err("Expected at least one source".into());
+        }
+
+        Ok(DenoiseOpts {
+            sources,
+            method,
+            strength,
+            format,
+            output_dir,
+            force,
+        })
+    }
+
+    /// Destination of the denoised `source`, `<stem>_denoised.<ext>`
+    ///
+    /// # Errors
+    /// - Same as [`naming::default_path`]
+    pub fn destination(&self, source: &Path) -> Result<PathBuf, Message> {
+        let path = naming::default_path(source, "_denoised", self.format.as_deref())?;
+
+        Ok(match &self.output_dir {
+            Some(dir) => dir.join(path.file_name().unwrap()),
+            None => path,
+        })
+    }
+}
+
+/// Denoises every source with the parameters its estimated noise calls for, reporting both as
+/// an [`Event::Denoising`]
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if a destination exists and `force` isn't set
+/// - [`Error::Decode`] if a source can't be read
+/// - [`Error::Encode`] if a denoised image can't be written
+pub fn run(opts: &DenoiseOpts) -> Result<(), Error> {
+    let encode_opts = codec::EncodeOptions {
+        format: opts.format.clone(),
+        ..Default::default()
+    };
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    for source in &opts.sources {
+        let destination = opts
+            .destination(source)
+            .map_err(|e| Error::InvalidArgs(e.into()))?;
+
+        if !opts.force && destination.exists() {
+            return Err(Error::InvalidArgs(
+                tr!("already-exists", destination.display()).into(),
+            ));
+        }
+
+        let img = codec::open(source, &codec::DecodeOptions::default()).map_err(Error::Decode)?;
+        let noise = estimate_noise(&img);
+        let settings = match opts.method {
+            Method::Surface => {
+                DenoiseSettings::Surface(DenoiseParams::for_noise(noise, opts.strength))
+            }
+            Method::NlMeans => {
+                DenoiseSettings::NlMeans(NlMeansParams::for_noise(noise, opts.strength))
+            }
+        };
+
+        events::emit(Event::Denoising {
+            path: source.clone(),
+            noise,
+            settings,
+        });
+
+        let denoised = match settings {
+            DenoiseSettings::Surface(params) => denoise(&img, params, n_threads),
+            DenoiseSettings::NlMeans(params) => nl_means(&img, params, n_threads),
+        };
+
+        codec::save(&denoised, &destination, &encode_opts).map_err(Error::Encode)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use crate::mask::luma;
+use crate::region::Rect;
+use image::RgbImage;
+
+/// Side of the square cells the image is split into when looking for text
+const CELL: u32 = 8;
+
+/// Luma difference between horizontal neighbours that counts as a stroke edge
+const EDGE_THRESHOLD: f32 = 40.0;
+
+/// Fraction of a cell's pixels that must sit on a stroke edge for the cell to look like text
+const TEXT_DENSITY: f32 = 0.12;
+
+/// Luma below which a pixel counts as a dark module of a QR code or a bar
+const DARK: f32 = 128.0;
+
+/// Fraction of a cell's pixels that must sit on a vertical edge for the cell to look like a
+/// barcode, while almost none sit on a horizontal one
+const BAR_DENSITY: f32 = 0.15;
+
+/// Finds QR codes and 1D barcodes without decoding them.
+///
+/// QR codes are found through their three finder patterns, squares whose rows and columns
+/// both cross dark and light runs in a 1:1:3:1:1 ratio and which sit at the corners of a
+/// right isosceles triangle. Barcodes are cells crossed by many vertical edges but no
+/// horizontal ones, joined into strips.
+pub fn code_regions(img: &RgbImage) -> Vec<Rect> {
+    let mut rects = qr_regions(img);
+    rects.extend(barcode_regions(img));
+    rects
+}
+
+/// A finder pattern candidate: center and module size in pixels, and the number of rows
+/// that matched it
+#[derive(Debug, Clone, Copy)]
+struct Finder {
+    x: f32,
+    y: f32,
+    module: f32,
+    rows: f32,
+}
+
+fn qr_regions(img: &RgbImage) -> Vec<Rect> {
+    let (width, height) = img.dimensions();
+    let dark: Vec<bool> = img.pixels().map(|p| luma(p) < DARK).collect();
+    let at = |x: u32, y: u32| dark[(y * width + x) as usize];
+
+    let mut finders: Vec<Finder> = Vec::new();
+
+    for y in 0..height {
+        let runs = runs((0..width).map(|x| at(x, y)));
+
+        for w in runs.windows(5) {
+            let (start, is_dark, _) = w[0];
+            let lengths: [u32; 5] = std::array::from_fn(|i| w[i].2);
+
+            if !is_dark || !finder_ratio(lengths) {
+                continue;
+            }
+
+            let cx = start + lengths[0] + lengths[1] + lengths[2] / 2;
+            let module = lengths.iter().sum::<u32>() as f32 / 7.0;
+
+            if !finder_column(&at, cx, y, height, module) {
+                continue;
+            }
+
+            match finders.iter_mut().find(|f| {
+                (f.x - cx as f32).abs() < module * 2.0 && (f.y - y as f32).abs() < module * 4.0
+            }) {
+                // Average the rows crossing the same pattern to find its vertical center
+                Some(f) => {
+                    f.y = (f.y * f.rows + y as f32) / (f.rows + 1.0);
+                    f.rows += 1.0;
+                }
+                None => finders.push(Finder {
+                    x: cx as f32,
+                    y: y as f32,
+                    module,
+                    rows: 1.0,
+                }),
+            }
+        }
+    }
+
+    let mut used = vec![false; finders.len()];
+    let mut rects = Vec::new();
+
+    for i in 0..finders.len() {
+        for j in i + 1..finders.len() {
+            for k in j + 1..finders.len() {
+                if used[i] || used[j] || used[k] {
+                    continue;
+                }
+
+                if let Some(r) = qr_from_finders([finders[i], finders[j], finders[k]]) {
+                    used[i] = true;
+                    used[j] = true;
+                    used[k] = true;
+
+                    if let Some(r) = r.clamp_to(width, height) {
+                        rects.push(r);
+                    }
+                }
+            }
+        }
+    }
+
+    rects
+}
+
+/// Splits a line of pixels into runs of (start, is dark, length)
+fn runs(line: impl Iterator<Item = bool>) -> Vec<(u32, bool, u32)> {
+    let mut runs: Vec<(u32, bool, u32)> = Vec::new();
+
+    for (i, d) in line.enumerate() {
+        match runs.last_mut() {
+            Some((_, dark, len)) if *dark == d => *len += 1,
+            _ => runs.push((i as u32, d, 1)),
+        }
+    }
+
+    runs
+}
+
+fn finder_ratio(lengths: [u32; 5]) -> bool {
+    let total: u32 = lengths.iter().sum();
+
+    if total < 7 {
+        return false;
+    }
+
+    let module = total as f32 / 7.0;
+    let tolerance = module / 2.0;
+
+    lengths
+        .iter()
+        .zip([1.0, 1.0, 3.0, 1.0, 1.0])
+        .all(|(&len, expected)| {
+            (len as f32 - expected * module).abs() <= tolerance * expected.max(1.5)
+        })
+}
+
+/// Checks that the column through a horizontal finder match crosses the pattern the same way
+fn finder_column(at: &impl Fn(u32, u32) -> bool, x: u32, y: u32, height: u32, module: f32) -> bool {
+    let reach = (module * 5.0).ceil() as u32;
+    let top = y.saturating_sub(reach);
+    let bottom = (y + reach).min(height - 1);
+
+    let runs = runs((top..=bottom).map(|y| at(x, y)));
+
+    runs.windows(5).any(|w| {
+        let (start, is_dark, _) = w[0];
+        let lengths: [u32; 5] = std::array::from_fn(|i| w[i].2);
+        let center = top + start + lengths[0] + lengths[1];
+
+        is_dark && finder_ratio(lengths) && (center..center + lengths[2]).contains(&y)
+    })
+}
+
+/// Bounding box of the QR code whose finder patterns are `finders`, if they form a right
+/// isosceles triangle of similar patterns
+fn qr_from_finders(finders: [Finder; 3]) -> Option<Rect> {
+    let module = finders.iter().map(|f| f.module).sum::<f32>() / 3.0;
+
+    if finders
+        .iter()
+        .any(|f| f.module > module * 1.5 || f.module < module / 1.5)
+    {
+        return None;
+    }
+
+    let dist = |a: Finder, b: Finder| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+
+    // The corner is the finder opposite the longest side
+    let corner = (0..3).max_by(|&a, &b| {
+        let side = |i: usize| dist(finders[(i + 1) % 3], finders[(i + 2) % 3]);
+        side(a).total_cmp(&side(b))
+    })?;
+
+    let (a, b, c) = (
+        finders[corner],
+        finders[(corner + 1) % 3],
+        finders[(corner + 2) % 3],
+    );
+    let (ab, ac, bc) = (dist(a, b), dist(a, c), dist(b, c));
+
+    if ab < module * 14.0
+        || (ab - ac).abs() > ab * 0.15
+        || (bc - ab * 2f32.sqrt()).abs() > bc * 0.15
+    {
+        return None;
+    }
+
+    let d = Finder {
+        x: b.x + c.x - a.x,
+        y: b.y + c.y - a.y,
+        module,
+        rows: 0.0,
+    };
+    let corners = [a, b, c, d];
+
+    // Finder centers are 3.5 modules in from the edge, plus a module of slack
+    let margin = module * 4.5;
+    let x0 = corners.iter().map(|f| f.x).fold(f32::MAX, f32::min) - margin;
+    let y0 = corners.iter().map(|f| f.y).fold(f32::MAX, f32::min) - margin;
+    let x1 = corners.iter().map(|f| f.x).fold(f32::MIN, f32::max) + margin;
+    let y1 = corners.iter().map(|f| f.y).fold(f32::MIN, f32::max) + margin;
+
+    let (x0, y0) = (x0.max(0.0) as u32, y0.max(0.0) as u32);
+
+    Some(Rect::new(
+        x0,
+        y0,
+        (x1.ceil() as u32).saturating_sub(x0),
+        (y1.ceil() as u32).saturating_sub(y0),
+    ))
+}
+
+fn barcode_regions(img: &RgbImage) -> Vec<Rect> {
+    let (width, height) = img.dimensions();
+    let (cols, rows) = (width.div_ceil(CELL), height.div_ceil(CELL));
+
+    let mut vertical = vec![0u32; (cols * rows) as usize];
+    let mut horizontal = vec![0u32; (cols * rows) as usize];
+
+    for y in 1..height {
+        for x in 1..width {
+            let l = luma(img.get_pixel(x, y));
+            let cell = ((y / CELL) * cols + x / CELL) as usize;
+
+            if (l - luma(img.get_pixel(x - 1, y))).abs() > EDGE_THRESHOLD {
+                vertical[cell] += 1;
+            }
+
+            if (l - luma(img.get_pixel(x, y - 1))).abs() > EDGE_THRESHOLD {
+                horizontal[cell] += 1;
+            }
+        }
+    }
+
+    let area = (CELL * CELL) as f32;
+    let bars: Vec<bool> = vertical
+        .iter()
+        .zip(&horizontal)
+        .map(|(&v, &h)| v as f32 / area > BAR_DENSITY && (h as f32) < v as f32 / 8.0)
+        .collect();
+
+    components(&bars, cols, rows)
+        .into_iter()
+        .filter(|cells| cells.width >= 3)
+        .filter_map(|cells| cells_to_pixels(cells).pad(CELL).clamp_to(width, height))
+        .collect()
+}
+
+/// Finds text-like regions without any OCR: glyphs produce many sharp horizontal luma
+/// transitions close together, so cells dense in them are joined into lines and returned as
+/// rectangles wider than they are tall.
+pub fn text_regions(img: &RgbImage) -> Vec<Rect> {
+    let (width, height) = img.dimensions();
+
+    glyph_lines(img)
+        .into_iter()
+        .filter(|cells| cells.width >= 2 && cells.width >= cells.height)
+        .filter_map(|cells| cells_to_pixels(cells).pad(CELL / 4).clamp_to(width, height))
+        .collect()
+}
+
+/// Finds licence-plate-like regions: short lines of glyphs, two to six times wider than they
+/// are tall, printed in high contrast on a flat plate.
+pub fn plate_regions(img: &RgbImage) -> Vec<Rect> {
+    let (width, height) = img.dimensions();
+
+    glyph_lines(img)
+        .into_iter()
+        .filter(|cells| {
+            let aspect = cells.width as f32 / cells.height as f32;
+            (PLATE_ASPECT.0..=PLATE_ASPECT.1).contains(&aspect)
+        })
+        .filter_map(|cells| cells_to_pixels(cells).pad(CELL).clamp_to(width, height))
+        .filter(|r| contrast(img, r) > PLATE_CONTRAST)
+        .collect()
+}
+
+/// Width to height ratios a plate may have, from square-ish US plates to long EU ones
+const PLATE_ASPECT: (f32, f32) = (2.0, 6.0);
+
+/// Luma spread between the darkest and brightest tenth of a plate's pixels
+const PLATE_CONTRAST: f32 = 100.0;
+
+/// Groups of cells dense in the sharp horizontal luma transitions glyphs produce, with the
+/// gaps between words bridged so each line is one group
+fn glyph_lines(img: &RgbImage) -> Vec<Rect> {
+    let (width, height) = img.dimensions();
+    let (cols, rows) = (width.div_ceil(CELL), height.div_ceil(CELL));
+
+    let mut edges = vec![0u32; (cols * rows) as usize];
+
+    for y in 0..height {
+        for x in 1..width {
+            let d = luma(img.get_pixel(x, y)) - luma(img.get_pixel(x - 1, y));
+
+            if d.abs() > EDGE_THRESHOLD {
+                edges[((y / CELL) * cols + x / CELL) as usize] += 1;
+            }
+        }
+    }
+
+    let mut text: Vec<bool> = edges
+        .iter()
+        .map(|&n| n as f32 / (CELL * CELL) as f32 > TEXT_DENSITY)
+        .collect();
+
+    for r in 0..rows {
+        for c in 1..cols.saturating_sub(1) {
+            let i = (r * cols + c) as usize;
+            if text[i - 1] && text[i + 1] {
+                text[i] = true;
+            }
+        }
+    }
+
+    components(&text, cols, rows)
+}
+
+fn cells_to_pixels(cells: Rect) -> Rect {
+    Rect::new(
+        cells.x * CELL,
+        cells.y * CELL,
+        cells.width * CELL,
+        cells.height * CELL,
+    )
+}
+
+/// Difference between the 90th and 10th percentile of the luma inside `rect`
+fn contrast(img: &RgbImage, rect: &Rect) -> f32 {
+    let mut lumas: Vec<f32> = (rect.y..rect.bottom())
+        .flat_map(|y| (rect.x..rect.right()).map(move |x| (x, y)))
+        .map(|(x, y)| luma(img.get_pixel(x, y)))
+        .collect();
+
+    lumas.sort_by(f32::total_cmp);
+
+    lumas[lumas.len() * 9 / 10] - lumas[lumas.len() / 10]
+}
+
+/// Bounding boxes, in cells, of the 4-connected groups of set cells
+fn components(cells: &[bool], cols: u32, rows: u32) -> Vec<Rect> {
+    let mut seen = vec![false; cells.len()];
+    let mut boxes = Vec::new();
+
+    for start in 0..cells.len() {
+        if !cells[start] || seen[start] {
+            continue;
+        }
+
+        let (mut x0, mut y0, mut x1, mut y1) = (u32::MAX, u32::MAX, 0, 0);
+        let mut stack = vec![start];
+        seen[start] = true;
+
+        while let Some(i) = stack.pop() {
+            let (c, r) = (i as u32 % cols, i as u32 / cols);
+            (x0, y0, x1, y1) = (x0.min(c), y0.min(r), x1.max(c), y1.max(r));
+
+            let neighbours = [
+                (c > 0).then(|| i - 1),
+                (c + 1 < cols).then(|| i + 1),
+                (r > 0).then(|| i - cols as usize),
+                (r + 1 < rows).then(|| i + cols as usize),
+            ];
+
+            for n in neighbours.into_iter().flatten() {
+                if cells[n] && !seen[n] {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+
+        boxes.push(Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1));
+    }
+
+    boxes
+}
@@ -0,0 +1,111 @@
+use crate::locale::Message;
+use crate::tr;
+use image::RgbImage;
+use std::str::FromStr;
+
+/// How a high-precision buffer is rounded to 8 bits per channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Truncate every value, like the blur always did
+    None,
+    /// Add a tiled 8x8 Bayer threshold before rounding down
+    Ordered,
+    /// Push every pixel's rounding error onto its unvisited neighbours (Floyd-Steinberg)
+    Diffusion,
+}
+
+impl FromStr for Dither {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Dither::None),
+            "ordered" => Ok(Dither::Ordered),
+            "diffusion" => Ok(Dither::Diffusion),
+            _ => Err(tr!("unknown-dither", s)),
+        }
+    }
+}
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Converts an interleaved RGB buffer of values in 0..=255 to an image, hiding the banding
+/// that rounding smooth gradients to 8 bits leaves with the chosen dither
+///
+/// # Examples
+/// ```
+/// use vincent_blur::dither::{quantize, Dither};
+///
+/// // A flat 100.25 comes out as a mix of 100 and 101 that averages to about 100.25
+/// let data = vec![100.25; 16 * 16 * 3];
+/// let img = quantize(16, 16, &data, Dither::Diffusion);
+///
+/// let mean = img.iter().map(|&v| v as f32).sum::<f32>() / data.len() as f32;
+/// assert!((mean - 100.25).abs() < 0.05);
+/// ```
+///
+/// # Panics
+/// - If `data` doesn't hold `width * height * 3` values
+pub fn quantize(width: u32, height: u32, data: &[f32], dither: Dither) -> RgbImage {
+    assert_eq!(data.len(), width as usize * height as usize * 3);
+
+    let raw = match dither {
+        Dither::None => data.iter().map(|&v| v as u8).collect(),
+        Dither::Ordered => data
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let (x, y) = ((i / 3) % width as usize, (i / 3) / width as usize);
+                let t = (BAYER[y % 8][x % 8] as f32 + 0.5) / 64.0;
+
+                (v + t).floor().clamp(0.0, 255.0) as u8
+            })
+            .collect(),
+        Dither::Diffusion => diffuse(width as usize, data),
+    };
+
+    RgbImage::from_raw(width, height, raw).unwrap()
+}
+
+fn diffuse(width: usize, data: &[f32]) -> Vec<u8> {
+    let stride = width * 3;
+    let mut current = vec![0.0f32; stride];
+    let mut next = vec![0.0f32; stride];
+    let mut out = Vec::with_capacity(data.len());
+
+    for row in data.chunks_exact(stride) {
+        for (x, px) in row.chunks_exact(3).enumerate() {
+            for (c, &v) in px.iter().enumerate() {
+                let i = x * 3 + c;
+                let wanted = v + current[i];
+                let q = wanted.round().clamp(0.0, 255.0);
+                let err = wanted - q;
+
+                out.push(q as u8);
+
+                if x + 1 < width {
+                    current[i + 3] += err * 7.0 / 16.0;
+                    next[i + 3] += err / 16.0;
+                }
+                if x > 0 {
+                    next[i - 3] += err * 3.0 / 16.0;
+                }
+                next[i] += err * 5.0 / 16.0;
+            }
+        }
+
+        std::mem::swap(&mut current, &mut next);
+        next.fill(0.0);
+    }
+
+    out
+}
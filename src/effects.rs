@@ -0,0 +1,119 @@
+use crate::mask::luma;
+use crate::{blur_passes, Direction, Kernel};
+use image::{Rgb, RgbImage};
+
+/// Keeps the pixels whose luma is above `threshold` and turns the rest black
+pub fn bright_pass(img: &RgbImage, threshold: u8) -> RgbImage {
+    RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let p = *img.get_pixel(x, y);
+
+        match luma(&p) > threshold as f32 {
+            true => p,
+            false => Rgb([0; 3]),
+        }
+    })
+}
+
+/// Screen-blends `light` over `base`, which brightens like projecting both images at once and
+/// never darkens
+///
+/// # Panics
+/// - If the images don't have the same dimensions
+pub fn screen(base: &RgbImage, light: &RgbImage) -> RgbImage {
+    assert_eq!(base.dimensions(), light.dimensions());
+
+    let mut out = base.clone();
+
+    for (o, l) in out.iter_mut().zip(light.iter()) {
+        *o = 255 - ((255 - *o as u16) * (255 - *l as u16) / 255) as u8;
+    }
+
+    out
+}
+
+/// Makes the bright parts of an image glow: pixels brighter than `threshold` are blurred and
+/// screen-blended back over the original
+///
+/// # Examples
+/// ```
+/// use vincent_blur::effects::bloom;
+///
+/// let mut img = image::RgbImage::from_pixel(21, 21, image::Rgb([20; 3]));
+/// img.put_pixel(10, 10, image::Rgb([255; 3]));
+///
+/// let glowing = bloom(5, 2.0, 200, &img);
+///
+/// // The light spills onto its neighbours
+/// assert!(glowing.get_pixel(11, 10)[0] > 20);
+/// assert_eq!(glowing.get_pixel(0, 0)[0], 20);
+/// ```
+pub fn bloom(radius: u8, sigma: f64, threshold: u8, img: &RgbImage) -> RgbImage {
+    let glow = blur_passes(
+        Kernel::new(radius, sigma),
+        1,
+        Direction::Both,
+        &bright_pass(img, threshold),
+    );
+
+    screen(img, &glow)
+}
+
+/// Adds Gaussian noise with a standard deviation of `amount` levels to every channel, e.g. to
+/// break up the banding heavy blurs leave in smooth gradients. The same `seed` always adds the
+/// same noise.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::effects::add_noise;
+///
+/// let mut a = image::RgbImage::from_pixel(8, 8, image::Rgb([128; 3]));
+/// let mut b = a.clone();
+///
+/// add_noise(&mut a, 1.5, 42);
+/// add_noise(&mut b, 1.5, 42);
+///
+/// assert_eq!(a, b);
+/// assert!(a.pixels().any(|p| p[0] != 128));
+/// ```
+pub fn add_noise(img: &mut RgbImage, amount: f32, seed: u64) {
+    for (v, n) in img.iter_mut().zip(gaussian_noise(seed)) {
+        *v = (*v as f32 + n * amount).round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+/// Endless standard normal samples, the same for the same `seed`
+pub(crate) fn gaussian_noise(seed: u64) -> impl Iterator<Item = f32> {
+    let mut rng = SplitMix64(seed);
+
+    std::iter::repeat_with(move || rng.gaussian())
+}
+
+/// Small deterministic generator, good enough for film grain and test images
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Uniform in (0, 1]
+    fn uniform(&mut self) -> f32 {
+        ((self.next_u64() >> 40) + 1) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Standard normal sample through the Box-Muller transform
+    fn gaussian(&mut self) -> f32 {
+        let (u, v) = (self.uniform(), self.uniform());
+
+        (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos()
+    }
+}
@@ -0,0 +1,84 @@
+use crate::cli::error::ArgError;
+use crate::locale::Message;
+use crate::tr;
+use std::fmt;
+
+/// Errors that end a blur run, each with its own process exit code so scripts can tell them
+/// apart. They display in English; [`Error::message`] keeps them translatable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Bad command line options or environment variables. Exit code 2.
+    InvalidArgs(ArgError),
+    /// The source image couldn't be read or decoded. Exit code 3.
+    Decode(Message),
+    /// The blurred image couldn't be encoded or written. Exit code 4.
+    Encode(Message),
+    /// The run was stopped before it finished. Exit code 5.
+    Cancelled,
+    /// Some files of a batch failed while others succeeded. Exit code 6.
+    PartialBatch { failed: usize, total: usize },
+    /// A worker thread panicked while blurring. Exit code 7.
+    Worker(String),
+    /// The blur didn't finish before its deadline, e.g. `--timeout`. Exit code 8.
+    Timeout,
+    /// The image is too large to blur within the limits. Exit code 9.
+    TooLarge(Message),
+    /// A redacted region can still be read, see [`audit`](crate::audit). Exit code 10.
+    Recoverable(Message),
+    /// A backend disagrees with the others, see [`self_test`](crate::backend::self_test).
+    /// Exit code 11.
+    Mismatch(Message),
+}
+
+impl Error {
+    /// Process exit code for this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::InvalidArgs(_) => 2,
+            Error::Decode(_) => 3,
+            Error::Encode(_) => 4,
+            Error::Cancelled => 5,
+            Error::PartialBatch { .. } => 6,
+            Error::Worker(_) => 7,
+            Error::Timeout => 8,
+            Error::TooLarge(_) => 9,
+            Error::Recoverable(_) => 10,
+            Error::Mismatch(_) => 11,
+        }
+    }
+
+    /// What went wrong, to be translated where it is shown
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::locale::{self, Catalog};
+    /// use vincent_blur::Error;
+    ///
+    /// locale::set_catalog(Catalog::load("fr", None).unwrap());
+    ///
+    /// assert_eq!(Error::Cancelled.to_string(), "Cancelled");
+    /// assert_eq!(Error::Cancelled.message().translate(), "Annulé");
+    /// ```
+    pub fn message(&self) -> Message {
+        match self {
+            Error::InvalidArgs(e) => e.message.clone(),
+            Error::Decode(m)
+            | Error::Encode(m)
+            | Error::TooLarge(m)
+            | Error::Recoverable(m)
+            | Error::Mismatch(m) => m.clone(),
+            Error::Worker(e) => tr!("worker-failed", e),
+            Error::Cancelled => "Cancelled".into(),
+            Error::Timeout => "Timed out".into(),
+            Error::PartialBatch { failed, total } => tr!("files-failed", failed, total),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,288 @@
+use crate::audit::Recovery;
+use crate::control::Control;
+use crate::denoise::{DenoiseSettings, NoiseEstimate};
+use crate::locale::{tr, Message};
+use crate::region::Rect;
+use crate::tr;
+use crate::Error;
+use image::RgbImage;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Something the library reports while it works, instead of printing it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A 2D blur started on a `width`x`height` image, taking `calculations` multiply-adds
+    Started {
+        width: u32,
+        height: u32,
+        calculations: u128,
+    },
+    /// Another tenth of a 2D blur is done
+    Progress { percent: u32 },
+    /// A 2D blur is done
+    Finished,
+    /// A file of a batch run with `keep_going` failed, and the others carry on
+    FileFailed { path: PathBuf, error: Message },
+    /// A redacted region was checked for detail that can still be recovered, see
+    /// [`audit::run`](crate::audit::run)
+    RegionChecked(Recovery),
+    /// A source is about to be denoised with the settings its noise calls for, see
+    /// [`denoise::run`](crate::denoise::run)
+    Denoising {
+        path: PathBuf,
+        noise: NoiseEstimate,
+        settings: DenoiseSettings,
+    },
+}
+
+/// Receives the [`Event`]s of every blur, from whichever thread runs it
+pub trait EventSink: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+/// Ignores every event, the default sink
+pub struct Silent;
+
+impl EventSink for Silent {
+    fn event(&self, _: &Event) {}
+}
+
+/// Writes the events to stderr, the way the `blur` binary reports progress, translated with
+/// the current [catalog](crate::locale)
+pub struct Console;
+
+impl EventSink for Console {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::Started {
+                width,
+                height,
+                calculations,
+            } => {
+                eprintln!("{}", tr!("image-dimensions", width, height).translate());
+                eprintln!("{}", tr!("calculations", calculations).translate());
+            }
+            Event::Progress { percent } => {
+                eprintln!("{}", tr!("percent-done", percent).translate())
+            }
+            Event::Finished => eprintln!("{}", tr("Done!")),
+            Event::FileFailed { path, error } => {
+                eprintln!("{} {}: {}", tr("Error:"), path.display(), error.translate())
+            }
+            Event::RegionChecked(recovery) => {
+                let (rect, score) = (recovery.rect, format!("{:.2}", recovery.score));
+                let message = match (recovery.is_recoverable(), recovery.sigma) {
+                    (true, Some(sigma)) => tr!("region-readable", rect, sigma, score),
+                    (true, None) => tr!("region-readable-as-is", rect, score),
+                    (false, Some(sigma)) => tr!("region-safe", rect, sigma, score),
+                    (false, None) => tr!("region-safe-as-is", rect, score),
+                };
+
+                eprintln!("{}", message.translate());
+            }
+            Event::Denoising {
+                path,
+                noise,
+                settings,
+            } => {
+                let (path, luma, chroma) = (
+                    path.display(),
+                    format!("{:.1}", noise.luma),
+                    format!("{:.1}", noise.chroma),
+                );
+                let message = match settings {
+                    DenoiseSettings::Surface(p) => tr!(
+                        "denoise-surface",
+                        path,
+                        luma,
+                        chroma,
+                        p.radius,
+                        p.sigma,
+                        p.threshold,
+                        format!("{:.1}", p.chroma_sigma)
+                    ),
+                    DenoiseSettings::NlMeans(p) => tr!(
+                        "denoise-nl-means",
+                        path,
+                        luma,
+                        chroma,
+                        p.patch_radius,
+                        p.search_radius,
+                        format!("{:.1}", p.h)
+                    ),
+                };
+
+                eprintln!("{}", message.translate());
+            }
+        }
+    }
+}
+
+/// Forwards the events to the [`log`](crate::logging) facade, in English, for services that
+/// collect logs rather than read stderr
+#[cfg(feature = "log")]
+pub struct Log;
+
+#[cfg(feature = "log")]
+impl EventSink for Log {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::Started {
+                width,
+                height,
+                calculations,
+            } => log::info!("started width={width} height={height} calculations={calculations}"),
+            Event::Progress { percent } => log::debug!("progress percent={percent}"),
+            Event::Finished => log::info!("finished"),
+            Event::FileFailed { path, error } => {
+                log::error!(
+                    "failed path={} error={:?}",
+                    path.display(),
+                    error.to_string()
+                )
+            }
+            Event::RegionChecked(r) => match r.is_recoverable() {
+                true => log::warn!(
+                    "readable rect={} score={} sigma={:?}",
+                    r.rect,
+                    r.score,
+                    r.sigma
+                ),
+                false => log::info!("safe rect={} score={} sigma={:?}", r.rect, r.score, r.sigma),
+            },
+            Event::Denoising {
+                path,
+                noise,
+                settings,
+            } => log::info!(
+                "denoising path={} luma={} chroma={} settings={settings:?}",
+                path.display(),
+                noise.luma,
+                noise.chroma
+            ),
+        }
+    }
+}
+
+impl<T: EventSink> EventSink for &T {
+    fn event(&self, event: &Event) {
+        (**self).event(event);
+    }
+}
+
+static SINK: RwLock<Option<Arc<dyn EventSink>>> = RwLock::new(None);
+
+/// Sends every following event to `sink` instead of the current one
+///
+/// # Examples
+/// ```
+/// use std::sync::Mutex;
+/// use vincent_blur::events::{self, Event, EventSink};
+///
+/// struct Collect(Mutex<Vec<Event>>);
+///
+/// impl EventSink for Collect {
+///     fn event(&self, event: &Event) {
+///         self.0.lock().unwrap().push(event.clone());
+///     }
+/// }
+///
+/// static SEEN: Collect = Collect(Mutex::new(Vec::new()));
+/// events::set_sink(&SEEN);
+///
+/// vincent_blur::blur_sync(1, 1.0, image::RgbImage::new(4, 3));
+///
+/// assert_eq!(SEEN.0.lock().unwrap().last(), Some(&Event::Finished));
+/// ```
+pub fn set_sink(sink: impl EventSink + 'static) {
+    *SINK.write().unwrap() = Some(Arc::new(sink));
+}
+
+/// Hands `event` to the installed sink, if any
+pub(crate) fn emit(event: Event) {
+    let sink = SINK.read().unwrap().clone();
+
+    if let Some(sink) = sink {
+        sink.event(&event);
+    }
+}
+
+/// What a blur started with [`blur_async_events`](crate::blur_async_events) reports as it goes
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlurEvent {
+    /// Always the first event
+    Started { width: u32, height: u32 },
+    /// The pixels of `rect` are final
+    TileDone { rect: Rect },
+    /// Another tenth of the image is done
+    Progress { percent: u32 },
+    /// A worker panicked, so the blur will fail
+    WorkerError { message: String },
+    /// Always the last event when every worker succeeded
+    Finished { stats: BlurStats },
+}
+
+/// Summary of a finished blur
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlurStats {
+    pub elapsed: Duration,
+    /// Multiply-adds of the 2D kernel
+    pub calculations: u128,
+    pub n_threads: usize,
+    pub tiles: usize,
+}
+
+/// A blur running in the background, see [`blur_async_events`](crate::blur_async_events)
+pub struct BlurTask {
+    pub(crate) events: Receiver<BlurEvent>,
+    pub(crate) handle: JoinHandle<Result<RgbImage, Error>>,
+    pub(crate) control: Control,
+}
+
+impl BlurTask {
+    /// Events in the order they happened. The channel closes once the blur is over.
+    pub fn events(&self) -> &Receiver<BlurEvent> {
+        &self.events
+    }
+
+    /// A handle to pause, resume or resize the blur while it runs
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::events::BlurEvent;
+    /// use vincent_blur::{blur_async, blur_async_events};
+    ///
+    /// let img = image::RgbImage::from_fn(24, 64, |x, y| image::Rgb([(x * 9) as u8, (y * 3) as u8, 0]));
+    /// let task = blur_async_events(2, 1.5, 2, img.clone());
+    /// let control = task.control();
+    ///
+    /// control.pause();
+    /// control.set_threads(3);
+    /// control.resume();
+    ///
+    /// let stats = task.events().iter().find_map(|e| match e {
+    ///     BlurEvent::Finished { stats } => Some(stats),
+    ///     _ => None,
+    /// });
+    /// assert!(stats.is_some());
+    ///
+    /// assert_eq!(task.wait().unwrap(), blur_async(2, 1.5, 2, img));
+    /// ```
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// Waits for the blurred image
+    ///
+    /// # Errors
+    /// - [`Error::Worker`] if a worker panicked
+    pub fn wait(self) -> Result<RgbImage, Error> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(Error::Worker("The blur thread panicked".to_string())))
+    }
+}
@@ -0,0 +1,107 @@
+use crate::timings::{self, Stage};
+use image::RgbImage;
+
+/// Fractional bits of the kernel weights
+const WEIGHT_BITS: u32 = 16;
+
+/// Fractional bits kept between the horizontal and vertical passes
+const FRACTION_BITS: u32 = 8;
+
+/// The weights in 16.16 fixed point, scaled so they add up to at most 1.0. Every accumulator
+/// then stays below `255 << 8` times 1.0, which fits in a `u32`.
+pub(crate) fn weights(weights: &[f32]) -> Vec<u32> {
+    let sum: f64 = weights.iter().map(|&w| w as f64).sum();
+
+    weights
+        .iter()
+        .map(|&w| (w as f64 / sum * (1 << WEIGHT_BITS) as f64) as u32)
+        .collect()
+}
+
+/// Sum of the weights whose taps land inside `0..len` for every position, like the float passes
+fn edge_norms(len: usize, weights: &[u32]) -> Vec<u32> {
+    let r = weights.len() / 2;
+
+    (0..len)
+        .map(|i| {
+            let lo = r.saturating_sub(i);
+            let hi = (len + r - i).min(weights.len());
+            weights[lo..hi].iter().sum::<u32>().max(1)
+        })
+        .collect()
+}
+
+/// Blurs an 8-bit image with a horizontal then a vertical pass of `weights` using only integer
+/// arithmetic. The horizontal pass keeps 8 fractional bits for the vertical one, so the result
+/// is within 1 of the float passes.
+pub(crate) fn blur(img: &RgbImage, weights: &[u32]) -> RgbImage {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let r = weights.len() / 2;
+    let stride = width * 3;
+
+    let mut rows = vec![0u16; img.as_raw().len()];
+    let mut acc = vec![0u32; stride];
+    let norms = edge_norms(width, weights);
+
+    timings::time(Stage::Horizontal, || {
+        for (src_row, dst_row) in img
+            .as_raw()
+            .chunks_exact(stride)
+            .zip(rows.chunks_exact_mut(stride))
+        {
+            acc.fill(0);
+
+            for (k, &w) in weights.iter().enumerate() {
+                let lo = r.saturating_sub(k);
+                let hi = (width + r).saturating_sub(k).min(width);
+
+                if lo >= hi {
+                    continue;
+                }
+
+                let s = &src_row[(lo + k - r) * 3..(hi + k - r) * 3];
+
+                for (a, &s) in acc[lo * 3..hi * 3].iter_mut().zip(s) {
+                    *a += w * s as u32;
+                }
+            }
+
+            for ((d, a), norm) in dst_row
+                .chunks_exact_mut(3)
+                .zip(acc.chunks_exact(3))
+                .zip(&norms)
+            {
+                for (d, a) in d.iter_mut().zip(a) {
+                    *d = ((a << FRACTION_BITS) / norm) as u16;
+                }
+            }
+        }
+    });
+
+    let mut out = RgbImage::new(img.width(), img.height());
+    let norms = edge_norms(height, weights);
+
+    timings::time(Stage::Vertical, || {
+        for (y, dst_row) in out.chunks_exact_mut(stride).enumerate() {
+            acc.fill(0);
+
+            for (k, &w) in weights.iter().enumerate() {
+                if y + k < r || y + k - r >= height {
+                    continue;
+                }
+
+                let sy = y + k - r;
+
+                for (a, &s) in acc.iter_mut().zip(&rows[sy * stride..(sy + 1) * stride]) {
+                    *a += w * s as u32;
+                }
+            }
+
+            for (d, a) in dst_row.iter_mut().zip(&acc) {
+                *d = ((a / norms[y]) >> FRACTION_BITS) as u8;
+            }
+        }
+    });
+
+    out
+}
@@ -0,0 +1,122 @@
+use crate::region::{self, Rect, Shape};
+use crate::{backend, codec, mask, ops, Error, Kernel};
+use arbitrary::Arbitrary;
+use image::{Rgb, RgbImage};
+use std::path::Path;
+
+/// Limits every entry point decodes with, so a fuzzer spends its time on many small inputs
+/// instead of a few huge ones
+const FUZZ_LIMITS: codec::DecodeOptions = codec::DecodeOptions {
+    raster_size: Some((256, 256)),
+    max_dimension: Some(256),
+    max_pixels: Some(256 * 256),
+    max_alloc: Some(64 << 20),
+};
+
+/// Arbitrary bytes decoded as an image, blurred with arbitrary parameters by one of the
+/// backends of this build
+#[derive(Debug, Arbitrary)]
+pub struct BlurInput<'a> {
+    pub radius: u8,
+    pub sigma: f64,
+    /// Index into [`backend::all`], wrapping around
+    pub backend: u8,
+    pub image: &'a [u8],
+}
+
+/// An `--ops` expression run on a small synthetic image
+#[derive(Debug, Arbitrary)]
+pub struct OpsInput<'a> {
+    pub expr: &'a str,
+    pub width: u8,
+    pub height: u8,
+}
+
+/// Decodes `data` the way the `blur` binary decodes a source
+///
+/// # Errors
+/// - [`Error::Decode`] if `data` isn't an image or exceeds the fuzzing limits
+///
+/// # Examples
+/// ```
+/// use vincent_blur::fuzz_targets;
+///
+/// assert!(fuzz_targets::decode(b"P3 1 1 255 0 0 0").is_ok());
+/// assert!(fuzz_targets::decode(&[0xff; 16]).is_err());
+/// ```
+pub fn decode(data: &[u8]) -> Result<RgbImage, Error> {
+    codec::decode(data.to_vec(), Path::new("fuzz"), &FUZZ_LIMITS).map_err(Error::Decode)
+}
+
+/// Decodes `input.image` and blurs it with the chosen backend
+///
+/// # Errors
+/// - [`Error::Decode`] if the image can't be decoded, see [`decode`]
+/// - [`Error::InvalidArgs`] if the radius is 0 or the sigma isn't a positive number
+pub fn blur(input: BlurInput) -> Result<RgbImage, Error> {
+    if input.radius == 0 || !(input.sigma.is_finite() && input.sigma > 0.0) {
+        return Err(Error::InvalidArgs(
+            format!(
+                "Expected a positive radius and sigma, got {} and {}",
+                input.radius, input.sigma
+            )
+            .into(),
+        ));
+    }
+
+    let img = decode(input.image)?;
+    let backends = backend::all(2);
+    let backend = &backends[input.backend as usize % backends.len()];
+
+    Ok(backend.blur(Kernel::new(input.radius, input.sigma), img))
+}
+
+/// Parses `input.expr` with [`ops::parse`] and runs it on a gradient of the given size
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if the expression doesn't parse
+/// - Any error of [`Pipeline::run`](crate::pipeline::Pipeline::run)
+pub fn ops(input: OpsInput) -> Result<RgbImage, Error> {
+    let pipeline = ops::parse(input.expr).map_err(|e| Error::InvalidArgs(e.to_string().into()))?;
+
+    let img = RgbImage::from_fn(
+        input.width.max(1) as u32,
+        input.height.max(1) as u32,
+        |x, y| Rgb([x as u8, y as u8, (x ^ y) as u8]),
+    );
+
+    pipeline.run(&img)
+}
+
+/// Parses `src` with every region parser, as a `--regions-file`, a `--region` rectangle or
+/// geometry, and a shape, resolving percentages against a small image and building its mask
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if none of the parsers accepts `src`
+pub fn regions(src: &str) -> Result<mask::Mask, Error> {
+    let (width, height) = (64, 48);
+
+    let mut rects: Vec<Rect> = region::parse_json(src).unwrap_or_default();
+    rects.extend(region::parse_csv(src).unwrap_or_default());
+
+    let resolved = region::Relative::Region(src.to_string()).resolve(width, height);
+    rects.extend(resolved.parse::<Rect>().ok());
+
+    let mut shapes = Vec::new();
+    for relative in [
+        region::Relative::Circle(src.to_string()),
+        region::Relative::Ellipse(src.to_string()),
+        region::Relative::Polygon(src.to_string()),
+    ] {
+        let resolved = relative.resolve(width, height);
+        shapes.extend(Shape::circle(&resolved).ok());
+        shapes.extend(Shape::ellipse(&resolved).ok());
+        shapes.extend(Shape::polygon(&resolved).ok());
+    }
+
+    if rects.is_empty() && shapes.is_empty() {
+        return Err(Error::InvalidArgs(format!("No region in {src:?}").into()));
+    }
+
+    Ok(mask::rects(width, height, &rects).union(&mask::shapes(width, height, &shapes)))
+}
@@ -0,0 +1,266 @@
+use crate::region::Rect;
+use crate::tr;
+use crate::{Algorithm, Error};
+use eframe::egui;
+use image::RgbImage;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Options that can be tuned in the window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub radius: u8,
+    pub sigma: f64,
+    pub algorithm: Algorithm,
+    /// Rectangles to blur, in pixels of the output
+    pub regions: Vec<Rect>,
+    /// Rectangles to leave sharp, in pixels of the output
+    pub keep_regions: Vec<Rect>,
+}
+
+impl Settings {
+    /// Command line options that reproduce the settings
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::gui::Settings;
+    /// use vincent_blur::region::Rect;
+    /// use vincent_blur::Algorithm;
+    ///
+    /// let settings = Settings {
+    ///     radius: 12,
+    ///     sigma: 4.5,
+    ///     algorithm: Algorithm::Gaussian,
+    ///     regions: vec![Rect::new(10, 20, 100, 50)],
+    ///     keep_regions: vec![Rect::new(30, 30, 8, 8)],
+    /// };
+    ///
+    /// assert_eq!(
+    ///     settings.flags(),
+    ///     "-r 12 -s 4.5 -a gaussian --region 10,20,100,50 --keep-region 30,30,8,8"
+    /// );
+    /// ```
+    pub fn flags(&self) -> String {
+        let mut flags = vec![
+            format!("-r {}", self.radius),
+            format!("-s {}", self.sigma),
+            format!("-a {}", self.algorithm),
+        ];
+        flags.extend(self.regions.iter().map(|r| format!("--region {r}")));
+        flags.extend(
+            self.keep_regions
+                .iter()
+                .map(|r| format!("--keep-region {r}")),
+        );
+
+        flags.join(" ")
+    }
+}
+
+/// Opens a window showing `render(settings)` with sliders for the radius, sigma and algorithm.
+/// Dragging on the image adds a region to blur or keep. `render` also returns the size in its
+/// image of one pixel of the output, to convert the regions. Returns the settings when the user
+/// presses save.
+///
+/// # Errors
+/// - [`Error::Cancelled`] if the window is closed without saving
+/// - If the window can't be opened
+pub fn tune<F>(title: &str, settings: Settings, render: F) -> Result<Settings, Error>
+where
+    F: Fn(&Settings) -> Result<(RgbImage, f64), Error> + 'static,
+{
+    let chosen = Rc::new(RefCell::new(None));
+
+    let app = TuneApp {
+        settings,
+        shown: None,
+        texture: None,
+        scale: 1.0,
+        error: None,
+        keep: false,
+        drag_start: None,
+        render,
+        chosen: Rc::clone(&chosen),
+    };
+
+    eframe::run_native(
+        title,
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(app))),
+    )
+    .map_err(|e| Error::InvalidArgs(tr!("window-failed", e).into()))?;
+
+    let chosen = chosen.borrow_mut().take();
+    chosen.ok_or(Error::Cancelled)
+}
+
+struct TuneApp<F> {
+    settings: Settings,
+    /// Settings of the image in `texture`
+    shown: Option<Settings>,
+    texture: Option<egui::TextureHandle>,
+    /// Size in `texture` of one pixel of the output
+    scale: f64,
+    error: Option<String>,
+    /// Whether dragging adds a region to keep rather than one to blur
+    keep: bool,
+    /// Where the current drag started, in pixels of the output
+    drag_start: Option<egui::Pos2>,
+    render: F,
+    chosen: Rc<RefCell<Option<Settings>>>,
+}
+
+impl<F> TuneApp<F>
+where
+    F: Fn(&Settings) -> Result<(RgbImage, f64), Error>,
+{
+    fn refresh(&mut self, ctx: &egui::Context) {
+        if self.shown.as_ref() == Some(&self.settings) {
+            return;
+        }
+        self.shown = Some(self.settings.clone());
+
+        match (self.render)(&self.settings) {
+            Ok((img, scale)) => {
+                let size = [img.width() as usize, img.height() as usize];
+                let pixels = egui::ColorImage::from_rgb(size, img.as_raw());
+
+                self.texture = Some(ctx.load_texture("preview", pixels, Default::default()));
+                self.scale = scale;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    /// Shows the image and turns drags on it into regions
+    fn image(&mut self, ui: &mut egui::Ui) {
+        let Some(texture) = &self.texture else {
+            return;
+        };
+
+        let response = ui.add(
+            egui::Image::new(texture)
+                .shrink_to_fit()
+                .sense(egui::Sense::drag()),
+        );
+        let shown = response.rect;
+
+        // Screen points per output pixel
+        let zoom = shown.width() / texture.size()[0] as f32 * self.scale as f32;
+        let to_output = |p: egui::Pos2| {
+            let p = ((p - shown.min) / zoom).to_pos2();
+            egui::pos2(p.x.max(0.0), p.y.max(0.0))
+        };
+        let to_screen = |r: &Rect| {
+            egui::Rect::from_min_size(
+                shown.min + egui::vec2(r.x as f32, r.y as f32) * zoom,
+                egui::vec2(r.width as f32, r.height as f32) * zoom,
+            )
+        };
+
+        let painter = ui.painter_at(shown);
+        let stroke = |color| egui::Stroke::new(2.0, color);
+
+        for r in &self.settings.regions {
+            painter.rect_stroke(to_screen(r), 0.0, stroke(egui::Color32::LIGHT_BLUE));
+        }
+        for r in &self.settings.keep_regions {
+            painter.rect_stroke(to_screen(r), 0.0, stroke(egui::Color32::LIGHT_GREEN));
+        }
+
+        let pointer = response.interact_pointer_pos().map(to_output);
+
+        if response.drag_started() {
+            self.drag_start = pointer;
+        }
+
+        let Some(start) = self.drag_start else {
+            return;
+        };
+
+        let dragged = pointer.map(|end| {
+            let (min, max) = (start.min(end), start.max(end));
+            Rect::new(
+                min.x.round() as u32,
+                min.y.round() as u32,
+                ((max.x - min.x).round() as u32).max(1),
+                ((max.y - min.y).round() as u32).max(1),
+            )
+        });
+
+        if let Some(r) = &dragged {
+            painter.rect_stroke(to_screen(r), 0.0, stroke(egui::Color32::WHITE));
+        }
+
+        if response.drag_stopped() {
+            self.drag_start = None;
+
+            match (dragged, self.keep) {
+                (Some(r), false) => self.settings.regions.push(r),
+                (Some(r), true) => self.settings.keep_regions.push(r),
+                (None, _) => {}
+            }
+        }
+    }
+}
+
+impl<F> eframe::App for TuneApp<F>
+where
+    F: Fn(&Settings) -> Result<(RgbImage, f64), Error>,
+{
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::SidePanel::left("settings").show(ctx, |ui| {
+            ui.add(egui::Slider::new(&mut self.settings.radius, 1..=u8::MAX).text("radius"));
+            ui.add(
+                egui::Slider::new(&mut self.settings.sigma, 0.5..=100.0)
+                    .logarithmic(true)
+                    .text("sigma"),
+            );
+
+            egui::ComboBox::from_label("algorithm")
+                .selected_text(self.settings.algorithm.to_string())
+                .show_ui(ui, |ui| {
+                    for algorithm in [Algorithm::Gaussian, Algorithm::Surface] {
+                        ui.selectable_value(
+                            &mut self.settings.algorithm,
+                            algorithm,
+                            algorithm.to_string(),
+                        );
+                    }
+                });
+
+            ui.separator();
+
+            ui.label("Drag on the image to add a region to");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.keep, false, "blur");
+                ui.radio_value(&mut self.keep, true, "keep");
+            });
+            if ui.button("Clear regions").clicked() {
+                self.settings.regions.clear();
+                self.settings.keep_regions.clear();
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    *self.chosen.borrow_mut() = Some(self.settings.clone());
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                if ui.button("Cancel").clicked() {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+
+            if let Some(e) = &self.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        });
+
+        self.refresh(ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| self.image(ui));
+    }
+}
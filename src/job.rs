@@ -0,0 +1,286 @@
+use crate::cli::{self, error::ArgError};
+use crate::dither::Dither;
+use crate::locale::Message;
+use crate::pipeline::Pipeline;
+use crate::tr;
+use crate::{batch, codec, ops, Error};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// CLI options of the `run` subcommand
+pub struct RunOpts {
+    /// Path of the job file
+    pub job: PathBuf,
+    pub json_summary: bool,
+}
+
+impl RunOpts {
+    /// Constructs a new RunOpts from CLI options, skipping the program name and the `run`
+    /// subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If no job file or more than one is given
+    pub fn new(cli_opts: env::Args) -> Result<RunOpts, ArgError> {
+        let mut job: Option<PathBuf> = None;
+        let mut json_summary = false;
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--summary" => {
+                    json_summary = match cli_opts.next().as_deref() {
+                        Some("text") => false,
+                        Some("json") => true,
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                "--summary",
+                                "Expected text or json after --summary",
+                            ))
+                        }
+                    };
+                }
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur run [--summary <text|json>] <job> [--help|-h]\n",
+                        "   <job>               TOML file listing the images to blur and the",
+                        "                       operations to run on them. Paths in it are relative",
+                        "                       to the file.",
+                        "   --summary           Print the report as text or json. Default is text.",
+                        "   -h, --help          Print help.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ if job.is_none() => job = Some(PathBuf::from(arg)),
+                _ => return Err(cli_opts.invalid(tr!("unexpected-argument", arg))),
+            }
+        }
+
+        Ok(RunOpts {
+            job: job.ok_or("Expected a job file")?,
+            json_summary,
+        })
+    }
+}
+
+/// A reproducible batch recipe: which images to blur and the operations to run on them
+///
+/// ```toml
+/// format = "jpg"
+/// dither = "ordered"
+/// jobs = 4
+///
+/// ops = [
+///     "linear",
+///     { stage = "gaussian", r = 8, s = 3 },
+///     "srgb",
+///     { stage = "tint", color = "000000", opacity = 0.35 },
+/// ]
+///
+/// [[images]]
+/// input = "photos/beach.png"
+/// output = "out/beach.jpg"
+/// ```
+///
+/// `ops` is a list of stages, each written as in `--ops` or as a table naming the `stage` and
+/// its arguments, or a single `--ops` expression. See [`ops::parse`] for the stages.
+pub struct Job {
+    /// Source/destination pairs, resolved against the job file's directory
+    pub images: Vec<(PathBuf, PathBuf)>,
+    pub pipeline: Pipeline,
+    pub encode_opts: codec::EncodeOptions,
+    pub force: bool,
+    pub keep_going: bool,
+    pub jobs: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    #[serde(default)]
+    images: Vec<ImageEntry>,
+    ops: Ops,
+    dither: Option<String>,
+    format: Option<String>,
+    #[serde(default)]
+    progressive: bool,
+    #[serde(default)]
+    interlaced: bool,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    keep_going: bool,
+    jobs: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageEntry {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Ops {
+    Expression(String),
+    Stages(Vec<Stage>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stage {
+    Expression(String),
+    Table(toml::Table),
+}
+
+impl Stage {
+    /// The stage as an `--ops` expression
+    fn expression(&self) -> Result<String, Message> {
+        let table = match self {
+            Stage::Expression(e) => return Ok(e.clone()),
+            Stage::Table(t) => t,
+        };
+
+        let name = match table.get("stage") {
+            Some(toml::Value::String(s)) => s,
+            _ => return Err("Expected a stage name in every operation table".into()),
+        };
+
+        let args = table
+            .iter()
+            .filter(|(k, _)| *k != "stage")
+            .map(|(k, v)| match v {
+                toml::Value::String(s) => Ok(format!("{k}={s}")),
+                toml::Value::Integer(i) => Ok(format!("{k}={i}")),
+                toml::Value::Float(f) => Ok(format!("{k}={f}")),
+                _ => Err(tr!("stage-value", k, name)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(format!("{name}({})", args.join(",")))
+    }
+}
+
+impl Job {
+    /// Reads and parses a job file
+    ///
+    /// # Errors
+    /// - [`Error::InvalidArgs`] if the file can't be read or isn't a valid job
+    pub fn load(path: &Path) -> Result<Job, Error> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| Error::InvalidArgs(tr!("read-failed", path.display(), e).into()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+
+        Job::parse(&src, base)
+            .map_err(|e| Error::InvalidArgs(tr!("invalid-job", path.display()).with(e).into()))
+    }
+
+    /// Parses a job, resolving relative image paths against `base`
+    ///
+    /// # Examples
+    /// ```
+    /// use std::path::Path;
+    /// use vincent_blur::job::Job;
+    ///
+    /// let job = Job::parse(
+    ///     r#"
+    ///     ops = ["gaussian(r=8,s=3)", { stage = "tint", color = "000000", opacity = 0.35 }]
+    ///
+    ///     [[images]]
+    ///     input = "a.png"
+    ///     output = "out/a.png"
+    ///     "#,
+    ///     Path::new("recipes"),
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(job.pipeline.len(), 2);
+    /// assert_eq!(job.images[0].1, Path::new("recipes/out/a.png"));
+    /// ```
+    ///
+    /// # Errors
+    /// - If the TOML is invalid or has unknown keys
+    /// - If an operation is invalid, see [`ops::parse`]
+    pub fn parse(src: &str, base: &Path) -> Result<Job, Message> {
+        let file: JobFile = toml::from_str(src).map_err(|e| e.to_string())?;
+
+        let expressions = match file.ops {
+            Ops::Expression(e) => vec![e],
+            Ops::Stages(stages) => stages
+                .iter()
+                .map(Stage::expression)
+                .collect::<Result<_, _>>()?,
+        };
+
+        let dither: Dither = file.dither.as_deref().unwrap_or("none").parse()?;
+        let mut pipeline = Pipeline::new().dither(dither);
+
+        for (i, e) in expressions.iter().enumerate() {
+            let stages = ops::parse(e)
+                .map_err(|err| tr!("invalid-operation", i + 1, err.pointer(e)).with(err.into()))?;
+            pipeline.append(stages);
+        }
+
+        if let Some(f) = &file.format {
+            if !codec::is_supported(f) {
+                return Err(tr!("unsupported-format", f));
+            }
+        }
+
+        Ok(Job {
+            images: file
+                .images
+                .into_iter()
+                .map(|i| (base.join(i.input), base.join(i.output)))
+                .collect(),
+            pipeline,
+            encode_opts: codec::EncodeOptions {
+                format: file.format.map(|f| f.to_lowercase()),
+                progressive: file.progressive,
+                interlaced: file.interlaced,
+            },
+            force: file.force,
+            keep_going: file.keep_going,
+            jobs: file.jobs.unwrap_or(1).max(1),
+        })
+    }
+}
+
+/// Runs the job's pipeline on every image, `jobs` at a time
+///
+/// # Errors
+/// - Same as [`batch::run`]
+pub fn run(job: Job) -> Result<batch::Report, Error> {
+    let Job {
+        images,
+        pipeline,
+        encode_opts,
+        force,
+        keep_going,
+        jobs,
+    } = job;
+    let pipeline = Arc::new(pipeline);
+
+    batch::run(&images, jobs, keep_going, move |original, blurred| {
+        if !force && blurred != Path::new(codec::STDIO) && blurred.exists() {
+            return Err(Error::InvalidArgs(
+                tr!("already-exists-in-job", blurred.display()).into(),
+            ));
+        }
+
+        let img = codec::open(original, &codec::DecodeOptions::default()).map_err(Error::Decode)?;
+        let img = pipeline.run(&img)?;
+
+        codec::save(&img, blurred, &encode_opts).map_err(Error::Encode)
+    })
+}
@@ -0,0 +1,173 @@
+use crate::backend::TOLERANCE;
+use crate::locale::Message;
+use crate::metrics::radius_for;
+use crate::separable;
+use crate::testgen::{self, Pattern};
+use crate::tr;
+use grid::Grid;
+use image::RgbImage;
+
+/// Unnormalized weight of the taps `d` away from the center along one axis.
+///
+/// When `faded`, i.e. when the radius was derived from sigma, the outermost taps are scaled
+/// by how far 3 sigma reaches into them, so they fade in as sigma grows instead of appearing
+/// at full weight. The blur then changes smoothly with a fractional sigma, e.g. when an
+/// animation ramps it up frame by frame, rather than stepping each time the radius grows.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::kernel::tap;
+///
+/// let gaussian = (-16.0 / 3.125_f64).exp();
+///
+/// // 3 sigma reaches three quarters into the taps 4 away from the center
+/// assert!((tap(4, 1.25, true) / gaussian - 0.75).abs() < 1e-9);
+/// // The weights are continuous as the radius grows from 3 to 4
+/// assert!(tap(4, 1.0 + 1e-9, true) < 1e-9);
+/// // A radius given explicitly keeps every tap whole
+/// assert_eq!(tap(4, 1.25, false), gaussian);
+/// ```
+pub fn tap(d: i32, sigma: f64, faded: bool) -> f64 {
+    let fade = match faded {
+        true => (sigma * 3.0 + 1.0 - d.abs() as f64).clamp(0.0, 1.0),
+        false => 1.0,
+    };
+
+    (-((d * d) as f64) / (2.0 * sigma * sigma)).exp() * fade
+}
+
+/// A Gaussian kernel of `2 * radius + 1` taps on each axis, as every backend applies it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Kernel {
+    pub radius: u8,
+    pub sigma: f64,
+    /// Whether the radius was derived from sigma, which fades the outer taps in
+    pub faded: bool,
+}
+
+impl Kernel {
+    pub fn new(radius: u8, sigma: f64) -> Self {
+        Kernel {
+            radius,
+            sigma,
+            faded: false,
+        }
+    }
+
+    /// The kernel of `sigma` with the radius [`radius_for`] derives from it, as `--sigma`
+    /// without `--radius` blurs
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::kernel::Kernel;
+    ///
+    /// // The outer taps of a derived radius fade in, those of an explicit one don't
+    /// let (derived, explicit) = (Kernel::derived(1.25), Kernel::new(4, 1.25));
+    /// assert_eq!(derived.radius, explicit.radius);
+    /// assert!(derived.weight(4, 0) < explicit.weight(4, 0));
+    /// let ratio = |k: Kernel| k.weight(0, 4) / k.weight(0, 3);
+    /// assert!((ratio(derived) / ratio(explicit) - 0.75).abs() < 1e-9);
+    /// ```
+    pub fn derived(sigma: f64) -> Self {
+        Kernel {
+            faded: true,
+            ..Kernel::new(radius_for(sigma), sigma)
+        }
+    }
+
+    /// Weight of the tap at `dx, dy` from the center, normalized so all the taps add up to 1.
+    /// Taps past the radius weigh 0.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::kernel::Kernel;
+    ///
+    /// let kernel = Kernel::new(2, 1.0);
+    /// let total: f64 = (-2..=2).flat_map(|x| (-2..=2).map(move |y| kernel.weight(x, y))).sum();
+    ///
+    /// assert!((total - 1.0).abs() < 1e-9);
+    /// assert!(kernel.weight(0, 0) > kernel.weight(1, 0));
+    /// assert_eq!(kernel.weight(3, 0), 0.0);
+    /// ```
+    pub fn weight(&self, dx: i32, dy: i32) -> f64 {
+        let r = self.radius as i32;
+
+        if dx.abs() > r || dy.abs() > r {
+            return 0.0;
+        }
+
+        let total: f64 = (-r..=r).map(|d| self.gaussian(d)).sum();
+
+        self.gaussian(dx) * self.gaussian(dy) / (total * total)
+    }
+
+    fn gaussian(&self, d: i32) -> f64 {
+        tap(d, self.sigma, self.faded)
+    }
+
+    /// The same kernel with another standard deviation, e.g. for one channel of
+    /// [`blur_channels_passes`](crate::blur_channels_passes)
+    pub fn with_sigma(&self, sigma: f64) -> Kernel {
+        Kernel { sigma, ..*self }
+    }
+
+    /// The weights of one axis, for the separable passes
+    pub(crate) fn weights(&self) -> Vec<f32> {
+        separable::gaussian_weights(self.radius as usize, self.sigma, self.faded)
+    }
+
+    /// The weights of the full 2D kernel, for the backends that convolve pixel by pixel
+    pub(crate) fn matrix(&self) -> Grid<f64> {
+        crate::get_gaussian_matrix(self.radius, self.sigma, self.faded)
+    }
+
+    /// Blurs a black image with one white pixel in the middle through `blur`, which gets the
+    /// kernel and image, and checks that every pixel comes out as 255 times the weight
+    /// of its offset from the white one, within [`TOLERANCE`]. The image leaves a radius of
+    /// margin around the kernel, so no border handling reaches the response.
+    ///
+    /// # Errors
+    /// - The first offset whose value is off, with the expected and actual values
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::blur_sync;
+    /// use vincent_blur::kernel::Kernel;
+    ///
+    /// let kernel = Kernel::new(3, 1.5);
+    ///
+    /// assert!(kernel.verify(|k, img| blur_sync(k.radius, k.sigma, img)).is_ok());
+    /// assert!(kernel.verify(|k, img| blur_sync(k.radius, k.sigma * 2.0, img)).is_err());
+    /// ```
+    pub fn verify(&self, blur: impl Fn(Kernel, RgbImage) -> RgbImage) -> Result<(), Message> {
+        let side = self.radius as u32 * 4 + 1;
+        let center = (side / 2) as i32;
+
+        let impulse = testgen::generate(Pattern::Impulse, side, side, 0);
+        let blurred = blur(*self, impulse);
+
+        if blurred.dimensions() != (side, side) {
+            return Err(tr!(
+                "impulse-size",
+                side,
+                side,
+                blurred.width(),
+                blurred.height()
+            ));
+        }
+
+        for (x, y, p) in blurred.enumerate_pixels() {
+            let (dx, dy) = (x as i32 - center, y as i32 - center);
+            let expected = 255.0 * self.weight(dx, dy);
+
+            if let Some(&v) =
+                p.0.iter()
+                    .find(|&&v| (v as f64 - expected).abs() > TOLERANCE as f64)
+            {
+                return Err(tr!("impulse-differs", dx, dy, format!("{expected:.2}"), v));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::locale::Message;
+use crate::tr;
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+use std::str::FromStr;
+
+/// What fills the space around an image padded to a new aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadStyle {
+    /// A blurred copy of the image scaled to cover the canvas
+    Blur,
+    /// A solid color
+    Color(Rgb<u8>),
+}
+
+impl FromStr for PadStyle {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blur" => Ok(PadStyle::Blur),
+            _ => crate::parse_color(s)
+                .map(PadStyle::Color)
+                .ok_or_else(|| tr!("unknown-pad-style", s)),
+        }
+    }
+}
+
+/// Smallest canvas with the aspect ratio `aspect` (width, height) that contains a
+/// `width`x`height` image
+///
+/// # Examples
+/// ```
+/// use vincent_blur::layout::padded_size;
+///
+/// assert_eq!(padded_size(1080, 1920, (16, 9)), (3414, 1920));
+/// assert_eq!(padded_size(1920, 1080, (16, 9)), (1920, 1080));
+/// ```
+pub fn padded_size(width: u32, height: u32, aspect: (u32, u32)) -> (u32, u32) {
+    let (aw, ah) = (aspect.0 as u64, aspect.1 as u64);
+    let (w, h) = (width as u64, height as u64);
+
+    if w * ah >= h * aw {
+        (width, (w * ah).div_ceil(aw) as u32)
+    } else {
+        ((h * aw).div_ceil(ah) as u32, height)
+    }
+}
+
+/// Scales an image so it covers a `width`x`height` canvas and crops the overflow evenly from
+/// both sides
+pub fn cover(img: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let scale = (width as f64 / img.width() as f64).max(height as f64 / img.height() as f64);
+    let scaled_w = ((img.width() as f64 * scale).ceil() as u32).max(width);
+    let scaled_h = ((img.height() as f64 * scale).ceil() as u32).max(height);
+
+    let scaled = imageops::resize(img, scaled_w, scaled_h, FilterType::Triangle);
+
+    imageops::crop_imm(
+        &scaled,
+        (scaled_w - width) / 2,
+        (scaled_h - height) / 2,
+        width,
+        height,
+    )
+    .to_image()
+}
+
+/// Crops the largest square out of the middle of an image
+pub fn center_square(img: &RgbImage) -> RgbImage {
+    let size = img.width().min(img.height());
+
+    imageops::crop_imm(
+        img,
+        (img.width() - size) / 2,
+        (img.height() - size) / 2,
+        size,
+        size,
+    )
+    .to_image()
+}
+
+/// Draws `img` in the middle of `canvas`
+pub fn overlay_center(canvas: &mut RgbImage, img: &RgbImage) {
+    let x = (canvas.width() as i64 - img.width() as i64) / 2;
+    let y = (canvas.height() as i64 - img.height() as i64) / 2;
+
+    imageops::overlay(canvas, img, x, y);
+}
+
+/// Downscales `img` so its longest side is at most `size`, returning the factor it was scaled
+/// by. Images that already fit are returned as they are.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::layout::fit_within;
+///
+/// let (small, factor) = fit_within(&image::RgbImage::new(1280, 720), 640);
+///
+/// assert_eq!(small.dimensions(), (640, 360));
+/// assert_eq!(factor, 0.5);
+/// ```
+pub fn fit_within(img: &RgbImage, size: u32) -> (RgbImage, f64) {
+    let (width, height) = img.dimensions();
+    let factor = (size as f64 / width.max(height) as f64).min(1.0);
+
+    match factor < 1.0 {
+        true => {
+            let w = ((width as f64 * factor).round() as u32).max(1);
+            let h = ((height as f64 * factor).round() as u32).max(1);
+            (imageops::resize(img, w, h, FilterType::Triangle), factor)
+        }
+        false => (img.clone(), 1.0),
+    }
+}
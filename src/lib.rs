@@ -1,28 +1,340 @@
+use cli::error::{ArgError, Fault};
 use grid::*;
+use image::imageops::FilterType;
 use image::{ImageBuffer, Rgb, RgbImage};
+use locale::Message;
+use std::borrow::Cow;
 use std::env;
-use std::sync::{mpsc, Arc};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-mod threadpool;
-use std::path::PathBuf;
-use threadpool::*;
+pub mod adaptive;
+pub mod affinity;
+pub mod audit;
+pub mod backend;
+pub mod batch;
+pub mod bench;
+pub mod blurrer;
+pub mod build_info;
+pub mod cache;
+pub mod cli;
+pub mod codec;
+pub mod control;
+mod deadline;
+pub mod denoise;
+#[cfg(feature = "redact")]
+pub mod detect;
+pub mod dither;
+pub mod effects;
+mod error;
+pub mod events;
+mod fixed;
+#[cfg(feature = "fuzz")]
+pub mod fuzz_targets;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod job;
+pub mod kernel;
+pub mod layout;
+pub mod list;
+pub mod locale;
+pub mod logging;
+pub mod mask;
+pub mod memory;
+pub mod metrics;
+pub mod naming;
+pub mod ops;
+pub mod pipeline;
+pub mod plan;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod region;
+mod rows;
+pub mod scratch;
+#[cfg(feature = "wasm")]
+pub mod script;
+#[cfg(feature = "portrait")]
+pub mod segment;
+mod separable;
+pub mod srcset;
+mod surface;
+pub mod temporal;
+pub mod testgen;
+pub mod threadpool;
+pub mod throttle;
+pub mod thumb;
+pub mod timings;
+use std::path::{Path, PathBuf};
+
+pub use error::Error;
+pub use kernel::Kernel;
+pub use surface::surface_blur;
+
+/// Axes along which the blur is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl FromStr for Direction {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "horizontal" | "h" => Ok(Direction::Horizontal),
+            "vertical" | "v" => Ok(Direction::Vertical),
+            "both" => Ok(Direction::Both),
+            _ => Err(tr!("unknown-direction", s)),
+        }
+    }
+}
+
+/// Blur algorithms selectable with `--algorithm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Gaussian,
+    /// Edge-preserving blur, see [`surface_blur`]
+    Surface,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::Gaussian, Algorithm::Surface];
+}
+
+impl FromStr for Algorithm {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gaussian" => Ok(Algorithm::Gaussian),
+            "surface" => Ok(Algorithm::Surface),
+            _ => Err(tr!("unknown-algorithm", s)),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Algorithm::Gaussian => "gaussian",
+            Algorithm::Surface => "surface",
+        })
+    }
+}
+
+/// How `--redact-*` hides the regions it finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactStyle {
+    /// Replace the region with large blocks of its average color, see [`pixelate`]
+    Pixelate,
+    /// Blur the region with the regular blur options
+    Blur,
+}
+
+impl FromStr for RedactStyle {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pixelate" => Ok(RedactStyle::Pixelate),
+            "blur" => Ok(RedactStyle::Blur),
+            _ => Err(tr!("unknown-redact-style", s)),
+        }
+    }
+}
+
+/// How the pixels of the source map onto the scene, see `--projection`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// An ordinary flat image
+    Flat,
+    /// A 360° equirectangular panorama, see [`blur_equirect`]
+    Equirect,
+}
+
+impl FromStr for Projection {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Projection::Flat),
+            "equirect" => Ok(Projection::Equirect),
+            _ => Err(tr!("unknown-projection", s)),
+        }
+    }
+}
 
 /// Struct for CLI options
+#[derive(Clone)]
 pub struct Opts {
     pub radius: u8,
     pub sigma: f64,
+    /// Whether `radius` was derived from `sigma` rather than given, which fades its outer taps
+    /// in, see [`kernel::tap`]
+    pub radius_derived: bool,
     pub original: PathBuf,
     pub blurred: PathBuf,
     pub n_threads: usize,
+    /// Whether `n_threads` is only the most workers to use, neither `--threads` nor
+    /// `BLUR_THREADS` being set. Fewer are used for small images, see
+    /// [`backend::threads_for_work`].
+    pub adaptive_threads: bool,
+    pub format: Option<String>,
+    pub raster_size: Option<(u32, u32)>,
+    pub progressive: bool,
+    /// Save the unfinished blur every this many percent, see [`naming::partial`]
+    pub progressive_save: Option<u32>,
+    /// Point the progressive save spirals out from, the center when `None`, see
+    /// [`blur_async_focused`]
+    pub focus: Option<(u32, u32)>,
+    pub interlaced: bool,
+    /// Widths of the responsive variants written instead of the destination, see
+    /// [`srcset::variants`]
+    pub srcset: Vec<u32>,
+    /// Where to write the JSON or HTML manifest of the variants, see [`srcset::manifest`]
+    pub srcset_manifest: Option<PathBuf>,
+    pub force: bool,
+    /// Give the destination the modification and access times of the source
+    pub preserve_times: bool,
+    /// Give the destination the permissions of the source
+    pub preserve_mode: bool,
+    /// Print the [`sha256`](cache::sha256) of every file written
+    pub emit_hash: bool,
+    /// Write the [`sha256`](cache::sha256) of every file written to a `.sha256` sidecar
+    pub hash_sidecar: bool,
+    /// Every source/destination pair when `--batch` is given, empty otherwise
+    pub batch: Vec<(PathBuf, PathBuf)>,
+    pub keep_going: bool,
+    /// Blur every file of the batch, even those [`cache`] has as already blurred
+    pub no_cache: bool,
+    pub json_summary: bool,
+    /// Print the time spent decoding, blurring and encoding, see [`timings`]
+    pub timings: bool,
+    pub jobs: usize,
+    /// Time each file may take from decoding to the end of the blur, see
+    /// [`pipeline::Pipeline::run_until`]
+    pub timeout: Option<Duration>,
+    /// Most memory a blur may use, see [`pipeline::Pipeline::memory_budget`]
+    pub max_memory: Option<u64>,
+    /// Largest source width or height accepted, see [`codec::check_limits`]
+    pub max_dimension: Option<u32>,
+    /// Most source pixels accepted, see [`codec::check_limits`]
+    pub max_pixels: Option<u64>,
+    pub backend: String,
+    /// Where the threadpool backend's workers run
+    pub affinity: affinity::Affinity,
+    /// Retire threadpool workers as their throughput drops, see [`throttle::Governor`]
+    pub adaptive_workers: bool,
+    /// Run the blur at a lower priority, see [`affinity::lower_priority`]
+    pub nice: bool,
+    pub direction: Direction,
+    pub passes: usize,
+    pub algorithm: Algorithm,
+    pub projection: Projection,
+    pub tileable: bool,
+    /// Surface blur sample threshold, or the luma above which `bloom` glows
+    pub threshold: u8,
+    pub bloom: bool,
+    pub protect_edges: Option<f32>,
+    /// Vary sigma with the local detail, see [`adaptive::blur_adaptive`]
+    pub adaptive: bool,
+    /// Pick the sigma of every image so the blur has this SSIM to the source, see
+    /// [`metrics::sigma_for_ssim`]
+    pub target_ssim: Option<f64>,
+    pub luma_range: Option<(u8, u8)>,
+    pub key_color: Option<Rgb<u8>>,
+    pub key_tolerance: f32,
+    /// Rectangles to blur, leaving the rest of the image sharp
+    pub regions: Vec<region::Rect>,
+    /// Rectangles to leave sharp
+    pub keep_regions: Vec<region::Rect>,
+    /// Circles, ellipses and polygons to blur along with `regions`
+    pub shapes: Vec<region::Shape>,
+    /// Regions given in percentages of the image size, see [`Opts::resolve_regions`]
+    pub relative_regions: Vec<region::Relative>,
+    /// `--ops` expression that replaces the stages built from the other options
+    pub ops: Option<String>,
+    /// Tune the radius and sigma on a preview in the terminal before blurring
+    pub preview: bool,
+    /// Tune the radius, sigma and algorithm in a window before blurring
+    pub gui: bool,
+    /// Directory of plugin libraries whose stages `ops` can use
+    pub plugin_dir: Option<PathBuf>,
+    /// Quarter turns applied before cropping
+    pub rotate: Option<pipeline::Rotate>,
+    /// Mirroring applied after rotating
+    pub flip: Option<pipeline::Flip>,
+    /// Part of the image to keep before blurring
+    pub crop: Option<region::Rect>,
+    /// Size of the middle part of the image to keep before blurring
+    pub crop_center: Option<(u32, u32)>,
+    /// Size to resample the image to before blurring
+    pub resize: Option<(u32, u32)>,
+    /// Factor to resample the image by before blurring
+    pub scale: Option<f32>,
+    /// Resampling filter used by `resize` and `scale`
+    pub filter: FilterType,
+    /// Desaturate the blurred image
+    pub grayscale: bool,
+    /// Dark and light colors the blurred image's luma is mapped onto
+    pub duotone: Option<(Rgb<u8>, Rgb<u8>)>,
+    /// Brightness, contrast and gamma applied after blurring
+    pub tone: pipeline::Tone,
+    /// Color laid over the blurred image
+    pub tint: Option<pipeline::Tint>,
+    /// How the Gaussian blur is rounded back to 8 bits
+    pub dither: dither::Dither,
+    /// Standard deviation of the noise added after blurring
+    pub grain: Option<f32>,
+    /// Seed of the grain noise
+    pub seed: u64,
+    /// Opacity of the blurred image over the original
+    pub blend: Option<f32>,
+    /// ONNX person segmentation model that keeps people sharp, see `--portrait`
+    pub portrait_model: Option<PathBuf>,
+    /// Red, green and blue standard deviations when any of them differs from `sigma`
+    pub channel_sigmas: Option<[f64; 3]>,
+    pub vignette: bool,
+    pub vignette_shape: mask::VignetteShape,
+    /// Fraction of the image kept sharp by `vignette`
+    pub inner: f32,
+    /// Fraction of the image over which `vignette` ramps up to the full blur
+    pub feather: f32,
+    /// Pixels over which the edges of every region and mask fade into the blur, see
+    /// [`mask::Mask::soften`]
+    pub mask_feather: Option<u8>,
+    /// Hide text-like regions instead of blurring the whole image
+    pub redact_text: bool,
+    /// Hide QR codes and barcodes instead of blurring the whole image
+    pub redact_codes: bool,
+    /// Hide licence plates instead of blurring the whole image
+    pub redact_plates: bool,
+    pub redact_style: RedactStyle,
+    /// Block size used by `RedactStyle::Pixelate`
+    pub pixel_size: u32,
+    /// Crop to a square with the subject in a sharp circle over the blurred image
+    pub avatar: bool,
+    /// Aspect ratio (width, height) to pad the image to instead of blurring all of it
+    pub pad_to: Option<(u32, u32)>,
+    pub pad_style: layout::PadStyle,
 }
 
 impl Opts {
     /// Constructs a new Opts from CLI options
     ///
     /// # Example
-    /// ```
+    /// ```no_run
     /// use std::env;
-    /// use blur::Opts;
+    /// use vincent_blur::Opts;
     ///
     /// let Opts {
     ///     radius,
@@ -30,156 +342,1407 @@ impl Opts {
     ///     n_threads,
     ///     original: original_path,
     ///     blurred: blurred_path,
-    /// } = Opts::new(env::args());
+    ///     ..
+    /// } = Opts::new(env::args()).unwrap();
     /// ```
     ///
-    /// # Panics
+    /// # Errors
     /// - If there are too many arguments
     /// - If the cli options are not followed by a value
     /// - If the source file is not specified
-    pub fn new(mut cli_opts: env::Args) -> Result<Opts, String> {
-        let mut radius = 10;
-        let mut sigma = 10.0;
+    pub fn new(cli_opts: env::Args) -> Result<Opts, ArgError> {
+        let mut radius = None;
+        let mut sigma = None;
         let mut n_threads = 10;
-        let mut original: Option<PathBuf> = None;
-        let mut blurred: Option<PathBuf> = None;
+        let mut adaptive_threads = true;
+        let mut positional: Vec<PathBuf> = Vec::new();
+        let mut format: Option<String> = None;
+        let mut raster_size: Option<(u32, u32)> = None;
+        let mut progressive = false;
+        let mut progressive_save = None;
+        let mut focus = None;
+        let mut interlaced = false;
+        let mut srcset = Vec::new();
+        let mut srcset_manifest = None;
+        let mut force = false;
+        let mut preserve_times = false;
+        let mut preserve_mode = false;
+        let mut emit_hash = false;
+        let mut hash_sidecar = false;
+        let mut suffix: Option<String> = None;
+        let mut numbered = false;
+        let mut output_dir: Option<PathBuf> = None;
+        let mut batch = false;
+        let mut keep_going = false;
+        let mut no_cache = false;
+        let mut json_summary = false;
+        let mut timings = false;
+        let mut jobs = 1;
+        let mut timeout = None;
+        let mut max_memory = None;
+        let mut max_dimension = None;
+        let mut max_pixels = None;
+        let mut backend = "auto".to_string();
+        let mut affinity = affinity::Affinity::None;
+        let mut adaptive_workers = false;
+        let mut nice = false;
+        let mut direction = Direction::Both;
+        let mut passes = 1;
+        let mut algorithm = Algorithm::Gaussian;
+        let mut projection = Projection::Flat;
+        let mut tileable = false;
+        let mut threshold: Option<u8> = None;
+        let mut bloom = false;
+        let mut protect_edges: Option<f32> = None;
+        let mut adaptive = false;
+        let mut target_ssim = None;
+        let mut luma_range: Option<(u8, u8)> = None;
+        let mut key_color: Option<Rgb<u8>> = None;
+        let mut key_tolerance = 30.0;
+        let mut regions = Vec::new();
+        let mut keep_regions = Vec::new();
+        let mut shapes = Vec::new();
+        let mut relative_regions = Vec::new();
+        let mut blend: Option<f32> = None;
+        let mut ops: Option<String> = None;
+        let mut preview = false;
+        let mut gui = false;
+        let mut plugin_dir: Option<PathBuf> = None;
+        let mut rotate: Option<pipeline::Rotate> = None;
+        let mut flip: Option<pipeline::Flip> = None;
+        let mut crop: Option<region::Rect> = None;
+        let mut crop_center: Option<(u32, u32)> = None;
+        let mut resize: Option<(u32, u32)> = None;
+        let mut scale: Option<f32> = None;
+        let mut filter = FilterType::Triangle;
+        let mut grayscale = false;
+        let mut duotone: Option<(Rgb<u8>, Rgb<u8>)> = None;
+        let mut tone = pipeline::Tone::default();
+        let mut tint: Option<pipeline::Tint> = None;
+        let mut dither = dither::Dither::None;
+        let mut grain: Option<f32> = None;
+        let mut seed: Option<u64> = None;
+        let mut portrait = false;
+        let mut portrait_model: Option<PathBuf> = None;
+        let mut channel_sigmas: [Option<f64>; 3] = [None; 3];
+        let mut vignette = false;
+        let mut vignette_shape = mask::VignetteShape::Ellipse;
+        let mut inner = 0.6;
+        let mut feather = 0.15;
+        let mut mask_feather = None;
+        let mut redact_text = false;
+        let mut redact_codes = false;
+        let mut redact_plates = false;
+        let mut redact_style = RedactStyle::Pixelate;
+        let mut pixel_size = 16;
+        let mut avatar = false;
+        let mut pad_to: Option<(u32, u32)> = None;
+        let mut pad_style = layout::PadStyle::Blur;
 
-        if cli_opts.len() > 9 {
-            return Err("Too many arguments".to_string());
+        if let Ok(s) = env::var("BLUR_RADIUS") {
+            radius = Some(parse_positive(&s).ok_or_else(|| {
+                ArgError::new(
+                    Fault::Variable("BLUR_RADIUS"),
+                    tr!("number-in", "BLUR_RADIUS"),
+                )
+            })?);
         }
 
+        if let Ok(s) = env::var("BLUR_SIGMA") {
+            sigma = Some(parse_positive(&s).ok_or_else(|| {
+                ArgError::new(Fault::Variable("BLUR_SIGMA"), tr!("float-in", "BLUR_SIGMA"))
+            })?);
+        }
+
+        if let Ok(s) = env::var("BLUR_THREADS") {
+            n_threads = parse_positive(&s).ok_or_else(|| {
+                ArgError::new(
+                    Fault::Variable("BLUR_THREADS"),
+                    tr!("number-in", "BLUR_THREADS"),
+                )
+            })?;
+            adaptive_threads = false;
+        }
+
+        if let Ok(s) = env::var("BLUR_JOBS") {
+            jobs = parse_positive(&s).ok_or_else(|| {
+                ArgError::new(Fault::Variable("BLUR_JOBS"), tr!("number-in", "BLUR_JOBS"))
+            })?;
+        }
+
+        if let Ok(s) = env::var("BLUR_BACKEND") {
+            if !backend::NAMES.contains(&s.as_str()) {
+                return Err(ArgError::new(
+                    Fault::Variable("BLUR_BACKEND"),
+                    tr!("unknown-backend-in", s, "BLUR_BACKEND"),
+                ));
+            }
+            backend = s;
+        }
+
+        if let Ok(s) = env::var("BLUR_FORMAT") {
+            if !codec::is_supported(&s) {
+                return Err(ArgError::new(
+                    Fault::Variable("BLUR_FORMAT"),
+                    tr!("unsupported-format-in", s, "BLUR_FORMAT"),
+                ));
+            }
+            format = Some(s.to_lowercase());
+        }
+
+        if let Some(s) = env::var_os("BLUR_PORTRAIT_MODEL") {
+            portrait_model = Some(PathBuf::from(s));
+        }
+
+        if let Some(s) = env::var_os("BLUR_PLUGIN_DIR") {
+            plugin_dir = Some(PathBuf::from(s));
+        }
+
+        if let Ok(s) = env::var("BLUR_SUFFIX") {
+            suffix = Some(s);
+        }
+
+        if let Some(s) = env::var_os("BLUR_OUTPUT_DIR") {
+            output_dir = Some(PathBuf::from(s));
+        }
+
+        let mut cli_opts = cli::Args::new(cli_opts);
         cli_opts.next();
 
         while let Some(arg) = cli_opts.next() {
-            match arg.as_str() {
-                "--radius" | "-r" => {
-                    radius = match cli_opts.next() {
-                        Some(s) => match s.parse::<u8>() {
-                            Ok(t) if t > 0 => t,
-                            _ => {
-                                return Err("Expected a number greater than 0 after --radius|-r"
-                                    .to_string())
-                            }
-                        },
+            let Some(name) = cli::options::canonical(&arg).map_err(|e| cli_opts.unknown(e))? else {
+                positional.push(PathBuf::from(arg));
+                continue;
+            };
+
+            match name {
+                "--radius" => {
+                    radius = Some(
+                        cli_opts
+                            .next()
+                            .as_deref()
+                            .and_then(parse_positive)
+                            .ok_or_else(|| {
+                                cli_opts.invalid_value(name, tr!("number-after", "--radius|-r"))
+                            })?,
+                    );
+                }
+                "--sigma" => {
+                    sigma = Some(
+                        cli_opts
+                            .next()
+                            .as_deref()
+                            .and_then(parse_positive)
+                            .ok_or_else(|| {
+                                cli_opts.invalid_value(name, tr!("float-after", "--sigma|-s"))
+                            })?,
+                    );
+                }
+                "--threads" => {
+                    n_threads = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, tr!("number-after", "--threads|-t"))
+                        })?;
+                    adaptive_threads = false;
+                }
+                "--backend" => {
+                    backend = match cli_opts.next() {
+                        Some(s) if backend::NAMES.contains(&s.as_str()) => s,
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                tr!("one-of-after", backend::NAMES.join(", "), name),
+                            ))
+                        }
+                    };
+                }
+                "--affinity" => {
+                    affinity = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected none, cores or numa after --affinity",
+                            )
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value(name, e))?;
+
+                    if cfg!(not(feature = "affinity")) && affinity != affinity::Affinity::None {
+                        return Err(cli_opts.invalid_value(
+                            name,
+                            "--affinity requires building with the `affinity` feature",
+                        ));
+                    }
+                }
+                "--adaptive-workers" => adaptive_workers = true,
+                "--nice" => nice = true,
+                "--direction" => {
+                    direction = cli_opts
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected horizontal, vertical or both after --direction|-d",
+                            )
+                        })?;
+                }
+                "--passes" => {
+                    passes = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, tr!("number-after", "--passes|-p"))
+                        })?;
+                }
+                "--algorithm" => {
+                    algorithm = cli_opts
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected gaussian or surface after --algorithm|-a",
+                            )
+                        })?;
+                }
+                "--projection" => {
+                    projection = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts
+                                .invalid_value(name, "Expected flat or equirect after --projection")
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value(name, e))?;
+                }
+                "--tileable" => {
+                    tileable = true;
+                }
+                "--threshold" => {
+                    threshold = match cli_opts.next().map(|s| s.parse()) {
+                        Some(Ok(t)) => Some(t),
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a number from 0 to 255 after --threshold",
+                            ))
+                        }
+                    };
+                }
+                "--bloom" => {
+                    bloom = true;
+                }
+                "--adaptive" => adaptive = true,
+                "--target-ssim" => {
+                    target_ssim = match cli_opts.next().map(|s| s.parse::<f64>()) {
+                        Some(Ok(t)) if (0.0..1.0).contains(&t) => Some(t),
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a number from 0 to 1 after --target-ssim",
+                            ))
+                        }
+                    };
+                }
+                "--protect-edges" => {
+                    protect_edges = match cli_opts.next().map(|s| s.parse::<f32>()) {
+                        Some(Ok(t)) if (0.0..=1.0).contains(&t) => Some(t),
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a number from 0 to 1 after --protect-edges",
+                            ))
+                        }
+                    };
+                }
+                "--luma-range" => {
+                    luma_range = match cli_opts.next().as_deref().and_then(parse_range) {
+                        Some(range) => Some(range),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected <low>:<high> from 0 to 255 after --luma-range",
+                            ))
+                        }
+                    };
+                }
+                "--key-color" => {
+                    key_color = match cli_opts.next().as_deref().and_then(parse_color) {
+                        Some(c) => Some(c),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a hex color like 00ff00 after --key-color",
+                            ))
+                        }
+                    };
+                }
+                "--key-tolerance" => {
+                    key_tolerance = match cli_opts.next().map(|s| s.parse::<f32>()) {
+                        Some(Ok(t)) if t >= 0.0 => t,
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a number of at least 0 after --key-tolerance",
+                            ))
+                        }
+                    };
+                }
+                "--ops" => {
+                    ops = match cli_opts.next() {
+                        Some(o) => Some(o),
+                        None => {
+                            return Err(cli_opts.invalid_value(name, "Expected stages after --ops"))
+                        }
+                    };
+                }
+                "--preview" => {
+                    preview = true;
+                }
+                "--gui" => {
+                    gui = true;
+                }
+                "--plugin-dir" => {
+                    plugin_dir = match cli_opts.next() {
+                        Some(d) => Some(PathBuf::from(d)),
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value(name, "Expected a directory after --plugin-dir"))
+                        }
+                    };
+                }
+                "--rotate" => {
+                    rotate = Some(
+                        cli_opts
+                            .next()
+                            .ok_or_else(|| {
+                                cli_opts
+                                    .invalid_value(name, "Expected 90, 180 or 270 after --rotate")
+                            })?
+                            .parse()
+                            .map_err(|e| cli_opts.invalid_value(name, e))?,
+                    );
+                }
+                "--flip" => {
+                    flip = Some(
+                        cli_opts
+                            .next()
+                            .ok_or_else(|| {
+                                cli_opts.invalid_value(name, "Expected h or v after --flip")
+                            })?
+                            .parse()
+                            .map_err(|e| cli_opts.invalid_value(name, e))?,
+                    );
+                }
+                "--region" => {
+                    let s = cli_opts.next().ok_or_else(|| {
+                        cli_opts.invalid_value(name, "Expected x,y,width,height after --region")
+                    })?;
+
+                    match s.contains('%') {
+                        true => relative_regions.push(region::Relative::Region(s)),
+                        false => {
+                            regions.push(s.parse().map_err(|e| cli_opts.invalid_value(name, e))?)
+                        }
+                    }
+                }
+                "--region-circle" => {
+                    let s = cli_opts.next().ok_or_else(|| {
+                        cli_opts.invalid_value(name, "Expected cx,cy,r after --region-circle")
+                    })?;
+
+                    match s.contains('%') {
+                        true => relative_regions.push(region::Relative::Circle(s)),
+                        false => shapes.push(
+                            region::Shape::circle(&s)
+                                .map_err(|e| cli_opts.invalid_value(name, e))?,
+                        ),
+                    }
+                }
+                "--region-ellipse" => {
+                    let s = cli_opts.next().ok_or_else(|| {
+                        cli_opts.invalid_value(name, "Expected cx,cy,rx,ry after --region-ellipse")
+                    })?;
+
+                    match s.contains('%') {
+                        true => relative_regions.push(region::Relative::Ellipse(s)),
+                        false => shapes.push(
+                            region::Shape::ellipse(&s)
+                                .map_err(|e| cli_opts.invalid_value(name, e))?,
+                        ),
+                    }
+                }
+                "--region-poly" => {
+                    let s = cli_opts.next().ok_or_else(|| {
+                        cli_opts.invalid_value(name, "Expected x1,y1;x2,y2;... after --region-poly")
+                    })?;
+
+                    match s.contains('%') {
+                        true => relative_regions.push(region::Relative::Polygon(s)),
+                        false => shapes.push(
+                            region::Shape::polygon(&s)
+                                .map_err(|e| cli_opts.invalid_value(name, e))?,
+                        ),
+                    }
+                }
+                "--regions-file" => {
+                    let path = cli_opts.next().ok_or_else(|| {
+                        cli_opts
+                            .invalid_value(name, "Expected a JSON or CSV file after --regions-file")
+                    })?;
+
+                    regions.extend(
+                        region::read_file(Path::new(&path))
+                            .map_err(|e| cli_opts.invalid_value(name, e))?,
+                    );
+                }
+                "--keep-region" => {
+                    let s = cli_opts.next().ok_or_else(|| {
+                        cli_opts
+                            .invalid_value(name, "Expected x,y,width,height after --keep-region")
+                    })?;
+
+                    match s.contains('%') {
+                        true => relative_regions.push(region::Relative::KeepRegion(s)),
+                        false => keep_regions
+                            .push(s.parse().map_err(|e| cli_opts.invalid_value(name, e))?),
+                    }
+                }
+                "--crop" => {
+                    crop = Some(
+                        cli_opts
+                            .next()
+                            .ok_or_else(|| {
+                                cli_opts
+                                    .invalid_value(name, "Expected x,y,width,height after --crop")
+                            })?
+                            .parse()
+                            .map_err(|e| cli_opts.invalid_value(name, e))?,
+                    );
+                }
+                "--crop-center" => {
+                    crop_center = match cli_opts.next().as_deref().and_then(parse_size) {
+                        Some(s) => Some(s),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a size like 800x600 after --crop-center",
+                            ))
+                        }
+                    };
+                }
+                "--resize" => {
+                    resize = match cli_opts.next().as_deref().and_then(parse_size) {
+                        Some(s) => Some(s),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a size like 1280x720 after --resize",
+                            ))
+                        }
+                    };
+                }
+                "--scale" => {
+                    scale = match cli_opts.next().as_deref().and_then(parse_percent) {
+                        Some(s) if s > 0.0 => Some(s),
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a percentage greater than 0 after --scale",
+                            ))
+                        }
+                    };
+                }
+                "--grayscale" => {
+                    grayscale = true;
+                }
+                "--duotone" => {
+                    duotone = match cli_opts.next().as_deref().and_then(parse_duotone) {
+                        Some(d) => Some(d),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected two colors like 1a1040,ffd0a0 after --duotone",
+                            ))
+                        }
+                    };
+                }
+                "--brightness" => {
+                    tone.brightness = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_percent)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, "Expected a percentage after --brightness")
+                        })?;
+                }
+                "--contrast" => {
+                    tone.contrast = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_percent)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, "Expected a percentage after --contrast")
+                        })?;
+                }
+                "--gamma" => {
+                    tone.gamma = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, tr!("number-after", "--gamma"))
+                        })?;
+                }
+                "--tint" => {
+                    tint = match cli_opts.next().as_deref().and_then(parse_tint) {
+                        Some(t) => Some(t),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a color like 000000@0.35 after --tint",
+                            ))
+                        }
+                    };
+                }
+                "--filter" => {
+                    filter = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_filter)
+                        .ok_or_else(|| cli_opts.invalid_value(name, "Expected nearest, triangle, catmull-rom, gaussian or lanczos after --filter"))?;
+                }
+                "--dither" => {
+                    dither = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected none, ordered or diffusion after --dither",
+                            )
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value(name, e))?;
+                }
+                "--grain" => {
+                    grain = match cli_opts.next().as_deref().and_then(parse_positive) {
+                        Some(g) => Some(g),
                         None => {
+                            return Err(cli_opts.invalid_value(name, tr!("number-after", "--grain")))
+                        }
+                    };
+                }
+                "--seed" => {
+                    seed = match cli_opts.next().map(|s| s.parse()) {
+                        Some(Ok(s)) => Some(s),
+                        _ => {
                             return Err(
-                                "Expected a number greater than 0 after --radius|-r".to_string()
+                                cli_opts.invalid_value(name, "Expected a number after --seed")
                             )
                         }
                     };
                 }
-                "--sigma" | "-s" => {
-                    sigma = match cli_opts.next() {
-                        Some(s) => match s.parse::<f64>() {
-                            Ok(t) if t > 0.0 => t,
-                            _ => {
-                                return Err(
-                                    "Expected a float greater than 0 after --sigma|-s".to_string()
-                                )
-                            }
-                        },
+                "--blend" => {
+                    blend = match cli_opts.next().map(|s| s.parse::<f32>()) {
+                        Some(Ok(a)) if (0.0..=1.0).contains(&a) => Some(a),
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a number from 0 to 1 after --blend",
+                            ))
+                        }
+                    };
+                }
+                "--portrait" => {
+                    portrait = true;
+                }
+                "--portrait-model" => {
+                    portrait_model = match cli_opts.next() {
+                        Some(m) => Some(PathBuf::from(m)),
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value(name, "Expected a path after --portrait-model"))
+                        }
+                    };
+                }
+                "--sigma-r" | "--sigma-g" | "--sigma-b" => {
+                    let c = match arg.as_str() {
+                        "--sigma-r" => 0,
+                        "--sigma-g" => 1,
+                        _ => 2,
+                    };
+
+                    channel_sigmas[c] = match cli_opts.next().as_deref().and_then(parse_positive) {
+                        Some(t) => Some(t),
+                        None => return Err(cli_opts.invalid_value(name, tr!("float-after", arg))),
+                    };
+                }
+                "--vignette-blur" => {
+                    vignette = true;
+                }
+                "--vignette-shape" => {
+                    vignette_shape = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected ellipse or rect after --vignette-shape",
+                            )
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value(name, e))?;
+                }
+                "--inner" => {
+                    inner = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_percent)
+                        .ok_or_else(|| {
+                            cli_opts
+                                .invalid_value(name, "Expected a percentage like 60% after --inner")
+                        })?;
+                }
+                "--feather" => {
+                    feather = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_percent)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected a percentage like 15% after --feather",
+                            )
+                        })?;
+                }
+                "--mask-feather" => {
+                    mask_feather =
+                        Some(cli_opts.next().as_deref().and_then(parse_positive).ok_or_else(|| cli_opts.invalid_value(name, "Expected a number of pixels from 1 to 255 after --mask-feather"))?);
+                }
+                "--redact-text" => {
+                    redact_text = true;
+                }
+                "--redact-codes" => {
+                    redact_codes = true;
+                }
+                "--redact-plates" => {
+                    redact_plates = true;
+                }
+                "--redact-style" => {
+                    redact_style = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected pixelate or blur after --redact-style",
+                            )
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value(name, e))?;
+                }
+                "--pixel-size" => {
+                    pixel_size = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, tr!("number-after", "--pixel-size"))
+                        })?;
+                }
+                "--avatar" => {
+                    avatar = true;
+                }
+                "--pad-to" => {
+                    pad_to = match cli_opts.next().as_deref().and_then(parse_aspect) {
+                        Some(a) => Some(a),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected an aspect ratio like 16:9 after --pad-to",
+                            ))
+                        }
+                    };
+                }
+                "--pad-style" => {
+                    pad_style = cli_opts
+                        .next()
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                name,
+                                "Expected blur or a hex color after --pad-style",
+                            )
+                        })?
+                        .parse()
+                        .map_err(|e| cli_opts.invalid_value(name, e))?;
+                }
+                "--format" => {
+                    format = match cli_opts.next() {
+                        Some(s) if codec::is_supported(&s) => Some(s.to_lowercase()),
+                        Some(s) => {
+                            return Err(cli_opts.invalid_value(name, tr!("unsupported-format", s)))
+                        }
                         None => {
                             return Err(
-                                "Expected a float greater than 0 after --sigma|-s".to_string()
+                                cli_opts.invalid_value(name, "Expected a format after --format|-f")
                             )
                         }
                     };
                 }
-                "--threads" | "-t" => {
-                    n_threads = match cli_opts.next() {
-                        Some(s) => match s.parse::<usize>() {
-                            Ok(t) if t > 0 => t,
-                            _ => {
-                                return Err("Expected a number greater than 0 after --threads|-t"
-                                    .to_string())
-                            }
-                        },
+                "--raster-size" => {
+                    raster_size = match cli_opts.next().as_deref().and_then(parse_size) {
+                        Some(size) => Some(size),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected <width>x<height> or <size> after --raster-size",
+                            ))
+                        }
+                    };
+                }
+                "--progressive" => progressive = true,
+                "--progressive-save" => {
+                    progressive_save = match cli_opts.next().map(|s| s.parse::<u32>()) {
+                        Some(Ok(n)) if (1..=100).contains(&n) => Some(n),
+                        _ => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a percentage from 1 to 100 after --progressive-save",
+                            ))
+                        }
+                    };
+                }
+                "--focus" => {
+                    focus = match cli_opts.next().as_deref().and_then(parse_point) {
+                        Some(p) => Some(p),
                         None => {
                             return Err(
-                                "Expected a number greater than 0 after --threads|-t".to_string()
+                                cli_opts.invalid_value(name, "Expected <x>,<y> after --focus")
                             )
                         }
                     };
                 }
-                "--help" | "-h" => {
-                    let help = [
-                        "Usage: blur [--radius|-r <radius>] [--sigma|-s <sigma>] [--threads|-t <n_threads>] <source> [<destination>] [--help|-h]\n",
-                        "   <source>            Path to original image.",
-                        "   <destination>       Path of the blurred image. Default is",
-                        "                       <source>_blurred_<radius>x<sigma>.\n",
-                        "   -r, --radius        Blur radius. Default is 10px.",
-                        "   -s, --sigma         Gaussian blur standard deviation. Default is 10.",
-                        "   -t, --threads       Number of thread workers. Default is 10.",
-                        "   -h, --help          Prints this help."
-                    ].join("\n");
+                "--interlaced" => interlaced = true,
+                "--srcset" => {
+                    srcset = match cli_opts.next() {
+                        Some(s) => s
+                            .split(',')
+                            .map(|w| parse_positive(w.trim()))
+                            .collect::<Option<Vec<u32>>>()
+                            .ok_or_else(|| {
+                                cli_opts.invalid_value(
+                                    name,
+                                    "Expected widths like 320,640,1280 after --srcset",
+                                )
+                            })?,
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected widths like 320,640,1280 after --srcset",
+                            ))
+                        }
+                    };
+                }
+                "--srcset-manifest" => {
+                    srcset_manifest = match cli_opts.next() {
+                        Some(p) => Some(PathBuf::from(p)),
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value(name, "Expected a file after --srcset-manifest"))
+                        }
+                    };
+                }
+                "--force" => force = true,
+                "--preserve-times" => preserve_times = true,
+                "--preserve-mode" => preserve_mode = true,
+                "--emit-hash" => emit_hash = true,
+                "--hash-sidecar" => hash_sidecar = true,
+                "--suffix" => {
+                    suffix = match cli_opts.next() {
+                        Some(s) if !s.contains(std::path::is_separator) => Some(s),
+                        _ => {
+                            return Err(cli_opts
+                                .invalid_value(name, "Expected a file name suffix after --suffix"))
+                        }
+                    };
+                }
+                "--numbered" => numbered = true,
+                "--output-dir" => {
+                    output_dir = match cli_opts.next() {
+                        Some(s) => Some(PathBuf::from(s)),
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value(name, "Expected a directory after --output-dir|-o"))
+                        }
+                    };
+                }
+                "--batch" => batch = true,
+                "--keep-going" => keep_going = true,
+                "--no-cache" => no_cache = true,
+                "--jobs" => {
+                    jobs = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(name, tr!("number-after", "--jobs|-j"))
+                        })?;
+                }
+                "--max-memory" => {
+                    max_memory = match cli_opts.next().as_deref().and_then(parse_bytes) {
+                        Some(m) => Some(m),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a size like 512M or 2G after --max-memory",
+                            ))
+                        }
+                    };
+                }
+                "--max-dimension" => {
+                    max_dimension = match cli_opts.next().as_deref().and_then(parse_positive) {
+                        Some(d) => Some(d),
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value(name, tr!("number-after", "--max-dimension")))
+                        }
+                    };
+                }
+                "--max-pixels" => {
+                    max_pixels =
+                        match cli_opts.next().as_deref().and_then(parse_positive::<f64>) {
+                            Some(mp) => Some((mp * 1_000_000.0) as u64),
+                            None => return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a number of megapixels greater than 0 after --max-pixels",
+                            )),
+                        };
+                }
+                "--timeout" => {
+                    timeout = match cli_opts.next().as_deref().and_then(parse_duration) {
+                        Some(t) => Some(t),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                name,
+                                "Expected a duration like 30s, 500ms or 2m after --timeout",
+                            ))
+                        }
+                    };
+                }
+                "--summary" => {
+                    json_summary = match cli_opts.next().as_deref() {
+                        Some("text") => false,
+                        Some("json") => true,
+                        _ => {
+                            return Err(cli_opts
+                                .invalid_value(name, "Expected text or json after --summary"))
+                        }
+                    };
+                }
+                "--timings" => timings = true,
+                "--version" => {
+                    println!("{}", build_info::BuildInfo::detect());
+                    std::process::exit(0);
+                }
+                "--help" => {
+                    println!("{}", cli::help());
+                    std::process::exit(0);
+                }
+                _ => return Err(cli_opts.unknown(tr!("unknown-option", arg))),
+            }
+        }
+
+        if positional.is_empty() {
+            return Err("Expected an original image".into());
+        }
+
+        if tileable && projection != Projection::Flat {
+            return Err(ArgError::new(
+                Fault::Option("--tileable"),
+                "--tileable can only be used with --projection flat",
+            ));
+        }
+
+        if crop.is_some() && crop_center.is_some() {
+            return Err(ArgError::new(
+                Fault::Option("--crop"),
+                "--crop and --crop-center can't be used together",
+            ));
+        }
+
+        if grayscale && duotone.is_some() {
+            return Err(ArgError::new(
+                Fault::Option("--grayscale"),
+                "--grayscale and --duotone can't be used together",
+            ));
+        }
+
+        if resize.is_some() && scale.is_some() {
+            return Err(ArgError::new(
+                Fault::Option("--resize"),
+                "--resize and --scale can't be used together",
+            ));
+        }
+
+        if avatar && pad_to.is_some() {
+            return Err(ArgError::new(
+                Fault::Option("--avatar"),
+                "--avatar and --pad-to can't be used together",
+            ));
+        }
+
+        // --adaptive runs its own blur, which has none of these modes, and --progressive-save
+        // only hooks into the plain one
+        let blur_modes = [
+            ("--algorithm", algorithm != Algorithm::Gaussian),
+            ("--tileable", tileable),
+            ("--projection", projection != Projection::Flat),
+            ("--sigma-r", channel_sigmas.iter().any(Option::is_some)),
+            ("--passes", passes > 1),
+            ("--direction", direction != Direction::Both),
+        ];
+
+        if let Some((mode, _)) = blur_modes.iter().find(|(_, set)| adaptive && *set) {
+            return Err(ArgError::new(
+                Fault::Option("--adaptive"),
+                tr!("cant-combine", "--adaptive", mode),
+            ));
+        }
+
+        if let Some((mode, _)) = blur_modes
+            .iter()
+            .chain([&("--adaptive", adaptive)])
+            .find(|(_, set)| progressive_save.is_some() && *set)
+        {
+            return Err(ArgError::new(
+                Fault::Option("--progressive-save"),
+                tr!("cant-combine", "--progressive-save", mode),
+            ));
+        }
+
+        if !batch && positional.len() > 2 {
+            return Err("Too many arguments, use --batch to blur several images".into());
+        }
+
+        if batch && positional.iter().any(|p| p == Path::new(codec::STDIO)) {
+            return Err("Can't read from stdin in batch mode".into());
+        }
+
+        if preview && gui {
+            return Err(ArgError::new(
+                Fault::Option("--preview"),
+                "--preview and --gui can't be used together",
+            ));
+        }
+
+        if (preview || gui) && (batch || positional.iter().any(|p| p == Path::new(codec::STDIO))) {
+            return Err(ArgError::new(
+                Fault::Option("--preview"),
+                "--preview and --gui need a single source and destination file",
+            ));
+        }
+
+        if progressive_save.is_some()
+            && (batch || positional.iter().any(|p| p == Path::new(codec::STDIO)))
+        {
+            return Err(ArgError::new(
+                Fault::Option("--progressive-save"),
+                "--progressive-save needs a single source and destination file",
+            ));
+        }
+
+        if focus.is_some() && progressive_save.is_none() {
+            return Err(ArgError::new(
+                Fault::Option("--focus"),
+                "--focus needs --progressive-save",
+            ));
+        }
+
+        if !srcset.is_empty() && progressive_save.is_some() {
+            return Err(ArgError::new(
+                Fault::Option("--srcset"),
+                "--srcset can't be combined with --progressive-save",
+            ));
+        }
+
+        if srcset_manifest.is_some() && (srcset.is_empty() || batch) {
+            return Err(ArgError::new(
+                Fault::Option("--srcset-manifest"),
+                "--srcset-manifest needs --srcset and a single source",
+            ));
+        }
+
+        // A sigma on its own gets the radius covering it, whose outer taps fade in with it
+        let (radius, sigma, radius_derived) = match (radius, sigma) {
+            (None, Some(sigma)) => (metrics::radius_for(sigma), sigma, true),
+            (radius, sigma) => (radius.unwrap_or(10), sigma.unwrap_or(10.0), false),
+        };
+
+        let suffix = suffix.unwrap_or_else(|| naming::blurred_suffix(radius, sigma));
+
+        let default_destination = |original: &Path| -> Result<PathBuf, Message> {
+            if original == Path::new(codec::STDIO) {
+                return Ok(PathBuf::from(codec::STDIO));
+            }
+
+            let path = naming::default_path(original, &suffix, format.as_deref())?;
+
+            Ok(match &output_dir {
+                Some(dir) => dir.join(path.file_name().unwrap()),
+                None => path,
+            })
+        };
+
+        let mut files = Vec::with_capacity(positional.len());
+
+        if batch {
+            for original in positional {
+                let blurred = default_destination(&original)?;
+                files.push((original, blurred));
+            }
+        } else {
+            let mut positional = positional.into_iter();
+            let original = positional.next().unwrap();
+
+            let blurred = match positional.next() {
+                Some(b) => b,
+                None => default_destination(&original)?,
+            };
+
+            files.push((original, blurred));
+        }
+
+        if numbered {
+            for (_, blurred) in files.iter_mut() {
+                if blurred != Path::new(codec::STDIO) {
+                    *blurred = naming::numbered(blurred);
+                }
+            }
+        }
+
+        naming::check_unique(&files)?;
+
+        let (original, blurred) = files[0].clone();
+
+        if !batch {
+            files.clear();
+        }
+
+        let opts = Opts {
+            radius,
+            sigma,
+            radius_derived,
+            n_threads,
+            adaptive_threads,
+            original,
+            blurred,
+            format,
+            raster_size,
+            progressive,
+            progressive_save,
+            focus,
+            interlaced,
+            srcset,
+            srcset_manifest,
+            force,
+            preserve_times,
+            preserve_mode,
+            emit_hash,
+            hash_sidecar,
+            batch: files,
+            keep_going,
+            no_cache,
+            json_summary,
+            timings,
+            jobs,
+            timeout,
+            max_memory,
+            max_dimension,
+            max_pixels,
+            backend,
+            affinity,
+            adaptive_workers,
+            nice,
+            direction,
+            passes,
+            algorithm,
+            projection,
+            tileable,
+            threshold: threshold.unwrap_or(match bloom {
+                true => 200,
+                false => 25,
+            }),
+            bloom,
+            protect_edges,
+            adaptive,
+            target_ssim,
+            luma_range,
+            key_color,
+            key_tolerance,
+            regions,
+            keep_regions,
+            shapes,
+            relative_regions,
+            preview,
+            gui,
+            ops,
+            plugin_dir,
+            rotate,
+            flip,
+            crop,
+            crop_center,
+            resize,
+            scale,
+            filter,
+            grayscale,
+            duotone,
+            tone,
+            tint,
+            dither,
+            grain,
+            seed: seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64)
+            }),
+            blend,
+            portrait_model: match portrait {
+                true => Some(portrait_model.ok_or("Expected --portrait-model with --portrait")?),
+                false => None,
+            },
+            channel_sigmas: match channel_sigmas.iter().any(Option::is_some) {
+                true => Some(channel_sigmas.map(|s| s.unwrap_or(sigma))),
+                false => None,
+            },
+            vignette,
+            vignette_shape,
+            inner,
+            feather,
+            mask_feather,
+            redact_text,
+            redact_codes,
+            redact_plates,
+            redact_style,
+            pixel_size,
+            avatar,
+            pad_to,
+            pad_style,
+        };
+
+        // Catches malformed percentages before any image is read
+        opts.resolve_regions(10_000, 10_000)?;
+
+        logging::debug!(
+            "resolved radius={} sigma={} algorithm={} backend={} threads={} adaptive_threads={} jobs={}",
+            opts.radius,
+            opts.sigma,
+            opts.algorithm,
+            opts.backend,
+            opts.n_threads,
+            opts.adaptive_threads,
+            opts.jobs
+        );
+
+        Ok(opts)
+    }
+
+    /// The Gaussian kernel of `radius` and `sigma`, whose outer taps fade in when the radius
+    /// was derived from sigma
+    pub fn kernel(&self) -> Kernel {
+        Kernel {
+            radius: self.radius,
+            sigma: self.sigma,
+            faded: self.radius_derived,
+        }
+    }
+
+    /// The options with `relative_regions` added to the regions and shapes in pixels of a
+    /// `width`x`height` image, so the same percentages fit every image of a batch
+    ///
+    /// # Errors
+    /// - If a region is empty or malformed once in pixels
+    pub fn resolve_regions(&self, width: u32, height: u32) -> Result<Cow<'_, Opts>, Message> {
+        if self.relative_regions.is_empty() {
+            return Ok(Cow::Borrowed(self));
+        }
+
+        let mut opts = self.clone();
+
+        for relative in &self.relative_regions {
+            let s = relative.resolve(width, height);
+            let err = |e| tr!("region-doesnt-fit", relative, width, height).with(e);
+
+            match relative {
+                region::Relative::Region(_) => opts.regions.push(s.parse().map_err(err)?),
+                region::Relative::KeepRegion(_) => opts.keep_regions.push(s.parse().map_err(err)?),
+                region::Relative::Circle(_) => {
+                    opts.shapes.push(region::Shape::circle(&s).map_err(err)?)
+                }
+                region::Relative::Ellipse(_) => {
+                    opts.shapes.push(region::Shape::ellipse(&s).map_err(err)?)
+                }
+                region::Relative::Polygon(_) => {
+                    opts.shapes.push(region::Shape::polygon(&s).map_err(err)?)
+                }
+            }
+        }
+
+        opts.relative_regions.clear();
+
+        Ok(Cow::Owned(opts))
+    }
+}
+
+fn parse_positive<T: FromStr + PartialOrd + Default>(s: &str) -> Option<T> {
+    s.parse::<T>().ok().filter(|t| *t > T::default())
+}
+
+fn parse_range(s: &str) -> Option<(u8, u8)> {
+    let (lo, hi) = s.split_once(':')?;
+
+    match (lo.parse::<u8>(), hi.parse::<u8>()) {
+        (Ok(lo), Ok(hi)) if lo <= hi => Some((lo, hi)),
+        _ => None,
+    }
+}
+
+fn parse_color(s: &str) -> Option<Rgb<u8>> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+
+    if s.len() != 6 || !s.is_ascii() {
+        return None;
+    }
 
-                    println!("{help}");
-                    std::process::exit(1);
-                }
-                _ => match original {
-                    Some(_) => blurred = Some(PathBuf::from(arg)),
-                    None => original = Some(PathBuf::from(arg)),
-                },
-            }
-        }
+    let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
 
-        if original.is_none() {
-            return Err("Expected an original image".to_string());
-        }
+    Some(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
 
-        if blurred.is_none() {
-            let mut blurred_path: PathBuf = original.clone().unwrap();
+/// Parses `dark,light` colors
+fn parse_duotone(s: &str) -> Option<(Rgb<u8>, Rgb<u8>)> {
+    let (dark, light) = s.split_once(',')?;
 
-            let fname = match blurred_path.file_stem() {
-                Some(s) => s.to_str().unwrap(),
-                _ => return Err("Expected a filename".to_string()),
-            };
+    Some((parse_color(dark)?, parse_color(light)?))
+}
 
-            let ext = match blurred_path.extension() {
-                Some(e) => e.to_str().unwrap(),
-                _ => return Err("expected an extension".to_string()),
-            };
+/// Parses `rrggbb@opacity`, where a missing opacity means fully opaque
+fn parse_tint(s: &str) -> Option<pipeline::Tint> {
+    let (color, opacity) = s.split_once('@').unwrap_or((s, "1"));
+    let opacity = opacity.parse::<f32>().ok()?;
 
-            let blurred_fname = format!("{}_blurred_{}x{}.{}", fname, radius, sigma, ext);
+    (0.0..=1.0).contains(&opacity).then_some(pipeline::Tint {
+        color: parse_color(color)?,
+        opacity,
+    })
+}
 
-            blurred_path.set_file_name(blurred_fname);
+/// Parses `60%` (or plain `60`) as 0.6
+fn parse_percent(s: &str) -> Option<f32> {
+    let percent = s.strip_suffix('%').unwrap_or(s).parse::<f32>().ok()?;
 
-            blurred = Some(blurred_path);
-        }
+    (percent >= 0.0).then_some(percent / 100.0)
+}
 
-        Ok(Opts {
-            radius,
-            sigma,
-            n_threads,
-            original: original.unwrap(),
-            blurred: blurred.unwrap(),
-        })
+fn parse_filter(s: &str) -> Option<FilterType> {
+    match s {
+        "nearest" => Some(FilterType::Nearest),
+        "triangle" => Some(FilterType::Triangle),
+        "catmull-rom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos" => Some(FilterType::Lanczos3),
+        _ => None,
+    }
+}
+
+/// Parses `x,y`
+fn parse_point(s: &str) -> Option<(u32, u32)> {
+    let (x, y) = s.split_once(',')?;
+
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Parses a positive duration in `ms`, `s` or `m`, seconds without a unit
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (n, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: f64 = parse_positive(n)?;
+
+    let secs = match unit {
+        "ms" => n / 1000.0,
+        "s" => n,
+        "m" => n * 60.0,
+        _ => return None,
+    };
+
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// Parses a positive number of bytes with an optional K, M or G suffix in binary units
+fn parse_bytes(s: &str) -> Option<u64> {
+    let (n, shift) = match s.char_indices().last()? {
+        (i, 'K' | 'k') => (&s[..i], 10),
+        (i, 'M' | 'm') => (&s[..i], 20),
+        (i, 'G' | 'g') => (&s[..i], 30),
+        _ => (s, 0),
+    };
+
+    parse_positive::<u64>(n)?.checked_mul(1 << shift)
+}
+
+fn parse_aspect(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.split_once(':')?;
+
+    Some((parse_positive(w)?, parse_positive(h)?))
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.split_once('x').unwrap_or((s, s));
+
+    match (w.parse::<u32>(), h.parse::<u32>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Some((w, h)),
+        _ => None,
     }
 }
 
-fn gaussian(x: i32, y: i32, sigma: f64) -> f64 {
-    (-(x.pow(2) + y.pow(2)) as f64 / (2.0 * sigma * sigma)).exp()
+fn gaussian(x: i32, y: i32, sigma: f64, faded: bool) -> f64 {
+    kernel::tap(x, sigma, faded) * kernel::tap(y, sigma, faded)
         / (2.0 * std::f64::consts::PI * sigma * sigma)
 }
 
-fn get_gaussian_matrix(radius: u8, sigma: f64) -> Grid<f64> {
-    let width = radius * 2 + 1;
+fn get_gaussian_matrix(radius: u8, sigma: f64, faded: bool) -> Grid<f64> {
+    timings::time(timings::Stage::Kernel, || {
+        let width = radius as usize * 2 + 1;
 
-    let mut matrix = Grid::new(width as usize, width as usize);
+        let mut matrix = Grid::new(width, width);
 
-    for x in 0..width {
-        for y in 0..width {
-            let el = matrix
-                .get_mut(x, y)
-                .expect("get_gaussian_matrix: Index out of bounds");
+        for x in 0..width {
+            for y in 0..width {
+                let el = matrix
+                    .get_mut(x, y)
+                    .expect("get_gaussian_matrix: Index out of bounds");
 
-            *el = gaussian(x as i32 - radius as i32, y as i32 - radius as i32, sigma);
+                *el = gaussian(
+                    x as i32 - radius as i32,
+                    y as i32 - radius as i32,
+                    sigma,
+                    faded,
+                );
+            }
         }
-    }
 
-    matrix
+        matrix
+    })
 }
 
+/// Blurred value of pixel (`x`, `y`). It only reads `original_img` and sums the taps in an order
+/// fixed by the kernel, so every worker computes the same bytes for a pixel, which keeps the
+/// multithreaded blurs bit-identical whatever their number of workers.
 fn calculate_new_pixel(x: i32, y: i32, matrix: &Grid<f64>, original_img: &RgbImage) -> Rgb<u8> {
     let mut r = 0.0;
     let mut g = 0.0;
@@ -187,28 +1750,43 @@ fn calculate_new_pixel(x: i32, y: i32, matrix: &Grid<f64>, original_img: &RgbIma
     let mut total = 0.0;
 
     let radius = matrix.rows() as i32 / 2;
+    let width = original_img.width() as i32;
+    let height = original_img.height() as i32;
 
-    for i in 0..(matrix.rows() as i32) {
-        for k in 0..(matrix.cols() as i32) {
-            let x = x + i - radius;
-            let y = y + k - radius;
+    // The kernel is symmetric in both axes, so the up to four taps mirrored around the center
+    // share a weight and are summed before multiplying. Mirrors outside the image are skipped.
+    for i in 0..=radius {
+        let xs = mirrored(x, i, width);
 
-            if x < 0
-                || y < 0
-                || x >= original_img.width() as i32
-                || y >= original_img.height() as i32
-            {
-                continue;
+        for k in 0..=radius {
+            let ys = mirrored(y, k, height);
+
+            let mut taps = [0u32; 3];
+            let mut n = 0;
+
+            for &sx in xs.iter().flatten() {
+                for &sy in ys.iter().flatten() {
+                    let pixel = original_img.get_pixel(sx, sy);
+
+                    taps[0] += pixel[0] as u32;
+                    taps[1] += pixel[1] as u32;
+                    taps[2] += pixel[2] as u32;
+                    n += 1;
+                }
             }
 
-            let pixel = original_img.get_pixel(x as u32, y as u32);
+            if n == 0 {
+                continue;
+            }
 
-            let el = matrix.get(i, k).expect("Index out of bounds");
+            let el = matrix
+                .get(radius + i, radius + k)
+                .expect("Index out of bounds");
 
-            r += pixel[0] as f64 * el;
-            g += pixel[1] as f64 * el;
-            b += pixel[2] as f64 * el;
-            total += el;
+            r += taps[0] as f64 * el;
+            g += taps[1] as f64 * el;
+            b += taps[2] as f64 * el;
+            total += n as f64 * el;
         }
     }
 
@@ -219,11 +1797,23 @@ fn calculate_new_pixel(x: i32, y: i32, matrix: &Grid<f64>, original_img: &RgbIma
     Rgb::from([r as u8, g as u8, b as u8])
 }
 
-/// Blurs image using a thread pool
+/// `center - offset` and `center + offset` where they are inside `0..len`, with the center
+/// only once when `offset` is 0
+fn mirrored(center: i32, offset: i32, len: i32) -> [Option<u32>; 2] {
+    let inside = |v: i32| (0..len).contains(&v).then_some(v as u32);
+
+    [
+        inside(center - offset),
+        (offset > 0).then(|| inside(center + offset)).flatten(),
+    ]
+}
+
+/// Blurs image on `n_threads` threads, each writing its own band of rows. Every pixel is
+/// computed on its own, so the result is the same bytes whatever `n_threads` is.
 ///
 /// # Examples
-/// ```
-/// use blur::{Opts, blur_async};
+/// ```no_run
+/// use vincent_blur::{Opts, blur_async};
 ///
 /// use std::env;
 /// use std::error::Error;
@@ -235,7 +1825,8 @@ fn calculate_new_pixel(x: i32, y: i32, matrix: &Grid<f64>, original_img: &RgbIma
 ///         original: original_path,
 ///         blurred: blurred_path,
 ///         n_threads,
-///     } = Opts::new(env::args());
+///         ..
+///     } = Opts::new(env::args())?;
 ///
 ///     let original_img = image::open(original_path)?.to_rgb8();
 ///
@@ -251,67 +1842,669 @@ pub fn blur_async(
     sigma: f64,
     n_threads: usize,
     original_img: RgbImage,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    blur_async_progress(radius, sigma, n_threads, original_img, 100, |_, _| {})
+}
+
+/// Same as [`blur_async`], calling `on_progress` with the image so far every `every` percent.
+/// Pixels that aren't blurred yet still hold the original.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::blur_async_progress;
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([((x + y) * 8) as u8; 3]));
+/// let mut seen = Vec::new();
+///
+/// let blurred = blur_async_progress(2, 1.5, 4, img.clone(), 25, |_, percent| seen.push(percent));
+///
+/// assert_eq!(seen, [25, 50, 75]);
+/// assert_eq!(blurred, vincent_blur::blur_async(2, 1.5, 4, img));
+/// ```
+pub fn blur_async_progress(
+    radius: u8,
+    sigma: f64,
+    n_threads: usize,
+    original_img: RgbImage,
+    every: u32,
+    on_progress: impl FnMut(&RgbImage, u32),
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    blur_async_pinned(
+        Kernel::new(radius, sigma),
+        n_threads,
+        &[],
+        original_img,
+        every,
+        on_progress,
+    )
+}
+
+/// Same as [`blur_async_progress`] with any [`Kernel`] and worker `i` pinned to `cores[i % cores.len()]`, see
+/// [`affinity::Affinity::cores`]. Each worker blurs the same band of rows at every step and is
+/// the first to write to it, so on NUMA machines the band's memory ends up on its node.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{affinity::Affinity, blur_async, blur_async_pinned, Kernel};
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([((x + y) * 8) as u8; 3]));
+/// let cores = Affinity::Cores.cores();
+///
+/// let blurred = blur_async_pinned(Kernel::new(2, 1.5), 4, &cores, img.clone(), 100, |_, _| {});
+///
+/// assert_eq!(blurred, blur_async(2, 1.5, 4, img));
+/// ```
+pub fn blur_async_pinned(
+    kernel: Kernel,
+    n_threads: usize,
+    cores: &[usize],
+    original_img: RgbImage,
+    every: u32,
+    mut on_progress: impl FnMut(&RgbImage, u32),
 ) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
     let width = original_img.width();
     let height = original_img.height();
 
-    println!("Image dimensions: {}x{}", width, height);
+    events::emit(started(kernel.radius, &original_img));
 
-    let n_calculations = width as u128 * height as u128;
-    let m_size = (radius as u128 * 2 + 1).pow(2);
+    // Zeroed pages are only mapped when a worker first writes to them
+    let mut img_buf = RgbImage::new(width, height);
+    let m = kernel.matrix();
 
-    println!("Number of caculations: {}", n_calculations * m_size);
+    let stride = width as usize * 3;
+    let band_rows = (height as usize).div_ceil(n_threads.max(1)).max(1);
+    let every = every.clamp(1, 100) as usize;
+    let rows_done = AtomicU32::new(0);
+    let deadline = deadline::current();
 
-    let mut img_buf = ImageBuffer::new(width, height);
-    let m = Arc::new(get_gaussian_matrix(radius, sigma));
+    // Every step blurs the next part of each band, and the image is only handed to
+    // `on_progress` between steps, once every band is written
+    let mut prev = 0;
 
-    let (tx, rx) = mpsc::channel();
-    let img = Arc::new(original_img);
+    for step in (every..100).step_by(every).chain([100]) {
+        let raw: &mut [u8] = &mut img_buf;
+
+        thread::scope(|s| {
+            for (i, band) in raw.chunks_mut(band_rows * stride).enumerate() {
+                let (m, img, rows_done) = (&m, &original_img, &rows_done);
+                let core = cores.get(i % cores.len().max(1)).copied();
+                let y0 = i * band_rows;
+
+                s.spawn(move || {
+                    if let Some(core) = core {
+                        affinity::pin(core);
+                    }
+
+                    if prev == 0 {
+                        band.copy_from_slice(&img.as_raw()[y0 * stride..y0 * stride + band.len()]);
+                    }
+
+                    let rows = band.len() / stride;
+                    let (from, to) = (rows * prev / 100, rows * step / 100);
+
+                    for (y, row) in
+                        (y0 + from..).zip(band[from * stride..to * stride].chunks_exact_mut(stride))
+                    {
+                        if deadline::passed(deadline) {
+                            break;
+                        }
+
+                        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+                            px.copy_from_slice(&calculate_new_pixel(x as i32, y as i32, m, img).0);
+                        }
+
+                        // Exactly one row crosses each tenth of the image
+                        let n = rows_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        let tenth = n as u64 * 10 / height as u64;
+                        if n < height && tenth > (n as u64 - 1) * 10 / height as u64 {
+                            events::emit(events::Event::Progress {
+                                percent: tenth as u32 * 10,
+                            });
+                        }
+                    }
+                });
+            }
+        });
+
+        prev = step;
+
+        if deadline::passed(deadline) {
+            return img_buf;
+        }
 
-    let pool = ThreadPool::new(n_threads);
+        if step < 100 {
+            on_progress(&img_buf, step as u32);
+        }
+    }
+    events::emit(events::Event::Finished);
 
-    for (x, y, _) in img_buf.enumerate_pixels() {
-        let _m = Arc::clone(&m);
-        let _img = Arc::clone(&img);
-        let _tx = tx.clone();
+    img_buf
+}
 
-        pool.execute(Box::new(move || {
-            let new_pixel = calculate_new_pixel(x as i32, y as i32, &_m, &_img);
-            _tx.send((x, y, new_pixel)).unwrap();
-        }))
+/// [`events::Event::Started`] for a 2D blur of `img` with `radius`
+fn started(radius: u8, img: &RgbImage) -> events::Event {
+    events::Event::Started {
+        width: img.width(),
+        height: img.height(),
+        calculations: calculations(radius, img),
     }
+}
+
+/// Multiply-adds of a 2D blur of `img` with `radius`
+fn calculations(radius: u8, img: &RgbImage) -> u128 {
+    let m_size = (radius as u128 * 2 + 1).pow(2);
 
-    let mut counter = 0;
+    img.width() as u128 * img.height() as u128 * m_size
+}
+
+/// Side of the tiles blurred by [`blur_async_focused`]
+const FOCUS_TILE: u32 = 64;
+
+/// Same as [`blur_async_pinned`], but the image is blurred in tiles spiraling out from
+/// `focus`, see [`region::center_out`], so the images handed to `on_progress` show the area
+/// around it first
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{blur_async, blur_async_focused, Kernel};
+///
+/// let img = image::RgbImage::from_fn(256, 256, |x, y| image::Rgb([(x ^ y) as u8; 3]));
+/// let blurred = blur_async(2, 1.5, 4, img.clone());
+///
+/// let mut first = None;
+/// let kernel = Kernel::new(2, 1.5);
+/// let done = blur_async_focused(kernel, 4, &[], img.clone(), 10, (128, 128), |partial, _| {
+///     first.get_or_insert_with(|| partial.clone());
+/// });
+///
+/// let first = first.unwrap();
+/// assert_eq!(first.get_pixel(128, 128), blurred.get_pixel(128, 128));
+/// assert_eq!(first.get_pixel(0, 0), img.get_pixel(0, 0));
+/// assert_eq!(done, blurred);
+/// ```
+pub fn blur_async_focused(
+    kernel: Kernel,
+    n_threads: usize,
+    cores: &[usize],
+    original_img: RgbImage,
+    every: u32,
+    focus: (u32, u32),
+    mut on_progress: impl FnMut(&RgbImage, u32),
+) -> RgbImage {
+    let (width, height) = original_img.dimensions();
+    let mut img_buf = original_img.clone();
+    let m = kernel.matrix();
+
+    let tiles = region::center_out(width, height, FOCUS_TILE, focus);
+    let every = every.clamp(1, 100) as usize;
+    let deadline = deadline::current();
+    let mut prev = 0;
+
+    for step in (every..100).step_by(every).chain([100]) {
+        let todo = &tiles[tiles.len() * prev / 100..tiles.len() * step / 100];
+        let per_worker = todo.len().div_ceil(n_threads.max(1)).max(1);
+
+        let blurred: Vec<(region::Rect, Vec<u8>)> = thread::scope(|s| {
+            let workers: Vec<_> = todo
+                .chunks(per_worker)
+                .enumerate()
+                .map(|(i, tiles)| {
+                    let (m, img) = (&m, &original_img);
+                    let core = cores.get(i % cores.len().max(1)).copied();
+
+                    s.spawn(move || {
+                        if let Some(core) = core {
+                            affinity::pin(core);
+                        }
+
+                        tiles
+                            .iter()
+                            .take_while(|_| !deadline::passed(deadline))
+                            .map(|t| {
+                                let pixels = (t.y..t.bottom())
+                                    .flat_map(|y| (t.x..t.right()).map(move |x| (x, y)))
+                                    .flat_map(|(x, y)| {
+                                        calculate_new_pixel(x as i32, y as i32, m, img).0
+                                    })
+                                    .collect();
+
+                                (*t, pixels)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect()
+        });
 
-    let mut last = 0;
+        let stride = width as usize * 3;
+        let raw: &mut [u8] = &mut img_buf;
 
-    while let Ok(res) = rx.recv() {
-        let (x, y, pix) = res;
+        for (t, pixels) in blurred {
+            let row_len = t.width as usize * 3;
 
-        let pixel = img_buf.get_pixel_mut(x, y);
-        *pixel = pix;
-        counter += 1;
+            for (y, row) in (t.y as usize..).zip(pixels.chunks_exact(row_len)) {
+                let start = y * stride + t.x as usize * 3;
+                raw[start..start + row_len].copy_from_slice(row);
+            }
+        }
 
-        if counter == width * height {
+        prev = step;
+
+        if deadline::passed(deadline) {
             break;
         }
 
-        let percent = counter as u128 * 100 / n_calculations;
-        if percent % 10 == 0 && percent != last {
-            println!("{}% done", percent);
-            last = percent;
+        if step < 100 {
+            on_progress(&img_buf, step as u32);
         }
     }
-    println!("Done!");
 
     img_buf
 }
 
-/// Blurs image one pixel at a time. It is the same as blur_async with 1 thread.
+/// Rows of the tiles reported by [`blur_async_events`]
+const EVENT_TILE_ROWS: u32 = 16;
+
+/// Starts [`blur_async`] in the background and returns right away. The task's
+/// [`events`](events::BlurTask::events) tell when tiles of rows are done, how far along the
+/// blur is and whether a worker failed, so a GUI or service can show progress and draw tiles as
+/// they finish without polling.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::events::BlurEvent;
+/// use vincent_blur::{blur_async, blur_async_events};
+///
+/// let img = image::RgbImage::from_fn(20, 40, |x, y| image::Rgb([(x * 12) as u8, (y * 6) as u8, 9]));
+/// let task = blur_async_events(2, 1.5, 3, img.clone());
+///
+/// let events: Vec<BlurEvent> = task.events().iter().collect();
+///
+/// assert_eq!(events[0], BlurEvent::Started { width: 20, height: 40 });
+/// assert_eq!(events.iter().filter(|e| matches!(e, BlurEvent::TileDone { .. })).count(), 3);
+/// assert!(events.contains(&BlurEvent::Progress { percent: 100 }));
+/// assert!(matches!(events.last(), Some(BlurEvent::Finished { .. })));
+///
+/// assert_eq!(task.wait().unwrap(), blur_async(2, 1.5, 3, img));
+/// ```
+pub fn blur_async_events(
+    radius: u8,
+    sigma: f64,
+    n_threads: usize,
+    original_img: RgbImage,
+) -> events::BlurTask {
+    blur_async_controlled(
+        radius,
+        sigma,
+        control::Control::new(n_threads),
+        original_img,
+    )
+}
+
+/// Same as [`blur_async_events`], on as many workers as `control` asks for and steered by it
+/// from the start, so a blur can be queued paused and only run once it is resumed
+///
+/// # Examples
+/// ```
+/// use vincent_blur::control::Control;
+/// use vincent_blur::events::BlurEvent;
+/// use vincent_blur::{blur_async, blur_async_controlled};
+///
+/// let img = image::RgbImage::from_fn(20, 40, |x, y| image::Rgb([(x * 12) as u8, (y * 6) as u8, 9]));
+/// let control = Control::new(2);
+/// control.pause();
+///
+/// let task = blur_async_controlled(2, 1.5, control.clone(), img.clone());
+/// assert_eq!(task.events().recv(), Ok(BlurEvent::Started { width: 20, height: 40 }));
+/// assert!(task.control().is_paused());
+///
+/// control.resume();
+/// assert_eq!(task.wait().unwrap(), blur_async(2, 1.5, 2, img));
+/// ```
+pub fn blur_async_controlled(
+    radius: u8,
+    sigma: f64,
+    control: control::Control,
+    original_img: RgbImage,
+) -> events::BlurTask {
+    let (tx, rx) = mpsc::channel();
+    let flow = control.clone();
+
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        let (width, height) = original_img.dimensions();
+
+        let _ = tx.send(events::BlurEvent::Started { width, height });
+
+        let m = Kernel::new(radius, sigma).matrix();
+        let mut img_buf = RgbImage::new(width, height);
+        let stride = width as usize * 3;
+        let raw: &mut [u8] = &mut img_buf;
+
+        let tiles = Mutex::new(
+            raw.chunks_mut((EVENT_TILE_ROWS as usize * stride).max(1))
+                .enumerate(),
+        );
+        let rows_done = Mutex::new(0);
+        let error = Mutex::new(None);
+        let mut spawned = 0;
+
+        thread::scope(|s| {
+            // Workers are started as the control asks for more, and park while it wants fewer
+            let mut wanted = Some(flow.threads());
+
+            while let Some(n) = wanted {
+                for i in spawned..n {
+                    let (m, img, tx, flow) = (&m, &original_img, tx.clone(), &flow);
+                    let (tiles, rows_done, error) = (&tiles, &rows_done, &error);
+
+                    s.spawn(move || {
+                        while flow.wait_turn(i) {
+                            let Some((i, tile)) = tiles.lock().unwrap().next() else {
+                                flow.finish();
+                                break;
+                            };
+                            let y0 = i as u32 * EVENT_TILE_ROWS;
+                            let rows = (tile.len() / stride) as u32;
+
+                            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                                for (y, row) in (y0..).zip(tile.chunks_exact_mut(stride)) {
+                                    for (x, px) in row.chunks_exact_mut(3).enumerate() {
+                                        px.copy_from_slice(
+                                            &calculate_new_pixel(x as i32, y as i32, m, img).0,
+                                        );
+                                    }
+                                }
+                            }));
+
+                            if let Err(e) = res {
+                                let message = match e.downcast_ref::<&str>() {
+                                    Some(s) => s.to_string(),
+                                    None => e.downcast_ref::<String>().cloned().unwrap_or_default(),
+                                };
+
+                                let _ = tx.send(events::BlurEvent::WorkerError {
+                                    message: message.clone(),
+                                });
+                                error.lock().unwrap().get_or_insert(message);
+                                flow.finish();
+                                break;
+                            }
+
+                            let _ = tx.send(events::BlurEvent::TileDone {
+                                rect: region::Rect::new(0, y0, width, rows),
+                            });
+
+                            // Sent under the lock so the percentages arrive in order
+                            let mut done = rows_done.lock().unwrap();
+                            let tenths = |rows: u32| rows as u64 * 10 / height as u64;
+
+                            for tenth in tenths(*done) + 1..=tenths(*done + rows) {
+                                let _ = tx.send(events::BlurEvent::Progress {
+                                    percent: tenth as u32 * 10,
+                                });
+                            }
+                            *done += rows;
+                        }
+                    });
+                }
+
+                spawned = spawned.max(n);
+                wanted = flow.wait_for_more(spawned);
+            }
+        });
+
+        if let Some(message) = error.into_inner().unwrap() {
+            return Err(Error::Worker(message));
+        }
+
+        let _ = tx.send(events::BlurEvent::Finished {
+            stats: events::BlurStats {
+                elapsed: start.elapsed(),
+                calculations: calculations(radius, &original_img),
+                n_threads: spawned,
+                tiles: height.div_ceil(EVENT_TILE_ROWS) as usize,
+            },
+        });
+
+        Ok(img_buf)
+    });
+
+    events::BlurTask {
+        events: rx,
+        handle,
+        control,
+    }
+}
+
+/// Applies the blur of `kernel` `passes` times in a row. Repeated Gaussian blurs add up to a
+/// single blur with sigma `sigma * sqrt(passes)`, but stay within the kernel radius of each
+/// pass.
+///
+/// The passes run back to back on f32 buffers, so the image is only converted to 8 bits once
+/// at the end.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{blur_passes, Direction, Kernel};
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([((x + y) * 8) as u8; 3]));
+/// let softer = blur_passes(Kernel::new(3, 2.0), 4, Direction::Both, &img);
+///
+/// assert_eq!(softer.dimensions(), img.dimensions());
+/// ```
+pub fn blur_passes(
+    kernel: Kernel,
+    passes: usize,
+    direction: Direction,
+    img: &RgbImage,
+) -> RgbImage {
+    separable::blur_passes(img, &kernel.weights(), passes, direction)
+}
+
+/// Replaces every `block`x`block` square of the image with its average color
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pixelate;
+///
+/// let img = image::RgbImage::from_fn(4, 4, |x, _| image::Rgb([if x % 2 == 0 { 0 } else { 200 }; 3]));
+/// let pixelated = pixelate(&img, 2);
+///
+/// assert!(pixelated.pixels().all(|p| p[0] == 100));
+/// ```
+pub fn pixelate(img: &RgbImage, block: u32) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let mut out = RgbImage::new(width, height);
+
+    for by in (0..height).step_by(block as usize) {
+        for bx in (0..width).step_by(block as usize) {
+            let (w, h) = (block.min(width - bx), block.min(height - by));
+            let mut sum = [0u64; 3];
+
+            for y in by..by + h {
+                for x in bx..bx + w {
+                    for (s, &v) in sum.iter_mut().zip(&img.get_pixel(x, y).0) {
+                        *s += v as u64;
+                    }
+                }
+            }
+
+            let n = (w * h) as u64;
+            let average = Rgb(sum.map(|s| ((s + n / 2) / n) as u8));
+
+            for y in by..by + h {
+                for x in bx..bx + w {
+                    out.put_pixel(x, y, average);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Like [`blur_passes`], but rounds the result to 8 bits with `dither` instead of truncating
+/// it, so smooth blurred gradients don't band
+pub fn blur_dithered(
+    kernel: Kernel,
+    passes: usize,
+    direction: Direction,
+    dither: dither::Dither,
+    img: &RgbImage,
+) -> RgbImage {
+    let data = separable::blur_passes_f32(img, &kernel.weights(), passes, direction);
+    let out = dither::quantize(img.width(), img.height(), &data, dither);
+
+    scratch::give(data);
+    out
+}
+
+/// Blurs a 360° equirectangular panorama `passes` times. Rows wrap around so there is no seam
+/// where the left and right edges meet, and their kernel widens by 1/cos(latitude) so the
+/// blur covers the same angle on the sphere instead of smearing less near the poles.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{blur_equirect, Kernel};
+///
+/// // A panorama whose left edge is white and right edge black
+/// let img = image::RgbImage::from_fn(32, 16, |x, _| image::Rgb([if x == 0 { 255 } else { 0 }; 3]));
+/// let blurred = blur_equirect(Kernel::new(3, 2.0), 1, &img);
+///
+/// // The right edge is blurred across the seam
+/// assert!(blurred.get_pixel(31, 8)[0] > 0);
+/// ```
+pub fn blur_equirect(kernel: Kernel, passes: usize, img: &RgbImage) -> RgbImage {
+    separable::blur_equirect(img, kernel, passes)
+}
+
+/// Blurs a texture as if it tiled the plane: both axes wrap around, so the result tiles as
+/// seamlessly as the source did.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{blur_tileable, Direction, Kernel};
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([((x * y) % 7 * 36) as u8; 3]));
+/// let blurred = blur_tileable(Kernel::new(3, 2.0), 1, Direction::Both, &img);
+///
+/// assert_eq!(blurred.dimensions(), img.dimensions());
+/// ```
+pub fn blur_tileable(
+    kernel: Kernel,
+    passes: usize,
+    direction: Direction,
+    img: &RgbImage,
+) -> RgbImage {
+    separable::blur_tileable(img, &kernel.weights(), passes, direction)
+}
+
+/// Blurs the red, green and blue channels with their own standard deviations, e.g. to soften
+/// blue more than the others for a chromatic-aberration look, or to denoise chroma harder
+/// than luma.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::blur_channels;
+///
+/// let img = image::RgbImage::from_fn(9, 9, |x, y| image::Rgb([if x == 4 && y == 4 { 255 } else { 0 }; 3]));
+/// let blurred = blur_channels(4, [0.5, 1.0, 3.0], &img);
+///
+/// // The blue channel spreads furthest
+/// let center = blurred.get_pixel(4, 4);
+/// assert!(center[0] > center[1] && center[1] > center[2]);
+/// ```
+pub fn blur_channels(radius: u8, sigmas: [f64; 3], img: &RgbImage) -> RgbImage {
+    let kernels = sigmas.map(|sigma| Kernel::new(radius, sigma));
+
+    blur_channels_passes(kernels, 1, Direction::Both, img)
+}
+
+/// [`blur_channels`] with a kernel per channel, see [`Kernel::with_sigma`], applied `passes`
+/// times along the axes of `direction`
+pub fn blur_channels_passes(
+    kernels: [Kernel; 3],
+    passes: usize,
+    direction: Direction,
+    img: &RgbImage,
+) -> RgbImage {
+    separable::blur_channels(img, &kernels.map(|k| k.weights()), passes, direction)
+}
+
+/// Reads, blurs and writes an image without blocking the tokio runtime. File I/O goes through
+/// `tokio::fs` and decoding, blurring and encoding run on the blocking thread pool, with the
+/// blur itself spread over `n_threads` workers like [`blur_async`].
+///
+/// # Examples
+/// ```no_run
+/// # async fn run() -> Result<(), vincent_blur::Error> {
+/// vincent_blur::blur_tokio("photo.jpg", "photo_blurred.jpg", 10, 10.0, 4).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - [`Error::Decode`] if the source can't be read or decoded
+/// - [`Error::Encode`] if the result can't be encoded or written
+/// - [`Error::Cancelled`] if the blocking task panicked or was cancelled
+#[cfg(feature = "tokio")]
+pub async fn blur_tokio(
+    original: impl AsRef<Path>,
+    blurred: impl AsRef<Path>,
+    radius: u8,
+    sigma: f64,
+    n_threads: usize,
+) -> Result<(), Error> {
+    let original = original.as_ref().to_path_buf();
+    let blurred = blurred.as_ref().to_path_buf();
+
+    let bytes = tokio::fs::read(&original)
+        .await
+        .map_err(|e| Error::Decode(tr!("read-failed", original.display(), e)))?;
+
+    tokio::task::spawn_blocking(move || {
+        let img = codec::decode(bytes, &original, &Default::default()).map_err(Error::Decode)?;
+        let img = blur_async(radius, sigma, n_threads, img);
+
+        codec::save(&img, &blurred, &Default::default()).map_err(Error::Encode)
+    })
+    .await
+    .map_err(|_| Error::Cancelled)?
+}
+
+/// Blurs image along the x axis only, which is the first pass of the separable Gaussian blur.
+/// Useful for motion-like streaks or for composing custom multi-pass pipelines.
 ///
 /// # Examples
 /// ```
-/// use blur::{Opts, blur_sync};
+/// use vincent_blur::blur_horizontal;
+///
+/// let img = image::RgbImage::from_fn(8, 8, |x, _| image::Rgb([x as u8 * 30; 3]));
+/// let streaked = blur_horizontal(2, 1.5, &img);
+///
+/// // Columns are mixed, rows are left alone
+/// assert_eq!(streaked.get_pixel(3, 0), streaked.get_pixel(3, 7));
+/// ```
+pub fn blur_horizontal(radius: u8, sigma: f64, img: &RgbImage) -> RgbImage {
+    blur_passes(Kernel::new(radius, sigma), 1, Direction::Horizontal, img)
+}
+
+/// Blurs image along the y axis only, which is the second pass of the separable Gaussian blur
+pub fn blur_vertical(radius: u8, sigma: f64, img: &RgbImage) -> RgbImage {
+    blur_passes(Kernel::new(radius, sigma), 1, Direction::Vertical, img)
+}
+
+/// Blurs image one pixel at a time. It is the same as blur_async with 1 thread.
+///
+/// # Examples
+/// ```no_run
+/// use vincent_blur::{Opts, blur_sync};
 ///
 /// use std::env;
 /// use std::error::Error;
@@ -323,7 +2516,8 @@ pub fn blur_async(
 ///         original: original_path,
 ///         blurred: blurred_path,
 ///         n_threads,
-///     } = Opts::new(env::args());
+///         ..
+///     } = Opts::new(env::args())?;
 ///
 ///     let original_img = image::open(original_path)?.to_rgb8();
 ///
@@ -335,20 +2529,14 @@ pub fn blur_async(
 /// }
 /// ```
 pub fn blur_sync(radius: u8, sigma: f64, original_img: RgbImage) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    println!(
-        "Image dimensions: {}x{}",
-        original_img.width(),
-        original_img.height()
-    );
-
-    let m_size = (radius as u128 * 2 + 1).pow(2);
+    blur_sync_kernel(Kernel::new(radius, sigma), original_img)
+}
 
-    println!(
-        "Number of caculations: {}",
-        original_img.width() as u128 * original_img.height() as u128 * m_size
-    );
+/// [`blur_sync`] with any [`Kernel`]
+pub(crate) fn blur_sync_kernel(kernel: Kernel, original_img: RgbImage) -> RgbImage {
+    events::emit(started(kernel.radius, &original_img));
 
-    let m = get_gaussian_matrix(radius, sigma);
+    let m = kernel.matrix();
 
     let mut img_buf = ImageBuffer::new(original_img.width(), original_img.height());
 
@@ -356,7 +2544,7 @@ pub fn blur_sync(radius: u8, sigma: f64, original_img: RgbImage) -> ImageBuffer<
         *pixel = calculate_new_pixel(x as i32, y as i32, &m, &original_img);
     }
 
-    println!("Done!");
+    events::emit(events::Event::Finished);
 
     img_buf
 }
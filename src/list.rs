@@ -0,0 +1,143 @@
+use crate::build_info::BuildInfo;
+use crate::cli::{self, error::ArgError};
+use crate::tr;
+use crate::{ops, Algorithm, Error};
+use serde_json::json;
+use std::env;
+
+/// CLI options of the `list` subcommand. With none of the categories set, all of them are
+/// listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListOpts {
+    pub algorithms: bool,
+    pub formats: bool,
+    pub backends: bool,
+    pub json: bool,
+}
+
+impl ListOpts {
+    /// Constructs a new ListOpts from CLI options, skipping the program name and the `list`
+    /// subcommand
+    ///
+    /// # Errors
+    /// - If an argument isn't one of the options
+    pub fn new(cli_opts: env::Args) -> Result<ListOpts, ArgError> {
+        let mut opts = ListOpts::default();
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--algorithms" => opts.algorithms = true,
+                "--formats" => opts.formats = true,
+                "--backends" => opts.backends = true,
+                "--json" => opts.json = true,
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur list [--algorithms] [--formats] [--backends] [--json] [--help|-h]\n",
+                        "   --algorithms        Blur algorithms for --algorithm and stages for --ops.",
+                        "   --formats           Image formats sources can be read from and written to.",
+                        "   --backends          Blur implementations for --backend.",
+                        "   --json              Print a JSON object instead of text.",
+                        "   -h, --help          Print help.\n",
+                        "Lists what this build supports on this machine, one name per line under a",
+                        "heading for each category, or everything when no category is given.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => return Err(cli_opts.unknown(tr!("unknown-option", arg))),
+            }
+        }
+
+        if !(opts.algorithms || opts.formats || opts.backends) {
+            opts.algorithms = true;
+            opts.formats = true;
+            opts.backends = true;
+        }
+
+        Ok(opts)
+    }
+}
+
+/// The categories of `opts` from `info`, as text or JSON
+///
+/// # Examples
+/// ```
+/// use vincent_blur::build_info::BuildInfo;
+/// use vincent_blur::list::{render, ListOpts};
+///
+/// let info = BuildInfo::detect();
+/// let opts = ListOpts { backends: true, ..ListOpts::default() };
+///
+/// assert!(render(&opts, &info).starts_with("backends:\n   auto\n   sync\n"));
+///
+/// let opts = ListOpts { algorithms: true, json: true, ..ListOpts::default() };
+/// let json: serde_json::Value = serde_json::from_str(&render(&opts, &info)).unwrap();
+///
+/// assert_eq!(json["algorithms"][0], "gaussian");
+/// assert!(json["stages"].as_array().unwrap().contains(&"unsharp".into()));
+/// assert!(json.get("backends").is_none());
+/// ```
+pub fn render(opts: &ListOpts, info: &BuildInfo) -> String {
+    let algorithms: Vec<String> = Algorithm::ALL.iter().map(|a| a.to_string()).collect();
+    let backends: Vec<&str> = std::iter::once("auto")
+        .chain(info.backends.iter().copied())
+        .collect();
+
+    if opts.json {
+        let mut root = serde_json::Map::new();
+
+        if opts.algorithms {
+            root.insert("algorithms".into(), json!(algorithms));
+            root.insert("stages".into(), json!(ops::STAGES));
+        }
+        if opts.formats {
+            root.insert(
+                "formats".into(),
+                json!({ "decode": info.decodes, "encode": info.encodes }),
+            );
+        }
+        if opts.backends {
+            root.insert("backends".into(), json!(backends));
+        }
+
+        return serde_json::Value::Object(root).to_string();
+    }
+
+    let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+
+    if opts.algorithms {
+        sections.push((
+            "algorithms",
+            algorithms.iter().map(String::as_str).collect(),
+        ));
+        sections.push(("stages", ops::STAGES.to_vec()));
+    }
+    if opts.formats {
+        sections.push(("decode", info.decodes.clone()));
+        sections.push(("encode", info.encodes.clone()));
+    }
+    if opts.backends {
+        sections.push(("backends", backends));
+    }
+
+    sections
+        .iter()
+        .map(|(heading, names)| {
+            let names: Vec<String> = names.iter().map(|n| format!("   {n}")).collect();
+            format!("{heading}:\n{}", names.join("\n"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints what `opts` asks for on stdout
+pub fn run(opts: &ListOpts) -> Result<(), Error> {
+    println!("{}", render(opts, &BuildInfo::detect()));
+    Ok(())
+}
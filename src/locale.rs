@@ -0,0 +1,436 @@
+use std::borrow::Cow;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// Catalogs built into the binary, by language
+const BUILT_IN: [(&str, &str); 1] = [("fr", include_str!("../locales/fr.po"))];
+
+/// The English text of the keyed messages, which the other catalogs fall back to
+static ENGLISH: LazyLock<Catalog> =
+    LazyLock::new(|| Catalog::parse(include_str!("../locales/en.po")).expect("en.po is valid"));
+
+/// Translations of the user-facing messages into one language.
+///
+/// Fixed messages, like the help of the options, are keyed by their English text. Messages with
+/// parts that vary are keyed by a name, e.g. `already-blurred`, whose English text is in
+/// `locales/en.po`, and have `{}` placeholders for the parts, e.g. `{} already blurred`. The
+/// translation gets the parts in the same order, or in any order as `{0}`, `{1}`, ...
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Catalog {
+    entries: Vec<(String, String)>,
+}
+
+impl Catalog {
+    /// A catalog that leaves every message in English
+    pub fn english() -> Catalog {
+        Catalog::default()
+    }
+
+    /// Parses the `msgid`/`msgstr` pairs of a gettext PO file. Comments, contexts and plural
+    /// forms are ignored, and so are entries without a translation.
+    ///
+    /// # Errors
+    /// - If a line isn't a comment, a keyword followed by a quoted string, or a quoted string
+    ///   continuing the previous one
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::locale::Catalog;
+    ///
+    /// let catalog = Catalog::parse(r#"
+    /// #. A comment
+    /// msgid "Done!"
+    /// msgstr "Terminé !"
+    ///
+    /// msgid "unknown-option"
+    /// msgstr ""
+    /// "Option inconnue {}"
+    /// "#).unwrap();
+    ///
+    /// assert_eq!(catalog.translate("Done!"), "Terminé !");
+    /// assert_eq!(catalog.format("unknown-option", &[&"--radus"]), "Option inconnue --radus");
+    /// assert_eq!(catalog.translate("Cancelled"), "Cancelled");
+    /// ```
+    pub fn parse(src: &str) -> Result<Catalog, String> {
+        let mut entries = Vec::new();
+        let mut msgid: Option<String> = None;
+        let mut current: Option<(&str, String)> = None;
+
+        let mut finish = |current: Option<(&str, String)>, msgid: &mut Option<String>| match current
+        {
+            Some(("msgid", s)) => *msgid = Some(s),
+            Some(("msgstr", s)) => {
+                if let Some(id) = msgid.take().filter(|id| !id.is_empty() && !s.is_empty()) {
+                    entries.push((id, s));
+                }
+            }
+            _ => {}
+        };
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('"') {
+                let (_, s) = current
+                    .as_mut()
+                    .ok_or(format!("Line {}: string without a keyword", i + 1))?;
+                s.push_str(&unquote(line).ok_or(format!("Line {}: unclosed string", i + 1))?);
+                continue;
+            }
+
+            let (keyword, rest) = line
+                .split_once(char::is_whitespace)
+                .ok_or(format!("Line {}: expected a keyword and a string", i + 1))?;
+            let value = unquote(rest.trim()).ok_or(format!("Line {}: unclosed string", i + 1))?;
+
+            finish(current.take(), &mut msgid);
+            current = match keyword {
+                "msgid" => Some(("msgid", value)),
+                "msgstr" => Some(("msgstr", value)),
+                _ => None,
+            };
+        }
+
+        finish(current, &mut msgid);
+
+        Ok(Catalog { entries })
+    }
+
+    /// The catalog of `language`, e.g. `fr_CA`, falling back to the base language `fr`. A
+    /// `<language>.po` file in `dir` takes precedence over the built-in catalogs, so products
+    /// embedding blur can ship their own.
+    ///
+    /// # Errors
+    /// - If the file in `dir` can't be read or parsed
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::locale::Catalog;
+    ///
+    /// let french = Catalog::load("fr_CA", None).unwrap();
+    ///
+    /// assert_eq!(french.format("unknown-option", &[&"--radus"]), "Option inconnue --radus");
+    /// assert_eq!(Catalog::load("xx", None).unwrap(), Catalog::english());
+    /// ```
+    pub fn load(language: &str, dir: Option<&Path>) -> Result<Catalog, String> {
+        let base = language.split('_').next().unwrap_or(language);
+
+        for name in [language, base] {
+            if let Some(path) = dir.map(|d| d.join(format!("{name}.po"))) {
+                if path.exists() {
+                    let src = fs::read_to_string(&path)
+                        .map_err(|e| crate::tr!("read-failed", path.display(), e).to_string())?;
+                    return Catalog::parse(&src).map_err(|e| format!("{}: {e}", path.display()));
+                }
+            }
+
+            if let Some((_, src)) = BUILT_IN.iter().find(|(l, _)| *l == name) {
+                return Catalog::parse(src);
+            }
+        }
+
+        Ok(Catalog::english())
+    }
+
+    /// The catalog of the language the environment asks for, see [`language`], with catalogs
+    /// in `$BLUR_LOCALE_DIR` first. English when no catalog matches or one is invalid.
+    pub fn from_env() -> Catalog {
+        let dir = env::var_os("BLUR_LOCALE_DIR");
+
+        language()
+            .and_then(|l| Catalog::load(&l, dir.as_deref().map(Path::new)).ok())
+            .unwrap_or_default()
+    }
+
+    /// The fixed `message` in the catalog's language, or unchanged when the catalog has no
+    /// translation of it
+    pub fn translate<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        match self.lookup(message) {
+            Some(s) => Cow::Owned(s.to_string()),
+            None => Cow::Borrowed(message),
+        }
+    }
+
+    /// The message `key` in the catalog's language with its placeholders filled with `parts`,
+    /// falling back to the English text, then to the key itself
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::locale::Catalog;
+    ///
+    /// let catalog =
+    ///     Catalog::parse("msgid \"files-failed\"\nmsgstr \"{1} Dateien, {0} fehlerhaft\"").unwrap();
+    ///
+    /// assert_eq!(catalog.format("files-failed", &[&2, &5]), "5 Dateien, 2 fehlerhaft");
+    /// assert_eq!(catalog.format("already-blurred", &[&3]), "3 already blurred");
+    /// ```
+    pub fn format(&self, key: &str, parts: &[&dyn Display]) -> String {
+        let parts: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+
+        self.fill(key, &parts)
+    }
+
+    /// `message` in the catalog's language
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::locale::Catalog;
+    /// use vincent_blur::tr;
+    ///
+    /// let french = Catalog::load("fr", None).unwrap();
+    ///
+    /// assert_eq!(french.message(&tr!("unknown-option", "--radus")), "Option inconnue --radus");
+    /// assert_eq!(french.message(&"Done!".into()), "Terminé !");
+    /// ```
+    pub fn message(&self, message: &Message) -> String {
+        let mut parts = message.parts.clone();
+        parts.extend(message.nested.iter().map(|m| self.message(m)));
+
+        self.fill(&message.key, &parts)
+    }
+
+    fn fill(&self, key: &str, parts: &[String]) -> String {
+        let template = self
+            .lookup(key)
+            .or_else(|| ENGLISH.lookup(key))
+            .unwrap_or(key);
+
+        fill(template, parts)
+    }
+
+    fn lookup(&self, id: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(i, _)| i == id)
+            .map(|(_, s)| s.as_str())
+    }
+}
+
+/// The language of the messages from `LC_ALL`, `LC_MESSAGES` or `LANG`, the first that is set,
+/// without its encoding, e.g. `fr_FR` for `fr_FR.UTF-8`. `None` for the `C` and `POSIX` locales.
+pub fn language() -> Option<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|v| env::var(v).ok().filter(|l| !l.is_empty()))?;
+
+    parse_language(&locale)
+}
+
+/// `fr_FR` for `fr_FR.UTF-8@euro`
+///
+/// # Examples
+/// ```
+/// use vincent_blur::locale::parse_language;
+///
+/// assert_eq!(parse_language("fr_FR.UTF-8").as_deref(), Some("fr_FR"));
+/// assert_eq!(parse_language("de_DE@euro").as_deref(), Some("de_DE"));
+/// assert_eq!(parse_language("C.UTF-8"), None);
+/// ```
+pub fn parse_language(locale: &str) -> Option<String> {
+    let language = locale.split(['.', '@']).next()?;
+
+    match language {
+        "" | "C" | "POSIX" => None,
+        l => Some(l.to_string()),
+    }
+}
+
+/// `translation` with its `{}` and `{N}` replaced by `parts`
+fn fill(translation: &str, parts: &[String]) -> String {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut rest = translation;
+
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let placeholder = rest.find('}').and_then(|close| {
+            let index = match &rest[1..close] {
+                "" => next,
+                n => n.parse().ok()?,
+            };
+            Some((index, close))
+        });
+
+        match placeholder.and_then(|(i, close)| Some((i, close, parts.get(i)?))) {
+            Some((i, close, part)) => {
+                out.push_str(part);
+                next = i + 1;
+                rest = &rest[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// A PO string without its quotes, with `\"`, `\\`, `\n` and `\t` unescaped
+fn unquote(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                c => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+
+    Some(out)
+}
+
+static CATALOG: RwLock<Option<Arc<Catalog>>> = RwLock::new(None);
+
+/// Translates every following message with `catalog`. Until this is called, messages stay in
+/// English.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::locale::{self, Catalog};
+///
+/// assert_eq!(locale::tr("Done!"), "Done!");
+///
+/// locale::set_catalog(Catalog::parse("msgid \"Done!\"\nmsgstr \"Fertig!\"").unwrap());
+/// assert_eq!(locale::tr("Done!"), "Fertig!");
+/// ```
+pub fn set_catalog(catalog: Catalog) {
+    *CATALOG.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(catalog));
+}
+
+/// The fixed `message` in the language of the current catalog, see [`set_catalog`], for the
+/// text the `blur` binary prints itself, like its help. Messages that are returned rather than
+/// printed are [`Message`]s instead.
+pub fn tr(message: &str) -> Cow<'_, str> {
+    let catalog = CATALOG.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+    match catalog {
+        Some(catalog) => Cow::Owned(catalog.translate(message).into_owned()),
+        None => Cow::Borrowed(message),
+    }
+}
+
+/// A user-facing message kept as its key and the parts that vary, so it is only translated
+/// where it is shown. It displays in English whatever the current catalog, so errors returned
+/// by the library don't depend on the locale; the `blur` binary prints it with
+/// [`Message::translate`].
+///
+/// The key is a name in `locales/en.po`, e.g. `already-blurred`, or the English text of a
+/// fixed message. A message made from a `String` is such a fixed message.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::locale::{self, Catalog, Message};
+/// use vincent_blur::tr;
+///
+/// let message = tr!("unknown-option", "--radus");
+/// locale::set_catalog(Catalog::load("fr", None).unwrap());
+///
+/// assert_eq!(message.to_string(), "Unknown option --radus");
+/// assert_eq!(message.translate(), "Option inconnue --radus");
+/// assert_eq!(Message::from("Done!").translate(), "Terminé !");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    key: Cow<'static, str>,
+    parts: Vec<String>,
+    /// Messages filling the placeholders after `parts`, translated along with this one
+    nested: Vec<Message>,
+}
+
+impl Message {
+    /// The message `key` with its placeholders filled with `parts`, see [`tr!`](crate::tr)
+    pub fn new(key: &'static str, parts: Vec<String>) -> Message {
+        Message {
+            key: Cow::Borrowed(key),
+            parts,
+            nested: Vec::new(),
+        }
+    }
+
+    /// The message with `message` as its next part, after the ones given to
+    /// [`tr!`](crate::tr), so a message that wraps another error translates it as well
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::tr;
+    ///
+    /// let message = tr!("invalid-job", "job.toml").with(tr!("unknown-dither", "halftone"));
+    ///
+    /// assert_eq!(message.to_string(), "Invalid job job.toml: Unknown dither halftone");
+    /// ```
+    pub fn with(mut self, message: Message) -> Message {
+        self.nested.push(message);
+        self
+    }
+
+    /// The message in the language of the current catalog, see [`set_catalog`]
+    pub fn translate(&self) -> String {
+        let catalog = CATALOG.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+        catalog.as_deref().unwrap_or(&ENGLISH).message(self)
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Message {
+        Message {
+            key: Cow::Owned(text),
+            parts: Vec::new(),
+            nested: Vec::new(),
+        }
+    }
+}
+
+impl From<&'static str> for Message {
+    fn from(text: &'static str) -> Message {
+        Message {
+            key: Cow::Borrowed(text),
+            parts: Vec::new(),
+            nested: Vec::new(),
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&ENGLISH.message(self))
+    }
+}
+
+impl std::error::Error for Message {}
+
+/// The [`Message`] `key` with its placeholders filled with the following arguments. It
+/// displays in English, and [`Message::translate`] gives it in the language of the current
+/// catalog.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::tr;
+///
+/// assert_eq!(tr!("already-blurred", 3).to_string(), "3 already blurred");
+/// assert_eq!(tr!("image-dimensions", 640, 480).translate(), "Image dimensions: 640x480");
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($key:expr $(, $part:expr)* $(,)?) => {
+        $crate::locale::Message::new($key, vec![$(::std::string::ToString::to_string(&$part)),*])
+    };
+}
@@ -0,0 +1,87 @@
+//! Records of the steps of a blur through the [`log`](https://docs.rs/log) facade: files opened,
+//! decoded and encoded at `info`, parameters and scheduling decisions at `debug`. Messages are
+//! `key=value` pairs after a short description, so log pipelines can pick the fields out.
+//!
+//! Without the `log` feature the macros compile to nothing and the library stays silent.
+
+/// Logs at `info` with the `log` feature, does nothing otherwise
+macro_rules! info {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::info!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format!($($arg)+);
+        }
+    };
+}
+
+/// Logs at `debug` with the `log` feature, does nothing otherwise
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::debug!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use {debug, info};
+
+/// Writes the records to stderr as `level target: message`, for the `blur` binary and other
+/// programs that don't install a logger of their own
+#[cfg(feature = "log")]
+pub struct Stderr;
+
+#[cfg(feature = "log")]
+impl log::Log for Stderr {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "{:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs [`Stderr`] at the level of `$BLUR_LOG`, e.g. `debug`. Returns whether records are
+/// logged, which is not the case when the variable is unset or invalid, or another logger is
+/// already installed.
+///
+/// # Examples
+/// ```
+/// std::env::set_var("BLUR_LOG", "debug");
+///
+/// assert!(vincent_blur::logging::init_from_env());
+/// assert_eq!(log::max_level(), log::LevelFilter::Debug);
+/// ```
+#[cfg(feature = "log")]
+pub fn init_from_env() -> bool {
+    let Some(level) = std::env::var("BLUR_LOG")
+        .ok()
+        .and_then(|l| l.parse::<log::LevelFilter>().ok())
+        .filter(|l| *l != log::LevelFilter::Off)
+    else {
+        return false;
+    };
+
+    static LOGGER: Stderr = Stderr;
+
+    if log::set_logger(&LOGGER).is_err() {
+        return false;
+    }
+    log::set_max_level(level);
+
+    true
+}
@@ -1,22 +1,1213 @@
 use vincent_blur::*;
 
+use cli::error::{ArgError, Fault};
+use image::RgbImage;
+use pipeline::{Filter, FloatImage, Pipeline};
 use std::env;
-use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let Opts {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", cli::error::report(&e));
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<(), Error> {
+    locale::set_catalog(locale::Catalog::from_env());
+    events::set_sink(events::Console);
+
+    #[cfg(feature = "log")]
+    if logging::init_from_env() {
+        events::set_sink(events::Log);
+    }
+
+    if env::args().nth(1).as_deref() == Some("temporal") {
+        let opts = temporal::TemporalOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return temporal::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("list") {
+        let opts = list::ListOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return list::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("completions") {
+        let opts =
+            cli::completions::CompletionsOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return cli::completions::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("thumb") {
+        let opts = thumb::ThumbOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return thumb::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("denoise") {
+        let opts = denoise::DenoiseOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return denoise::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("verify-redaction") {
+        let opts = audit::VerifyOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return audit::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("testgen") {
+        let opts = testgen::TestgenOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        return testgen::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("bench") {
+        let opts = bench::BenchOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        events::set_sink(events::Silent);
+        return bench::run(&opts);
+    }
+
+    if env::args().nth(1).as_deref() == Some("--self-test") {
+        let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        events::set_sink(events::Silent);
+        return self_test(n_threads);
+    }
+
+    if env::args().nth(1).as_deref() == Some("run") {
+        let opts = job::RunOpts::new(env::args()).map_err(Error::InvalidArgs)?;
+        let report = job::run(job::Job::load(&opts.job)?)?;
+
+        if opts.json_summary {
+            println!("{}", report.to_json());
+        } else {
+            eprintln!("{}", report.translate());
+        }
+
+        return report.result();
+    }
+
+    let mut opts = Opts::new(env::args()).map_err(Error::InvalidArgs)?;
+
+    if opts.preview {
+        opts = preview(&opts)?;
+    }
+
+    if opts.gui {
+        opts = gui(&opts)?;
+    }
+
+    if opts.nice {
+        if let Err(e) = affinity::lower_priority(affinity::NICENESS) {
+            eprintln!("{}", tr!("nice-failed", e).translate());
+        }
+    }
+
+    if opts.timings {
+        timings::start();
+    }
+
+    let opts = Arc::new(opts);
+    let pipeline = Arc::new(build_pipeline(&opts)?);
+
+    if opts.batch.is_empty() {
+        let result = blur_file(&opts, &pipeline, &opts.original, &opts.blurred);
+        print_timings();
+        return result;
+    }
+
+    let mut files = opts.batch.clone();
+    let mut cache = open_cache(&opts);
+    let mut skipped = Vec::new();
+
+    if let Some(cache) = &cache {
+        let (fresh, stale): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(original, blurred)| cache.is_fresh(original, blurred));
+        skipped = fresh.into_iter().map(|(original, _)| original).collect();
+        files = stale;
+    }
+
+    let mut report = if opts.jobs == 1 && opts.srcset.is_empty() {
+        let decode_opts = Arc::clone(&opts);
+        let blur_opts = Arc::clone(&opts);
+        let encode_opts = Arc::clone(&opts);
+        batch::pipelined(
+            &files,
+            opts.keep_going,
+            move |original, blurred| decode_file(&decode_opts, original, blurred),
+            |frame| blur_frame(&blur_opts, &pipeline, frame),
+            move |frame, blurred| encode_file(&encode_opts, &frame, blurred),
+        )?
+    } else {
+        let opts = Arc::clone(&opts);
+        batch::run(
+            &files,
+            opts.jobs,
+            opts.keep_going,
+            move |original, blurred| blur_file(&opts, &pipeline, original, blurred),
+        )?
+    };
+    report.skipped = skipped;
+
+    if let Some(cache) = &mut cache {
+        update_cache(cache, &files, &report);
+    }
+
+    print_timings();
+
+    if opts.json_summary {
+        println!("{}", report.to_json());
+    } else {
+        eprintln!("{}", report.translate());
+    }
+
+    report.result()
+}
+
+/// The record of the files already blurred with the settings of this run, unless `--no-cache`
+/// is given or the settings don't always give the same output
+fn open_cache(opts: &Opts) -> Option<cache::Cache> {
+    if opts.no_cache || !opts.srcset.is_empty() {
+        return None;
+    }
+
+    let params = cache::fingerprint(env::args().skip(1))?;
+
+    Some(cache::Cache::load(Path::new(cache::FILE), &params))
+}
+
+/// Records the files of the batch that were blurred, and saves `cache`
+fn update_cache(cache: &mut cache::Cache, files: &[(PathBuf, PathBuf)], report: &batch::Report) {
+    let saved = files
+        .iter()
+        .filter(|(original, _)| report.succeeded.contains(original))
+        .try_for_each(|(original, blurred)| cache.record(original, blurred))
+        .and_then(|()| cache.save());
+
+    if let Err(e) = saved {
+        eprintln!("{}", tr!("cache-failed", cache::FILE).with(e).translate());
+    }
+}
+
+/// Runs [`backend::self_test`], reporting every check on stderr
+///
+/// # Errors
+/// - [`Error::Mismatch`] if a backend strays from [`backend::SyncCpu`] or from the kernel
+fn self_test(n_threads: usize) -> Result<(), Error> {
+    let checks = backend::self_test(n_threads);
+
+    for check in &checks {
+        match check {
+            backend::Check::Matches {
+                backend,
+                radius,
+                sigma,
+            } => eprintln!(
+                "{}",
+                tr!("backend-matches", backend, radius, sigma).translate()
+            ),
+            backend::Check::Differs {
+                comparison: c,
+                radius,
+                sigma,
+            } => eprintln!(
+                "{}",
+                tr!(
+                    "backend-differs",
+                    c.backend,
+                    c.max_diff,
+                    c.at.0,
+                    c.at.1,
+                    radius,
+                    sigma
+                )
+                .translate()
+            ),
+            backend::Check::WrongKernel {
+                backend,
+                radius,
+                sigma,
+                error,
+            } => eprintln!(
+                "{}",
+                tr!("backend-wrong-kernel", backend, radius, sigma)
+                    .with(error.clone())
+                    .translate()
+            ),
+        }
+    }
+
+    match checks.iter().filter(|c| c.is_failure()).count() {
+        0 => Ok(()),
+        n => Err(Error::Mismatch(tr!("backend-checks-failed", n))),
+    }
+}
+
+/// Prints the time spent in each stage on stderr, if `--timings` started timing them
+fn print_timings() {
+    if let Some(timings) = timings::finish() {
+        eprintln!("{timings}");
+    }
+}
+
+fn blur_file(
+    opts: &Opts,
+    pipeline: &Pipeline,
+    original_path: &Path,
+    blurred_path: &Path,
+) -> Result<(), Error> {
+    if !opts.srcset.is_empty() {
+        return blur_srcset(opts, pipeline, original_path, blurred_path);
+    }
+
+    let frame = decode_file(opts, original_path, blurred_path)?;
+    let frame = blur_frame(opts, pipeline, frame)?;
+
+    encode_file(opts, &frame, blurred_path)
+}
+
+/// Blurs the source once at full size and scales the result down to every `--srcset` width,
+/// so each variant shows the same blur
+fn blur_srcset(
+    opts: &Opts,
+    pipeline: &Pipeline,
+    original_path: &Path,
+    blurred_path: &Path,
+) -> Result<(), Error> {
+    if blurred_path == Path::new(codec::STDIO) {
+        return Err(Error::InvalidArgs(ArgError::new(
+            Fault::Option("--srcset"),
+            "--srcset can't write to stdout",
+        )));
+    }
+
+    // The destination itself is never written, only its variants
+    let frame = blur_frame(opts, pipeline, decode_source(opts, original_path)?)?;
+    let img = match frame.image {
+        Image::Rgb(img) => img,
+        Image::Cmyk(cmyk) => cmyk.to_rgb(),
+    };
+
+    let variants = srcset::variants(blurred_path, img.width(), img.height(), &opts.srcset);
+
+    if variants.is_empty() {
+        return Err(Error::InvalidArgs(
+            tr!("srcset-too-wide", img.width()).into(),
+        ));
+    }
+
+    for variant in &variants {
+        check_destination(opts, &variant.path)?;
+    }
+
+    for variant in &variants {
+        let resized = Frame {
+            image: Image::Rgb(srcset::resize(&img, variant, opts.filter)),
+            source: frame.source.clone(),
+        };
+
+        encode_file(opts, &resized, &variant.path)?;
+    }
+
+    match &opts.srcset_manifest {
+        Some(manifest) => codec::write(manifest, srcset::manifest(manifest, &variants).as_bytes())
+            .map_err(Error::Encode),
+        None => Ok(()),
+    }
+}
+
+/// A decoded source
+struct Frame {
+    image: Image,
+    /// Metadata of the source file when its times or permissions are carried over
+    source: Option<fs::Metadata>,
+}
+
+/// Decoded pixels, kept as CMYK planes when the source was a CMYK TIFF
+enum Image {
+    Rgb(RgbImage),
+    Cmyk(codec::CmykImage),
+}
+
+fn decode_file(opts: &Opts, original_path: &Path, blurred_path: &Path) -> Result<Frame, Error> {
+    check_destination(opts, blurred_path)?;
+    decode_source(opts, original_path)
+}
+
+/// Refuses to overwrite an existing `blurred_path` without `--force`
+fn check_destination(opts: &Opts, blurred_path: &Path) -> Result<(), Error> {
+    if !opts.force && blurred_path != Path::new(codec::STDIO) && blurred_path.exists() {
+        return Err(Error::InvalidArgs(
+            tr!("already-exists", blurred_path.display()).into(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn decode_source(opts: &Opts, original_path: &Path) -> Result<Frame, Error> {
+    let decode_opts = decode_options(opts);
+    let image = timings::time(timings::Stage::Decode, || {
+        let bytes = codec::read(original_path).map_err(Error::Decode)?;
+
+        if let Some((width, height)) = codec::dimensions(&bytes) {
+            codec::check_limits(width, height, &decode_opts).map_err(Error::TooLarge)?;
+        }
+
+        Ok(
+            match codec::decode_cmyk(&bytes, original_path).map_err(Error::Decode)? {
+                Some(cmyk) => Image::Cmyk(cmyk),
+                None => Image::Rgb(
+                    codec::decode(bytes, original_path, &decode_opts).map_err(Error::Decode)?,
+                ),
+            },
+        )
+    })?;
+
+    // The size of SVG sources is only known once they are rasterized
+    if let Image::Rgb(img) = &image {
+        codec::check_limits(img.width(), img.height(), &decode_opts).map_err(Error::TooLarge)?;
+    }
+
+    let source = match opts.preserve_times || opts.preserve_mode {
+        true if original_path != Path::new(codec::STDIO) => Some(
+            fs::metadata(original_path)
+                .map_err(|e| Error::Decode(tr!("read-failed", original_path.display(), e)))?,
+        ),
+        _ => None,
+    };
+
+    Ok(Frame { image, source })
+}
+
+fn decode_options(opts: &Opts) -> codec::DecodeOptions {
+    codec::DecodeOptions {
+        raster_size: opts.raster_size,
+        max_dimension: opts.max_dimension,
+        max_pixels: opts.max_pixels,
+        max_alloc: opts.max_memory,
+    }
+}
+
+/// Blurs `frame` through `pipeline`, within `--timeout` of the moment it starts so the time a
+/// frame waits in a batch queue doesn't count against it
+fn blur_frame(opts: &Opts, pipeline: &Pipeline, frame: Frame) -> Result<Frame, Error> {
+    let deadline = opts.timeout.map(|t| Instant::now() + t);
+    let run = |img: &RgbImage| match deadline {
+        Some(deadline) => pipeline.run_until(img, deadline),
+        None => pipeline.run(img),
+    };
+
+    let image = match &frame.image {
+        Image::Rgb(img) => Image::Rgb(run(img)?),
+        Image::Cmyk(cmyk) => Image::Cmyk(codec::CmykImage {
+            cmy: run(&cmyk.cmy)?,
+            k: run(&cmyk.k)?,
+        }),
+    };
+
+    Ok(Frame { image, ..frame })
+}
+
+fn encode_file(opts: &Opts, frame: &Frame, blurred_path: &Path) -> Result<(), Error> {
+    let encode_opts = codec::EncodeOptions {
+        format: opts.format.clone(),
+        progressive: opts.progressive,
+        interlaced: opts.interlaced,
+    };
+
+    let bytes = timings::time(timings::Stage::Encode, || {
+        let bytes = match &frame.image {
+            Image::Rgb(img) => codec::encode(img, blurred_path, &encode_opts)?,
+            Image::Cmyk(cmyk) => codec::encode_cmyk(cmyk, blurred_path, &encode_opts)?,
+        };
+        codec::write(blurred_path, &bytes).map(|()| bytes)
+    })
+    .map_err(Error::Encode)?;
+
+    if opts.progressive_save.is_some() {
+        let _ = fs::remove_file(naming::partial(blurred_path));
+    }
+
+    if opts.emit_hash || opts.hash_sidecar {
+        emit_hash(opts, &bytes, blurred_path)?;
+    }
+
+    match &frame.source {
+        Some(source) if blurred_path != Path::new(codec::STDIO) => {
+            preserve(opts, source, blurred_path)
+                .map_err(|e| Error::Encode(tr!("update-failed", blurred_path.display(), e)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Prints the hash of the `bytes` written to `blurred_path` and writes it to its sidecar, as
+/// asked by `--emit-hash` and `--hash-sidecar`
+fn emit_hash(opts: &Opts, bytes: &[u8], blurred_path: &Path) -> Result<(), Error> {
+    let hash = cache::sha256(bytes);
+    let to_stdout = blurred_path == Path::new(codec::STDIO);
+
+    if opts.emit_hash {
+        match to_stdout {
+            true => eprintln!("{hash}  {}", blurred_path.display()),
+            false => println!("{hash}  {}", blurred_path.display()),
+        }
+    }
+
+    if opts.hash_sidecar && !to_stdout {
+        codec::write(
+            &naming::sidecar(blurred_path),
+            format!("{hash}\n").as_bytes(),
+        )
+        .map_err(Error::Encode)?;
+    }
+
+    Ok(())
+}
+
+/// Carries the times and permissions of the source over to the destination, as asked by
+/// `--preserve-times` and `--preserve-mode`
+fn preserve(opts: &Opts, source: &fs::Metadata, path: &Path) -> std::io::Result<()> {
+    if opts.preserve_times {
+        let times = fs::FileTimes::new()
+            .set_accessed(source.accessed()?)
+            .set_modified(source.modified()?);
+
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_times(times)?;
+    }
+
+    if opts.preserve_mode {
+        fs::set_permissions(path, source.permissions())?;
+    }
+
+    Ok(())
+}
+
+/// Every stage the options ask for, quantized once at the end
+fn build_pipeline(opts: &Arc<Opts>) -> Result<Pipeline, Error> {
+    if let Some(src) = &opts.ops {
+        return match ops::parse_with(src, &load_plugins(opts)?) {
+            Ok(pipeline) => Ok(pipeline
+                .dither(opts.dither)
+                .memory_budget(memory_budget(opts))),
+            Err(e) => Err(Error::InvalidArgs(
+                tr!("invalid-ops", e.pointer(src)).with(e.into()).into(),
+            )),
+        };
+    }
+
+    let mut pipeline = Pipeline::new()
+        .dither(opts.dither)
+        .memory_budget(memory_budget(opts));
+
+    if let Some(rotate) = opts.rotate {
+        pipeline.push(Box::new(rotate));
+    }
+
+    if let Some(flip) = opts.flip {
+        pipeline.push(Box::new(flip));
+    }
+
+    if let Some(rect) = opts.crop {
+        pipeline.push(Box::new(pipeline::Crop { rect }));
+    }
+
+    if let Some((width, height)) = opts.crop_center {
+        pipeline.push(Box::new(pipeline::CropCenter { width, height }));
+    }
+
+    if let Some((width, height)) = opts.resize {
+        pipeline.push(Box::new(pipeline::Resize {
+            width,
+            height,
+            filter: opts.filter,
+        }));
+    }
+
+    if let Some(factor) = opts.scale {
+        pipeline.push(Box::new(pipeline::Scale {
+            factor,
+            filter: opts.filter,
+        }));
+    }
+
+    pipeline.push(Box::new(Render {
+        opts: Arc::clone(opts),
+    }));
+
+    if opts.grayscale {
+        pipeline.push(Box::new(pipeline::Grayscale));
+    }
+
+    if let Some((dark, light)) = opts.duotone {
+        pipeline.push(Box::new(pipeline::Duotone { dark, light }));
+    }
+
+    if !opts.tone.is_identity() {
+        pipeline.push(Box::new(opts.tone));
+    }
+
+    if let Some(tint) = opts.tint {
+        pipeline.push(Box::new(tint));
+    }
+
+    if let Some(amount) = opts.grain {
+        pipeline.push(Box::new(pipeline::Noise {
+            amount,
+            seed: opts.seed,
+        }));
+    }
+
+    Ok(pipeline)
+}
+
+/// Memory the blur of an image may use: `--max-memory`, or what the system has available if
+/// that is less
+fn memory_budget(opts: &Opts) -> Option<u64> {
+    [opts.max_memory, memory::available()]
+        .into_iter()
+        .flatten()
+        .min()
+}
+
+/// Largest side of the interactive previews, in pixels
+#[cfg(any(feature = "preview", feature = "gui"))]
+const PREVIEW_SIZE: u32 = 640;
+
+/// The source downscaled for tuning the options interactively
+#[cfg(any(feature = "preview", feature = "gui"))]
+struct PreviewSource {
+    img: RgbImage,
+    factor: f64,
+    /// Whether `img` is the full resolution source, so stages in pixel coordinates match
+    full_res: bool,
+}
+
+#[cfg(any(feature = "preview", feature = "gui"))]
+impl PreviewSource {
+    fn open(opts: &Opts) -> Result<Self, Error> {
+        let original = codec::open(&opts.original, &decode_options(opts)).map_err(Error::Decode)?;
+
+        let full_res = opts.crop.is_some()
+            || opts.crop_center.is_some()
+            || opts.resize.is_some()
+            || opts.ops.is_some();
+
+        Ok(match full_res {
+            true => PreviewSource {
+                img: original,
+                factor: 1.0,
+                full_res,
+            },
+            false => {
+                let (img, factor) = layout::fit_within(&original, PREVIEW_SIZE);
+                PreviewSource {
+                    img,
+                    factor,
+                    full_res,
+                }
+            }
+        })
+    }
+
+    /// Renders with `opts`, scaling the radius, sigma and regions down with the image. Also
+    /// returns the size in the render of one pixel of the full resolution output.
+    fn render(&self, opts: &Opts) -> Result<(RgbImage, f64), Error> {
+        let scale = |r: &region::Rect| {
+            let v = |v: u32| (v as f64 * self.factor).round() as u32;
+            region::Rect::new(v(r.x), v(r.y), v(r.width).max(1), v(r.height).max(1))
+        };
+
+        let mut scaled = opts.clone();
+        scaled.radius = ((opts.radius as f64 * self.factor).round() as u8).max(1);
+        scaled.sigma = opts.sigma * self.factor;
+        scaled.regions = opts.regions.iter().map(scale).collect();
+        scaled.keep_regions = opts.keep_regions.iter().map(scale).collect();
+        scaled.mask_feather = opts
+            .mask_feather
+            .map(|f| ((f as f64 * self.factor).round() as u8).max(1));
+        scaled.shapes = opts
+            .shapes
+            .iter()
+            .map(|s| s.scale(self.factor as f32))
+            .collect();
+
+        let img = build_pipeline(&Arc::new(scaled))?.run(&self.img)?;
+
+        Ok(match self.full_res {
+            true => layout::fit_within(&img, PREVIEW_SIZE),
+            false => (img, self.factor),
+        })
+    }
+}
+
+/// Lets the user tune the radius and sigma on a downscaled render, returning the options with
+/// the chosen values
+#[cfg(feature = "preview")]
+fn preview(opts: &Opts) -> Result<Opts, Error> {
+    let source = PreviewSource::open(opts)?;
+
+    let (radius, sigma) = preview::interactive(opts.radius, opts.sigma, |radius, sigma| {
+        source
+            .render(&Opts {
+                radius,
+                sigma,
+                ..opts.clone()
+            })
+            .map(|(img, _)| img)
+    })?;
+
+    Ok(Opts {
         radius,
         sigma,
-        original: original_path,
-        blurred: blurred_path,
-        n_threads,
-    } = Opts::new(env::args())?;
+        ..opts.clone()
+    })
+}
 
-    let original_img = image::open(original_path)?.to_rgb8();
+#[cfg(not(feature = "preview"))]
+fn preview(_opts: &Opts) -> Result<Opts, Error> {
+    Err(Error::InvalidArgs(ArgError::new(
+        Fault::Option("--preview"),
+        "--preview requires building with the preview feature",
+    )))
+}
 
-    let img_buf = blur_async(radius, sigma, n_threads, original_img);
+/// Lets the user tune the radius, sigma and algorithm and draw regions in a window, returning
+/// the options with the chosen values. The equivalent flags are printed so the result can be
+/// reproduced without the window.
+#[cfg(feature = "gui")]
+fn gui(opts: &Opts) -> Result<Opts, Error> {
+    let source = PreviewSource::open(opts)?;
+    let base = opts.clone();
 
-    img_buf.save(blurred_path)?;
+    let settings = gui::tune(
+        &format!("blur {}", opts.original.display()),
+        gui::Settings {
+            radius: opts.radius,
+            sigma: opts.sigma,
+            algorithm: opts.algorithm,
+            regions: opts.regions.clone(),
+            keep_regions: opts.keep_regions.clone(),
+        },
+        move |s| {
+            source.render(&Opts {
+                radius: s.radius,
+                sigma: s.sigma,
+                algorithm: s.algorithm,
+                regions: s.regions.clone(),
+                keep_regions: s.keep_regions.clone(),
+                ..base.clone()
+            })
+        },
+    )?;
 
-    Ok(())
+    eprintln!("{}", tr!("options", settings.flags()).translate());
+
+    Ok(Opts {
+        radius: settings.radius,
+        sigma: settings.sigma,
+        algorithm: settings.algorithm,
+        regions: settings.regions,
+        keep_regions: settings.keep_regions,
+        ..opts.clone()
+    })
+}
+
+#[cfg(not(feature = "gui"))]
+fn gui(_opts: &Opts) -> Result<Opts, Error> {
+    Err(Error::InvalidArgs(ArgError::new(
+        Fault::Option("--gui"),
+        "--gui requires building with the gui feature",
+    )))
+}
+
+/// Stages of the plugins in `--plugin-dir`
+#[cfg(feature = "plugins")]
+fn load_plugins(opts: &Opts) -> Result<Vec<Arc<dyn ops::StageFactory>>, Error> {
+    let Some(dir) = &opts.plugin_dir else {
+        return Ok(Vec::new());
+    };
+
+    // The user chose to trust the libraries in this directory
+    let plugins = unsafe { plugin::discover(dir) }.map_err(|e| Error::InvalidArgs(e.into()))?;
+
+    Ok(plugins
+        .into_iter()
+        .map(|p| p as Arc<dyn ops::StageFactory>)
+        .collect())
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load_plugins(opts: &Opts) -> Result<Vec<Arc<dyn ops::StageFactory>>, Error> {
+    match opts.plugin_dir {
+        Some(_) => Err(Error::InvalidArgs(ArgError::new(
+            Fault::Option("--plugin-dir"),
+            "--plugin-dir requires building with the plugins feature",
+        ))),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The blur itself along with whatever mode the options select
+struct Render {
+    opts: Arc<Opts>,
+}
+
+impl Filter for Render {
+    fn name(&self) -> &str {
+        "blur"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let rgb = img.to_rgb(dither::Dither::None);
+
+        let tuned;
+        let opts = match self.opts.target_ssim {
+            Some(target) => {
+                let sigma = metrics::sigma_for_ssim(&rgb, target);
+                eprintln!(
+                    "{}",
+                    tr!("sigma-for-ssim", format!("{sigma:.2}"), target).translate()
+                );
+
+                tuned = Opts {
+                    radius: metrics::radius_for(sigma),
+                    sigma,
+                    radius_derived: true,
+                    ..Opts::clone(&self.opts)
+                };
+                &tuned
+            }
+            None => &*self.opts,
+        };
+
+        blur_settled(opts, img, rgb)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        let opts = &self.opts;
+
+        match opts.direction {
+            _ if !blurs_locally(opts) => None,
+            Direction::Horizontal => Some(0),
+            _ => Some(opts.kernel().radius as u32 * opts.passes as u32),
+        }
+    }
+}
+
+/// Whether every pixel of the blur only depends on the pixels within its kernel, so it can
+/// run on strips of the image. Masks, detectors and the blurs that see the whole image can't.
+fn blurs_locally(opts: &Opts) -> bool {
+    let masked = opts.portrait_model.is_some()
+        || opts.protect_edges.is_some()
+        || opts.luma_range.is_some()
+        || opts.key_color.is_some()
+        || opts.vignette
+        || !opts.regions.is_empty()
+        || !opts.relative_regions.is_empty()
+        || !opts.shapes.is_empty()
+        || !opts.keep_regions.is_empty()
+        || opts.blend.is_some();
+
+    opts.algorithm == Algorithm::Gaussian
+        && opts.projection == Projection::Flat
+        && opts.target_ssim.is_none()
+        && !opts.adaptive
+        && !opts.tileable
+        && !(opts.redact_text || opts.redact_codes || opts.redact_plates)
+        && !opts.avatar
+        && !opts.bloom
+        && opts.pad_to.is_none()
+        && opts.progressive_save.is_none()
+        && !masked
+}
+
+/// Blurs `img`, also given as `rgb`, as [`Render`] does once `opts` are settled
+fn blur_settled(opts: &Opts, img: FloatImage, rgb: RgbImage) -> Result<FloatImage, Error> {
+    if !blurs_in_float(opts) {
+        img.recycle();
+        return Ok(FloatImage::from_rgb(&render_image(opts, rgb)?));
+    }
+
+    let blurred = pipeline::Gaussian {
+        kernel: opts.kernel(),
+        passes: opts.passes,
+        direction: opts.direction,
+    }
+    .apply(img.clone())?;
+
+    match build_mask(opts, &rgb)? {
+        Some(mask) => Ok(mask.composite_float(&img, &blurred)),
+        None => Ok(blurred),
+    }
+}
+
+/// Whether the blur can stay in f32 between the stages around it. Only plain Gaussian blurs
+/// can, and they are only worth it when another stage would otherwise see rounded values, be it
+/// a resampling before the blur or a color adjustment after it; a lone blur is left to the
+/// faster multithreaded backends.
+fn blurs_in_float(opts: &Opts) -> bool {
+    let plain = opts.algorithm == Algorithm::Gaussian
+        && opts.projection == Projection::Flat
+        && !opts.tileable
+        && opts.channel_sigmas.is_none()
+        && !(opts.redact_text || opts.redact_codes || opts.redact_plates)
+        && !opts.avatar
+        && !opts.bloom
+        && !opts.adaptive
+        && opts.progressive_save.is_none()
+        && opts.pad_to.is_none();
+
+    let resampled = opts.resize.is_some() || opts.scale.is_some();
+    let adjusted =
+        opts.grayscale || opts.duotone.is_some() || !opts.tone.is_identity() || opts.tint.is_some();
+
+    let chained = opts.passes > 1
+        || opts.direction != Direction::Both
+        || opts.dither != dither::Dither::None
+        || opts.grain.is_some()
+        || resampled
+        || adjusted;
+
+    plain && chained
+}
+
+fn render_image(opts: &Opts, img: RgbImage) -> Result<RgbImage, Error> {
+    if opts.redact_text || opts.redact_codes || opts.redact_plates {
+        return redact_image(opts, img);
+    }
+
+    if opts.avatar {
+        return avatar_image(opts, img);
+    }
+
+    if opts.bloom {
+        let glow = blur_image(opts, effects::bright_pass(&img, opts.threshold))?;
+        return Ok(effects::screen(&img, &glow));
+    }
+
+    match opts.pad_to {
+        Some(aspect) => pad_image(opts, img, aspect),
+        None => blur_image(opts, img),
+    }
+}
+
+/// Hides the regions found by the --redact-* detectors and leaves the rest of the image as is
+fn redact_image(opts: &Opts, img: RgbImage) -> Result<RgbImage, Error> {
+    let rects = detect_regions(opts, &img)?;
+
+    if rects.is_empty() {
+        return Ok(img);
+    }
+
+    let hidden = match opts.redact_style {
+        RedactStyle::Pixelate => pixelate(&img, opts.pixel_size),
+        RedactStyle::Blur => blur_image(opts, img.clone())?,
+    };
+
+    Ok(mask::rects(img.width(), img.height(), &rects).composite(&img, &hidden))
+}
+
+#[cfg(feature = "redact")]
+fn detect_regions(opts: &Opts, img: &RgbImage) -> Result<Vec<region::Rect>, Error> {
+    let mut rects = Vec::new();
+
+    if opts.redact_text {
+        rects.extend(detect::text_regions(img));
+    }
+
+    if opts.redact_codes {
+        rects.extend(detect::code_regions(img));
+    }
+
+    if opts.redact_plates {
+        rects.extend(detect::plate_regions(img));
+    }
+
+    Ok(rects)
+}
+
+#[cfg(not(feature = "redact"))]
+fn detect_regions(_: &Opts, _: &RgbImage) -> Result<Vec<region::Rect>, Error> {
+    Err(Error::InvalidArgs(
+        "--redact-* requires building with the `redact` feature".into(),
+    ))
+}
+
+/// Diameter of the --avatar circle as a fraction of the square's side
+const AVATAR_INNER: f32 = 0.8;
+
+/// Width of the anti-aliased --avatar circle edge in pixels
+const AVATAR_EDGE: f32 = 1.5;
+
+fn avatar_image(opts: &Opts, img: RgbImage) -> Result<RgbImage, Error> {
+    // Blur the whole image before cropping so the matte doesn't clamp at the crop edges
+    let blurred = layout::center_square(&blur_image(opts, img.clone())?);
+    let original = layout::center_square(&img);
+
+    let size = original.width();
+    let circle = mask::vignette(
+        size,
+        size,
+        AVATAR_INNER,
+        AVATAR_EDGE * 2.0 / size as f32,
+        mask::VignetteShape::Ellipse,
+    );
+
+    Ok(circle.composite(&original, &blurred))
+}
+
+/// How much smaller than the canvas the --pad-style blur background is blurred, which makes
+/// the blur that much wider once it is scaled back up
+const PAD_DOWNSCALE: u32 = 8;
+
+fn pad_image(opts: &Opts, img: RgbImage, aspect: (u32, u32)) -> Result<RgbImage, Error> {
+    let (width, height) = layout::padded_size(img.width(), img.height(), aspect);
+
+    let mut canvas = match opts.pad_style {
+        layout::PadStyle::Color(c) => RgbImage::from_pixel(width, height, c),
+        layout::PadStyle::Blur => {
+            let small = layout::cover(
+                &img,
+                (width / PAD_DOWNSCALE).max(1),
+                (height / PAD_DOWNSCALE).max(1),
+            );
+            let small = blur_image(opts, small)?;
+
+            image::imageops::resize(&small, width, height, image::imageops::FilterType::Triangle)
+        }
+    };
+
+    layout::overlay_center(&mut canvas, &img);
+
+    Ok(canvas)
+}
+
+fn blur_image(opts: &Opts, img: RgbImage) -> Result<RgbImage, Error> {
+    match build_mask(opts, &img)? {
+        Some(mask) => {
+            let blurred = blur_image_unmasked(opts, img.clone())?;
+            Ok(mask.composite(&img, &blurred))
+        }
+        None => blur_image_unmasked(opts, img),
+    }
+}
+
+/// Luma levels over which --luma-range fades out on each side of the band
+const LUMA_FEATHER: f32 = 10.0;
+
+/// RGB distance over which --key-color fades out past --key-tolerance
+const KEY_FEATHER: f32 = 15.0;
+
+/// Combines every mask option into the one that decides how much of the blur each pixel gets,
+/// softened by `--mask-feather`. The `--redact-*` areas don't go through it, so they stay fully
+/// hidden up to their edges.
+fn build_mask(opts: &Opts, img: &RgbImage) -> Result<Option<mask::Mask>, Error> {
+    let opts = opts
+        .resolve_regions(img.width(), img.height())
+        .map_err(|e| Error::InvalidArgs(e.into()))?;
+    let mut masks = Vec::new();
+
+    if let Some(model) = &opts.portrait_model {
+        masks.push(portrait_mask(model, img)?);
+    }
+
+    if let Some(strength) = opts.protect_edges {
+        masks.push(mask::edge_protection(img, strength));
+    }
+
+    if let Some((lo, hi)) = opts.luma_range {
+        masks.push(mask::luma_range(img, lo, hi, LUMA_FEATHER));
+    }
+
+    if let Some(key) = opts.key_color {
+        masks.push(mask::chroma_key(img, key, opts.key_tolerance, KEY_FEATHER));
+    }
+
+    if opts.vignette {
+        masks.push(mask::vignette(
+            img.width(),
+            img.height(),
+            opts.inner,
+            opts.feather,
+            opts.vignette_shape,
+        ));
+    }
+
+    if !opts.regions.is_empty() || !opts.shapes.is_empty() {
+        let rects = mask::rects(img.width(), img.height(), &opts.regions);
+        masks.push(rects.union(&mask::shapes(img.width(), img.height(), &opts.shapes)));
+    }
+
+    if !opts.keep_regions.is_empty() {
+        let keep = mask::rects(img.width(), img.height(), &opts.keep_regions);
+        masks.push(mask::Mask::from_fn(img.width(), img.height(), |x, y| {
+            1.0 - keep.get(x, y)
+        }));
+    }
+
+    if let Some(opacity) = opts.blend {
+        masks.push(mask::Mask::new(img.width(), img.height(), opacity));
+    }
+
+    let mask = masks.into_iter().reduce(|a, b| a.multiply(&b));
+
+    Ok(match opts.mask_feather {
+        Some(f) => mask.map(|m| m.soften(f, f as f64 / 2.0)),
+        None => mask,
+    })
+}
+
+/// Pixels over which the --portrait cut-out fades into the blurred background
+#[cfg(feature = "portrait")]
+const PORTRAIT_FEATHER: u8 = 8;
+
+#[cfg(feature = "portrait")]
+fn portrait_mask(model: &Path, img: &RgbImage) -> Result<mask::Mask, Error> {
+    segment::PortraitModel::load(model)
+        .and_then(|mut m| m.background_mask(img, PORTRAIT_FEATHER))
+        .map_err(|e| Error::InvalidArgs(e.into()))
+}
+
+#[cfg(not(feature = "portrait"))]
+fn portrait_mask(_: &Path, _: &RgbImage) -> Result<mask::Mask, Error> {
+    Err(Error::InvalidArgs(ArgError::new(
+        Fault::Option("--portrait"),
+        "--portrait requires building with the `portrait` feature",
+    )))
+}
+
+fn blur_image_unmasked(opts: &Opts, img: RgbImage) -> Result<RgbImage, Error> {
+    if opts.algorithm == Algorithm::Surface {
+        return Ok(surface_blur(
+            opts.kernel(),
+            opts.threshold,
+            n_threads(opts, &img, kernel_taps(opts.radius)),
+            img,
+        ));
+    }
+
+    if opts.adaptive {
+        return Ok(adaptive::blur_adaptive(opts.kernel(), &img));
+    }
+
+    if opts.projection == Projection::Equirect {
+        return Ok(blur_equirect(opts.kernel(), opts.passes, &img));
+    }
+
+    if opts.tileable {
+        return Ok(blur_tileable(
+            opts.kernel(),
+            opts.passes,
+            opts.direction,
+            &img,
+        ));
+    }
+
+    if let Some(sigmas) = opts.channel_sigmas {
+        return Ok(blur_channels_passes(
+            sigmas.map(|sigma| opts.kernel().with_sigma(sigma)),
+            opts.passes,
+            opts.direction,
+            &img,
+        ));
+    }
+
+    if opts.passes > 1 || opts.direction != Direction::Both {
+        return Ok(blur_passes(
+            opts.kernel(),
+            opts.passes,
+            opts.direction,
+            &img,
+        ));
+    }
+
+    if let Some(every) = opts.progressive_save {
+        return Ok(blur_saving_progress(opts, img, every));
+    }
+
+    Ok(pick_backend(opts, &img)?.blur(opts.kernel(), img))
+}
+
+/// Blurs on the thread pool, writing the unfinished image every `every` percent to
+/// [`naming::partial`]
+fn blur_saving_progress(opts: &Opts, img: RgbImage, every: u32) -> RgbImage {
+    let partial = naming::partial(&opts.blurred);
+    let encode_opts = codec::EncodeOptions {
+        format: opts.format.clone(),
+        progressive: opts.progressive,
+        interlaced: opts.interlaced,
+    };
+
+    let focus = opts.focus.unwrap_or((img.width() / 2, img.height() / 2));
+
+    blur_async_focused(
+        opts.kernel(),
+        n_threads(opts, &img, kernel_taps(opts.radius)),
+        &opts.affinity.cores(),
+        img,
+        every,
+        focus,
+        |img, percent| match codec::save(img, &partial, &encode_opts) {
+            Ok(()) => eprintln!(
+                "{}",
+                tr!("partial-saved", percent, partial.display()).translate()
+            ),
+            Err(e) => eprintln!(
+                "{}",
+                tr!("partial-failed", partial.display()).with(e).translate()
+            ),
+        },
+    )
+}
+
+/// Images below this many pixels are blurred on a single thread when several files run at once,
+/// since the other jobs already keep the cores busy
+const SMALL_IMAGE_PIXELS: u64 = 512 * 512;
+
+/// Taps of the full 2D kernel of `radius`
+fn kernel_taps(radius: u8) -> u64 {
+    (radius as u64 * 2 + 1).pow(2)
+}
+
+/// Workers for blurring `img` with `taps` taps per pixel. An explicit `--threads` is used as is.
+fn n_threads(opts: &Opts, img: &RgbImage, taps: u64) -> usize {
+    let pixels = img.width() as u64 * img.height() as u64;
+
+    if opts.jobs > 1 && pixels < SMALL_IMAGE_PIXELS {
+        return 1;
+    }
+
+    match opts.adaptive_threads {
+        true => backend::threads_for_work(pixels, taps, opts.n_threads),
+        false => opts.n_threads,
+    }
+}
+
+fn pick_backend(opts: &Opts, img: &RgbImage) -> Result<Box<dyn backend::Backend>, Error> {
+    let n_threads = n_threads(opts, img, kernel_taps(opts.radius));
+
+    let backend = match backend::from_name(&opts.backend, n_threads)
+        .map_err(|e| Error::InvalidArgs(e.into()))?
+    {
+        Some(backend) => backend,
+        None => <dyn backend::Backend>::auto(opts.radius, img.width(), img.height(), n_threads),
+    };
+
+    Ok(match backend.name() {
+        "threadpool" => Box::new(backend::ThreadPoolCpu {
+            n_threads,
+            affinity: opts.affinity,
+            adaptive: opts.adaptive_workers,
+        }),
+        _ => backend,
+    })
 }
@@ -0,0 +1,324 @@
+use crate::locale::Message;
+use crate::pipeline::FloatImage;
+use crate::region::{Rect, Shape};
+use crate::separable;
+use crate::tr;
+use image::{Rgb, RgbImage};
+use std::str::FromStr;
+
+/// Per-pixel blend weights between an original image and its blurred version. A weight of 1
+/// takes the blurred pixel, 0 keeps the original one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Mask {
+    /// Creates a mask with every weight set to `value`
+    pub fn new(width: u32, height: u32, value: f32) -> Self {
+        Mask {
+            width,
+            height,
+            data: vec![value.clamp(0.0, 1.0); width as usize * height as usize],
+        }
+    }
+
+    /// Creates a mask from a function of the pixel coordinates. Weights are clamped to 0..=1.
+    pub fn from_fn(width: u32, height: u32, f: impl Fn(u32, u32) -> f32) -> Self {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y).clamp(0.0, 1.0))
+            .collect();
+
+        Mask {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Weight at `(x, y)`
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Combines two masks so a pixel is only blurred as much as both allow
+    ///
+    /// # Panics
+    /// - If the masks don't have the same dimensions
+    pub fn multiply(&self, other: &Mask) -> Mask {
+        assert_eq!((self.width, self.height), (other.width, other.height));
+
+        Mask {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(a, b)| a * b)
+                .collect(),
+        }
+    }
+
+    /// Combines two masks so a pixel is blurred as much as either asks
+    ///
+    /// # Panics
+    /// - If the masks don't have the same dimensions
+    pub fn union(&self, other: &Mask) -> Mask {
+        assert_eq!((self.width, self.height), (other.width, other.height));
+
+        Mask {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(a, b)| a.max(*b))
+                .collect(),
+        }
+    }
+
+    /// Blurs the weights with a Gaussian so hard boundaries turn into smooth transitions
+    pub fn soften(&self, radius: u8, sigma: f64) -> Mask {
+        let weights = separable::gaussian_weights(radius as usize, sigma, false);
+        let width = self.width as usize;
+
+        let mut tmp = vec![0.0; self.data.len()];
+        let mut data = vec![0.0; self.data.len()];
+        separable::horizontal(&self.data, &mut tmp, width, 1, &weights);
+        separable::vertical(&tmp, &mut data, width, 1, &weights);
+
+        Mask {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Blends `blurred` over `original` using the weights as opacity
+    ///
+    /// # Panics
+    /// - If the images and the mask don't all have the same dimensions
+    pub fn composite(&self, original: &RgbImage, blurred: &RgbImage) -> RgbImage {
+        assert_eq!(original.dimensions(), (self.width, self.height));
+        assert_eq!(blurred.dimensions(), (self.width, self.height));
+
+        let mut out = RgbImage::new(self.width, self.height);
+
+        for ((px, (o, b)), m) in out
+            .pixels_mut()
+            .zip(original.pixels().zip(blurred.pixels()))
+            .zip(&self.data)
+        {
+            *px = Rgb([0, 1, 2].map(|c| (o[c] as f32 * (1.0 - m) + b[c] as f32 * m).round() as u8));
+        }
+
+        out
+    }
+
+    /// [`Mask::composite`] for f32 images, without rounding
+    ///
+    /// # Panics
+    /// - If the images and the mask don't all have the same dimensions
+    pub fn composite_float(&self, original: &FloatImage, blurred: &FloatImage) -> FloatImage {
+        assert_eq!(original.dimensions(), (self.width, self.height));
+        assert_eq!(blurred.dimensions(), (self.width, self.height));
+
+        let data = original
+            .as_raw()
+            .chunks_exact(3)
+            .zip(blurred.as_raw().chunks_exact(3))
+            .zip(&self.data)
+            .flat_map(|((o, b), m)| [0, 1, 2].map(|c| o[c] * (1.0 - m) + b[c] * m))
+            .collect();
+
+        FloatImage::from_raw(self.width, self.height, data).unwrap()
+    }
+}
+
+/// Mask that attenuates the blur near strong edges. Edge strength comes from a Sobel filter on
+/// the luma, normalized to the strongest edge and softened so the protection fades out around
+/// outlines; `strength` is how much of the blur is removed on the strongest edge.
+pub fn edge_protection(img: &RgbImage, strength: f32) -> Mask {
+    let edges = sobel(img);
+    let (width, height) = img.dimensions();
+
+    let weights = separable::gaussian_weights(2, 1.0, false);
+    let mut tmp = vec![0.0; edges.len()];
+    let mut soft = vec![0.0; edges.len()];
+    separable::horizontal(&edges, &mut tmp, width as usize, 1, &weights);
+    separable::vertical(&tmp, &mut soft, width as usize, 1, &weights);
+
+    let max = soft.iter().cloned().fold(0.0, f32::max);
+
+    Mask::from_fn(width, height, |x, y| {
+        let edge = match max > 0.0 {
+            true => soft[y as usize * width as usize + x as usize] / max,
+            false => 0.0,
+        };
+
+        1.0 - strength * edge
+    })
+}
+
+/// Mask that only blurs pixels whose luma (0 to 255) lies in `lo..=hi`, fading out linearly
+/// over `feather` luma levels on each side of the band
+pub fn luma_range(img: &RgbImage, lo: u8, hi: u8, feather: f32) -> Mask {
+    let (lo, hi) = (lo as f32, hi as f32);
+
+    Mask::from_fn(img.width(), img.height(), |x, y| {
+        let l = luma(img.get_pixel(x, y));
+        let distance = (lo - l).max(l - hi).max(0.0);
+
+        match feather > 0.0 {
+            true => 1.0 - distance / feather,
+            false => (distance == 0.0) as u8 as f32,
+        }
+    })
+}
+
+/// Mask that blurs pixels within `tolerance` (Euclidean RGB distance) of the key color, fading
+/// out linearly over `feather` more units of distance
+pub fn chroma_key(img: &RgbImage, key: Rgb<u8>, tolerance: f32, feather: f32) -> Mask {
+    Mask::from_fn(img.width(), img.height(), |x, y| {
+        let p = img.get_pixel(x, y);
+        let distance = (0..3)
+            .map(|c| (p[c] as f32 - key[c] as f32).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let beyond = (distance - tolerance).max(0.0);
+
+        match feather > 0.0 {
+            true => 1.0 - beyond / feather,
+            false => (beyond == 0.0) as u8 as f32,
+        }
+    })
+}
+
+/// Mask that fully blurs the pixels inside any of `rects` and keeps the rest
+pub fn rects(width: u32, height: u32, rects: &[Rect]) -> Mask {
+    Mask::from_fn(width, height, |x, y| {
+        rects.iter().any(|r| r.contains(x, y)) as u8 as f32
+    })
+}
+
+/// Mask that fully blurs the pixels inside any of `shapes` and keeps the rest. Outlines are
+/// anti-aliased over one pixel, so curves and slanted edges don't show stairs.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::mask::shapes;
+/// use vincent_blur::region::Shape;
+///
+/// let mask = shapes(40, 40, &[Shape::circle("20,20,10").unwrap()]);
+///
+/// assert_eq!(mask.get(20, 20), 1.0);
+/// assert_eq!(mask.get(30, 20), 0.5);
+/// assert_eq!(mask.get(35, 20), 0.0);
+/// ```
+pub fn shapes(width: u32, height: u32, shapes: &[Shape]) -> Mask {
+    Mask::from_fn(width, height, |x, y| {
+        let d = shapes
+            .iter()
+            .map(|s| s.distance(x as f32, y as f32))
+            .fold(f32::INFINITY, f32::min);
+
+        0.5 - d
+    })
+}
+
+/// Shape of the sharp center region of a [`vignette`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VignetteShape {
+    Ellipse,
+    Rect,
+}
+
+impl FromStr for VignetteShape {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ellipse" => Ok(VignetteShape::Ellipse),
+            "rect" => Ok(VignetteShape::Rect),
+            _ => Err(tr!("unknown-vignette-shape", s)),
+        }
+    }
+}
+
+/// Mask that keeps a centered region sharp and blurs toward the borders. `inner` is the size
+/// of the sharp region and `feather` the width of the ramp after it, both as fractions of the
+/// image size, so an `inner` of 0.6 keeps the middle 60% of each axis sharp.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::mask::{vignette, VignetteShape};
+///
+/// let mask = vignette(100, 100, 0.6, 0.15, VignetteShape::Rect);
+///
+/// assert_eq!(mask.get(50, 50), 0.0);
+/// assert_eq!(mask.get(0, 50), 1.0);
+/// ```
+pub fn vignette(width: u32, height: u32, inner: f32, feather: f32, shape: VignetteShape) -> Mask {
+    let (half_w, half_h) = (width as f32 / 2.0, height as f32 / 2.0);
+
+    Mask::from_fn(width, height, |x, y| {
+        let dx = (x as f32 + 0.5 - half_w).abs() / half_w;
+        let dy = (y as f32 + 0.5 - half_h).abs() / half_h;
+
+        let distance = match shape {
+            VignetteShape::Ellipse => (dx * dx + dy * dy).sqrt(),
+            VignetteShape::Rect => dx.max(dy),
+        };
+        let beyond = (distance - inner).max(0.0);
+
+        match feather > 0.0 {
+            true => beyond / feather,
+            false => (beyond > 0.0) as u8 as f32,
+        }
+    })
+}
+
+pub(crate) fn luma(p: &Rgb<u8>) -> f32 {
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+/// Sobel gradient magnitude of the luma, with edge pixels clamped
+fn sobel(img: &RgbImage) -> Vec<f32> {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+
+    let luma = |x: i64, y: i64| {
+        luma(img.get_pixel(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32))
+    };
+
+    let mut out = Vec::with_capacity((width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let gx = luma(x + 1, y - 1) + 2.0 * luma(x + 1, y) + luma(x + 1, y + 1)
+                - luma(x - 1, y - 1)
+                - 2.0 * luma(x - 1, y)
+                - luma(x - 1, y + 1);
+            let gy = luma(x - 1, y + 1) + 2.0 * luma(x, y + 1) + luma(x + 1, y + 1)
+                - luma(x - 1, y - 1)
+                - 2.0 * luma(x, y - 1)
+                - luma(x + 1, y - 1);
+
+            out.push((gx * gx + gy * gy).sqrt());
+        }
+    }
+
+    out
+}
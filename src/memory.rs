@@ -0,0 +1,87 @@
+use crate::tr;
+use crate::Error;
+use std::fs;
+
+/// Bytes per pixel a blur needs at its peak: the 8-bit source and result, the f32 image the
+/// pipeline works on and the second f32 buffer of the separable passes
+const BYTES_PER_PIXEL: u64 = 3 + 3 + 3 * 4 * 2;
+
+/// Bytes per pixel that stay allocated for the whole image when it is blurred in strips: the
+/// 8-bit source and result
+const WHOLE_BYTES_PER_PIXEL: u64 = 3 + 3;
+
+/// Strips are a multiple of this many rows, so the 8x8 ordered dither lines up across them
+pub const STRIP_ALIGN: u32 = 8;
+
+/// Estimated peak memory of blurring a `width`x`height` image
+///
+/// # Examples
+/// ```
+/// use vincent_blur::memory;
+///
+/// assert_eq!(memory::required(1000, 1000), 30_000_000);
+/// ```
+pub fn required(width: u32, height: u32) -> u64 {
+    (width as u64 * height as u64).saturating_mul(BYTES_PER_PIXEL)
+}
+
+/// Memory the system can give out without swapping, from `MemAvailable` in `/proc/meminfo`.
+/// `None` where that isn't available. Nothing in the library reads it on its own; callers pass
+/// it as a budget if they want blurs to stay within it.
+pub fn available() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Rows of a `width`x`height` image to blur at a time so the blur stays within `budget` bytes:
+/// the whole height if the image fits, otherwise strips of a multiple of [`STRIP_ALIGN`] rows,
+/// each read along with `halo` more rows above and below it. `halo` is `None` for blurs that
+/// can only run on the whole image.
+///
+/// # Errors
+/// - [`Error::TooLarge`] if the image doesn't fit and can't be split, or not even the smallest
+///   strip fits
+///
+/// # Examples
+/// ```
+/// use vincent_blur::memory;
+///
+/// assert_eq!(memory::strip_rows(1000, 1000, Some(10), 30_000_000), Ok(1000));
+/// assert_eq!(memory::strip_rows(1000, 1000, Some(10), 10_000_000), Ok(112));
+/// assert!(memory::strip_rows(1000, 1000, None, 10_000_000).is_err());
+/// assert!(memory::strip_rows(1000, 1000, Some(10), 6_000_000).is_err());
+/// ```
+pub fn strip_rows(width: u32, height: u32, halo: Option<u32>, budget: u64) -> Result<u32, Error> {
+    let needed = required(width, height);
+
+    if needed <= budget {
+        return Ok(height);
+    }
+
+    let too_large = || {
+        Error::TooLarge(tr!(
+            "memory-over-budget",
+            width,
+            height,
+            mib(needed),
+            mib(budget)
+        ))
+    };
+
+    let halo = halo.ok_or_else(too_large)?;
+    let whole = (width as u64 * height as u64).saturating_mul(WHOLE_BYTES_PER_PIXEL);
+    let rows = budget.saturating_sub(whole) / required(width, 1).max(1);
+    let rows = rows.saturating_sub(2 * halo as u64) / STRIP_ALIGN as u64 * STRIP_ALIGN as u64;
+
+    match rows {
+        0 => Err(too_large()),
+        rows => Ok(rows.min(height as u64) as u32),
+    }
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{} MiB", bytes.div_ceil(1 << 20))
+}
@@ -0,0 +1,124 @@
+use crate::mask::luma;
+use crate::{blur_passes, separable, Direction, Kernel};
+use image::RgbImage;
+
+/// Radius and standard deviation of the Gaussian window SSIM compares images over
+const SSIM_RADIUS: usize = 5;
+const SSIM_SIGMA: f64 = 1.5;
+
+/// Stabilizers of the SSIM ratios, `(0.01 * 255)²` and `(0.03 * 255)²`
+const C1: f32 = 6.5025;
+const C2: f32 = 58.5225;
+
+/// Smallest and largest sigma [`sigma_for_ssim`] tries
+const MIN_SIGMA: f64 = 0.1;
+const MAX_SIGMA: f64 = 85.0;
+
+/// Halvings of the sigma range [`sigma_for_ssim`] does, enough to get within 0.5% of a sigma
+const SEARCH_STEPS: usize = 12;
+
+/// Structural similarity of the luma of two images, 1 for identical images and falling toward
+/// 0 as their local means, contrasts and structures diverge. Uses the usual 11x11 Gaussian
+/// window with a sigma of 1.5.
+///
+/// # Panics
+/// - If the images don't have the same dimensions
+///
+/// # Examples
+/// ```
+/// use vincent_blur::metrics::ssim;
+/// use vincent_blur::{blur_passes, Direction, Kernel};
+///
+/// let img = image::RgbImage::from_fn(48, 48, |x, y| image::Rgb([((x ^ y) * 5) as u8; 3]));
+///
+/// assert_eq!(ssim(&img, &img), 1.0);
+///
+/// let soft = blur_passes(Kernel::new(3, 1.0), 1, Direction::Both, &img);
+/// let softer = blur_passes(Kernel::new(12, 4.0), 1, Direction::Both, &img);
+/// assert!(ssim(&img, &softer) < ssim(&img, &soft));
+/// ```
+pub fn ssim(a: &RgbImage, b: &RgbImage) -> f64 {
+    assert_eq!(a.dimensions(), b.dimensions());
+
+    let width = a.width() as usize;
+
+    // Interleaved a, b, a², b² and ab, blurred together
+    let moments: Vec<f32> = a
+        .pixels()
+        .zip(b.pixels())
+        .flat_map(|(pa, pb)| {
+            let (la, lb) = (luma(pa), luma(pb));
+            [la, lb, la * la, lb * lb, la * lb]
+        })
+        .collect();
+
+    let weights = separable::gaussian_weights(SSIM_RADIUS, SSIM_SIGMA, false);
+    let mut tmp = vec![0.0; moments.len()];
+    let mut local = vec![0.0; moments.len()];
+    separable::horizontal(&moments, &mut tmp, width, 5, &weights);
+    separable::vertical(&tmp, &mut local, width, 5, &weights);
+
+    let sum: f64 = local
+        .chunks_exact(5)
+        .map(|m| {
+            let (mu_a, mu_b) = (m[0], m[1]);
+            let var_a = m[2] - mu_a * mu_a;
+            let var_b = m[3] - mu_b * mu_b;
+            let cov = m[4] - mu_a * mu_b;
+
+            ((2.0 * mu_a * mu_b + C1) * (2.0 * cov + C2)
+                / ((mu_a * mu_a + mu_b * mu_b + C1) * (var_a + var_b + C2))) as f64
+        })
+        .sum();
+
+    (sum / (a.width() as f64 * a.height() as f64)).min(1.0)
+}
+
+/// Radius covering 3 standard deviations of a Gaussian of `sigma`
+pub fn radius_for(sigma: f64) -> u8 {
+    (sigma * 3.0).ceil().clamp(1.0, 255.0) as u8
+}
+
+/// Smallest Gaussian sigma whose blur of `img` has an [`ssim`] to it of at most `target`,
+/// found by bisection, so images of any content and size end up equally hard to recognize.
+/// Returns the largest sigma tried if even that blur stays more similar than `target`.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::metrics::{sigma_for_ssim, ssim};
+/// use vincent_blur::{blur_passes, Direction, Kernel};
+///
+/// let img = image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([((x * y) % 256) as u8; 3]));
+/// let sigma = sigma_for_ssim(&img, 0.5);
+/// let blurred = blur_passes(Kernel::derived(sigma), 1, Direction::Both, &img);
+///
+/// assert!(ssim(&img, &blurred) <= 0.5);
+/// ```
+pub fn sigma_for_ssim(img: &RgbImage, target: f64) -> f64 {
+    let similarity = |sigma: f64| {
+        let blurred = blur_passes(Kernel::derived(sigma), 1, Direction::Both, img);
+        ssim(img, &blurred)
+    };
+
+    // The search runs on a log scale since small sigmas change the image the most
+    let (mut lo, mut hi) = (MIN_SIGMA.ln(), MAX_SIGMA.ln());
+
+    if similarity(MIN_SIGMA) <= target {
+        return MIN_SIGMA;
+    }
+
+    if similarity(MAX_SIGMA) > target {
+        return MAX_SIGMA;
+    }
+
+    for _ in 0..SEARCH_STEPS {
+        let mid = (lo + hi) / 2.0;
+
+        match similarity(mid.exp()) <= target {
+            true => hi = mid,
+            false => lo = mid,
+        }
+    }
+
+    hi.exp()
+}
@@ -0,0 +1,164 @@
+use crate::locale::Message;
+use crate::tr;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Decimals kept by [`format_param`]
+const PARAM_DECIMALS: usize = 3;
+
+/// Suffix appended to the source file stem when no destination is given
+///
+/// # Examples
+/// ```
+/// use vincent_blur::naming::blurred_suffix;
+///
+/// assert_eq!(blurred_suffix(10, 10.0), "_blurred_10x10");
+/// assert_eq!(blurred_suffix(10, 10.5), "_blurred_10x10.5");
+/// assert_eq!(blurred_suffix(3, 1.0 / 3.0), "_blurred_3x0.333");
+/// ```
+pub fn blurred_suffix(radius: u8, sigma: f64) -> String {
+    format!("_blurred_{}x{}", radius, format_param(sigma))
+}
+
+/// Formats a float parameter for a file name: at most 3 decimals without trailing zeros, so
+/// `10.0` and `10.5` read `10` and `10.5`. Values too large or too small to be told apart that
+/// way, which `{}` would spell out in dozens of digits, use a short exponent like `1e-7`.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::naming::format_param;
+///
+/// assert_eq!(format_param(2.0), "2");
+/// assert_eq!(format_param(0.1 + 0.2), "0.3");
+/// assert_eq!(format_param(1e-7), "1e-7");
+/// assert_eq!(format_param(2.5e20), "2.5e20");
+/// ```
+pub fn format_param(value: f64) -> String {
+    let magnitude = value.abs();
+
+    if magnitude != 0.0 && !(1e-3..1e9).contains(&magnitude) {
+        // Rounds the mantissa like the fixed notation below
+        let exponent = format!("{:.*e}", PARAM_DECIMALS, value);
+        let (mantissa, exponent) = exponent.split_once('e').unwrap();
+
+        return format!("{}e{}", trim_zeros(mantissa), exponent);
+    }
+
+    trim_zeros(&format!("{:.*}", PARAM_DECIMALS, value)).to_string()
+}
+
+fn trim_zeros(fixed: &str) -> &str {
+    match fixed.trim_end_matches('0').trim_end_matches('.') {
+        "-0" => "0",
+        trimmed => trimmed,
+    }
+}
+
+/// Checks that no two sources would be written to the same destination, e.g. two files of the
+/// same name from different directories blurred into one `--output-dir`
+///
+/// # Errors
+/// - A message naming both sources and their shared destination
+///
+/// # Examples
+/// ```
+/// use std::path::PathBuf;
+/// use vincent_blur::naming::check_unique;
+///
+/// let pair = |src: &str, dst: &str| (PathBuf::from(src), PathBuf::from(dst));
+///
+/// assert!(check_unique(&[pair("a/cat.png", "out/cat.png"), pair("dog.png", "out/dog.png")]).is_ok());
+/// assert!(check_unique(&[pair("a/cat.png", "out/cat.png"), pair("b/cat.png", "out/cat.png")]).is_err());
+/// ```
+pub fn check_unique(files: &[(PathBuf, PathBuf)]) -> Result<(), Message> {
+    let mut seen = HashMap::with_capacity(files.len());
+
+    for (original, blurred) in files {
+        if blurred == Path::new(crate::codec::STDIO) {
+            continue;
+        }
+
+        if let Some(first) = seen.insert(blurred, original) {
+            return Err(tr!(
+                "same-destination",
+                first.display(),
+                original.display(),
+                blurred.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the default destination `<source stem><suffix>.<ext>` next to the source. The
+/// extension defaults to the source extension.
+///
+/// # Errors
+/// - If the source has no file name, or no extension and `ext` is `None`
+pub fn default_path(original: &Path, suffix: &str, ext: Option<&str>) -> Result<PathBuf, Message> {
+    let fname = match original.file_stem() {
+        Some(s) => s.to_string_lossy(),
+        _ => return Err("Expected a filename".into()),
+    };
+
+    let ext = match (ext, original.extension()) {
+        (Some(e), _) => e.to_string(),
+        (None, Some(e)) => e.to_string_lossy().into_owned(),
+        _ => return Err("expected an extension".into()),
+    };
+
+    Ok(original.with_file_name(format!("{}{}.{}", fname, suffix, ext)))
+}
+
+/// Returns `path` if nothing exists there yet, otherwise the first free `<stem>_<n>.<ext>`
+/// counting up from 1
+pub fn numbered(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|e| e.to_string_lossy());
+
+    (1..)
+        .map(|n| match &ext {
+            Some(ext) => path.with_file_name(format!("{}_{}.{}", stem, n, ext)),
+            None => path.with_file_name(format!("{}_{}", stem, n)),
+        })
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+/// Where `--progressive-save` keeps the unfinished render of `path`, `<stem>.partial.<ext>`
+///
+/// # Examples
+/// ```
+/// use std::path::Path;
+/// use vincent_blur::naming::partial;
+///
+/// assert_eq!(partial(Path::new("out/photo.jpg")), Path::new("out/photo.partial.jpg"));
+/// ```
+pub fn partial(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}.partial.{}", stem, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}.partial", stem)),
+    }
+}
+
+/// Where `--hash-sidecar` writes the pixel hash of `path`, `<name>.sha256`
+///
+/// # Examples
+/// ```
+/// use std::path::Path;
+/// use vincent_blur::naming::sidecar;
+///
+/// assert_eq!(sidecar(Path::new("out/photo.jpg")), Path::new("out/photo.jpg.sha256"));
+/// ```
+pub fn sidecar(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    path.with_file_name(format!("{name}.sha256"))
+}
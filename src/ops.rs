@@ -0,0 +1,544 @@
+use crate::locale::Message;
+use crate::pipeline::{self, Filter, Pipeline};
+use crate::region::Rect;
+use crate::tr;
+use crate::{
+    parse_color, parse_filter, parse_percent, parse_positive, parse_size, Direction, Kernel,
+};
+use image::imageops::FilterType;
+use std::fmt;
+use std::sync::Arc;
+
+/// A syntax or parameter error in an `--ops` expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte offset in the expression where the problem starts
+    pub position: usize,
+    pub message: Message,
+}
+
+impl ParseError {
+    fn new(position: usize, message: impl Into<Message>) -> Self {
+        ParseError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// The expression with a caret under the error, for showing below the message
+    pub fn pointer(&self, src: &str) -> String {
+        let column = src[..self.position.min(src.len())].chars().count();
+        format!("  {src}\n  {}^", " ".repeat(column))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for Message {
+    fn from(e: ParseError) -> Message {
+        tr!("at-column", e.position + 1).with(e.message)
+    }
+}
+
+/// Builds stages that aren't part of the crate, such as plugins, when an expression names them
+pub trait StageFactory: Send + Sync {
+    /// Name used in expressions
+    fn name(&self) -> &str;
+
+    /// Builds the stage from its arguments, written back as `value,key=value` text
+    ///
+    /// # Errors
+    /// - If the arguments are invalid
+    fn build(&self, args: &str) -> Result<Box<dyn Filter>, Message>;
+}
+
+/// Names of the built-in stages usable in this build, see [`parse`]
+pub const STAGES: &[&str] = &[
+    "linear",
+    "srgb",
+    "gaussian",
+    "unsharp",
+    "noise",
+    "resize",
+    "scale",
+    "crop",
+    "crop-center",
+    "rotate",
+    "flip",
+    "brightness",
+    "contrast",
+    "gamma",
+    "tint",
+    "grayscale",
+    "duotone",
+    #[cfg(feature = "wasm")]
+    "wasm",
+];
+
+/// Parses a chain of stages separated by `|` into the same [`Pipeline`] the individual flags
+/// build. Each stage is a name, optionally followed by arguments in parentheses that are given
+/// in order or as `name=value`:
+///
+/// | Stage | Arguments |
+/// |-------|-----------|
+/// | `linear`, `srgb` | |
+/// | `gaussian` | `r` = 10, `s` = 10, `passes` = 1, `direction` = both |
+/// | `unsharp` | `amount` = 1, `r` = 4, `s` = 2 |
+/// | `noise` | `amount`, `seed` = 0 |
+/// | `resize` | `size` (`WxH`), `filter` = triangle |
+/// | `scale` | `factor` (`50%` or `0.5`), `filter` = triangle |
+/// | `crop` | `x`, `y`, `w`, `h` |
+/// | `crop-center` | `size` |
+/// | `rotate` | `degrees` |
+/// | `flip` | `axis` (`h` or `v`) |
+/// | `brightness`, `contrast` | `factor` |
+/// | `gamma` | `gamma` |
+/// | `tint` | `color`, `opacity` = 1 |
+/// | `grayscale` | |
+/// | `duotone` | `dark`, `light` |
+/// | `wasm` | `file`, `threads` = all cores, see `script::WasmFilter` (`wasm` feature) |
+///
+/// # Examples
+/// ```
+/// use vincent_blur::ops;
+///
+/// let pipeline = ops::parse("linear | gaussian(r=8,s=3) | unsharp(1.2) | srgb").unwrap();
+/// assert_eq!(pipeline.len(), 4);
+///
+/// let err = ops::parse("linear | gaussian(r=8,,s=3)").err().unwrap();
+/// assert_eq!(err.position, 22);
+/// ```
+///
+/// # Errors
+/// - If the expression isn't well formed, or names an unknown stage or argument
+/// - If an argument is missing or its value is invalid
+pub fn parse(src: &str) -> Result<Pipeline, ParseError> {
+    parse_with(src, &[])
+}
+
+/// Same as [`parse`], looking up names that aren't built-in stages in `factories`
+///
+/// # Errors
+/// - Same as [`parse`]
+pub fn parse_with(src: &str, factories: &[Arc<dyn StageFactory>]) -> Result<Pipeline, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(src),
+        next: 0,
+    };
+    let mut pipeline = Pipeline::new();
+
+    loop {
+        let call = parser.call()?;
+        pipeline.push(build(&call, factories)?);
+
+        match parser.bump() {
+            (_, Token::Pipe) => continue,
+            (_, Token::End) => return Ok(pipeline),
+            (pos, t) => return Err(ParseError::new(pos, tr!("expected-but-found", "|", t))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Open,
+    Close,
+    Comma,
+    Equals,
+    Pipe,
+    End,
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{w}"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Equals => write!(f, "="),
+            Token::Pipe => write!(f, "|"),
+            Token::End => write!(f, "the end"),
+        }
+    }
+}
+
+/// Splits `src` into tokens with their byte offsets, ending with [`Token::End`]
+fn tokenize(src: &str) -> Vec<(usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+
+    while let Some((pos, c)) = chars.next() {
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '=' => Token::Equals,
+            '|' => Token::Pipe,
+            c if c.is_whitespace() => continue,
+            _ => {
+                let mut end = pos + c.len_utf8();
+
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "(),=|".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+
+                Token::Word(&src[pos..end])
+            }
+        };
+
+        tokens.push((pos, token));
+    }
+
+    tokens.push((src.len(), Token::End));
+    tokens
+}
+
+/// One stage as written, before its arguments are checked
+struct Call<'a> {
+    name: &'a str,
+    position: usize,
+    args: Vec<Arg<'a>>,
+}
+
+struct Arg<'a> {
+    /// Name and offset of `name=`, if given
+    key: Option<(&'a str, usize)>,
+    value: &'a str,
+    position: usize,
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> (usize, Token<'a>) {
+        self.tokens[self.next]
+    }
+
+    fn bump(&mut self) -> (usize, Token<'a>) {
+        let token = self.peek();
+        self.next = (self.next + 1).min(self.tokens.len() - 1);
+        token
+    }
+
+    fn word(&mut self, what: &str) -> Result<(usize, &'a str), ParseError> {
+        match self.bump() {
+            (pos, Token::Word(w)) => Ok((pos, w)),
+            (pos, t) => Err(ParseError::new(pos, tr!("expected-but-found", what, t))),
+        }
+    }
+
+    /// `name` or `name(arg, key=value, ...)`
+    fn call(&mut self) -> Result<Call<'a>, ParseError> {
+        let (position, name) = self.word("a stage")?;
+        let mut args = Vec::new();
+
+        if self.peek().1 != Token::Open {
+            return Ok(Call {
+                name,
+                position,
+                args,
+            });
+        }
+        self.bump();
+
+        if self.peek().1 == Token::Close {
+            self.bump();
+            return Ok(Call {
+                name,
+                position,
+                args,
+            });
+        }
+
+        loop {
+            let (pos, word) = self.word("an argument")?;
+
+            args.push(match self.peek().1 {
+                Token::Equals => {
+                    self.bump();
+                    let (value_pos, value) = self.word("a value")?;
+                    Arg {
+                        key: Some((word, pos)),
+                        value,
+                        position: value_pos,
+                    }
+                }
+                _ => Arg {
+                    key: None,
+                    value: word,
+                    position: pos,
+                },
+            });
+
+            match self.bump() {
+                (_, Token::Comma) => continue,
+                (_, Token::Close) => break,
+                (pos, t) => {
+                    return Err(ParseError::new(pos, tr!("expected-but-found", ", or )", t)))
+                }
+            }
+        }
+
+        Ok(Call {
+            name,
+            position,
+            args,
+        })
+    }
+}
+
+/// Arguments of a [`Call`] matched to the stage's parameters
+struct Bound<'a> {
+    stage: &'a str,
+    position: usize,
+    params: &'static [&'static str],
+    values: Vec<Option<(&'a str, usize)>>,
+}
+
+impl Call<'_> {
+    fn bind(&self, params: &'static [&'static str]) -> Result<Bound<'_>, ParseError> {
+        let mut values = vec![None; params.len()];
+        let mut next = 0;
+
+        for arg in &self.args {
+            let i = match arg.key {
+                Some((key, pos)) => params
+                    .iter()
+                    .position(|p| *p == key)
+                    .ok_or_else(|| ParseError::new(pos, tr!("no-argument", self.name, key)))?,
+                None if next < params.len() => next,
+                None => {
+                    return Err(ParseError::new(
+                        arg.position,
+                        tr!("too-many-arguments", self.name),
+                    ))
+                }
+            };
+
+            if values[i].is_some() {
+                return Err(ParseError::new(arg.position, tr!("given-twice", params[i])));
+            }
+
+            values[i] = Some((arg.value, arg.position));
+            next = i + 1;
+        }
+
+        Ok(Bound {
+            stage: self.name,
+            position: self.position,
+            params,
+            values,
+        })
+    }
+}
+
+impl Bound<'_> {
+    /// Parses argument `i` if it was given
+    fn get<T>(&self, i: usize, parse: impl Fn(&str) -> Option<T>) -> Result<Option<T>, ParseError> {
+        self.values[i]
+            .map(|(value, pos)| {
+                parse(value).ok_or_else(|| {
+                    ParseError::new(
+                        pos,
+                        tr!("invalid-argument", self.params[i], self.stage, value),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    /// Parses argument `i`, which must be given
+    fn require<T>(&self, i: usize, parse: impl Fn(&str) -> Option<T>) -> Result<T, ParseError> {
+        self.get(i, parse)?.ok_or_else(|| {
+            ParseError::new(
+                self.position,
+                tr!("stage-needs", self.stage, self.params[i]),
+            )
+        })
+    }
+}
+
+/// `50%` or a plain factor like `0.5`
+fn factor(s: &str) -> Option<f32> {
+    match s.ends_with('%') {
+        true => parse_percent(s),
+        false => s.parse().ok().filter(|f: &f32| *f >= 0.0),
+    }
+}
+
+fn build(call: &Call, factories: &[Arc<dyn StageFactory>]) -> Result<Box<dyn Filter>, ParseError> {
+    Ok(match call.name {
+        "linear" => {
+            call.bind(&[])?;
+            Box::new(pipeline::Linear)
+        }
+        "srgb" => {
+            call.bind(&[])?;
+            Box::new(pipeline::Srgb)
+        }
+        "gaussian" => {
+            let a = call.bind(&["r", "s", "passes", "direction"])?;
+            Box::new(pipeline::Gaussian {
+                kernel: Kernel::new(
+                    a.get(0, parse_positive)?.unwrap_or(10),
+                    a.get(1, parse_positive)?.unwrap_or(10.0),
+                ),
+                passes: a.get(2, parse_positive)?.unwrap_or(1),
+                direction: a
+                    .get(3, |s| s.parse::<Direction>().ok())?
+                    .unwrap_or(Direction::Both),
+            })
+        }
+        "unsharp" => {
+            let a = call.bind(&["amount", "r", "s"])?;
+            Box::new(pipeline::Unsharp {
+                amount: a.get(0, factor)?.unwrap_or(1.0),
+                radius: a.get(1, parse_positive)?.unwrap_or(4),
+                sigma: a.get(2, parse_positive)?.unwrap_or(2.0),
+            })
+        }
+        "noise" => {
+            let a = call.bind(&["amount", "seed"])?;
+            Box::new(pipeline::Noise {
+                amount: a.require(0, parse_positive)?,
+                seed: a.get(1, |s| s.parse().ok())?.unwrap_or(0),
+            })
+        }
+        "resize" => {
+            let a = call.bind(&["size", "filter"])?;
+            let (width, height) = a.require(0, parse_size)?;
+            Box::new(pipeline::Resize {
+                width,
+                height,
+                filter: a.get(1, parse_filter)?.unwrap_or(FilterType::Triangle),
+            })
+        }
+        "scale" => {
+            let a = call.bind(&["factor", "filter"])?;
+            Box::new(pipeline::Scale {
+                factor: a.require(0, |s| factor(s).filter(|f| *f > 0.0))?,
+                filter: a.get(1, parse_filter)?.unwrap_or(FilterType::Triangle),
+            })
+        }
+        "crop" => {
+            let a = call.bind(&["x", "y", "w", "h"])?;
+            Box::new(pipeline::Crop {
+                rect: Rect::new(
+                    a.require(0, |s| s.parse().ok())?,
+                    a.require(1, |s| s.parse().ok())?,
+                    a.require(2, parse_positive)?,
+                    a.require(3, parse_positive)?,
+                ),
+            })
+        }
+        "crop-center" => {
+            let a = call.bind(&["size"])?;
+            let (width, height) = a.require(0, parse_size)?;
+            Box::new(pipeline::CropCenter { width, height })
+        }
+        "rotate" => {
+            let a = call.bind(&["degrees"])?;
+            Box::new(a.require(0, |s| s.parse::<pipeline::Rotate>().ok())?)
+        }
+        "flip" => {
+            let a = call.bind(&["axis"])?;
+            Box::new(a.require(0, |s| s.parse::<pipeline::Flip>().ok())?)
+        }
+        "brightness" => {
+            let a = call.bind(&["factor"])?;
+            Box::new(pipeline::Tone {
+                brightness: a.require(0, factor)?,
+                ..pipeline::Tone::default()
+            })
+        }
+        "contrast" => {
+            let a = call.bind(&["factor"])?;
+            Box::new(pipeline::Tone {
+                contrast: a.require(0, factor)?,
+                ..pipeline::Tone::default()
+            })
+        }
+        "gamma" => {
+            let a = call.bind(&["gamma"])?;
+            Box::new(pipeline::Tone {
+                gamma: a.require(0, parse_positive)?,
+                ..pipeline::Tone::default()
+            })
+        }
+        "tint" => {
+            let a = call.bind(&["color", "opacity"])?;
+            Box::new(pipeline::Tint {
+                color: a.require(0, parse_color)?,
+                opacity: a
+                    .get(1, |s| s.parse().ok().filter(|o| (0.0..=1.0).contains(o)))?
+                    .unwrap_or(1.0),
+            })
+        }
+        "grayscale" => {
+            call.bind(&[])?;
+            Box::new(pipeline::Grayscale)
+        }
+        "duotone" => {
+            let a = call.bind(&["dark", "light"])?;
+            Box::new(pipeline::Duotone {
+                dark: a.require(0, parse_color)?,
+                light: a.require(1, parse_color)?,
+            })
+        }
+        #[cfg(feature = "wasm")]
+        "wasm" => {
+            let a = call.bind(&["file", "threads"])?;
+            let file = a.require(0, |s| Some(std::path::PathBuf::from(s)))?;
+            let threads = a
+                .get(1, parse_positive)?
+                .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+                .unwrap_or(1);
+
+            Box::new(
+                crate::script::WasmFilter::load(&file, threads)
+                    .map_err(|e| ParseError::new(call.position, e))?,
+            )
+        }
+        #[cfg(not(feature = "wasm"))]
+        "wasm" => {
+            return Err(ParseError::new(
+                call.position,
+                "wasm requires building with the wasm feature",
+            ))
+        }
+        name => {
+            let factory = factories
+                .iter()
+                .find(|f| f.name() == name)
+                .ok_or_else(|| ParseError::new(call.position, tr!("unknown-stage", name)))?;
+
+            let args: Vec<String> = call
+                .args
+                .iter()
+                .map(|a| match a.key {
+                    Some((key, _)) => format!("{key}={}", a.value),
+                    None => a.value.to_string(),
+                })
+                .collect();
+
+            factory
+                .build(&args.join(","))
+                .map_err(|e| ParseError::new(call.position, tr!("stage-failed", name).with(e)))?
+        }
+    })
+}
@@ -0,0 +1,910 @@
+use crate::dither::{self, Dither};
+use crate::locale::Message;
+use crate::region::Rect;
+use crate::tr;
+use crate::{deadline, effects, memory, scratch, separable, Direction, Error, Kernel};
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgb, RgbImage};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// An RGB image with one f32 per channel, in the same 0..=255 range as 8-bit images but
+/// without rounding. Pipeline stages pass these to each other so precision is only lost once,
+/// when the result is quantized for encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatImage {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl FloatImage {
+    /// Creates a black image
+    pub fn new(width: u32, height: u32) -> Self {
+        FloatImage {
+            width,
+            height,
+            data: vec![0.0; width as usize * height as usize * 3],
+        }
+    }
+
+    /// Wraps an interleaved RGB buffer, or returns `None` if it doesn't hold
+    /// `width * height * 3` values
+    pub fn from_raw(width: u32, height: u32, data: Vec<f32>) -> Option<Self> {
+        (data.len() == width as usize * height as usize * 3).then_some(FloatImage {
+            width,
+            height,
+            data,
+        })
+    }
+
+    pub fn from_rgb(img: &RgbImage) -> Self {
+        FloatImage {
+            width: img.width(),
+            height: img.height(),
+            data: separable::to_f32(img),
+        }
+    }
+
+    /// Rounds to 8 bits per channel with `dither`
+    pub fn to_rgb(&self, dither: Dither) -> RgbImage {
+        dither::quantize(self.width, self.height, &self.data, dither)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Interleaved RGB values, row by row
+    pub fn as_raw(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn as_raw_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    /// Hands the buffer back to the [`scratch`](crate::scratch) pool for the next image to
+    /// reuse instead of freeing it
+    pub fn recycle(self) {
+        scratch::give(self.data);
+    }
+
+    fn into_buffer(self) -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+        ImageBuffer::from_raw(self.width, self.height, self.data).unwrap()
+    }
+
+    fn from_buffer(buf: ImageBuffer<Rgb<f32>, Vec<f32>>) -> Self {
+        FloatImage {
+            width: buf.width(),
+            height: buf.height(),
+            data: buf.into_raw(),
+        }
+    }
+}
+
+/// One stage of a [`Pipeline`]
+pub trait Filter: Send + Sync {
+    /// Short name used in error messages
+    fn name(&self) -> &str;
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error>;
+
+    /// Rows above and below a strip of the image the filter reads to get the strip's rows
+    /// right, or `None` if it needs the whole image. Pipelines whose filters all have one can
+    /// run on strips when the image doesn't fit their [memory budget](Pipeline::memory_budget).
+    fn halo(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// A chain of filters run on a [`FloatImage`], quantized to 8 bits once at the end
+///
+/// # Examples
+/// ```
+/// use vincent_blur::dither::Dither;
+/// use vincent_blur::pipeline::{Gaussian, Noise, Pipeline};
+/// use vincent_blur::{Direction, Kernel};
+///
+/// let pipeline = Pipeline::new()
+///     .then(Gaussian {
+///         kernel: Kernel::new(3, 2.0),
+///         passes: 1,
+///         direction: Direction::Both,
+///     })
+///     .then(Noise { amount: 1.0, seed: 7 })
+///     .dither(Dither::Ordered);
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 128]));
+/// let out = pipeline.run(&img).unwrap();
+///
+/// assert_eq!(out.dimensions(), img.dimensions());
+/// ```
+pub struct Pipeline {
+    filters: Vec<Box<dyn Filter>>,
+    dither: Dither,
+    budget: Option<u64>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::new()
+    }
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline that truncates when quantizing
+    pub fn new() -> Self {
+        Pipeline {
+            filters: Vec::new(),
+            dither: Dither::None,
+            budget: None,
+        }
+    }
+
+    /// Appends a filter
+    pub fn then(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Appends a boxed filter
+    pub fn push(&mut self, filter: Box<dyn Filter>) {
+        self.filters.push(filter);
+    }
+
+    /// Appends every filter of `other`, keeping this pipeline's dithering
+    pub fn append(&mut self, other: Pipeline) {
+        self.filters.extend(other.filters);
+    }
+
+    /// Sets how the result is rounded to 8 bits
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Keeps runs within about `budget` bytes, see [`memory::required`]. Images that don't fit
+    /// are blurred in strips of rows if every filter has a [halo](Filter::halo) and the dither
+    /// isn't [`Dither::Diffusion`], and refused otherwise. `None`, the default, lifts the budget.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::pipeline::{Gaussian, Noise, Pipeline};
+    /// use vincent_blur::{Direction, Error, Kernel};
+    ///
+    /// let gaussian = Gaussian {
+    ///     kernel: Kernel::new(3, 2.0),
+    ///     passes: 2,
+    ///     direction: Direction::Both,
+    /// };
+    /// let img = image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * y) as u8, 0, 0]));
+    ///
+    /// let strips = Pipeline::new().then(gaussian).memory_budget(Some(80_000));
+    /// assert_eq!(strips.run(&img), Pipeline::new().then(gaussian).run(&img));
+    ///
+    /// let whole = Pipeline::new()
+    ///     .then(Noise { amount: 1.0, seed: 7 })
+    ///     .memory_budget(Some(80_000));
+    /// assert!(matches!(whole.run(&img), Err(Error::TooLarge(_))));
+    /// ```
+    pub fn memory_budget(mut self, budget: Option<u64>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Runs every filter in order without quantizing
+    ///
+    /// # Errors
+    /// - The first error returned by a filter
+    pub fn run_float(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        self.filters.iter().try_fold(img, |img, f| f.apply(img))
+    }
+
+    /// Converts `img` to f32, runs every filter and quantizes the result
+    ///
+    /// # Errors
+    /// - Same as [`Pipeline::run_float`]
+    pub fn run(&self, img: &RgbImage) -> Result<RgbImage, Error> {
+        self.run_with(img, None)
+    }
+
+    /// Same as [`Pipeline::run`], giving up once `deadline` has passed. It is checked between
+    /// filters, between the rows of the separable blurs and by the workers of the 2D blurs,
+    /// which stop early.
+    ///
+    /// # Errors
+    /// - [`Error::Timeout`] if the result wasn't ready by `deadline`
+    /// - Same as [`Pipeline::run_float`]
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// use vincent_blur::pipeline::{Gaussian, Pipeline};
+    /// use vincent_blur::{Direction, Error, Kernel};
+    ///
+    /// let pipeline = Pipeline::new().then(Gaussian {
+    ///     kernel: Kernel::new(3, 2.0),
+    ///     passes: 1,
+    ///     direction: Direction::Both,
+    /// });
+    /// let img = image::RgbImage::new(16, 16);
+    ///
+    /// let later = Instant::now() + Duration::from_secs(60);
+    /// assert_eq!(pipeline.run_until(&img, later), pipeline.run(&img));
+    /// assert_eq!(pipeline.run_until(&img, Instant::now()), Err(Error::Timeout));
+    /// ```
+    pub fn run_until(&self, img: &RgbImage, deadline: Instant) -> Result<RgbImage, Error> {
+        self.run_with(img, Some(deadline))
+    }
+
+    /// Rows every filter reads above and below a strip, rounded up so strips start on a row of
+    /// the ordered dither's pattern, or `None` if the pipeline can't run on strips
+    fn halo(&self) -> Option<u32> {
+        if self.dither == Dither::Diffusion {
+            return None;
+        }
+
+        let halo = self
+            .filters
+            .iter()
+            .try_fold(0, |halo, f| Some(halo + f.halo()?))?;
+
+        Some(halo.next_multiple_of(memory::STRIP_ALIGN))
+    }
+
+    fn run_with(&self, img: &RgbImage, deadline: Option<Instant>) -> Result<RgbImage, Error> {
+        let (width, height) = img.dimensions();
+        let rows = match self.budget {
+            Some(budget) => memory::strip_rows(width, height, self.halo(), budget)?,
+            None => height,
+        };
+
+        if rows >= height {
+            return self.run_whole(img, deadline);
+        }
+
+        // Every strip is blurred with `halo` rows of context on both sides, of which only its
+        // own rows are kept
+        let halo = self.halo().unwrap_or(0);
+        let stride = width as usize * 3;
+        let mut out = Vec::with_capacity(stride * height as usize);
+
+        for y0 in (0..height).step_by(rows as usize) {
+            let y1 = (y0 + rows).min(height);
+            let (top, bottom) = (y0.saturating_sub(halo), (y1 + halo).min(height));
+
+            let raw = img.as_raw()[top as usize * stride..bottom as usize * stride].to_vec();
+            let strip = self.run_whole(
+                &RgbImage::from_raw(width, bottom - top, raw).unwrap(),
+                deadline,
+            )?;
+
+            let kept = (y0 - top) as usize * stride..(y1 - top) as usize * stride;
+            out.extend_from_slice(&strip.as_raw()[kept]);
+        }
+
+        Ok(RgbImage::from_raw(width, height, out).unwrap())
+    }
+
+    fn run_whole(&self, img: &RgbImage, deadline: Option<Instant>) -> Result<RgbImage, Error> {
+        let out = deadline::with(deadline, || {
+            self.filters
+                .iter()
+                .try_fold(FloatImage::from_rgb(img), |img, f| {
+                    if deadline::passed(deadline) {
+                        img.recycle();
+                        return Err(Error::Timeout);
+                    }
+
+                    f.apply(img)
+                })
+        })?;
+
+        if deadline::passed(deadline) {
+            out.recycle();
+            return Err(Error::Timeout);
+        }
+
+        let rgb = out.to_rgb(self.dither);
+
+        out.recycle();
+        Ok(rgb)
+    }
+}
+
+/// Separable Gaussian blur that stays in f32, see [`crate::blur_passes`]
+#[derive(Debug, Clone, Copy)]
+pub struct Gaussian {
+    pub kernel: Kernel,
+    pub passes: usize,
+    pub direction: Direction,
+}
+
+impl Filter for Gaussian {
+    fn name(&self) -> &str {
+        "gaussian"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let data = separable::run_passes(
+            img.data,
+            img.width as usize,
+            3,
+            &self.kernel.weights(),
+            self.passes,
+            self.direction,
+        );
+
+        Ok(FloatImage { data, ..img })
+    }
+
+    fn halo(&self) -> Option<u32> {
+        match self.direction {
+            Direction::Horizontal => Some(0),
+            _ => Some(self.kernel.radius as u32 * self.passes as u32),
+        }
+    }
+}
+
+/// Seeded Gaussian noise, see [`effects::add_noise`]
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    pub amount: f32,
+    pub seed: u64,
+}
+
+impl Filter for Noise {
+    fn name(&self) -> &str {
+        "noise"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        for (v, n) in img.data.iter_mut().zip(effects::gaussian_noise(self.seed)) {
+            *v = (*v + n * self.amount).clamp(0.0, 255.0);
+        }
+
+        Ok(img)
+    }
+}
+
+/// Runs an 8-bit operation as a stage, truncating its input to 8 bits first. Useful for
+/// operations that only exist on [`RgbImage`], such as the [`crate::backend`] blurs.
+pub struct Rgb8<F>(pub &'static str, pub F);
+
+impl<F> Filter for Rgb8<F>
+where
+    F: Fn(RgbImage) -> Result<RgbImage, Error> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let rgb = img.to_rgb(Dither::None);
+        img.recycle();
+
+        Ok(FloatImage::from_rgb(&(self.1)(rgb)?))
+    }
+}
+
+/// Resamples the image to exactly `width`x`height`
+#[derive(Debug, Clone, Copy)]
+pub struct Resize {
+    pub width: u32,
+    pub height: u32,
+    pub filter: FilterType,
+}
+
+impl Filter for Resize {
+    fn name(&self) -> &str {
+        "resize"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let resized = imageops::resize(&img.into_buffer(), self.width, self.height, self.filter);
+        let mut img = FloatImage::from_buffer(resized);
+
+        // Lanczos and Catmull-Rom ring past the input range
+        for v in img.data.iter_mut() {
+            *v = v.clamp(0.0, 255.0);
+        }
+
+        Ok(img)
+    }
+}
+
+/// Resamples the image by `factor` on both axes, keeping at least one pixel
+///
+/// # Examples
+/// ```
+/// use image::imageops::FilterType;
+/// use vincent_blur::pipeline::{Pipeline, Scale};
+///
+/// let img = image::RgbImage::new(64, 30);
+/// let half = Pipeline::new()
+///     .then(Scale { factor: 0.5, filter: FilterType::Triangle })
+///     .run(&img)
+///     .unwrap();
+///
+/// assert_eq!(half.dimensions(), (32, 15));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Scale {
+    pub factor: f32,
+    pub filter: FilterType,
+}
+
+impl Filter for Scale {
+    fn name(&self) -> &str {
+        "scale"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let scaled = |v: u32| ((v as f32 * self.factor).round() as u32).max(1);
+
+        Resize {
+            width: scaled(img.width),
+            height: scaled(img.height),
+            filter: self.filter,
+        }
+        .apply(img)
+    }
+}
+
+/// Keeps only the part of the image inside `rect`, clipped to the image
+#[derive(Debug, Clone, Copy)]
+pub struct Crop {
+    pub rect: Rect,
+}
+
+impl Filter for Crop {
+    fn name(&self) -> &str {
+        "crop"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let rect = self.rect.clamp_to(img.width, img.height).ok_or_else(|| {
+            Error::InvalidArgs(tr!("crop-outside", self.rect, img.width, img.height).into())
+        })?;
+
+        let stride = img.width as usize * 3;
+        let data = img
+            .data
+            .chunks_exact(stride)
+            .skip(rect.y as usize)
+            .take(rect.height as usize)
+            .flat_map(|row| &row[rect.x as usize * 3..rect.right() as usize * 3])
+            .copied()
+            .collect();
+
+        Ok(FloatImage {
+            width: rect.width,
+            height: rect.height,
+            data,
+        })
+    }
+}
+
+/// Keeps a `width`x`height` rectangle from the middle of the image, or all of an axis that is
+/// smaller than that
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pipeline::{CropCenter, Pipeline};
+///
+/// let img = image::RgbImage::new(64, 30);
+/// let cropped = Pipeline::new()
+///     .then(CropCenter { width: 20, height: 40 })
+///     .run(&img)
+///     .unwrap();
+///
+/// assert_eq!(cropped.dimensions(), (20, 30));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CropCenter {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Filter for CropCenter {
+    fn name(&self) -> &str {
+        "crop-center"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let (width, height) = (self.width.min(img.width), self.height.min(img.height));
+        let rect = Rect::new(
+            (img.width - width) / 2,
+            (img.height - height) / 2,
+            width,
+            height,
+        );
+
+        Crop { rect }.apply(img)
+    }
+}
+
+/// Turns the image clockwise by a quarter, half or three quarters
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pipeline::{Pipeline, Rotate};
+///
+/// let img = image::RgbImage::new(64, 30);
+/// let turned = Pipeline::new().then(Rotate::Cw90).run(&img).unwrap();
+///
+/// assert_eq!(turned.dimensions(), (30, 64));
+/// assert_eq!("270".parse::<Rotate>(), Ok(Rotate::Cw270));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotate {
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl FromStr for Rotate {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "90" => Ok(Rotate::Cw90),
+            "180" => Ok(Rotate::Cw180),
+            "270" | "-90" => Ok(Rotate::Cw270),
+            _ => Err(tr!("unknown-rotation", s)),
+        }
+    }
+}
+
+impl Filter for Rotate {
+    fn name(&self) -> &str {
+        "rotate"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let buf = img.into_buffer();
+
+        Ok(FloatImage::from_buffer(match self {
+            Rotate::Cw90 => imageops::rotate90(&buf),
+            Rotate::Cw180 => imageops::rotate180(&buf),
+            Rotate::Cw270 => imageops::rotate270(&buf),
+        }))
+    }
+}
+
+/// Mirrors the image left to right or top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+}
+
+impl FromStr for Flip {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "horizontal" | "h" => Ok(Flip::Horizontal),
+            "vertical" | "v" => Ok(Flip::Vertical),
+            _ => Err(tr!("unknown-flip", s)),
+        }
+    }
+}
+
+impl Filter for Flip {
+    fn name(&self) -> &str {
+        "flip"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let mut buf = img.into_buffer();
+
+        match self {
+            Flip::Horizontal => imageops::flip_horizontal_in_place(&mut buf),
+            Flip::Vertical => imageops::flip_vertical_in_place(&mut buf),
+        }
+
+        Ok(FloatImage::from_buffer(buf))
+    }
+}
+
+/// Tonal adjustment: `gamma` above 1 lifts the midtones, then `contrast` scales the distance
+/// from mid-grey and `brightness` scales the result, each leaving the image unchanged at 1
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pipeline::{Pipeline, Tone};
+///
+/// let img = image::RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 50]));
+/// let dimmed = Pipeline::new()
+///     .then(Tone { brightness: 0.5, ..Tone::default() })
+///     .run(&img)
+///     .unwrap();
+///
+/// assert_eq!(dimmed.get_pixel(0, 0), &image::Rgb([100, 50, 25]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub brightness: f32,
+    pub contrast: f32,
+    pub gamma: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            brightness: 1.0,
+            contrast: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Tone {
+    /// Whether applying this would leave the image unchanged
+    pub fn is_identity(&self) -> bool {
+        *self == Tone::default()
+    }
+}
+
+impl Filter for Tone {
+    fn name(&self) -> &str {
+        "tone"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        let exponent = 1.0 / self.gamma;
+
+        for v in img.data.iter_mut() {
+            let x = match self.gamma == 1.0 {
+                true => *v,
+                false => 255.0 * (*v / 255.0).powf(exponent),
+            };
+
+            *v = (((x - 127.5) * self.contrast + 127.5) * self.brightness).clamp(0.0, 255.0);
+        }
+
+        Ok(img)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Lays `color` over the image at `opacity`, e.g. to dim a blurred background behind UI
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pipeline::{Pipeline, Tint};
+///
+/// let img = image::RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 0]));
+/// let tinted = Pipeline::new()
+///     .then(Tint { color: image::Rgb([0, 0, 200]), opacity: 0.5 })
+///     .run(&img)
+///     .unwrap();
+///
+/// assert_eq!(tinted.get_pixel(0, 0), &image::Rgb([100, 50, 100]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tint {
+    pub color: Rgb<u8>,
+    pub opacity: f32,
+}
+
+impl Filter for Tint {
+    fn name(&self) -> &str {
+        "tint"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        for p in img.data.chunks_exact_mut(3) {
+            for (v, c) in p.iter_mut().zip(self.color.0) {
+                *v += (c as f32 - *v) * self.opacity;
+            }
+        }
+
+        Ok(img)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Replaces every pixel with its luma
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grayscale;
+
+impl Filter for Grayscale {
+    fn name(&self) -> &str {
+        "grayscale"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        for p in img.data.chunks_exact_mut(3) {
+            p.fill(luma(p));
+        }
+
+        Ok(img)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Maps the luma of every pixel onto a gradient from `dark` to `light`
+///
+/// # Examples
+/// ```
+/// use image::Rgb;
+/// use vincent_blur::pipeline::{Duotone, Pipeline};
+///
+/// let img = image::RgbImage::from_fn(2, 1, |x, _| Rgb([255 * x as u8; 3]));
+/// let toned = Pipeline::new()
+///     .then(Duotone { dark: Rgb([20, 0, 60]), light: Rgb([255, 200, 120]) })
+///     .run(&img)
+///     .unwrap();
+///
+/// assert_eq!(toned.get_pixel(0, 0), &Rgb([20, 0, 60]));
+/// assert_eq!(toned.get_pixel(1, 0), &Rgb([255, 200, 120]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duotone {
+    pub dark: Rgb<u8>,
+    pub light: Rgb<u8>,
+}
+
+impl Filter for Duotone {
+    fn name(&self) -> &str {
+        "duotone"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        for p in img.data.chunks_exact_mut(3) {
+            let t = luma(p) / 255.0;
+
+            for (c, v) in p.iter_mut().enumerate() {
+                let (dark, light) = (self.dark[c] as f32, self.light[c] as f32);
+                *v = dark + (light - dark) * t;
+            }
+        }
+
+        Ok(img)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Converts sRGB-encoded values to linear light, so the stages after it blend light rather than
+/// encoded values, e.g. before a [`Gaussian`]. Undo it with [`Srgb`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linear;
+
+impl Filter for Linear {
+    fn name(&self) -> &str {
+        "linear"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        for v in img.data.iter_mut() {
+            let c = *v / 255.0;
+            let c = match c <= 0.04045 {
+                true => c / 12.92,
+                false => ((c + 0.055) / 1.055).powf(2.4),
+            };
+
+            *v = c * 255.0;
+        }
+
+        Ok(img)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Converts linear light back to sRGB-encoded values, see [`Linear`]
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pipeline::{Linear, Pipeline, Srgb};
+///
+/// let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 77]));
+/// let round_trip = Pipeline::new().then(Linear).then(Srgb).run(&img).unwrap();
+///
+/// for (a, b) in img.pixels().zip(round_trip.pixels()) {
+///     for c in 0..3 {
+///         assert!(a[c].abs_diff(b[c]) <= 1);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Srgb;
+
+impl Filter for Srgb {
+    fn name(&self) -> &str {
+        "srgb"
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        for v in img.data.iter_mut() {
+            let c = (*v / 255.0).clamp(0.0, 1.0);
+            let c = match c <= 0.0031308 {
+                true => c * 12.92,
+                false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+            };
+
+            *v = c * 255.0;
+        }
+
+        Ok(img)
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+/// Sharpens by adding `amount` times the difference between the image and a Gaussian blur of
+/// it with `radius` and `sigma`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unsharp {
+    pub amount: f32,
+    pub radius: u8,
+    pub sigma: f64,
+}
+
+impl Filter for Unsharp {
+    fn name(&self) -> &str {
+        "unsharp"
+    }
+
+    fn apply(&self, img: FloatImage) -> Result<FloatImage, Error> {
+        let blurred = Gaussian {
+            kernel: Kernel::new(self.radius, self.sigma),
+            passes: 1,
+            direction: Direction::Both,
+        }
+        .apply(img.clone())?;
+
+        let data = img
+            .data
+            .iter()
+            .zip(&blurred.data)
+            .map(|(v, b)| (v + (v - b) * self.amount).clamp(0.0, 255.0))
+            .collect();
+
+        Ok(FloatImage { data, ..img })
+    }
+
+    fn halo(&self) -> Option<u32> {
+        Some(self.radius as u32)
+    }
+}
+
+/// Rec. 601 luma of an RGB pixel, same weights as the 8-bit masks
+fn luma(p: &[f32]) -> f32 {
+    (0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2]).clamp(0.0, 255.0)
+}
@@ -0,0 +1,130 @@
+use crate::pipeline::Gaussian;
+use crate::timings::{self, Stage};
+use crate::tr;
+use crate::{separable, Direction, Error};
+use image::RgbImage;
+
+/// Everything a separable blur of one image size needs, computed once: the kernel, the edge
+/// normalization of every row and column, the column blocks of the vertical pass and the
+/// working buffers. Executing the plan on frames of that size then only runs the passes.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::pipeline::Gaussian;
+/// use vincent_blur::plan::BlurPlan;
+/// use vincent_blur::{blur_passes, Direction, Kernel};
+///
+/// let gaussian = Gaussian {
+///     kernel: Kernel::new(4, 2.0),
+///     passes: 2,
+///     direction: Direction::Both,
+/// };
+/// let mut plan = BlurPlan::new(48, 32, gaussian);
+///
+/// for frame in 0..3 {
+///     let img = image::RgbImage::from_fn(48, 32, |x, y| {
+///         image::Rgb([(x * 5) as u8, (y * 7) as u8, frame * 80])
+///     });
+///
+///     assert_eq!(plan.execute(&img).unwrap(), blur_passes(gaussian.kernel, 2, Direction::Both, &img));
+/// }
+///
+/// assert!(plan.execute(&image::RgbImage::new(32, 48)).is_err());
+/// ```
+pub struct BlurPlan {
+    width: u32,
+    height: u32,
+    passes: usize,
+    direction: Direction,
+    weights: Vec<f32>,
+    row_norms: Vec<f32>,
+    column_norms: Vec<f32>,
+    block: usize,
+    src: Vec<f32>,
+    dst: Vec<f32>,
+}
+
+impl BlurPlan {
+    /// Plans `gaussian` for `width`x`height` RGB images
+    pub fn new(width: u32, height: u32, gaussian: Gaussian) -> Self {
+        let weights = gaussian.kernel.weights();
+        let len = width as usize * height as usize * 3;
+
+        BlurPlan {
+            width,
+            height,
+            passes: gaussian.passes,
+            direction: gaussian.direction,
+            row_norms: separable::edge_norms(width as usize, &weights),
+            column_norms: separable::edge_norms(height as usize, &weights),
+            block: separable::column_block(width as usize * 3, weights.len()),
+            weights,
+            src: vec![0.0; len],
+            dst: vec![0.0; len],
+        }
+    }
+
+    /// Size of the images the plan blurs
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Blurs `img` like [`blur_passes`](crate::blur_passes), without allocating anything but
+    /// the result
+    ///
+    /// # Errors
+    /// - [`Error::InvalidArgs`] if `img` isn't the size the plan was made for
+    pub fn execute(&mut self, img: &RgbImage) -> Result<RgbImage, Error> {
+        if img.dimensions() != self.dimensions() {
+            return Err(Error::InvalidArgs(
+                tr!(
+                    "plan-size",
+                    self.width,
+                    self.height,
+                    img.width(),
+                    img.height()
+                )
+                .into(),
+            ));
+        }
+
+        for (d, &s) in self.src.iter_mut().zip(img.as_raw()) {
+            *d = s as f32;
+        }
+
+        let width = self.width as usize;
+
+        for _ in 0..self.passes {
+            if self.direction != Direction::Vertical {
+                timings::time(Stage::Horizontal, || {
+                    separable::horizontal_with(
+                        &self.src,
+                        &mut self.dst,
+                        width,
+                        3,
+                        &self.weights,
+                        &self.row_norms,
+                    )
+                });
+                std::mem::swap(&mut self.src, &mut self.dst);
+            }
+
+            if self.direction != Direction::Horizontal {
+                timings::time(Stage::Vertical, || {
+                    separable::vertical_with(
+                        &self.src,
+                        &mut self.dst,
+                        width,
+                        3,
+                        &self.weights,
+                        &self.column_norms,
+                        self.block,
+                    )
+                });
+                std::mem::swap(&mut self.src, &mut self.dst);
+            }
+        }
+
+        Ok(separable::to_rgb(self.width, self.height, &self.src))
+    }
+}
@@ -0,0 +1,183 @@
+use crate::locale::Message;
+use crate::ops::StageFactory;
+use crate::pipeline::{Filter, FloatImage};
+use crate::tr;
+use crate::Error;
+use libloading::Library;
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Version of [`PluginV1`] this build understands
+pub const ABI_VERSION: u32 = 1;
+
+/// Symbol every plugin exports, an `extern "C" fn() -> *const PluginV1`
+pub const ENTRY_POINT: &[u8] = b"blur_plugin_v1";
+
+/// What a plugin library describes itself with. In C:
+///
+/// ```c
+/// typedef struct {
+///     uint32_t abi_version;  /* 1 */
+///     const char *name;      /* stage name used in --ops */
+///     /* Optional, returns 0 if the arguments are valid */
+///     int32_t (*check)(const char *args);
+///     /* Filters width * height interleaved RGB floats from 0 to 255 in place,
+///        returns 0 on success */
+///     int32_t (*apply)(float *data, uint32_t width, uint32_t height, const char *args);
+/// } BlurPluginV1;
+///
+/// const BlurPluginV1 *blur_plugin_v1(void);
+/// ```
+///
+/// `args` holds the stage's arguments as written in `--ops`, e.g. `0.5,mode=soft`. `apply` is
+/// called from several threads at once when files are blurred in parallel.
+#[repr(C)]
+pub struct PluginV1 {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub check: Option<unsafe extern "C" fn(args: *const c_char) -> i32>,
+    pub apply:
+        unsafe extern "C" fn(data: *mut f32, width: u32, height: u32, args: *const c_char) -> i32,
+}
+
+/// A loaded plugin library, usable as an `--ops` stage
+pub struct Plugin {
+    name: String,
+    check: Option<unsafe extern "C" fn(*const c_char) -> i32>,
+    apply: unsafe extern "C" fn(*mut f32, u32, u32, *const c_char) -> i32,
+    // Keeps the functions above loaded
+    _lib: Arc<Library>,
+}
+
+// Plugins promise that their functions can be called from any thread
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+impl Plugin {
+    /// Loads a plugin library
+    ///
+    /// # Safety
+    /// Loading runs the library's initializers, and its functions are trusted to follow
+    /// [`PluginV1`]
+    ///
+    /// # Errors
+    /// - If the library can't be loaded, doesn't export [`ENTRY_POINT`] or has another ABI
+    ///   version
+    pub unsafe fn load(path: &Path) -> Result<Plugin, Message> {
+        let fail = |e: &dyn std::fmt::Display| tr!("load-failed", path.display(), e);
+
+        let lib = Library::new(path).map_err(|e| fail(&e))?;
+        let entry = lib
+            .get::<unsafe extern "C" fn() -> *const PluginV1>(ENTRY_POINT)
+            .map_err(|e| fail(&e))?;
+
+        let desc = entry()
+            .as_ref()
+            .ok_or_else(|| fail(&"no plugin description"))?;
+
+        if desc.abi_version != ABI_VERSION {
+            return Err(tr!("load-failed", path.display()).with(tr!(
+                "abi-version",
+                desc.abi_version,
+                ABI_VERSION
+            )));
+        }
+
+        if desc.name.is_null() {
+            return Err(fail(&"no stage name"));
+        }
+
+        Ok(Plugin {
+            name: CStr::from_ptr(desc.name).to_string_lossy().into_owned(),
+            check: desc.check,
+            apply: desc.apply,
+            _lib: Arc::new(lib),
+        })
+    }
+}
+
+/// Loads every dynamic library in `dir`
+///
+/// # Safety
+/// Same as [`Plugin::load`], for every library in `dir`
+///
+/// # Errors
+/// - If `dir` can't be read or a library fails to load
+pub unsafe fn discover(dir: &Path) -> Result<Vec<Arc<Plugin>>, Message> {
+    let entries = fs::read_dir(dir).map_err(|e| tr!("read-failed", dir.display(), e))?;
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(std::env::consts::DLL_EXTENSION))
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|p| Plugin::load(p).map(Arc::new))
+        .collect()
+}
+
+impl StageFactory for Plugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn build(&self, args: &str) -> Result<Box<dyn Filter>, Message> {
+        let args = CString::new(args).map_err(|_| "Arguments contain a NUL byte")?;
+
+        if let Some(check) = self.check {
+            let code = unsafe { check(args.as_ptr()) };
+
+            if code != 0 {
+                return Err(tr!("invalid-arguments", args.to_string_lossy()));
+            }
+        }
+
+        Ok(Box::new(PluginStage {
+            name: self.name.clone(),
+            apply: self.apply,
+            args,
+            _lib: Arc::clone(&self._lib),
+        }))
+    }
+}
+
+/// One use of a plugin in a pipeline
+struct PluginStage {
+    name: String,
+    apply: unsafe extern "C" fn(*mut f32, u32, u32, *const c_char) -> i32,
+    args: CString,
+    _lib: Arc<Library>,
+}
+
+unsafe impl Send for PluginStage {}
+unsafe impl Sync for PluginStage {}
+
+impl Filter for PluginStage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        let (width, height) = img.dimensions();
+        let data = img.as_raw_mut();
+
+        let code = unsafe { (self.apply)(data.as_mut_ptr(), width, height, self.args.as_ptr()) };
+
+        if code != 0 {
+            return Err(Error::InvalidArgs(
+                tr!("plugin-failed", self.name, code).into(),
+            ));
+        }
+
+        // Keep a misbehaving plugin from breaking the stages after it
+        for v in data.iter_mut() {
+            *v = if v.is_nan() { 0.0 } else { v.clamp(0.0, 255.0) };
+        }
+
+        Ok(img)
+    }
+}
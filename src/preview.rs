@@ -0,0 +1,268 @@
+use crate::tr;
+use crate::Error;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, terminal};
+use image::RgbImage;
+use std::env;
+use std::io::{self, Write};
+
+/// Step of the sigma adjustment
+const SIGMA_STEP: f64 = 0.5;
+
+/// Inline image protocols understood by terminals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// kitty graphics protocol, also used by WezTerm and Ghostty
+    Kitty,
+    /// iTerm2 inline images
+    Iterm,
+    /// DEC sixel graphics, e.g. xterm -ti vt340, foot or mlterm
+    Sixel,
+}
+
+impl Protocol {
+    /// Guesses the protocol from the environment, preferring `BLUR_PREVIEW_PROTOCOL` when it's
+    /// set to kitty, iterm or sixel
+    pub fn detect() -> Protocol {
+        match env::var("BLUR_PREVIEW_PROTOCOL").as_deref() {
+            Ok("kitty") => return Protocol::Kitty,
+            Ok("iterm") => return Protocol::Iterm,
+            Ok("sixel") => return Protocol::Sixel,
+            _ => {}
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+
+        if term.contains("kitty") || env::var_os("KITTY_WINDOW_ID").is_some() {
+            Protocol::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Protocol::Iterm
+        } else {
+            Protocol::Sixel
+        }
+    }
+}
+
+/// Escape sequence that draws `img` at the cursor
+///
+/// # Examples
+/// ```
+/// use vincent_blur::preview::{encode, Protocol};
+///
+/// let img = image::RgbImage::from_pixel(4, 7, image::Rgb([255, 0, 0]));
+/// let out = String::from_utf8(encode(&img, Protocol::Sixel).unwrap()).unwrap();
+///
+/// assert!(out.starts_with("\x1bPq\"1;1;4;7"));
+/// assert!(out.ends_with("\x1b\\"));
+/// ```
+///
+/// # Errors
+/// - If the image can't be encoded as PNG for kitty or iTerm2
+pub fn encode(img: &RgbImage, protocol: Protocol) -> Result<Vec<u8>, String> {
+    match protocol {
+        Protocol::Sixel => Ok(sixel(img)),
+        Protocol::Kitty | Protocol::Iterm => {
+            let mut png = Vec::new();
+            img.write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .map_err(|e| e.to_string())?;
+            let data = STANDARD.encode(&png);
+
+            Ok(match protocol {
+                Protocol::Kitty => kitty(&data),
+                _ => format!("\x1b]1337;File=inline=1;size={}:{data}\x07", png.len()).into_bytes(),
+            })
+        }
+    }
+}
+
+/// kitty only takes 4096 bytes of payload per escape sequence
+fn kitty(data: &str) -> Vec<u8> {
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    let mut out = Vec::new();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+
+        match i {
+            0 => out.extend_from_slice(format!("\x1b_Ga=T,f=100,m={more};").as_bytes()),
+            _ => out.extend_from_slice(format!("\x1b_Gm={more};").as_bytes()),
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+
+    out
+}
+
+/// Sixel image with a 6x6x6 color cube palette
+fn sixel(img: &RgbImage) -> Vec<u8> {
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let index: Vec<u32> = img
+        .pixels()
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    let (width, height) = img.dimensions();
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+
+    for i in 0..216 {
+        let percent = |l: u32| l * 100 / 5;
+        out += &format!(
+            "#{i};2;{};{};{}",
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut colors: Vec<u32> = (band..band + rows)
+            .flat_map(|y| &index[(y * width) as usize..((y + 1) * width) as usize])
+            .copied()
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (n, &color) in colors.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            out += &format!("#{color}");
+
+            let bits = (0..width).map(|x| {
+                (0..rows)
+                    .filter(|r| index[((band + r) * width + x) as usize] == color)
+                    .fold(0u8, |bits, r| bits | (1 << r))
+            });
+
+            push_runs(&mut out, bits);
+        }
+
+        out.push('-');
+    }
+
+    out += "\x1b\\";
+    out.into_bytes()
+}
+
+/// Sixel characters with runs of 4 or more written as `!<count><char>`
+fn push_runs(out: &mut String, bits: impl Iterator<Item = u8>) {
+    let mut run: Option<(u8, usize)> = None;
+
+    let flush = |out: &mut String, (b, n): (u8, usize)| {
+        let c = (63 + b) as char;
+        match n {
+            1..=3 => out.extend(std::iter::repeat_n(c, n)),
+            _ => *out += &format!("!{n}{c}"),
+        }
+    };
+
+    for b in bits {
+        run = match run {
+            Some((prev, n)) if prev == b => Some((prev, n + 1)),
+            Some(r) => {
+                flush(out, r);
+                Some((b, 1))
+            }
+            None => Some((b, 1)),
+        };
+    }
+
+    if let Some(r) = run {
+        flush(out, r);
+    }
+}
+
+/// Shows `render(radius, sigma)` in the terminal and lets the user adjust the radius with
+/// left/right and the sigma with up/down. Returns the chosen radius and sigma when the user
+/// presses enter.
+///
+/// # Errors
+/// - [`Error::Cancelled`] if the user presses q or escape
+/// - The first error returned by `render`, or if the terminal can't be drawn to
+pub fn interactive<F>(radius: u8, sigma: f64, render: F) -> Result<(u8, f64), Error>
+where
+    F: Fn(u8, f64) -> Result<RgbImage, Error>,
+{
+    let protocol = Protocol::detect();
+    let term_err = |e: io::Error| Error::InvalidArgs(tr!("preview-failed", e).into());
+
+    terminal::enable_raw_mode().map_err(term_err)?;
+    let res = preview_loop(radius, sigma, protocol, render);
+    let restored = terminal::disable_raw_mode().map_err(term_err);
+
+    let mut stdout = io::stdout();
+    let _ = execute!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    );
+
+    let chosen = res?;
+    restored?;
+    Ok(chosen)
+}
+
+fn preview_loop<F>(
+    mut radius: u8,
+    mut sigma: f64,
+    protocol: Protocol,
+    render: F,
+) -> Result<(u8, f64), Error>
+where
+    F: Fn(u8, f64) -> Result<RgbImage, Error>,
+{
+    let term_err = |e: io::Error| Error::InvalidArgs(tr!("preview-failed", e).into());
+    let mut stdout = io::stdout();
+    let mut dirty = true;
+
+    loop {
+        if dirty {
+            let img = render(radius, sigma)?;
+            let drawn = encode(&img, protocol).map_err(|e| Error::InvalidArgs(e.into()))?;
+
+            execute!(
+                stdout,
+                terminal::Clear(terminal::ClearType::All),
+                cursor::MoveTo(0, 0)
+            )
+            .map_err(term_err)?;
+            if protocol == Protocol::Kitty {
+                stdout.write_all(b"\x1b_Ga=d\x1b\\").map_err(term_err)?;
+            }
+            stdout.write_all(&drawn).map_err(term_err)?;
+            write!(
+                stdout,
+                "\r\nradius {radius}  sigma {sigma:.1}    left/right radius, up/down sigma, enter to render, q to quit\r\n"
+            )
+            .map_err(term_err)?;
+            stdout.flush().map_err(term_err)?;
+
+            dirty = false;
+        }
+
+        let Event::Key(key) = event::read().map_err(term_err)? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Left if radius > 1 => radius -= 1,
+            KeyCode::Right if radius < u8::MAX => radius += 1,
+            KeyCode::Up => sigma += SIGMA_STEP,
+            KeyCode::Down if sigma > SIGMA_STEP => sigma -= SIGMA_STEP,
+            KeyCode::Enter => return Ok((radius, sigma)),
+            KeyCode::Esc | KeyCode::Char('q') => return Err(Error::Cancelled),
+            _ => continue,
+        }
+
+        dirty = true;
+    }
+}
@@ -0,0 +1,579 @@
+use crate::locale::Message;
+use crate::tr;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// An axis-aligned rectangle in pixel coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// First column past the rectangle, capped at `u32::MAX`
+    pub fn right(&self) -> u32 {
+        self.x.saturating_add(self.width)
+    }
+
+    /// First row past the rectangle, capped at `u32::MAX`
+    pub fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.height)
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Grows the rectangle by `margin` on every side, without going past 0 or `u32::MAX`
+    pub fn pad(&self, margin: u32) -> Rect {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+
+        Rect::new(
+            x,
+            y,
+            self.right().saturating_add(margin) - x,
+            self.bottom().saturating_add(margin) - y,
+        )
+    }
+
+    /// The part of the rectangle inside a `width`x`height` image, or `None` if it lies
+    /// entirely outside
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::region::Rect;
+    ///
+    /// assert_eq!(Rect::new(8, 2, 10, 4).clamp_to(12, 12), Some(Rect::new(8, 2, 4, 4)));
+    /// assert_eq!(Rect::new(20, 2, 10, 4).clamp_to(12, 12), None);
+    /// assert_eq!(Rect::new(u32::MAX - 5, 0, 10, 10).clamp_to(12, 12), None);
+    /// ```
+    pub fn clamp_to(&self, width: u32, height: u32) -> Option<Rect> {
+        let right = self.right().min(width);
+        let bottom = self.bottom().min(height);
+
+        match self.x < right && self.y < bottom {
+            true => Some(Rect::new(self.x, self.y, right - self.x, bottom - self.y)),
+            false => None,
+        }
+    }
+}
+
+/// Splits a `width`x`height` image into `tile`x`tile` tiles ordered in rings spiraling out
+/// from the tile holding `focus`, clockwise from the left within each ring. Tiles on the right
+/// and bottom edges are cut to fit.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::region::{center_out, Rect};
+///
+/// let tiles = center_out(30, 30, 10, (15, 15));
+///
+/// assert_eq!(tiles.len(), 9);
+/// assert_eq!(tiles[0], Rect::new(10, 10, 10, 10));
+/// assert_eq!(tiles[1], Rect::new(0, 10, 10, 10));
+/// assert_eq!(tiles[2], Rect::new(0, 0, 10, 10));
+/// ```
+pub fn center_out(width: u32, height: u32, tile: u32, focus: (u32, u32)) -> Vec<Rect> {
+    let tile = tile.max(1);
+    let (fx, fy) = (
+        (focus.0.min(width.saturating_sub(1)) / tile) as i64,
+        (focus.1.min(height.saturating_sub(1)) / tile) as i64,
+    );
+
+    let mut tiles: Vec<(i64, f64, Rect)> = (0..height)
+        .step_by(tile as usize)
+        .flat_map(|y| (0..width).step_by(tile as usize).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (dx, dy) = (x as i64 / tile as i64 - fx, y as i64 / tile as i64 - fy);
+            // Clockwise from the left, with y pointing down
+            let angle = (-dy as f64)
+                .atan2(-dx as f64)
+                .rem_euclid(std::f64::consts::TAU);
+
+            let rect = Rect::new(x, y, tile.min(width - x), tile.min(height - y));
+            (dx.abs().max(dy.abs()), angle, rect)
+        })
+        .collect();
+
+    tiles.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    tiles.into_iter().map(|(_, _, rect)| rect).collect()
+}
+
+impl FromStr for Rect {
+    type Err = Message;
+
+    /// Parses `x,y,width,height`, or an ImageMagick geometry, see [`geometry`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || tr!("expected-rectangle", s);
+
+        let values: Vec<u32> = geometry(s)
+            .split(',')
+            .map(|v| v.trim().parse().map_err(|_| err()))
+            .collect::<Result<_, _>>()?;
+
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Rect::new(x, y, width, height)),
+            _ => Err(err()),
+        }
+    }
+}
+
+/// Rewrites an ImageMagick geometry `<width>x<height>+<x>+<y>` as `x,y,width,height`, with the
+/// offsets defaulting to 0. Anything else, including negative offsets, is returned as is.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::region::geometry;
+///
+/// assert_eq!(geometry("300x200+10+20"), "10,20,300,200");
+/// assert_eq!(geometry("50%x50%"), "0,0,50%,50%");
+/// assert_eq!(geometry("10,20,300,200"), "10,20,300,200");
+/// ```
+pub fn geometry(s: &str) -> Cow<'_, str> {
+    let rewritten = (|| {
+        let (width, rest) = s.trim().split_once('x')?;
+        let (height, offsets) = rest.split_once('+').unwrap_or((rest, "0+0"));
+        let (x, y) = offsets.split_once('+')?;
+
+        Some(format!("{x},{y},{width},{height}"))
+    })();
+
+    rewritten.map_or(Cow::Borrowed(s), Cow::Owned)
+}
+
+impl fmt::Display for Rect {
+    /// Formats as `x,y,width,height`, the form [`Rect::from_str`] parses
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// A region that isn't an axis-aligned rectangle, in pixel coordinates where `(x, y)` is the
+/// center of pixel `x, y`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle {
+        cx: f32,
+        cy: f32,
+        r: f32,
+    },
+    Ellipse {
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+    },
+    /// Vertices in order, the last one joined back to the first
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl Shape {
+    /// Parses `cx,cy,r`
+    ///
+    /// # Errors
+    /// - If there aren't 3 numbers or the radius isn't above 0
+    pub fn circle(s: &str) -> Result<Shape, Message> {
+        match numbers(s)?[..] {
+            [cx, cy, r] if r > 0.0 => Ok(Shape::Circle { cx, cy, r }),
+            _ => Err(tr!("expected-circle", s)),
+        }
+    }
+
+    /// Parses `cx,cy,rx,ry`
+    ///
+    /// # Errors
+    /// - If there aren't 4 numbers or a radius isn't above 0
+    pub fn ellipse(s: &str) -> Result<Shape, Message> {
+        match numbers(s)?[..] {
+            [cx, cy, rx, ry] if rx > 0.0 && ry > 0.0 => Ok(Shape::Ellipse { cx, cy, rx, ry }),
+            _ => Err(tr!("expected-ellipse", s)),
+        }
+    }
+
+    /// Parses `x1,y1;x2,y2;...`
+    ///
+    /// # Errors
+    /// - If there are fewer than 3 vertices or one isn't a pair of numbers
+    pub fn polygon(s: &str) -> Result<Shape, Message> {
+        let err = || tr!("expected-polygon", s);
+
+        let vertices = s
+            .split(';')
+            .map(|v| match numbers(v).map_err(|_| err())?[..] {
+                [x, y] => Ok((x, y)),
+                _ => Err(err()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match vertices.len() >= 3 {
+            true => Ok(Shape::Polygon(vertices)),
+            false => Err(err()),
+        }
+    }
+
+    /// Signed distance in pixels from `(x, y)` to the outline, negative inside. Exact for
+    /// circles and polygons, a first-order approximation for ellipses that is exact on the
+    /// outline.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::region::Shape;
+    ///
+    /// let circle = Shape::circle("10,10,5").unwrap();
+    /// assert_eq!(circle.distance(10.0, 10.0), -5.0);
+    /// assert_eq!(circle.distance(18.0, 10.0), 3.0);
+    ///
+    /// let triangle = Shape::polygon("0,0;10,0;0,10").unwrap();
+    /// assert_eq!(triangle.distance(2.0, 1.0), -1.0);
+    /// assert_eq!(triangle.distance(-2.0, 5.0), 2.0);
+    /// ```
+    pub fn distance(&self, x: f32, y: f32) -> f32 {
+        match self {
+            Shape::Circle { cx, cy, r } => (x - cx).hypot(y - cy) - r,
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                let (dx, dy) = (x - cx, y - cy);
+                let f = (dx / rx).powi(2) + (dy / ry).powi(2) - 1.0;
+                let gradient = (2.0 * dx / (rx * rx)).hypot(2.0 * dy / (ry * ry));
+
+                match gradient > 0.0 {
+                    true => f / gradient,
+                    false => -rx.min(*ry),
+                }
+            }
+            Shape::Polygon(vertices) => {
+                let mut nearest = f32::INFINITY;
+                let mut inside = false;
+
+                for (i, &(ax, ay)) in vertices.iter().enumerate() {
+                    let (bx, by) = vertices[(i + 1) % vertices.len()];
+
+                    let (ex, ey) = (bx - ax, by - ay);
+                    let t = (((x - ax) * ex + (y - ay) * ey) / (ex * ex + ey * ey)).clamp(0.0, 1.0);
+                    nearest = nearest.min((x - ax - t * ex).hypot(y - ay - t * ey));
+
+                    if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * ex {
+                        inside = !inside;
+                    }
+                }
+
+                match inside {
+                    true => -nearest,
+                    false => nearest,
+                }
+            }
+        }
+    }
+
+    /// The shape with every coordinate and length multiplied by `factor`
+    pub fn scale(&self, factor: f32) -> Shape {
+        match self {
+            Shape::Circle { cx, cy, r } => Shape::Circle {
+                cx: cx * factor,
+                cy: cy * factor,
+                r: r * factor,
+            },
+            Shape::Ellipse { cx, cy, rx, ry } => Shape::Ellipse {
+                cx: cx * factor,
+                cy: cy * factor,
+                rx: rx * factor,
+                ry: ry * factor,
+            },
+            Shape::Polygon(vertices) => Shape::Polygon(
+                vertices
+                    .iter()
+                    .map(|(x, y)| (x * factor, y * factor))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// A region argument with percentages of the image size in it, e.g. `--region 10%,10%,30%,20%`,
+/// kept as given until the size of the image it applies to is known
+#[derive(Debug, Clone, PartialEq)]
+pub enum Relative {
+    Region(String),
+    KeepRegion(String),
+    Circle(String),
+    Ellipse(String),
+    Polygon(String),
+}
+
+impl Relative {
+    /// The argument in pixels of a `width`x`height` image. X coordinates, widths and
+    /// horizontal radii are percentages of the width, and y coordinates, heights and vertical
+    /// radii of the height. The radius of a circle is a percentage of the smaller side.
+    ///
+    /// # Examples
+    /// ```
+    /// use vincent_blur::region::Relative;
+    ///
+    /// let region = Relative::Region("10%,10%,30%,20%".to_string());
+    /// assert_eq!(region.resolve(200, 100), "20,10,60,20");
+    ///
+    /// let circle = Relative::Circle("50%,50%,25%".to_string());
+    /// assert_eq!(circle.resolve(200, 100), "100,50,25");
+    /// ```
+    pub fn resolve(&self, width: u32, height: u32) -> String {
+        match self {
+            Relative::Region(s) | Relative::KeepRegion(s) => {
+                percentages(&geometry(s), &[width, height])
+            }
+            Relative::Ellipse(s) => percentages(s, &[width, height]),
+            Relative::Circle(s) => percentages(s, &[width, height, width.min(height)]),
+            Relative::Polygon(s) => s
+                .split(';')
+                .map(|v| percentages(v, &[width, height]))
+                .collect::<Vec<_>>()
+                .join(";"),
+        }
+    }
+}
+
+impl fmt::Display for Relative {
+    /// Formats as given on the command line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Relative::Region(s)
+            | Relative::KeepRegion(s)
+            | Relative::Circle(s)
+            | Relative::Ellipse(s)
+            | Relative::Polygon(s) => f.write_str(s),
+        }
+    }
+}
+
+/// Replaces each `N%` among the comma-separated numbers of `s` with `N` percent of the size at
+/// the same position in `sizes`, rounded to whole pixels
+fn percentages(s: &str, sizes: &[u32]) -> String {
+    s.split(',')
+        .zip(sizes.iter().cycle())
+        .map(
+            |(v, size)| match v.trim().strip_suffix('%').map(|p| p.trim().parse::<f64>()) {
+                Some(Ok(p)) => ((p / 100.0 * *size as f64).round() as i64).to_string(),
+                _ => v.trim().to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses comma-separated numbers
+fn numbers(s: &str) -> Result<Vec<f32>, Message> {
+    s.split(',')
+        .map(|v| v.trim().parse().map_err(|_| tr!("expected-numbers", s)))
+        .collect()
+}
+
+/// Reads the rectangles of a sidecar file written by a detector, as JSON for a `.json` file and
+/// CSV otherwise, see [`parse_json`] and [`parse_csv`]
+///
+/// # Errors
+/// - If the file can't be read or holds no rectangles it understands
+pub fn read_file(path: &Path) -> Result<Vec<Rect>, Message> {
+    let src = fs::read_to_string(path).map_err(|e| tr!("read-failed", path.display(), e))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+    match is_json {
+        true => parse_json(&src),
+        false => parse_csv(&src),
+    }
+    .map_err(|e| tr!("invalid-regions-file", path.display()).with(e))
+}
+
+/// Parses the boxes of common detector outputs: a list, or a list under `annotations`,
+/// `regions`, `boxes` or `predictions`, of `[x, y, width, height]` arrays or of objects with
+/// a COCO-style `bbox`, `x`, `y`, `width` and `height` (or `w` and `h`), or `xmin`, `ymin`,
+/// `xmax` and `ymax`. Fractional coordinates are rounded outward.
+///
+/// # Errors
+/// - If the JSON is malformed or a box has none of these forms
+///
+/// # Examples
+/// ```
+/// use vincent_blur::region::{parse_json, Rect};
+///
+/// let coco = r#"{"annotations": [{"bbox": [10, 20, 30.5, 40], "category_id": 1}]}"#;
+/// assert_eq!(parse_json(coco).unwrap(), [Rect::new(10, 20, 31, 40)]);
+///
+/// let corners = r#"[{"label": "face", "xmin": 5, "ymin": 5, "xmax": 25, "ymax": 15}]"#;
+/// assert_eq!(parse_json(corners).unwrap(), [Rect::new(5, 5, 20, 10)]);
+/// ```
+pub fn parse_json(src: &str) -> Result<Vec<Rect>, Message> {
+    use serde_json::Value;
+
+    let root: Value = serde_json::from_str(src).map_err(|e| e.to_string())?;
+
+    let items = match &root {
+        Value::Array(items) => items,
+        Value::Object(o) => ["annotations", "regions", "boxes", "predictions"]
+            .iter()
+            .find_map(|k| o.get(*k).and_then(Value::as_array))
+            .ok_or("Expected a list of boxes")?,
+        _ => return Err("Expected a list of boxes".into()),
+    };
+
+    let number = |v: Option<&Value>| v.and_then(Value::as_f64);
+
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let xywh = |v: &Value| match v.as_array().map(|a| a.iter().map(Value::as_f64).collect())
+            {
+                Some(Some::<Vec<f64>>(b)) if b.len() == 4 => Some([b[0], b[1], b[2], b[3]]),
+                _ => None,
+            };
+
+            let get = |keys: &[&str]| keys.iter().find_map(|k| number(item.get(*k)));
+
+            let bounds = xywh(item)
+                .or_else(|| {
+                    ["bbox", "box"]
+                        .iter()
+                        .find_map(|k| item.get(*k).and_then(xywh))
+                })
+                .map(|[x, y, w, h]| [x, y, x + w, y + h])
+                .or_else(|| {
+                    let (x, y) = (get(&["x"])?, get(&["y"])?);
+                    Some([x, y, x + get(&["width", "w"])?, y + get(&["height", "h"])?])
+                })
+                .or_else(|| {
+                    Some([
+                        get(&["xmin", "x1"])?,
+                        get(&["ymin", "y1"])?,
+                        get(&["xmax", "x2"])?,
+                        get(&["ymax", "y2"])?,
+                    ])
+                });
+
+            bounds
+                .and_then(rect_from_bounds)
+                .ok_or_else(|| tr!("unknown-box", i + 1))
+        })
+        .collect()
+}
+
+/// Parses one box per line. A header row can name the columns `x`, `y`, `width` and `height`
+/// (or `w` and `h`), or `xmin`, `ymin`, `xmax` and `ymax`, among others like a label or a
+/// score; without one, the first four columns are `x,y,width,height`.
+///
+/// # Errors
+/// - If a value isn't a number or the header names none of these columns
+///
+/// # Examples
+/// ```
+/// use vincent_blur::region::{parse_csv, Rect};
+///
+/// assert_eq!(parse_csv("10,20,30,40\n1,2,3,4\n").unwrap(), [Rect::new(10, 20, 30, 40), Rect::new(1, 2, 3, 4)]);
+///
+/// let detector = "label,score,xmin,ymin,xmax,ymax\nface,0.98,5,5,25,15\n";
+/// assert_eq!(parse_csv(detector).unwrap(), [Rect::new(5, 5, 20, 10)]);
+/// ```
+pub fn parse_csv(src: &str) -> Result<Vec<Rect>, Message> {
+    let mut lines = src
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .peekable();
+
+    let fields = |line: &str| {
+        line.split(',')
+            .map(|f| f.trim().trim_matches('"').to_lowercase())
+            .collect::<Vec<_>>()
+    };
+
+    let header = match lines.peek() {
+        Some(first) if fields(first).iter().any(|f| f.parse::<f64>().is_err()) => {
+            Some(fields(lines.next().unwrap()))
+        }
+        _ => None,
+    };
+
+    let column = |names: &[&str]| -> Option<usize> {
+        header
+            .as_ref()?
+            .iter()
+            .position(|h| names.contains(&h.as_str()))
+    };
+
+    // Whether the columns are corners, and their indices
+    let (corners, columns) = match &header {
+        None => (false, [0, 1, 2, 3]),
+        Some(_) => {
+            let xywh = (|| {
+                Some([
+                    column(&["x"])?,
+                    column(&["y"])?,
+                    column(&["width", "w"])?,
+                    column(&["height", "h"])?,
+                ])
+            })();
+            let corners = (|| {
+                Some([
+                    column(&["xmin", "x1"])?,
+                    column(&["ymin", "y1"])?,
+                    column(&["xmax", "x2"])?,
+                    column(&["ymax", "y2"])?,
+                ])
+            })();
+
+            match (xywh, corners) {
+                (Some(c), _) => (false, c),
+                (None, Some(c)) => (true, c),
+                _ => return Err("Expected columns x,y,width,height or xmin,ymin,xmax,ymax".into()),
+            }
+        }
+    };
+
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            let row = fields(line);
+            let err = || tr!("unknown-line", i + 1 + header.is_some() as usize, line);
+
+            let [a, b, c, d] = columns.map(|c| row.get(c).and_then(|v| v.parse::<f64>().ok()));
+            let [a, b, c, d] = [
+                a.ok_or_else(err)?,
+                b.ok_or_else(err)?,
+                c.ok_or_else(err)?,
+                d.ok_or_else(err)?,
+            ];
+
+            let bounds = match corners {
+                true => [a, b, c, d],
+                false => [a, b, a + c, b + d],
+            };
+
+            rect_from_bounds(bounds).ok_or_else(err)
+        })
+        .collect()
+}
+
+/// The pixels covered by `[left, top, right, bottom]`, rounded outward and cut at 0
+fn rect_from_bounds([left, top, right, bottom]: [f64; 4]) -> Option<Rect> {
+    let (x, y) = (left.floor().max(0.0), top.floor().max(0.0));
+    let (right, bottom) = (right.ceil(), bottom.ceil());
+
+    (right > x && bottom > y)
+        .then(|| Rect::new(x as u32, y as u32, (right - x) as u32, (bottom - y) as u32))
+}
@@ -0,0 +1,58 @@
+use crate::deadline;
+use crate::threadpool::ThreadPool;
+use image::RgbImage;
+use std::sync::{mpsc, Arc};
+
+/// Builds an image by computing bands of rows on a pool of `n_threads` workers. `f` fills the
+/// interleaved RGB values of row `y`. Rows are skipped once the deadline of the calling
+/// thread has passed.
+///
+/// Each row is written by exactly one call of `f` into its own buffer, so as long as `f` only
+/// depends on `y`, the image is the same whatever `n_threads` is.
+pub(crate) fn map_rows<F>(width: u32, height: u32, n_threads: usize, f: F) -> RgbImage
+where
+    F: Fn(u32, &mut [u8]) + Send + Sync + 'static,
+{
+    let stride = width as usize * 3;
+    let band = height.div_ceil(n_threads.max(1) as u32 * 4).max(1);
+
+    let f = Arc::new(f);
+    let (tx, rx) = mpsc::channel();
+    let deadline = deadline::current();
+
+    {
+        let pool = ThreadPool::new(n_threads.max(1));
+
+        for y0 in (0..height).step_by(band as usize) {
+            let f = Arc::clone(&f);
+            let tx = tx.clone();
+
+            pool.execute(Box::new(move || {
+                let y1 = (y0 + band).min(height);
+                let mut buf = vec![0; (y1 - y0) as usize * stride];
+
+                for (y, row) in (y0..y1).zip(buf.chunks_exact_mut(stride)) {
+                    if deadline::passed(deadline) {
+                        break;
+                    }
+
+                    f(y, row);
+                }
+
+                tx.send((y0, buf)).unwrap();
+            }));
+        }
+    }
+
+    drop(tx);
+
+    let mut out = RgbImage::new(width, height);
+
+    for (y0, buf) in rx {
+        let start = y0 as usize * stride;
+        let raw: &mut [u8] = &mut out;
+        raw[start..start + buf.len()].copy_from_slice(&buf);
+    }
+
+    out
+}
@@ -0,0 +1,70 @@
+use std::sync::Mutex;
+
+/// Most buffers kept for reuse, enough for the source, destination and a few planes of the
+/// frames being blurred at the same time
+const MAX_BUFFERS: usize = 8;
+
+/// f32 working buffers of finished blurs, reused by the next ones so video frames and
+/// long-running services don't allocate and free several megabytes per image
+static POOL: Mutex<Vec<Vec<f32>>> = Mutex::new(Vec::new());
+
+/// A buffer of `len` values from the pool, or a new one if none is big enough. The values are
+/// left over from earlier blurs.
+pub(crate) fn take(len: usize) -> Vec<f32> {
+    let mut pool = POOL.lock().unwrap();
+
+    let best = pool
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| b.capacity() >= len)
+        .min_by_key(|(_, b)| b.capacity())
+        .map(|(i, _)| i);
+
+    match best {
+        Some(i) => {
+            let mut buf = pool.swap_remove(i);
+            buf.resize(len, 0.0);
+            buf
+        }
+        None => vec![0.0; len],
+    }
+}
+
+/// Returns a buffer to the pool, dropping the smallest one if the pool is full
+pub(crate) fn give(buf: Vec<f32>) {
+    if buf.capacity() == 0 {
+        return;
+    }
+
+    let mut pool = POOL.lock().unwrap();
+    pool.push(buf);
+
+    if pool.len() > MAX_BUFFERS {
+        let smallest = (0..pool.len()).min_by_key(|&i| pool[i].capacity()).unwrap();
+        pool.swap_remove(smallest);
+    }
+}
+
+/// Bytes held by buffers waiting to be reused
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{blur_passes, scratch, Direction, Kernel};
+///
+/// let img = image::RgbImage::new(64, 64);
+/// blur_passes(Kernel::new(3, 1.5), 2, Direction::Both, &img);
+///
+/// assert!(scratch::pooled_bytes() >= 64 * 64 * 3 * 4);
+///
+/// scratch::release();
+/// assert_eq!(scratch::pooled_bytes(), 0);
+/// ```
+pub fn pooled_bytes() -> usize {
+    let pool = POOL.lock().unwrap();
+    pool.iter().map(|b| b.capacity() * size_of::<f32>()).sum()
+}
+
+/// Frees the buffers kept for reuse, e.g. after the last frame of a video
+pub fn release() {
+    POOL.lock().unwrap().clear();
+}
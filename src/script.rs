@@ -0,0 +1,190 @@
+use crate::locale::Message;
+use crate::pipeline::{Filter, FloatImage};
+use crate::threadpool::ThreadPool;
+use crate::tr;
+use crate::Error;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use wasmi::{Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+/// A pipeline stage scripted in WebAssembly. The module gets no imports, so it can only see
+/// the pixels it is given, and it exports one of:
+///
+/// - `map_pixel(r: f32, g: f32, b: f32, x: i32, y: i32) -> (f32, f32, f32)`, called for every
+///   pixel with channels from 0 to 255
+/// - `map_region(ptr: i32, width: i32, height: i32, x: i32, y: i32)`, which filters in place
+///   the `width`x`height` block of interleaved RGB f32s at `ptr` in its exported `memory`, whose
+///   top left pixel is at (`x`, `y`) in the image. The module must also export
+///   `alloc(len: i32) -> i32`, returning where `len` bytes can be written.
+///
+/// The image is split into bands of rows, each run on its own instance in the thread pool.
+pub struct WasmFilter {
+    name: String,
+    engine: Engine,
+    module: Arc<Module>,
+    threads: usize,
+}
+
+/// `map_pixel` and `map_region`
+type MapPixel = TypedFunc<(f32, f32, f32, i32, i32), (f32, f32, f32)>;
+type MapRegion = TypedFunc<(i32, i32, i32, i32, i32), ()>;
+
+enum Entry {
+    Pixel(MapPixel),
+    Region {
+        map: MapRegion,
+        alloc: TypedFunc<i32, i32>,
+        memory: Memory,
+    },
+}
+
+impl WasmFilter {
+    /// Compiles a `.wasm` module and checks that it exports `map_pixel` or `map_region`
+    ///
+    /// # Errors
+    /// - If the file can't be read or isn't a valid module with one of those exports
+    pub fn load(path: &Path, threads: usize) -> Result<WasmFilter, Message> {
+        let bytes = std::fs::read(path).map_err(|e| tr!("read-failed", path.display(), e))?;
+        let name = path.display().to_string();
+
+        WasmFilter::new(&name, &bytes, threads)
+    }
+
+    /// Same as [`WasmFilter::load`] with the module's bytes, naming the stage `name` in errors
+    ///
+    /// # Errors
+    /// - Same as [`WasmFilter::load`]
+    pub fn new(name: &str, wasm: &[u8], threads: usize) -> Result<WasmFilter, Message> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).map_err(|e| tr!("invalid-module", name, e))?;
+
+        let filter = WasmFilter {
+            name: name.to_string(),
+            engine,
+            module: Arc::new(module),
+            threads: threads.max(1),
+        };
+        filter.instantiate()?;
+
+        Ok(filter)
+    }
+
+    fn instantiate(&self) -> Result<(Store<()>, Entry), Message> {
+        let mut store = Store::new(&self.engine, ());
+        let instance: Instance = Linker::<()>::new(&self.engine)
+            .instantiate(&mut store, &self.module)
+            .and_then(|i| i.start(&mut store))
+            .map_err(|e| tr!("instantiate-failed", self.name, e))?;
+
+        if let Ok(f) = instance.get_typed_func(&store, "map_pixel") {
+            return Ok((store, Entry::Pixel(f)));
+        }
+
+        let region = instance.get_typed_func(&store, "map_region").ok();
+        let alloc = instance.get_typed_func(&store, "alloc").ok();
+        let memory = instance.get_memory(&store, "memory");
+
+        match (region, alloc, memory) {
+            (Some(map), Some(alloc), Some(memory)) => {
+                Ok((store, Entry::Region { map, alloc, memory }))
+            }
+            _ => Err(tr!("no-wasm-entry", self.name)),
+        }
+    }
+
+    /// Runs the module on a band of rows `width` pixels wide, starting at row `y`
+    fn run_band(&self, band: &mut [f32], width: u32, y: u32) -> Result<(), Message> {
+        let (mut store, entry) = self.instantiate()?;
+        let trap = |e: wasmi::Error| tr!("stage-failed", self.name, e);
+
+        match entry {
+            Entry::Pixel(f) => {
+                for (i, p) in band.chunks_exact_mut(3).enumerate() {
+                    let (px, py) = ((i as u32 % width) as i32, (y + i as u32 / width) as i32);
+                    let (r, g, b) = f
+                        .call(&mut store, (p[0], p[1], p[2], px, py))
+                        .map_err(trap)?;
+                    p.copy_from_slice(&[r, g, b]);
+                }
+            }
+            Entry::Region { map, alloc, memory } => {
+                let bytes: Vec<u8> = band.iter().flat_map(|v| v.to_le_bytes()).collect();
+                let ptr = alloc.call(&mut store, bytes.len() as i32).map_err(trap)?;
+                let rows = band.len() as u32 / 3 / width;
+
+                memory
+                    .write(&mut store, ptr as usize, &bytes)
+                    .map_err(|e| tr!("bad-buffer", self.name, e))?;
+                map.call(&mut store, (ptr, width as i32, rows as i32, 0, y as i32))
+                    .map_err(trap)?;
+
+                let mut out = vec![0u8; bytes.len()];
+                memory
+                    .read(&store, ptr as usize, &mut out)
+                    .map_err(|e| tr!("bad-buffer", self.name, e))?;
+
+                for (v, b) in band.iter_mut().zip(out.chunks_exact(4)) {
+                    *v = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                }
+            }
+        }
+
+        // The module's output is untrusted
+        for v in band.iter_mut() {
+            *v = if v.is_nan() { 0.0 } else { v.clamp(0.0, 255.0) };
+        }
+
+        Ok(())
+    }
+}
+
+impl Filter for WasmFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(&self, mut img: FloatImage) -> Result<FloatImage, Error> {
+        let (width, height) = img.dimensions();
+
+        if width == 0 || height == 0 {
+            return Ok(img);
+        }
+
+        let band_rows = height.div_ceil(self.threads as u32).max(1);
+        let band_len = (band_rows * width * 3) as usize;
+
+        // Each job gets its own copy of the filter's compiled module and its band of rows
+        let (tx, rx) = mpsc::channel();
+
+        {
+            let pool = ThreadPool::new(self.threads);
+
+            for (i, band) in img.as_raw().chunks(band_len).enumerate() {
+                let filter = WasmFilter {
+                    name: self.name.clone(),
+                    engine: self.engine.clone(),
+                    module: Arc::clone(&self.module),
+                    threads: 1,
+                };
+                let mut band = band.to_vec();
+                let tx = tx.clone();
+
+                pool.execute(Box::new(move || {
+                    let res = filter.run_band(&mut band, width, i as u32 * band_rows);
+                    tx.send((i, res.map(|()| band))).unwrap();
+                }));
+            }
+        }
+
+        drop(tx);
+
+        let data = img.as_raw_mut();
+
+        for (i, band) in rx {
+            let band = band.map_err(|e| Error::InvalidArgs(e.into()))?;
+            data[i * band_len..i * band_len + band.len()].copy_from_slice(&band);
+        }
+
+        Ok(img)
+    }
+}
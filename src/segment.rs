@@ -0,0 +1,108 @@
+use crate::locale::Message;
+use crate::mask::Mask;
+use crate::tr;
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Luma, RgbImage};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::path::Path;
+
+/// Side of the square input used when the model doesn't fix its own input size
+const DEFAULT_INPUT_SIZE: u32 = 256;
+
+/// A person segmentation model run through ONNX Runtime, which is loaded from the shared
+/// library named by `ORT_DYLIB_PATH` or found on the library path.
+///
+/// The model takes one `1x3xHxW` RGB input scaled to 0..1 and returns a `1x1xHxW` or `1xHxW`
+/// map of person probabilities, or `1x2xHxW` background and person scores.
+pub struct PortraitModel {
+    session: Session,
+    input_size: (u32, u32),
+}
+
+impl PortraitModel {
+    /// Loads an ONNX model from disk
+    ///
+    /// # Errors
+    /// - If ONNX Runtime can't be loaded or the model is invalid
+    pub fn load(path: &Path) -> Result<Self, Message> {
+        let session = Session::builder()
+            .and_then(|b| b.commit_from_file(path))
+            .map_err(|e| tr!("load-failed", path.display(), e))?;
+
+        let input_size = session
+            .inputs
+            .first()
+            .and_then(|input| input.input_type.tensor_shape())
+            .and_then(|shape| match shape[..] {
+                [_, _, h, w] if h > 0 && w > 0 => Some((w as u32, h as u32)),
+                _ => None,
+            })
+            .unwrap_or((DEFAULT_INPUT_SIZE, DEFAULT_INPUT_SIZE));
+
+        Ok(PortraitModel {
+            session,
+            input_size,
+        })
+    }
+
+    /// Mask that keeps people sharp and blurs everything else, softened over `feather`
+    /// pixels so the cut-out blends in
+    ///
+    /// # Errors
+    /// - If the model fails to run or returns an unexpected shape
+    pub fn background_mask(&mut self, img: &RgbImage, feather: u8) -> Result<Mask, Message> {
+        let (w, h) = self.input_size;
+        let resized = imageops::resize(img, w, h, FilterType::Triangle);
+
+        let plane = (w * h) as usize;
+        let mut input = vec![0.0f32; plane * 3];
+
+        for (i, p) in resized.pixels().enumerate() {
+            for c in 0..3 {
+                input[c * plane + i] = p[c] as f32 / 255.0;
+            }
+        }
+
+        let tensor = Tensor::from_array(([1, 3, h as usize, w as usize], input))
+            .map_err(|e| e.to_string())?;
+        let outputs = self
+            .session
+            .run(ort::inputs![tensor])
+            .map_err(|e| tr!("portrait-failed", e))?;
+        let (shape, data) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| e.to_string())?;
+
+        let (out_w, out_h) = match shape[..] {
+            [.., oh, ow] => (ow as u32, oh as u32),
+            _ => return Err("Unexpected portrait model output".into()),
+        };
+        let out_plane = (out_w * out_h) as usize;
+
+        let person: Vec<f32> = match data.len() / out_plane.max(1) {
+            1 if data.iter().all(|v| (0.0..=1.0).contains(v)) => data.to_vec(),
+            1 => data.iter().map(|v| 1.0 / (1.0 + (-v).exp())).collect(),
+            2 => (0..out_plane)
+                .map(|i| {
+                    let (bg, fg) = (data[i], data[out_plane + i]);
+                    1.0 / (1.0 + (bg - fg).exp())
+                })
+                .collect(),
+            _ => return Err("Unexpected portrait model output".into()),
+        };
+
+        let person: ImageBuffer<Luma<f32>, Vec<f32>> =
+            ImageBuffer::from_raw(out_w, out_h, person).unwrap();
+        let person = imageops::resize(&person, img.width(), img.height(), FilterType::Triangle);
+
+        let mask = Mask::from_fn(img.width(), img.height(), |x, y| {
+            1.0 - person.get_pixel(x, y)[0]
+        });
+
+        Ok(match feather {
+            0 => mask,
+            f => mask.soften(f, f as f64 / 2.0),
+        })
+    }
+}
@@ -0,0 +1,509 @@
+use crate::kernel::{self, Kernel};
+use crate::timings::{self, Stage};
+use crate::{deadline, scratch, Direction};
+use image::RgbImage;
+
+/// Unnormalized 1D Gaussian weights for the taps `-radius..=radius`, with the outer ones
+/// faded in when `faded`, see [`kernel::tap`]. The passes divide by the weights that fall
+/// inside the image instead.
+pub(crate) fn gaussian_weights(radius: usize, sigma: f64, faded: bool) -> Vec<f32> {
+    timings::time(Stage::Kernel, || {
+        (-(radius as i64)..=radius as i64)
+            .map(|x| kernel::tap(x as i32, sigma, faded) as f32)
+            .collect()
+    })
+}
+
+/// Sum of the weights whose taps land inside `0..len` for every position, so edge pixels are
+/// averaged over the part of the kernel that is actually inside the image
+pub(crate) fn edge_norms(len: usize, weights: &[f32]) -> Vec<f32> {
+    let r = weights.len() / 2;
+
+    (0..len)
+        .map(|i| {
+            weights
+                .iter()
+                .enumerate()
+                .filter(|(k, _)| i + k >= r && i + k - r < len)
+                .map(|(_, w)| w)
+                .sum()
+        })
+        .collect()
+}
+
+/// Blurs every row of an interleaved buffer with `channels` values per pixel.
+///
+/// The weights are symmetric, so the taps at `-j` and `+j` are added before multiplying, and
+/// each pair is applied as one contiguous multiply-add over the part of the row it covers. That
+/// keeps the inner loops branch-free so the compiler can vectorize them. Near the edges, where
+/// one of the two taps is outside the row, the other is applied alone.
+pub(crate) fn horizontal(
+    src: &[f32],
+    dst: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+) {
+    timings::time(Stage::Horizontal, || {
+        horizontal_with(
+            src,
+            dst,
+            width,
+            channels,
+            weights,
+            &edge_norms(width, weights),
+        )
+    });
+}
+
+/// [`horizontal`] with the [`edge_norms`] of the width already computed
+pub(crate) fn horizontal_with(
+    src: &[f32],
+    dst: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+    norms: &[f32],
+) {
+    let r = weights.len() / 2;
+    let stride = width * channels;
+    let deadline = deadline::current();
+
+    for (src_row, dst_row) in src.chunks_exact(stride).zip(dst.chunks_exact_mut(stride)) {
+        if deadline::passed(deadline) {
+            return;
+        }
+
+        for (d, s) in dst_row.iter_mut().zip(src_row) {
+            *d = weights[r] * s;
+        }
+
+        for j in 1..=r.min(width.saturating_sub(1)) {
+            let w = weights[r + j];
+            let c = j * channels;
+
+            // Both taps inside
+            if 2 * j < width {
+                let d = &mut dst_row[c..stride - c];
+
+                for ((d, a), b) in d
+                    .iter_mut()
+                    .zip(&src_row[..stride - 2 * c])
+                    .zip(&src_row[2 * c..])
+                {
+                    *d += w * (a + b);
+                }
+            }
+
+            // Only the tap to the right inside
+            let right_only = j.min(width - j) * channels;
+            for (d, s) in dst_row[..right_only].iter_mut().zip(&src_row[c..]) {
+                *d += w * s;
+            }
+
+            // Only the tap to the left inside
+            let left_only = j.max(width - j) * channels;
+            for (d, s) in dst_row[left_only..]
+                .iter_mut()
+                .zip(&src_row[left_only - c..])
+            {
+                *d += w * s;
+            }
+        }
+
+        for (px, norm) in dst_row.chunks_exact_mut(channels).zip(norms) {
+            for v in px {
+                *v /= norm;
+            }
+        }
+    }
+}
+
+/// Bytes of source rows the vertical passes try to keep in cache, about the size of a core's
+/// L2 cache
+const VERTICAL_CACHE_BYTES: usize = 256 * 1024;
+
+/// Width in values of the column blocks the vertical passes work on, so the `2r + 1` source rows
+/// a block reads stay in cache while it moves down the image. Whole rows are used when they
+/// already fit.
+pub(crate) fn column_block(stride: usize, taps: usize) -> usize {
+    (VERTICAL_CACHE_BYTES / size_of::<f32>() / taps).clamp(64, stride.max(64))
+}
+
+/// Blurs every column of an interleaved buffer with `channels` values per pixel. The rows at
+/// `-j` and `+j` are added before multiplying, like in [`horizontal`].
+///
+/// Wide images are processed in blocks of columns, each running down the whole image, so the
+/// rows under the kernel are still in cache when the next destination row reads them.
+pub(crate) fn vertical(
+    src: &[f32],
+    dst: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+) {
+    timings::time(Stage::Vertical, || {
+        let stride = width * channels;
+        let height = src.len() / stride;
+        let norms = edge_norms(height, weights);
+        let block = column_block(stride, weights.len());
+
+        vertical_with(src, dst, width, channels, weights, &norms, block)
+    });
+}
+
+/// [`vertical`] with the [`edge_norms`] of the height and the [`column_block`] already computed
+pub(crate) fn vertical_with(
+    src: &[f32],
+    dst: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+    norms: &[f32],
+    block: usize,
+) {
+    let r = weights.len() / 2;
+    let stride = width * channels;
+    let height = src.len() / stride;
+    let deadline = deadline::current();
+
+    for c0 in (0..stride).step_by(block) {
+        let c1 = (c0 + block).min(stride);
+        let row = |y: usize| &src[y * stride + c0..y * stride + c1];
+
+        for y in 0..height {
+            if deadline::passed(deadline) {
+                return;
+            }
+
+            let dst_row = &mut dst[y * stride + c0..y * stride + c1];
+
+            for (d, s) in dst_row.iter_mut().zip(row(y)) {
+                *d = weights[r] * s;
+            }
+
+            for j in 1..=r {
+                let w = weights[r + j];
+                let above = y.checked_sub(j);
+                let below = Some(y + j).filter(|&b| b < height);
+
+                match (above, below) {
+                    (Some(a), Some(b)) => {
+                        for ((d, a), b) in dst_row.iter_mut().zip(row(a)).zip(row(b)) {
+                            *d += w * (a + b);
+                        }
+                    }
+                    (Some(sy), None) | (None, Some(sy)) => {
+                        for (d, s) in dst_row.iter_mut().zip(row(sy)) {
+                            *d += w * s;
+                        }
+                    }
+                    (None, None) => break,
+                }
+            }
+
+            for v in dst_row.iter_mut() {
+                *v /= norms[y];
+            }
+        }
+    }
+}
+
+/// Blurs one row of an interleaved buffer, wrapping around at both ends so the first and last
+/// pixels are neighbours
+pub(crate) fn horizontal_wrap_row(
+    src_row: &[f32],
+    dst_row: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+) {
+    let r = weights.len() / 2;
+    let norm: f32 = weights.iter().sum();
+
+    dst_row.fill(0.0);
+
+    for (k, &w) in weights.iter().enumerate() {
+        let shift = (k as i64 - r as i64).rem_euclid(width as i64) as usize;
+        let split = (width - shift) * channels;
+        let (head, tail) = dst_row.split_at_mut(split);
+
+        for (d, s) in head.iter_mut().zip(&src_row[shift * channels..]) {
+            *d += w * s;
+        }
+
+        for (d, s) in tail.iter_mut().zip(&src_row[..shift * channels]) {
+            *d += w * s;
+        }
+    }
+
+    for v in dst_row.iter_mut() {
+        *v /= norm;
+    }
+}
+
+/// Like [`horizontal`] but wrapping around at the left and right edges
+pub(crate) fn horizontal_wrap(
+    src: &[f32],
+    dst: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+) {
+    let stride = width * channels;
+    let deadline = deadline::current();
+
+    for (src_row, dst_row) in src.chunks_exact(stride).zip(dst.chunks_exact_mut(stride)) {
+        if deadline::passed(deadline) {
+            return;
+        }
+
+        horizontal_wrap_row(src_row, dst_row, width, channels, weights);
+    }
+}
+
+/// Like [`vertical`] but wrapping around at the top and bottom edges
+pub(crate) fn vertical_wrap(
+    src: &[f32],
+    dst: &mut [f32],
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+) {
+    let r = weights.len() / 2;
+    let stride = width * channels;
+    let height = src.len() / stride;
+    let norm: f32 = weights.iter().sum();
+    let block = column_block(stride, weights.len());
+    let deadline = deadline::current();
+
+    for c0 in (0..stride).step_by(block) {
+        let c1 = (c0 + block).min(stride);
+
+        for y in 0..height {
+            if deadline::passed(deadline) {
+                return;
+            }
+
+            let dst_row = &mut dst[y * stride + c0..y * stride + c1];
+            dst_row.fill(0.0);
+
+            for (k, &w) in weights.iter().enumerate() {
+                let sy = (y as i64 + k as i64 - r as i64).rem_euclid(height as i64) as usize;
+
+                for (d, s) in dst_row
+                    .iter_mut()
+                    .zip(&src[sy * stride + c0..sy * stride + c1])
+                {
+                    *d += w * s;
+                }
+            }
+
+            for v in dst_row.iter_mut() {
+                *v /= norm;
+            }
+        }
+    }
+}
+
+/// Converts an image to the f32 working buffer used by the passes
+pub(crate) fn to_f32(img: &RgbImage) -> Vec<f32> {
+    let mut buf = scratch::take(img.as_raw().len());
+
+    for (d, &s) in buf.iter_mut().zip(img.as_raw()) {
+        *d = s as f32;
+    }
+
+    buf
+}
+
+/// Converts the f32 working buffer back to an image, truncating like the 2D blur does
+pub(crate) fn to_rgb(width: u32, height: u32, data: &[f32]) -> RgbImage {
+    RgbImage::from_raw(width, height, data.iter().map(|&v| v as u8).collect()).unwrap()
+}
+
+/// Converts `src` to an image and returns both working buffers to the [`scratch`] pool
+fn recycle(width: u32, height: u32, src: Vec<f32>, dst: Vec<f32>) -> RgbImage {
+    let out = to_rgb(width, height, &src);
+
+    scratch::give(src);
+    scratch::give(dst);
+    out
+}
+
+/// Blurs an image `passes` times along the axes of `direction`, ping-ponging between two f32
+/// buffers so nothing is quantized or reallocated between passes
+pub(crate) fn blur_passes(
+    img: &RgbImage,
+    weights: &[f32],
+    passes: usize,
+    direction: Direction,
+) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let data = blur_passes_f32(img, weights, passes, direction);
+    let out = to_rgb(width, height, &data);
+
+    scratch::give(data);
+    out
+}
+
+/// [`blur_passes`] without the final conversion to 8 bits
+pub(crate) fn blur_passes_f32(
+    img: &RgbImage,
+    weights: &[f32],
+    passes: usize,
+    direction: Direction,
+) -> Vec<f32> {
+    run_passes(
+        to_f32(img),
+        img.width() as usize,
+        3,
+        weights,
+        passes,
+        direction,
+    )
+}
+
+/// Like [`blur_passes`] but with separate weights for the red, green and blue channels, which
+/// are blurred as separate planes
+pub(crate) fn blur_channels(
+    img: &RgbImage,
+    weights: &[Vec<f32>; 3],
+    passes: usize,
+    direction: Direction,
+) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let mut out = RgbImage::new(width, height);
+
+    for (c, weights) in weights.iter().enumerate() {
+        let mut plane = scratch::take(width as usize * height as usize);
+        for (v, p) in plane.iter_mut().zip(img.pixels()) {
+            *v = p[c] as f32;
+        }
+        let plane = run_passes(plane, width as usize, 1, weights, passes, direction);
+
+        for (px, &v) in out.pixels_mut().zip(&plane) {
+            px[c] = v as u8;
+        }
+        scratch::give(plane);
+    }
+
+    out
+}
+
+/// Runs `passes` blurs along the axes of `direction` on an interleaved buffer. The second
+/// buffer comes from and goes back to the [`scratch`] pool. The rows and passes stop early
+/// once the deadline of the calling thread has passed, leaving a partial result the caller
+/// discards.
+pub(crate) fn run_passes(
+    mut src: Vec<f32>,
+    width: usize,
+    channels: usize,
+    weights: &[f32],
+    passes: usize,
+    direction: Direction,
+) -> Vec<f32> {
+    let mut dst = scratch::take(src.len());
+    let deadline = deadline::current();
+
+    for _ in 0..passes {
+        if deadline::passed(deadline) {
+            break;
+        }
+
+        if direction != Direction::Vertical {
+            horizontal(&src, &mut dst, width, channels, weights);
+            std::mem::swap(&mut src, &mut dst);
+        }
+
+        if direction != Direction::Horizontal {
+            vertical(&src, &mut dst, width, channels, weights);
+            std::mem::swap(&mut src, &mut dst);
+        }
+    }
+
+    scratch::give(dst);
+    src
+}
+
+/// Blurs an image as if it tiled the plane, so both axes wrap around and the result still
+/// tiles seamlessly
+pub(crate) fn blur_tileable(
+    img: &RgbImage,
+    weights: &[f32],
+    passes: usize,
+    direction: Direction,
+) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let mut src = to_f32(img);
+    let mut dst = scratch::take(src.len());
+
+    for _ in 0..passes {
+        if direction != Direction::Vertical {
+            timings::time(Stage::Horizontal, || {
+                horizontal_wrap(&src, &mut dst, width as usize, 3, weights)
+            });
+            std::mem::swap(&mut src, &mut dst);
+        }
+
+        if direction != Direction::Horizontal {
+            timings::time(Stage::Vertical, || {
+                vertical_wrap(&src, &mut dst, width as usize, 3, weights)
+            });
+            std::mem::swap(&mut src, &mut dst);
+        }
+    }
+
+    recycle(width, height, src, dst)
+}
+
+/// Blurs an equirectangular panorama. Rows wrap around horizontally and their kernel is
+/// widened by 1/cos(latitude), up to half the width, so the blur covers the same angle on the
+/// sphere everywhere; columns are blurred normally.
+pub(crate) fn blur_equirect(img: &RgbImage, kernel: Kernel, passes: usize) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    let stride = w * 3;
+    let Kernel {
+        radius,
+        sigma,
+        faded,
+    } = kernel;
+
+    let row_weights: Vec<Vec<f32>> = (0..h)
+        .map(|y| {
+            let latitude = ((y as f64 + 0.5) / h as f64 - 0.5) * std::f64::consts::PI;
+            let scale = 1.0 / latitude.cos().max(f64::EPSILON);
+            let row_radius = (radius as f64 * scale).round().min((w / 2) as f64) as usize;
+
+            gaussian_weights(row_radius, sigma * scale, faded)
+        })
+        .collect();
+    let weights = gaussian_weights(radius as usize, sigma, faded);
+
+    let mut src = to_f32(img);
+    let mut dst = scratch::take(src.len());
+
+    for _ in 0..passes {
+        timings::time(Stage::Horizontal, || {
+            for ((src_row, dst_row), weights) in src
+                .chunks_exact(stride)
+                .zip(dst.chunks_exact_mut(stride))
+                .zip(&row_weights)
+            {
+                horizontal_wrap_row(src_row, dst_row, w, 3, weights);
+            }
+        });
+        std::mem::swap(&mut src, &mut dst);
+
+        vertical(&src, &mut dst, w, 3, &weights);
+        std::mem::swap(&mut src, &mut dst);
+    }
+
+    recycle(width, height, src, dst)
+}
+
+/// Blurs an image with a horizontal then a vertical pass of `weights`
+pub(crate) fn blur(img: &RgbImage, weights: &[f32]) -> RgbImage {
+    blur_passes(img, weights, 1, Direction::Both)
+}
@@ -0,0 +1,138 @@
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// One image of a responsive set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub width: u32,
+    pub height: u32,
+    pub path: PathBuf,
+}
+
+/// Where the variant `width` pixels wide of the destination `path` is written,
+/// `<stem>-<width>w.<ext>`
+///
+/// # Examples
+/// ```
+/// use std::path::Path;
+/// use vincent_blur::srcset;
+///
+/// assert_eq!(
+///     srcset::path(Path::new("out/hero_blurred_10x10.jpg"), 640),
+///     Path::new("out/hero_blurred_10x10-640w.jpg")
+/// );
+/// ```
+pub fn path(path: &Path, width: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}-{}w.{}", stem, width, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}-{}w", stem, width)),
+    }
+}
+
+/// The variants of `path` for a `width`x`height` source, smallest first. Widths larger than the
+/// source are left out, since upscaling only makes a bigger file of the same picture.
+pub fn variants(path: &Path, width: u32, height: u32, widths: &[u32]) -> Vec<Variant> {
+    let mut widths: Vec<u32> = widths.iter().copied().filter(|&w| w <= width).collect();
+    widths.sort_unstable();
+    widths.dedup();
+
+    widths
+        .into_iter()
+        .map(|w| Variant {
+            width: w,
+            height: (height as u64 * w as u64 / width as u64).max(1) as u32,
+            path: self::path(path, w),
+        })
+        .collect()
+}
+
+/// Scales `img` down to the size of `variant`
+pub fn resize(img: &RgbImage, variant: &Variant, filter: FilterType) -> RgbImage {
+    if img.dimensions() == (variant.width, variant.height) {
+        return img.clone();
+    }
+
+    imageops::resize(img, variant.width, variant.height, filter)
+}
+
+/// A variant as listed in a JSON manifest
+#[derive(Serialize)]
+struct Entry {
+    file: String,
+    width: u32,
+    height: u32,
+}
+
+/// Manifest of the variants: a JSON list of their file names and sizes for a `.json` `path`,
+/// otherwise an HTML `<img>` tag with their `srcset` to paste into a page. The file names are
+/// relative to the directory of the images.
+///
+/// # Examples
+/// ```
+/// use std::path::Path;
+/// use vincent_blur::srcset;
+///
+/// let variants = srcset::variants(Path::new("hero.jpg"), 1000, 500, &[320, 640, 1920]);
+///
+/// assert_eq!(
+///     srcset::manifest(Path::new("hero.html"), &variants),
+///     "<img src=\"hero-640w.jpg\" srcset=\"hero-320w.jpg 320w, hero-640w.jpg 640w\" \
+///      sizes=\"100vw\" width=\"640\" height=\"320\" alt=\"\">\n"
+/// );
+/// assert!(srcset::manifest(Path::new("hero.json"), &variants).starts_with("[{\"file\":"));
+/// ```
+pub fn manifest(path: &Path, variants: &[Variant]) -> String {
+    let name = |v: &Variant| {
+        v.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    let is_json = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+    if is_json {
+        let entries: Vec<Entry> = variants
+            .iter()
+            .map(|v| Entry {
+                file: name(v),
+                width: v.width,
+                height: v.height,
+            })
+            .collect();
+        let json = serde_json::to_string(&entries).expect("entries are serializable");
+
+        return format!("{json}\n");
+    }
+
+    let Some(largest) = variants.last() else {
+        return String::new();
+    };
+
+    let srcset: Vec<String> = variants
+        .iter()
+        .map(|v| format!("{} {}w", html_attribute(&name(v)), v.width))
+        .collect();
+
+    format!(
+        "<img src=\"{}\" srcset=\"{}\" sizes=\"100vw\" width=\"{}\" height=\"{}\" alt=\"\">\n",
+        html_attribute(&name(largest)),
+        srcset.join(", "),
+        largest.width,
+        largest.height
+    )
+}
+
+fn html_attribute(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
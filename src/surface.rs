@@ -0,0 +1,73 @@
+use crate::kernel::Kernel;
+use crate::rows::map_rows;
+use image::RgbImage;
+use std::sync::Arc;
+
+/// Edge-preserving "surface" blur. Like the Gaussian blur, but samples whose color differs from
+/// the center pixel by more than `threshold` in any channel are left out of the weighted
+/// average, so noise and skin get smoothed while edges stay crisp.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{surface_blur, Kernel};
+///
+/// // A hard black/white edge survives a surface blur untouched
+/// let img = image::RgbImage::from_fn(10, 4, |x, _| image::Rgb([if x < 5 { 0 } else { 255 }; 3]));
+/// assert_eq!(surface_blur(Kernel::new(3, 2.0), 25, 2, img.clone()), img);
+/// ```
+pub fn surface_blur(
+    kernel: Kernel,
+    threshold: u8,
+    n_threads: usize,
+    original_img: RgbImage,
+) -> RgbImage {
+    let (width, height) = original_img.dimensions();
+    let m = Arc::new(kernel.matrix());
+    let img = Arc::new(original_img);
+
+    map_rows(width, height, n_threads, move |y, row| {
+        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+            px.copy_from_slice(&surface_pixel(x as i32, y as i32, &m, &img, threshold));
+        }
+    })
+}
+
+fn surface_pixel(
+    x: i32,
+    y: i32,
+    matrix: &grid::Grid<f64>,
+    img: &RgbImage,
+    threshold: u8,
+) -> [u8; 3] {
+    let center = img.get_pixel(x as u32, y as u32);
+    let radius = matrix.rows() as i32 / 2;
+
+    let mut sum = [0.0; 3];
+    let mut total = 0.0;
+
+    for i in 0..matrix.rows() as i32 {
+        for k in 0..matrix.cols() as i32 {
+            let sx = x + i - radius;
+            let sy = y + k - radius;
+
+            if sx < 0 || sy < 0 || sx >= img.width() as i32 || sy >= img.height() as i32 {
+                continue;
+            }
+
+            let pixel = img.get_pixel(sx as u32, sy as u32);
+
+            if (0..3).any(|c| pixel[c].abs_diff(center[c]) > threshold) {
+                continue;
+            }
+
+            let el = matrix[(i as usize, k as usize)];
+
+            for c in 0..3 {
+                sum[c] += pixel[c] as f64 * el;
+            }
+            total += el;
+        }
+    }
+
+    sum.map(|v| (v / total).round() as u8)
+}
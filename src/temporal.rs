@@ -0,0 +1,307 @@
+use crate::cli::{self, error::ArgError};
+use crate::locale::Message;
+use crate::threadpool::ThreadPool;
+use crate::tr;
+use crate::{codec, naming, parse_positive, Error};
+use image::RgbImage;
+use std::collections::VecDeque;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+/// CLI options of the `temporal` subcommand
+pub struct TemporalOpts {
+    /// Source frames in playback order
+    pub frames: Vec<PathBuf>,
+    /// Number of consecutive frames averaged into each output frame
+    pub window: usize,
+    /// Standard deviation of the weights in frames, or `None` to weigh every frame equally
+    pub sigma: Option<f64>,
+    pub format: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub force: bool,
+}
+
+impl TemporalOpts {
+    /// Constructs a new TemporalOpts from CLI options, skipping the program name and the
+    /// `temporal` subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If no frames are given
+    pub fn new(cli_opts: env::Args) -> Result<TemporalOpts, ArgError> {
+        let mut frames = Vec::new();
+        let mut window = 9;
+        let mut sigma: Option<f64> = None;
+        let mut format: Option<String> = None;
+        let mut output_dir: Option<PathBuf> = None;
+        let mut force = false;
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--window" | "-w" => {
+                    window = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--window", tr!("number-after", "--window|-w"))
+                        })?;
+                }
+                "--sigma" | "-s" => {
+                    sigma = match cli_opts.next().as_deref().and_then(parse_positive) {
+                        Some(s) => Some(s),
+                        None => {
+                            return Err(
+                                cli_opts.invalid_value("--sigma", tr!("float-after", "--sigma|-s"))
+                            )
+                        }
+                    };
+                }
+                "--format" | "-f" => {
+                    format = match cli_opts.next() {
+                        Some(f) if codec::is_supported(&f) => Some(f.to_lowercase()),
+                        Some(f) => {
+                            return Err(
+                                cli_opts.invalid_value("--format", tr!("unsupported-format", f))
+                            )
+                        }
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value("--format", "Expected a format after --format|-f"))
+                        }
+                    };
+                }
+                "--output-dir" | "-o" => {
+                    output_dir = match cli_opts.next() {
+                        Some(d) => Some(PathBuf::from(d)),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                "--output-dir",
+                                "Expected a directory after --output-dir|-o",
+                            ))
+                        }
+                    };
+                }
+                "--force" => force = true,
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur temporal [--window|-w <frames>] [--sigma|-s <sigma>] [--format|-f <format>] [--output-dir|-o <dir>] [--force] <frames>... [--help|-h]\n",
+                        "   <frames>            Paths of the frames in playback order.",
+                        "   -w, --window        Number of consecutive frames averaged into each output",
+                        "                       frame, centered on it. Default is 9.",
+                        "   -s, --sigma         Weigh the frames with a Gaussian of this standard",
+                        "                       deviation, in frames. Default is an equal weight.",
+                        "   -f, --format        Output format. Default is the frame's format.",
+                        "   -o, --output-dir    Directory to write the frames to. Default is next to",
+                        "                       the source frames.",
+                        "   --force             Overwrite existing files.",
+                        "   -h, --help          Print help.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => frames.push(PathBuf::from(arg)),
+            }
+        }
+
+        if frames.is_empty() {
+            return Err("Expected at least one frame".into());
+        }
+
+        Ok(TemporalOpts {
+            frames,
+            window,
+            sigma,
+            format,
+            output_dir,
+            force,
+        })
+    }
+
+    /// Destination of the output frame for `frame`, `<stem>_temporal_<window>.<ext>`
+    ///
+    /// # Errors
+    /// - Same as [`naming::default_path`]
+    pub fn destination(&self, frame: &Path) -> Result<PathBuf, Message> {
+        let suffix = format!("_temporal_{}", self.window);
+        let path = naming::default_path(frame, &suffix, self.format.as_deref())?;
+
+        Ok(match &self.output_dir {
+            Some(dir) => dir.join(path.file_name().unwrap()),
+            None => path,
+        })
+    }
+}
+
+/// Weight of each frame in a window of `window` frames centered on the current one: equal
+/// weights, or a Gaussian of `sigma` frames
+pub fn window_weights(window: usize, sigma: Option<f64>) -> Vec<f32> {
+    let center = ((window - 1) / 2) as f64;
+
+    (0..window)
+        .map(|i| match sigma {
+            Some(sigma) => (-(i as f64 - center).powi(2) / (2.0 * sigma * sigma)).exp() as f32,
+            None => 1.0,
+        })
+        .collect()
+}
+
+/// Weighted average of `frames`, e.g. to simulate a long exposure from a burst of photos
+///
+/// # Examples
+/// ```
+/// use vincent_blur::temporal::blend_frames;
+///
+/// let black = image::RgbImage::new(2, 2);
+/// let white = image::RgbImage::from_pixel(2, 2, image::Rgb([255; 3]));
+///
+/// let blended = blend_frames(&[&black, &white, &white], &[1.0, 1.0, 2.0]).unwrap();
+///
+/// assert_eq!(blended.get_pixel(0, 0)[0], 191);
+/// ```
+///
+/// # Errors
+/// - If the frames don't all have the same dimensions
+/// - If there are no frames or the weights don't add up to more than 0
+pub fn blend_frames(frames: &[&RgbImage], weights: &[f32]) -> Result<RgbImage, Message> {
+    let (width, height) = match frames.first() {
+        Some(f) => f.dimensions(),
+        None => return Err("Expected at least one frame".into()),
+    };
+
+    if frames.iter().any(|f| f.dimensions() != (width, height)) {
+        return Err("Frames don't all have the same dimensions".into());
+    }
+
+    let total: f32 = weights.iter().take(frames.len()).sum();
+
+    if total <= 0.0 {
+        return Err("Expected frame weights that add up to more than 0".into());
+    }
+
+    let mut sum = vec![0.0f32; width as usize * height as usize * 3];
+
+    for (frame, &w) in frames.iter().zip(weights) {
+        for (s, &v) in sum.iter_mut().zip(frame.as_raw()) {
+            *s += w * v as f32;
+        }
+    }
+
+    let data = sum.iter().map(|s| (s / total).round() as u8).collect();
+
+    Ok(RgbImage::from_raw(width, height, data).unwrap())
+}
+
+/// Writes one output frame per source frame, each the average of the window of frames around
+/// it. Windows are cut short at the ends of the sequence, and only the frames of the current
+/// window are kept in memory. The next frame is decoded and the previous output encoded on two
+/// workers while a window is blended.
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if a destination exists and `force` isn't set
+/// - [`Error::Decode`] if a frame can't be read or doesn't match the others' dimensions
+/// - [`Error::Encode`] if an output frame can't be written
+pub fn run(opts: &TemporalOpts) -> Result<(), Error> {
+    let (decoded_tx, decoded_rx) = mpsc::sync_channel(1);
+    let (blended_tx, blended_rx) = mpsc::sync_channel::<(RgbImage, PathBuf)>(1);
+    let (encoded_tx, encoded_rx) = mpsc::channel();
+
+    let res = {
+        let pool = ThreadPool::new(2);
+        let frames = opts.frames.clone();
+
+        pool.execute(Box::new(move || {
+            for path in &frames {
+                let img =
+                    codec::open(path, &codec::DecodeOptions::default()).map_err(Error::Decode);
+
+                if decoded_tx.send(img).is_err() {
+                    break;
+                }
+            }
+        }));
+
+        let encode_opts = codec::EncodeOptions {
+            format: opts.format.clone(),
+            ..Default::default()
+        };
+
+        pool.execute(Box::new(move || {
+            for (img, destination) in blended_rx {
+                let res = codec::save(&img, &destination, &encode_opts).map_err(Error::Encode);
+                let failed = res.is_err();
+
+                encoded_tx.send(res).unwrap();
+
+                if failed {
+                    break;
+                }
+            }
+        }));
+
+        blend(opts, decoded_rx, blended_tx)
+    };
+
+    // Outputs are encoded behind the blending, so their errors come from earlier frames
+    encoded_rx.iter().collect::<Result<(), Error>>()?;
+    res
+}
+
+/// Blends the frames received from `decoded` and sends every output with its destination to
+/// `blended`, stopping early if the encoder has stopped
+fn blend(
+    opts: &TemporalOpts,
+    decoded: Receiver<Result<RgbImage, Error>>,
+    blended: SyncSender<(RgbImage, PathBuf)>,
+) -> Result<(), Error> {
+    let weights = window_weights(opts.window, opts.sigma);
+    let before = (opts.window - 1) / 2;
+    let after = opts.window / 2;
+
+    // Decoded frames starting at index `first`
+    let mut loaded: VecDeque<RgbImage> = VecDeque::new();
+    let mut first = 0;
+
+    for (i, frame) in opts.frames.iter().enumerate() {
+        let destination = opts
+            .destination(frame)
+            .map_err(|e| Error::InvalidArgs(e.into()))?;
+
+        if !opts.force && destination.exists() {
+            return Err(Error::InvalidArgs(
+                tr!("already-exists", destination.display()).into(),
+            ));
+        }
+
+        let lo = i.saturating_sub(before);
+        let hi = (i + after).min(opts.frames.len() - 1);
+
+        while first < lo {
+            loaded.pop_front();
+            first += 1;
+        }
+
+        while first + loaded.len() <= hi {
+            loaded.push_back(decoded.recv().unwrap()?);
+        }
+
+        let frames: Vec<&RgbImage> = loaded.iter().collect();
+        let window = &weights[lo + before - i..=hi + before - i];
+
+        let img = blend_frames(&frames, window)
+            .map_err(|e| Error::Decode(tr!("blend-failed", frame.display()).with(e)))?;
+
+        if blended.send((img, destination)).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use crate::cli::{self, error::ArgError};
+use crate::effects::SplitMix64;
+use crate::locale::Message;
+use crate::tr;
+use crate::{codec, parse_positive, parse_size, Error};
+use image::{Rgb, RgbImage};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Kind of synthetic image [`generate`] draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Diagonal ramp between two colors picked from the seed
+    Gradient,
+    /// Squares of `cell` pixels in two colors picked from the seed
+    Checkerboard { cell: u32 },
+    /// One white pixel in the middle of a black image
+    Impulse,
+    /// Uniform noise on every channel
+    Noise,
+}
+
+impl FromStr for Pattern {
+    type Err = Message;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gradient" => Ok(Pattern::Gradient),
+            "checkerboard" => Ok(Pattern::Checkerboard { cell: 8 }),
+            "impulse" => Ok(Pattern::Impulse),
+            "noise" => Ok(Pattern::Noise),
+            _ => Err(tr!("unknown-pattern", s)),
+        }
+    }
+}
+
+/// A `width`x`height` image of `pattern`. The same seed always draws the same image, on every
+/// platform.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::testgen::{generate, Pattern};
+///
+/// let a = generate(Pattern::Noise, 32, 16, 7);
+/// assert_eq!(a, generate(Pattern::Noise, 32, 16, 7));
+/// assert_ne!(a, generate(Pattern::Noise, 32, 16, 8));
+///
+/// let impulse = generate(Pattern::Impulse, 5, 5, 0);
+/// assert_eq!(impulse.get_pixel(2, 2), &image::Rgb([255; 3]));
+/// assert_eq!(impulse.pixels().filter(|p| p[0] > 0).count(), 1);
+/// ```
+pub fn generate(pattern: Pattern, width: u32, height: u32, seed: u64) -> RgbImage {
+    let mut rng = SplitMix64(seed);
+    let mut color = || Rgb([rng.next_u8(), rng.next_u8(), rng.next_u8()]);
+
+    match pattern {
+        Pattern::Gradient => {
+            let (from, to) = (color(), color());
+            let span = (width + height).saturating_sub(2).max(1) as f32;
+
+            RgbImage::from_fn(width, height, |x, y| {
+                let t = (x + y) as f32 / span;
+                Rgb([0, 1, 2]
+                    .map(|c| (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8))
+            })
+        }
+        Pattern::Checkerboard { cell } => {
+            let (a, b) = (color(), color());
+            let cell = cell.max(1);
+
+            RgbImage::from_fn(width, height, |x, y| match (x / cell + y / cell) % 2 {
+                0 => a,
+                _ => b,
+            })
+        }
+        Pattern::Impulse => {
+            let mut img = RgbImage::new(width, height);
+            if width > 0 && height > 0 {
+                img.put_pixel(width / 2, height / 2, Rgb([255; 3]));
+            }
+            img
+        }
+        Pattern::Noise => {
+            let data = (0..width as usize * height as usize * 3)
+                .map(|_| rng.next_u8())
+                .collect();
+
+            RgbImage::from_raw(width, height, data).unwrap()
+        }
+    }
+}
+
+/// CLI options of the `testgen` subcommand
+pub struct TestgenOpts {
+    pub pattern: Pattern,
+    pub destination: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub force: bool,
+}
+
+impl TestgenOpts {
+    /// Constructs a new TestgenOpts from CLI options, skipping the program name and the
+    /// `testgen` subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If the pattern or the destination is missing
+    pub fn new(cli_opts: env::Args) -> Result<TestgenOpts, ArgError> {
+        let mut positional = Vec::new();
+        let mut size = (256, 256);
+        let mut cell = 8;
+        let mut seed = 0;
+        let mut force = false;
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--size" => {
+                    size = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_size)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                "--size",
+                                "Expected a size like 640x480 after --size",
+                            )
+                        })?;
+                }
+                "--cell" => {
+                    cell = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--cell", tr!("number-after", "--cell"))
+                        })?;
+                }
+                "--seed" => {
+                    seed = cli_opts
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--seed", "Expected a number after --seed")
+                        })?;
+                }
+                "--force" => force = true,
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur testgen <pattern> <destination> [--size <w>x<h>] [--cell <pixels>] [--seed <seed>] [--force] [--help|-h]\n",
+                        "   <pattern>           gradient, checkerboard, impulse or noise.",
+                        "   <destination>       Path of the image to write.",
+                        "   --size              Size of the image. Default is 256x256.",
+                        "   --cell              Side of the checkerboard squares. Default is 8.",
+                        "   --seed              Picks the colors and the noise, the same seed always",
+                        "                       giving the same image. Default is 0.",
+                        "   --force             Overwrite an existing file.",
+                        "   -h, --help          Print help.\n",
+                        "Draws a synthetic image to test blurs against: an impulse blurs into the",
+                        "kernel itself, and the flat squares of a checkerboard stay unchanged.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        let [pattern, destination]: [String; 2] = positional
+            .try_into()
+            .map_err(|_| "Expected a pattern and a destination".to_string())?;
+
+        let pattern = match pattern.parse()? {
+            Pattern::Checkerboard { .. } => Pattern::Checkerboard { cell },
+            p => p,
+        };
+
+        Ok(TestgenOpts {
+            pattern,
+            destination: PathBuf::from(destination),
+            width: size.0,
+            height: size.1,
+            seed,
+            force,
+        })
+    }
+}
+
+/// Writes the image described by `opts`
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if the destination exists and `force` isn't set
+/// - [`Error::Encode`] if the image can't be written
+pub fn run(opts: &TestgenOpts) -> Result<(), Error> {
+    if !opts.force && opts.destination.exists() {
+        return Err(Error::InvalidArgs(
+            tr!("already-exists", opts.destination.display()).into(),
+        ));
+    }
+
+    let img = generate(opts.pattern, opts.width, opts.height, opts.seed);
+
+    codec::save(&img, &opts.destination, &codec::EncodeOptions::default()).map_err(Error::Encode)
+}
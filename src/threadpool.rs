@@ -1,34 +1,187 @@
-use std::sync::mpsc::Receiver;
-use std::sync::{mpsc, Arc, Mutex};
+use crate::logging;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 
+#[cfg(feature = "crossbeam")]
+use crossbeam_channel::{unbounded as channel, Sender};
+#[cfg(not(feature = "crossbeam"))]
+use std::sync::mpsc::{channel, Sender};
+
+/// Order in which queued jobs are picked up. Jobs of a higher priority run before every queued
+/// job of a lower one, e.g. so the tiles of an interactive preview finish before background
+/// work; jobs of the same priority run in the order they were queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    Background,
+    #[default]
+    Normal,
+    Interactive,
+}
+
+/// Queued jobs, one queue per priority from [`Priority::Background`] up
+type Queues = Arc<Mutex<[VecDeque<Job>; 3]>>;
+
+/// Number of jobs queued or running, signalled whenever it drops to 0
+type Pending = Arc<(Mutex<usize>, Condvar)>;
+
+/// Receiving end of the queued job messages, shared by the workers. crossbeam's channel has
+/// several consumers, while std's receiver has to be locked, so only one idle worker at a time
+/// waits on it.
+#[cfg(feature = "crossbeam")]
+type Tickets = crossbeam_channel::Receiver<()>;
+#[cfg(not(feature = "crossbeam"))]
+type Tickets = Arc<Mutex<std::sync::mpsc::Receiver<()>>>;
+
+/// Waits for the next queued job, or returns `false` once the pool is dropped
+#[cfg(feature = "crossbeam")]
+fn next(tickets: &Tickets) -> bool {
+    tickets.recv().is_ok()
+}
+
+#[cfg(not(feature = "crossbeam"))]
+fn next(tickets: &Tickets) -> bool {
+    tickets.lock().unwrap().recv().is_ok()
+}
+
+/// A fixed number of worker threads running boxed jobs, the pool the crate blurs bands and
+/// batches on. A job that panics is abandoned and its worker goes on with the next one.
+/// Dropping the pool waits for every queued job to finish.
+///
+/// # Examples
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use vincent_blur::threadpool::ThreadPool;
+///
+/// let pool = ThreadPool::new(4);
+/// let sum = Arc::new(AtomicUsize::new(0));
+///
+/// for i in 1..=10 {
+///     let sum = Arc::clone(&sum);
+///     pool.execute(Box::new(move || {
+///         sum.fetch_add(i, Ordering::SeqCst);
+///     }));
+/// }
+///
+/// pool.join();
+///
+/// assert_eq!(sum.load(Ordering::SeqCst), 55);
+/// assert_eq!(pool.len(), 4);
+/// assert!(pool.is_idle());
+/// ```
 pub struct ThreadPool {
-    tx: Option<mpsc::Sender<Job>>,
+    /// One message per queued job, telling a worker to take the most urgent one
+    tx: Option<Sender<()>>,
+    queues: Queues,
+    pending: Pending,
     threads: Vec<Worker>,
 }
 
 impl ThreadPool {
+    /// Starts `n_workers` threads
     pub fn new(n_workers: usize) -> Self {
-        let (tx, rx) = mpsc::channel();
+        let (tx, rx) = channel();
+        #[cfg(not(feature = "crossbeam"))]
         let rx = Arc::new(Mutex::new(rx));
+        let queues = Queues::default();
+        let pending = Pending::default();
 
         let mut handles = Vec::with_capacity(n_workers);
 
         for _ in 0..n_workers {
-            handles.push(Worker::new(Arc::clone(&rx)));
+            handles.push(Worker::new(
+                rx.clone(),
+                Arc::clone(&queues),
+                Arc::clone(&pending),
+            ));
         }
 
         ThreadPool {
             tx: Some(tx),
+            queues,
+            pending,
             threads: handles,
         }
     }
 
+    /// Queues `f` with [`Priority::Normal`]
     pub fn execute(&self, f: Job) {
+        self.execute_with_priority(Priority::Normal, f);
+    }
+
+    /// Queues `f` behind the jobs of the same or a higher priority
+    pub fn execute_with_priority(&self, priority: Priority, f: Job) {
+        *self.pending.0.lock().unwrap() += 1;
+        self.push(priority, f);
+    }
+
+    /// Queues `f` only if a worker is free to start it right away, so opportunistic work never
+    /// delays the jobs already queued
+    ///
+    /// # Errors
+    /// - `f` back if every worker is busy
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::mpsc;
+    /// use vincent_blur::threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = mpsc::channel::<()>();
+    ///
+    /// pool.execute(Box::new(move || rx.recv().unwrap()));
+    /// assert!(pool.try_execute(Box::new(|| {})).is_err());
+    ///
+    /// tx.send(()).unwrap();
+    /// pool.join();
+    /// assert!(pool.try_execute(Box::new(|| {})).is_ok());
+    /// ```
+    pub fn try_execute(&self, f: Job) -> Result<(), Job> {
+        {
+            let mut pending = self.pending.0.lock().unwrap();
+
+            if *pending >= self.len() {
+                return Err(f);
+            }
+            *pending += 1;
+        }
+
+        self.push(Priority::Normal, f);
+        Ok(())
+    }
+
+    fn push(&self, priority: Priority, f: Job) {
         if let Some(tx) = &self.tx {
-            tx.send(f).unwrap();
+            self.queues.lock().unwrap()[priority as usize].push_back(f);
+            tx.send(()).unwrap();
         }
     }
+
+    /// Waits until every queued job has finished. The pool can still be used afterwards.
+    pub fn join(&self) {
+        let (pending, idle) = &*self.pending;
+        drop(
+            idle.wait_while(pending.lock().unwrap(), |n| *n > 0)
+                .unwrap(),
+        );
+    }
+
+    /// Number of workers
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Whether the pool has no workers
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Whether no job is queued or running
+    pub fn is_idle(&self) -> bool {
+        *self.pending.0.lock().unwrap() == 0
+    }
 }
 
 impl Drop for ThreadPool {
@@ -36,8 +189,9 @@ impl Drop for ThreadPool {
         drop(self.tx.take());
 
         for worker in &mut self.threads {
+            // Workers catch the panics of their jobs, so there is nothing left to report here
             if let Some(handle) = worker.thread.take() {
-                handle.join().unwrap();
+                let _ = handle.join();
             }
         }
     }
@@ -48,16 +202,22 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(rx: Arc<Mutex<Receiver<Job>>>) -> Self {
-        let handle = thread::spawn(move || loop {
-            let msg = rx.lock().unwrap().recv();
+    fn new(tickets: Tickets, queues: Queues, pending: Pending) -> Self {
+        let handle = thread::spawn(move || {
+            while next(&tickets) {
+                let job = queues
+                    .lock()
+                    .unwrap()
+                    .iter_mut()
+                    .rev()
+                    .find_map(VecDeque::pop_front);
 
-            match msg {
-                Ok(job) => {
-                    job();
-                }
-                Err(_) => {
-                    break;
+                if let Some(job) = job {
+                    let _done = Done(&pending);
+
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        logging::debug!("job panicked, worker continues");
+                    }
                 }
             }
         });
@@ -68,4 +228,20 @@ impl Worker {
     }
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// Counts a job as finished when dropped
+struct Done<'a>(&'a Pending);
+
+impl Drop for Done<'_> {
+    fn drop(&mut self) {
+        let (pending, idle) = &**self.0;
+        let mut pending = pending.lock().unwrap();
+
+        *pending -= 1;
+        if *pending == 0 {
+            idle.notify_all();
+        }
+    }
+}
+
+/// A job run by a [`ThreadPool`]
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
@@ -0,0 +1,180 @@
+use crate::{affinity, calculate_new_pixel, deadline, events, logging, Kernel};
+use image::RgbImage;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rows of the tiles [`blur_adaptive`] hands out, small enough to time the workers often
+const TILE_ROWS: usize = 8;
+
+/// Share of the best throughput per tile below which a worker is retired
+const BACK_OFF: f64 = 0.8;
+
+/// Decides how many workers to keep from the throughput of the tiles they finish.
+///
+/// When a sustained all-core blur makes a laptop throttle, every core slows down and the
+/// tiles take longer. Once the average throughput of the last tiles falls below 80% of the best
+/// average so far, one worker is retired, which lets the remaining cores clock back up. The
+/// workers are never added back during a blur.
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use vincent_blur::throttle::Governor;
+///
+/// let governor = Governor::new(4);
+///
+/// // Two tiles per worker make a measurement
+/// for _ in 0..8 {
+///     governor.record(1_000_000, Duration::from_millis(10));
+/// }
+/// assert_eq!(governor.workers(), 4);
+///
+/// // The cores slow down to half speed
+/// for _ in 0..8 {
+///     governor.record(1_000_000, Duration::from_millis(20));
+/// }
+/// assert_eq!(governor.workers(), 3);
+/// ```
+#[derive(Debug)]
+pub struct Governor {
+    workers: AtomicUsize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Best average throughput, in kernel taps per second
+    best: f64,
+    /// Throughput of the tiles since the last measurement
+    recent: Vec<f64>,
+}
+
+impl Governor {
+    pub fn new(n_threads: usize) -> Governor {
+        Governor {
+            workers: AtomicUsize::new(n_threads.max(1)),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Workers to keep busy. Worker `i` stops taking tiles once this is `i` or less.
+    pub fn workers(&self) -> usize {
+        self.workers.load(Ordering::Relaxed)
+    }
+
+    /// Records that a worker computed `taps` kernel taps in `elapsed`
+    pub fn record(&self, taps: u64, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .recent
+            .push(taps as f64 / elapsed.as_secs_f64().max(f64::EPSILON));
+
+        let workers = self.workers();
+        if state.recent.len() < workers * 2 {
+            return;
+        }
+
+        let average = state.recent.iter().sum::<f64>() / state.recent.len() as f64;
+        state.recent.clear();
+
+        if average >= state.best {
+            state.best = average;
+        } else if average < state.best * BACK_OFF && workers > 1 {
+            self.workers.store(workers - 1, Ordering::Relaxed);
+            // Only a further slowdown retires another worker
+            state.best = average;
+
+            logging::debug!(
+                "backed off workers={} throughput={average:.0} taps/s",
+                workers - 1
+            );
+        }
+    }
+}
+
+/// Same as [`blur_async_pinned`](crate::blur_async_pinned), but the workers take tiles of rows
+/// one after another and a [`Governor`] retires them as the throughput drops, e.g. when the
+/// CPU throttles. The result is the same whatever the number of workers.
+///
+/// # Examples
+/// ```
+/// use vincent_blur::{blur_async, throttle::blur_adaptive, Kernel};
+///
+/// let img = image::RgbImage::from_fn(16, 40, |x, y| image::Rgb([((x + y) * 5) as u8; 3]));
+/// let blurred = blur_adaptive(Kernel::new(2, 1.5), 4, &[], img.clone());
+///
+/// assert_eq!(blurred, blur_async(2, 1.5, 4, img));
+/// ```
+pub fn blur_adaptive(
+    kernel: Kernel,
+    n_threads: usize,
+    cores: &[usize],
+    original_img: RgbImage,
+) -> RgbImage {
+    let (width, height) = original_img.dimensions();
+
+    let radius = kernel.radius;
+    events::emit(crate::started(radius, &original_img));
+
+    let m = kernel.matrix();
+    let mut img_buf = RgbImage::new(width, height);
+    let stride = width as usize * 3;
+    let taps_per_row = width as u64 * (radius as u64 * 2 + 1).pow(2);
+    let governor = Governor::new(n_threads);
+    let rows_done = Mutex::new(0);
+    let deadline = deadline::current();
+
+    let raw: &mut [u8] = &mut img_buf;
+    let tiles = Mutex::new(raw.chunks_mut((TILE_ROWS * stride).max(1)).enumerate());
+
+    thread::scope(|s| {
+        for i in 0..governor.workers() {
+            let (m, img, tiles, governor, rows_done) =
+                (&m, &original_img, &tiles, &governor, &rows_done);
+            let core = cores.get(i % cores.len().max(1)).copied();
+
+            s.spawn(move || {
+                if let Some(core) = core {
+                    affinity::pin(core);
+                }
+
+                while i < governor.workers() && !deadline::passed(deadline) {
+                    let Some((t, tile)) = tiles.lock().unwrap().next() else {
+                        break;
+                    };
+                    let y0 = t * TILE_ROWS;
+                    let rows = tile.len() / stride;
+                    let start = Instant::now();
+
+                    for (y, row) in (y0..).zip(tile.chunks_exact_mut(stride)) {
+                        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+                            px.copy_from_slice(&calculate_new_pixel(x as i32, y as i32, m, img).0);
+                        }
+                    }
+
+                    governor.record(taps_per_row * rows as u64, start.elapsed());
+
+                    let mut done = rows_done.lock().unwrap();
+                    let tenths = |rows: usize| rows * 10 / height as usize;
+
+                    for tenth in tenths(*done) + 1..=tenths(*done + rows) {
+                        if tenth < 10 {
+                            events::emit(events::Event::Progress {
+                                percent: tenth as u32 * 10,
+                            });
+                        }
+                    }
+                    *done += rows;
+                }
+            });
+        }
+    });
+
+    if !deadline::passed(deadline) {
+        events::emit(events::Event::Finished);
+    }
+
+    img_buf
+}
@@ -0,0 +1,235 @@
+use crate::cli::{self, error::ArgError};
+use crate::locale::Message;
+use crate::tr;
+use crate::{blur_async, codec, layout, naming, parse_positive, Error};
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// How much smaller than the thumbnail the blurred background is blurred, which makes the blur
+/// that much wider once it is scaled back up
+const BACKGROUND_DOWNSCALE: u32 = 8;
+
+/// CLI options of the `thumb` subcommand
+pub struct ThumbOpts {
+    pub sources: Vec<PathBuf>,
+    /// Side of the square thumbnails
+    pub size: u32,
+    /// Fit the whole image over a blurred copy of itself instead of cropping it to a square
+    pub blur_background: bool,
+    /// Blur of the background, in pixels of the background before it is scaled up
+    pub radius: u8,
+    pub sigma: f64,
+    pub format: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub force: bool,
+}
+
+impl ThumbOpts {
+    /// Constructs a new ThumbOpts from CLI options, skipping the program name and the `thumb`
+    /// subcommand
+    ///
+    /// # Errors
+    /// - If the cli options are not followed by a value
+    /// - If no sources are given
+    pub fn new(cli_opts: env::Args) -> Result<ThumbOpts, ArgError> {
+        let mut sources = Vec::new();
+        let mut size = 256;
+        let mut blur_background = false;
+        let mut radius = 4;
+        let mut sigma = 3.0;
+        let mut format: Option<String> = None;
+        let mut output_dir: Option<PathBuf> = None;
+        let mut force = false;
+
+        let mut cli_opts = cli::Args::new(cli_opts);
+        cli_opts.next();
+        cli_opts.next();
+
+        while let Some(arg) = cli_opts.next() {
+            match arg.as_str() {
+                "--size" => {
+                    size = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--size", tr!("number-after", "--size"))
+                        })?;
+                }
+                "--blur-background" => blur_background = true,
+                "--radius" | "-r" => {
+                    radius = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value(
+                                "--radius",
+                                "Expected a number from 1 to 255 after --radius|-r",
+                            )
+                        })?;
+                }
+                "--sigma" | "-s" => {
+                    sigma = cli_opts
+                        .next()
+                        .as_deref()
+                        .and_then(parse_positive)
+                        .ok_or_else(|| {
+                            cli_opts.invalid_value("--sigma", tr!("float-after", "--sigma|-s"))
+                        })?;
+                }
+                "--format" | "-f" => {
+                    format = match cli_opts.next() {
+                        Some(f) if codec::is_supported(&f) => Some(f.to_lowercase()),
+                        Some(f) => {
+                            return Err(
+                                cli_opts.invalid_value("--format", tr!("unsupported-format", f))
+                            )
+                        }
+                        None => {
+                            return Err(cli_opts
+                                .invalid_value("--format", "Expected a format after --format|-f"))
+                        }
+                    };
+                }
+                "--output-dir" | "-o" => {
+                    output_dir = match cli_opts.next() {
+                        Some(d) => Some(PathBuf::from(d)),
+                        None => {
+                            return Err(cli_opts.invalid_value(
+                                "--output-dir",
+                                "Expected a directory after --output-dir|-o",
+                            ))
+                        }
+                    };
+                }
+                "--force" => force = true,
+                "--help" | "-h" => {
+                    let help = [
+                        "Usage: blur thumb [--size <pixels>] [--blur-background] [--radius|-r <radius>] [--sigma|-s <sigma>] [--format|-f <format>] [--output-dir|-o <dir>] [--force] <sources>... [--help|-h]\n",
+                        "   <sources>           Paths of the images to make thumbnails of.",
+                        "   --size              Side of the square thumbnails. Default is 256.",
+                        "   --blur-background   Fit the whole image over a blurred, zoomed copy of",
+                        "                       itself. Default is to crop the middle square.",
+                        "   -r, --radius        Radius of the background blur, in pixels of an",
+                        "                       eighth of the thumbnail. Default is 4.",
+                        "   -s, --sigma         Sigma of the background blur. Default is 3.",
+                        "   -f, --format        Output format. Default is the source's format.",
+                        "   -o, --output-dir    Directory to write the thumbnails to. Default is next",
+                        "                       to the sources, as <stem>_thumb_<size>.<ext>.",
+                        "   --force             Overwrite existing files.",
+                        "   -h, --help          Print help.",
+                    ]
+                    .join("\n");
+
+                    println!("{help}");
+                    std::process::exit(0);
+                }
+                _ => sources.push(PathBuf::from(arg)),
+            }
+        }
+
+        if sources.is_empty() {
+            return Err("Expected at least one source".into());
+        }
+
+        Ok(ThumbOpts {
+            sources,
+            size,
+            blur_background,
+            radius,
+            sigma,
+            format,
+            output_dir,
+            force,
+        })
+    }
+
+    /// Destination of the thumbnail of `source`, `<stem>_thumb_<size>.<ext>`
+    ///
+    /// # Errors
+    /// - Same as [`naming::default_path`]
+    pub fn destination(&self, source: &Path) -> Result<PathBuf, Message> {
+        let suffix = format!("_thumb_{}", self.size);
+        let path = naming::default_path(source, &suffix, self.format.as_deref())?;
+
+        Ok(match &self.output_dir {
+            Some(dir) => dir.join(path.file_name().unwrap()),
+            None => path,
+        })
+    }
+}
+
+/// A `size`x`size` thumbnail of `img`: its middle square, or with `background` set to a
+/// radius and sigma, the whole image fit over a blurred copy of itself zoomed to cover the
+/// square
+///
+/// # Examples
+/// ```
+/// use vincent_blur::thumb::thumbnail;
+///
+/// let img = image::RgbImage::from_pixel(400, 200, image::Rgb([200, 40, 40]));
+///
+/// assert_eq!(thumbnail(&img, 64, None).dimensions(), (64, 64));
+///
+/// let fitted = thumbnail(&img, 64, Some((2, 1.5)));
+/// assert_eq!(fitted.dimensions(), (64, 64));
+/// assert_eq!(fitted.get_pixel(32, 32), &image::Rgb([200, 40, 40]));
+/// ```
+pub fn thumbnail(img: &RgbImage, size: u32, background: Option<(u8, f64)>) -> RgbImage {
+    let Some((radius, sigma)) = background else {
+        return layout::cover(img, size, size);
+    };
+
+    let small = (size / BACKGROUND_DOWNSCALE).max(1);
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let blurred = blur_async(radius, sigma, n_threads, layout::cover(img, small, small));
+    let mut canvas = imageops::resize(&blurred, size, size, FilterType::Triangle);
+
+    let scale = size as f64 / img.width().max(img.height()) as f64;
+    let width = ((img.width() as f64 * scale).round() as u32).clamp(1, size);
+    let height = ((img.height() as f64 * scale).round() as u32).clamp(1, size);
+
+    layout::overlay_center(
+        &mut canvas,
+        &imageops::resize(img, width, height, FilterType::Lanczos3),
+    );
+
+    canvas
+}
+
+/// Writes the thumbnail of every source
+///
+/// # Errors
+/// - [`Error::InvalidArgs`] if a destination exists and `force` isn't set
+/// - [`Error::Decode`] if a source can't be read
+/// - [`Error::Encode`] if a thumbnail can't be written
+pub fn run(opts: &ThumbOpts) -> Result<(), Error> {
+    let encode_opts = codec::EncodeOptions {
+        format: opts.format.clone(),
+        ..Default::default()
+    };
+    let background = opts.blur_background.then_some((opts.radius, opts.sigma));
+
+    for source in &opts.sources {
+        let destination = opts
+            .destination(source)
+            .map_err(|e| Error::InvalidArgs(e.into()))?;
+
+        if !opts.force && destination.exists() {
+            return Err(Error::InvalidArgs(
+                tr!("already-exists", destination.display()).into(),
+            ));
+        }
+
+        let img = codec::open(source, &codec::DecodeOptions::default()).map_err(Error::Decode)?;
+        let thumb = thumbnail(&img, opts.size, background);
+
+        codec::save(&thumb, &destination, &encode_opts).map_err(Error::Encode)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A step of blurring a file that `--timings` reports separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading and decoding the source
+    Decode,
+    /// Computing the Gaussian weights or matrix
+    Kernel,
+    /// Separable passes along the rows
+    Horizontal,
+    /// Separable passes along the columns
+    Vertical,
+    /// Convolutions with the full 2D kernel, which have no separate passes
+    Convolve,
+    /// Encoding and writing the destination
+    Encode,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Decode,
+        Stage::Kernel,
+        Stage::Horizontal,
+        Stage::Vertical,
+        Stage::Convolve,
+        Stage::Encode,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Kernel => "kernel",
+            Stage::Horizontal => "horizontal",
+            Stage::Vertical => "vertical",
+            Stage::Convolve => "2d",
+            Stage::Encode => "encode",
+        }
+    }
+}
+
+/// Wall-clock and CPU time spent on something. The CPU time is the whole process's, so it
+/// includes worker threads, and it is `None` where the platform doesn't report it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub wall: Duration,
+    pub cpu: Option<Duration>,
+}
+
+impl Span {
+    const ZERO: Span = Span {
+        wall: Duration::ZERO,
+        cpu: Some(Duration::ZERO),
+    };
+
+    fn add(&mut self, other: Span) {
+        self.wall += other.wall;
+        self.cpu = self.cpu.zip(other.cpu).map(|(a, b)| a + b);
+    }
+
+    fn saturating_sub(self, other: Span) -> Span {
+        Span {
+            wall: self.wall.saturating_sub(other.wall),
+            cpu: self.cpu.zip(other.cpu).map(|(a, b)| a.saturating_sub(b)),
+        }
+    }
+}
+
+/// Time spent in each [`Stage`] between [`start`] and [`finish`]. Stages running inside
+/// another, like the kernel build of a 2D blur, are only counted in the inner one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timings {
+    pub stages: [(Stage, Span); 6],
+    /// From [`start`] to [`finish`], including what no stage covers
+    pub total: Span,
+}
+
+impl Timings {
+    /// The part of [`total`](Timings::total) outside every stage, e.g. effects and metadata
+    pub fn other(&self) -> Span {
+        let mut stages = Span::ZERO;
+
+        for (_, span) in &self.stages {
+            stages.add(*span);
+        }
+
+        self.total.saturating_sub(stages)
+    }
+}
+
+impl fmt::Display for Timings {
+    /// One line per stage with its wall-clock and CPU time in milliseconds and its share of
+    /// the total wall-clock time
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use vincent_blur::timings::{Span, Stage, Timings};
+    ///
+    /// let ms = |ms| Span {
+    ///     wall: Duration::from_millis(ms),
+    ///     cpu: Some(Duration::from_millis(ms * 2)),
+    /// };
+    /// let timings = Timings {
+    ///     stages: Stage::ALL.map(|s| (s, ms(match s {
+    ///         Stage::Decode => 30,
+    ///         Stage::Horizontal | Stage::Vertical => 20,
+    ///         _ => 0,
+    ///     }))),
+    ///     total: ms(100),
+    /// };
+    ///
+    /// let report = timings.to_string();
+    ///
+    /// assert!(report.starts_with("stage            wall        cpu    wall %\n"));
+    /// assert!(report.contains("\ndecode         30.0ms     60.0ms     30.0%\n"));
+    /// assert!(report.contains("\nother          30.0ms     60.0ms     30.0%\n"));
+    /// assert!(report.ends_with("\ntotal         100.0ms    200.0ms    100.0%"));
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+        let share = |d: Duration| match self.total.wall.is_zero() {
+            true => "-".to_string(),
+            false => format!(
+                "{:.1}%",
+                d.as_secs_f64() / self.total.wall.as_secs_f64() * 100.0
+            ),
+        };
+
+        write!(
+            f,
+            "{:<10} {:>10} {:>10} {:>9}",
+            "stage", "wall", "cpu", "wall %"
+        )?;
+
+        let rows = self
+            .stages
+            .iter()
+            .map(|(stage, span)| (stage.name(), *span))
+            .chain([("other", self.other()), ("total", self.total)]);
+
+        for (name, span) in rows {
+            write!(
+                f,
+                "\n{name:<10} {:>10} {:>10} {:>9}",
+                ms(span.wall),
+                span.cpu.map_or("-".to_string(), ms),
+                share(span.wall)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Recording {
+    start: Mark,
+    stages: [(Stage, Span); 6],
+}
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+thread_local! {
+    /// Time of the stages nested in the one running on this thread, so it doesn't count it
+    static NESTED: Cell<Span> = const { Cell::new(Span::ZERO) };
+}
+
+/// Starts timing the stages, discarding what was recorded before
+pub fn start() {
+    *RECORDING.lock().unwrap_or_else(|e| e.into_inner()) = Some(Recording {
+        start: Mark::now(),
+        stages: Stage::ALL.map(|s| (s, Span::ZERO)),
+    });
+}
+
+/// Stops timing the stages and returns what was recorded since [`start`], or `None` if it
+/// wasn't called
+///
+/// # Examples
+/// ```
+/// use vincent_blur::timings::{self, Stage};
+///
+/// timings::start();
+/// vincent_blur::blur_horizontal(3, 1.5, &image::RgbImage::new(64, 64));
+/// let recorded = timings::finish().unwrap();
+///
+/// let horizontal = recorded.stages.iter().find(|(s, _)| *s == Stage::Horizontal).unwrap();
+/// assert!(!horizontal.1.wall.is_zero());
+/// assert!(recorded.total.wall >= horizontal.1.wall);
+/// assert_eq!(timings::finish(), None);
+/// ```
+pub fn finish() -> Option<Timings> {
+    let recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+
+    Some(Timings {
+        stages: recording.stages,
+        total: recording.start.elapsed(),
+    })
+}
+
+/// Runs `f`, adding its time to `stage` while the stages are being timed
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    if RECORDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_none()
+    {
+        return f();
+    }
+
+    let outer = NESTED.replace(Span::ZERO);
+    let start = Mark::now();
+
+    let out = f();
+
+    let elapsed = start.elapsed();
+    let nested = NESTED.get();
+    let mut parent = outer;
+    parent.add(elapsed);
+    NESTED.set(parent);
+
+    if let Some(recording) = RECORDING.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        if let Some((_, span)) = recording.stages.iter_mut().find(|(s, _)| *s == stage) {
+            span.add(elapsed.saturating_sub(nested));
+        }
+    }
+
+    out
+}
+
+/// A point in time, with the CPU time the process had used by then
+#[derive(Clone, Copy)]
+struct Mark {
+    wall: Instant,
+    cpu: Option<Duration>,
+}
+
+impl Mark {
+    fn now() -> Mark {
+        Mark {
+            wall: Instant::now(),
+            cpu: cpu_time(),
+        }
+    }
+
+    fn elapsed(self) -> Span {
+        let now = Mark::now();
+
+        Span {
+            wall: now.wall - self.wall,
+            cpu: self
+                .cpu
+                .zip(now.cpu)
+                .map(|(start, end)| end.saturating_sub(start)),
+        }
+    }
+}
+
+/// User and system time of the whole process, from `/proc/self/stat`
+#[cfg(target_os = "linux")]
+fn cpu_time() -> Option<Duration> {
+    /// Clock ticks per second of the times in `/proc`, fixed to 100 by the kernel's ABI
+    const TICKS: u64 = 100;
+
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // The command name in parentheses may contain spaces, the fields after it don't
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+    let utime: u64 = fields.nth(11)?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+
+    Some(Duration::from_millis((utime + stime) * 1000 / TICKS))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
@@ -1,73 +1,946 @@
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use vincent_blur::*;
 
+/// The 2D convolution of `img` with `kernel` at the pixels whose whole window lies inside the
+/// image, computed tap by tap as the reference the blurs are checked against
+fn reference_blur(kernel: Kernel, img: &image::RgbImage) -> Vec<(u32, u32, [f64; 3])> {
+    let r = kernel.radius as i32;
+    let (width, height) = (img.width() as i32, img.height() as i32);
+
+    (r..height - r)
+        .flat_map(|y| (r..width - r).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let mut sum = [0.0; 3];
+
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let p = img.get_pixel((x + dx) as u32, (y + dy) as u32);
+
+                    for c in 0..3 {
+                        sum[c] += kernel.weight(dx, dy) * p[c] as f64;
+                    }
+                }
+            }
+
+            (x as u32, y as u32, sum)
+        })
+        .collect()
+}
+
+fn assert_matches_reference(kernel: Kernel, img: &image::RgbImage, blurred: &image::RgbImage) {
+    for (x, y, expected) in reference_blur(kernel, img) {
+        let p = blurred.get_pixel(x, y);
+
+        for c in 0..3 {
+            assert!(
+                (p[c] as f64 - expected[c]).abs() <= backend::TOLERANCE as f64,
+                "{kernel:?} at {x},{y}: {p:?} vs {expected:?}"
+            );
+        }
+    }
+}
+
 #[test]
 fn small_radius() {
-    let img = image::open("assets/bg.jpg").unwrap().to_rgb8();
+    let img = testgen::generate(testgen::Pattern::Noise, 48, 40, 1);
+    let blurred = blur_sync(3, 10.0, img.clone());
+
+    assert_matches_reference(Kernel::new(3, 10.0), &img, &blurred);
+}
+
+#[test]
+fn big_radius() {
+    let img = testgen::generate(testgen::Pattern::Noise, 64, 56, 1);
+    let blurred = blur_sync(10, 10.0, img.clone());
+
+    assert_matches_reference(Kernel::new(10, 10.0), &img, &blurred);
+}
+
+#[test]
+fn async_small_radius() {
+    let img = testgen::generate(testgen::Pattern::Noise, 48, 40, 1);
+    let blurred = blur_async(3, 10.0, 10, img.clone());
+
+    assert_matches_reference(Kernel::new(3, 10.0), &img, &blurred);
+    assert_eq!(blurred, blur_sync(3, 10.0, img));
+}
+
+#[test]
+fn async_big_radius() {
+    let img = testgen::generate(testgen::Pattern::Noise, 64, 56, 1);
+    let blurred = blur_async(10, 10.0, 10, img.clone());
+
+    assert_matches_reference(Kernel::new(10, 10.0), &img, &blurred);
+    assert_eq!(blurred, blur_sync(10, 10.0, img));
+}
+
+/// A stage that counts how often it runs, then waits for `until` or fails with `fail`
+struct Probe {
+    runs: Arc<AtomicUsize>,
+    until: Option<Instant>,
+    fail: Option<Error>,
+}
+
+impl Probe {
+    fn new() -> (Probe, Arc<AtomicUsize>) {
+        let runs = Arc::default();
+        let probe = Probe {
+            runs: Arc::clone(&runs),
+            until: None,
+            fail: None,
+        };
+
+        (probe, runs)
+    }
+}
+
+impl pipeline::Filter for Probe {
+    fn name(&self) -> &str {
+        "probe"
+    }
+
+    fn apply(&self, img: pipeline::FloatImage) -> Result<pipeline::FloatImage, Error> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+
+        if let Some(until) = self.until {
+            thread::sleep(until.saturating_duration_since(Instant::now()));
+        }
+
+        match &self.fail {
+            Some(e) => Err(e.clone()),
+            None => Ok(img),
+        }
+    }
+}
+
+#[test]
+fn blurs_stop_once_the_deadline_passes() {
+    let img = testgen::generate(testgen::Pattern::Noise, 64, 56, 1);
+    let deadline = Instant::now() + Duration::from_millis(50);
+
+    let (first, first_runs) = Probe::new();
+    let (after, after_runs) = Probe::new();
+    let pipeline = pipeline::Pipeline::new()
+        .then(Probe {
+            until: Some(deadline),
+            ..first
+        })
+        .then(pipeline::Gaussian {
+            kernel: Kernel::new(10, 10.0),
+            passes: 1,
+            direction: Direction::Both,
+        })
+        .then(after);
+
+    assert_eq!(pipeline.run_until(&img, deadline), Err(Error::Timeout));
+    assert_eq!(first_runs.load(Ordering::SeqCst), 1);
+    assert_eq!(after_runs.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn cancelled_stage_stops_the_pipeline() {
+    let img = testgen::generate(testgen::Pattern::Noise, 48, 40, 1);
+
+    let (cancel, _) = Probe::new();
+    let (after, after_runs) = Probe::new();
+    let pipeline = pipeline::Pipeline::new()
+        .then(Probe {
+            fail: Some(Error::Cancelled),
+            ..cancel
+        })
+        .then(pipeline::Gaussian {
+            kernel: Kernel::new(3, 10.0),
+            passes: 1,
+            direction: Direction::Both,
+        })
+        .then(after);
+
+    assert_eq!(pipeline.run(&img), Err(Error::Cancelled));
+    assert_eq!(after_runs.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn impulse_response_matches_kernel() {
+    let kernels = [(1, 0.8), (4, 1.5), (12, 6.0)]
+        .into_iter()
+        .flat_map(|(radius, sigma)| [Kernel::new(radius, sigma), Kernel::derived(sigma)]);
+
+    for kernel in kernels {
+        for backend in backend::all(2) {
+            let res = kernel.verify(|k, img| backend.blur(k, img));
+            assert_eq!(res, Ok(()), "{} with {kernel:?}", backend.name());
+        }
+
+        let tileable = kernel.verify(|k, img| blur_tileable(k, 1, Direction::Both, &img));
+        assert_eq!(tileable, Ok(()), "tileable with {kernel:?}");
+    }
+}
+
+#[test]
+fn constant_areas_stay_unchanged() {
+    let img = testgen::generate(testgen::Pattern::Checkerboard { cell: 16 }, 48, 32, 5);
+
+    for backend in backend::all(2) {
+        let blurred = backend.blur(Kernel::new(3, 2.0), img.clone());
+
+        // Pixels whose whole 7x7 window lies within one square
+        for (x, y, p) in blurred.enumerate_pixels() {
+            if (3..13).contains(&(x % 16)) && (3..13).contains(&(y % 16)) {
+                let expected = img.get_pixel(x, y);
+
+                for c in 0..3 {
+                    assert!(
+                        p[c].abs_diff(expected[c]) <= backend::TOLERANCE,
+                        "{} at {x},{y}: {p:?} vs {expected:?}",
+                        backend.name()
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn qoi_and_pgm_round_trip() {
+    let img = image::RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 32, 128]));
+    let dir = std::env::temp_dir();
+
+    let qoi = dir.join("blur_round_trip.qoi");
+    codec::save(&img, &qoi, &Default::default()).unwrap();
+    assert_eq!(codec::open(&qoi, &Default::default()).unwrap(), img);
+
+    let pgm = dir.join("blur_round_trip.img");
+    codec::save(
+        &img,
+        &pgm,
+        &codec::EncodeOptions {
+            format: Some("pgm".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let gray = codec::open(&pgm, &Default::default()).unwrap();
+    assert_eq!(gray.dimensions(), img.dimensions());
+    assert!(gray.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+}
+
+#[cfg(feature = "jxl")]
+#[test]
+fn jxl_round_trips_losslessly() {
+    let img = testgen::generate(testgen::Pattern::Noise, 23, 17, 4);
+    let jxl = std::env::temp_dir().join("blur_round_trip.jxl");
+
+    codec::save(&img, &jxl, &Default::default()).unwrap();
+    assert!(codec::is_supported("JXL"));
+    assert_eq!(codec::open(&jxl, &Default::default()).unwrap(), img);
+
+    let small = codec::DecodeOptions {
+        max_pixels: Some(100),
+        ..Default::default()
+    };
+    assert!(codec::open(&jxl, &small).is_err());
+}
+
+#[test]
+fn interlaced_png_decodes_to_same_pixels() {
+    let img = image::RgbImage::from_fn(13, 11, |x, y| image::Rgb([x as u8 * 19, y as u8 * 23, 7]));
+    let png = std::env::temp_dir().join("blur_interlaced.png");
+
+    codec::save(
+        &img,
+        &png,
+        &codec::EncodeOptions {
+            interlaced: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(codec::open(&png, &Default::default()).unwrap(), img);
+}
+
+#[test]
+fn cmyk_tiff_keeps_separation() {
+    let (width, height) = (9, 7);
+    let data: Vec<u8> = (0..width * height)
+        .flat_map(|i| [i as u8, 40, 200, 255 - i as u8])
+        .collect();
+
+    let src = std::env::temp_dir().join("blur_cmyk_src.tiff");
+    let mut file = std::fs::File::create(&src).unwrap();
+    tiff::encoder::TiffEncoder::new(&mut file)
+        .unwrap()
+        .write_image::<tiff::encoder::colortype::CMYK8>(width, height, &data)
+        .unwrap();
+
+    let cmyk = codec::open_cmyk(&src).unwrap().unwrap();
+    assert_eq!(cmyk.cmy.get_pixel(3, 0).0, [3, 40, 200]);
+    assert_eq!(cmyk.k.get_pixel(3, 0).0, [252; 3]);
+
+    let dst = std::env::temp_dir().join("blur_cmyk_dst.tiff");
+    codec::save_cmyk(&cmyk, &dst, &Default::default()).unwrap();
+
+    let mut decoder = tiff::decoder::Decoder::new(std::fs::File::open(&dst).unwrap()).unwrap();
+    assert_eq!(decoder.colortype().unwrap(), tiff::ColorType::CMYK(8));
+    assert!(codec::open_cmyk(&src.with_extension("missing")).is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_blur_writes_output() {
+    let dir = std::env::temp_dir();
+    let src = dir.join("blur_tokio_src.png");
+    let dst = dir.join("blur_tokio_dst.png");
+
+    image::RgbImage::from_fn(12, 12, |x, _| image::Rgb([x as u8 * 20; 3]))
+        .save(&src)
+        .unwrap();
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    rt.block_on(blur_tokio(&src, &dst, 2, 1.0, 2)).unwrap();
+
+    assert_eq!(image::open(&dst).unwrap().to_rgb8().dimensions(), (12, 12));
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn codec_logs_its_steps() {
+    use std::sync::Mutex;
+
+    struct Collect(Mutex<Vec<String>>);
+
+    impl log::Log for Collect {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    static RECORDS: Collect = Collect(Mutex::new(Vec::new()));
+    log::set_logger(&RECORDS).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let path = std::env::temp_dir().join("blur_log_codec.png");
+    codec::save(&image::RgbImage::new(5, 4), &path, &Default::default()).unwrap();
+    codec::open(&path, &Default::default()).unwrap();
+
+    let records = RECORDS.0.lock().unwrap();
+    let path = path.display();
+
+    for expected in [
+        format!("encoded path={path} format=png width=5 height=4 "),
+        format!("wrote path={path} "),
+        format!("opened path={path} "),
+        format!("decoded path={path} format=png width=5 height=4"),
+    ] {
+        assert!(
+            records.iter().any(|r| r.starts_with(&expected)),
+            "{expected} missing from {records:?}"
+        );
+    }
+}
+
+#[test]
+fn paused_blur_makes_no_progress() {
+    use vincent_blur::control::Control;
+    use vincent_blur::events::BlurEvent;
+
+    let img = image::RgbImage::from_fn(64, 256, |x, y| image::Rgb([(x * 4) as u8, y as u8, 7]));
+    let control = Control::new(2);
+    control.pause();
+
+    let task = blur_async_controlled(3, 2.0, control.clone(), img.clone());
+    assert!(matches!(
+        task.events().recv(),
+        Ok(BlurEvent::Started { .. })
+    ));
+
+    // Paused before any tile was taken, so no worker gets to one until it resumes
+    assert!(task.control().is_paused());
+    assert!(task
+        .events()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+
+    control.set_threads(1);
+    control.resume();
+
+    let rest: Vec<_> = task.events().iter().collect();
+    let tiles = rest
+        .iter()
+        .filter(|e| matches!(e, BlurEvent::TileDone { .. }))
+        .count();
+    assert_eq!(tiles, 16);
+    assert!(matches!(rest.last(), Some(BlurEvent::Finished { .. })));
+    assert_eq!(task.wait().unwrap(), blur_async(3, 2.0, 2, img));
+}
+
+#[test]
+fn panicking_jobs_keep_their_workers() {
+    use vincent_blur::threadpool::ThreadPool;
+
+    let pool = ThreadPool::new(2);
+    let done = Arc::new(AtomicUsize::new(0));
+
+    for i in 0..8 {
+        let done = Arc::clone(&done);
+        pool.execute(Box::new(move || {
+            if i % 2 == 0 {
+                panic!("job {i} failed");
+            }
+            done.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+
+    pool.join();
+    assert_eq!(done.load(Ordering::SeqCst), 4);
 
     let (tx, rx) = mpsc::channel();
+    for _ in 0..pool.len() {
+        let tx = tx.clone();
+        pool.execute(Box::new(move || tx.send(()).unwrap()));
+    }
+    drop(tx);
 
-    thread::spawn(move || {
-        let img_blurred = blur_sync(3, 10.0, img);
-        img_blurred.save("assets/blurred_test_1.jpg").unwrap();
+    assert_eq!(rx.iter().count(), 2);
+    drop(pool);
+}
 
-        tx.send(()).unwrap();
+#[test]
+fn backends_agree() {
+    let img = image::RgbImage::from_fn(24, 17, |x, y| {
+        image::Rgb([(x * 10) as u8, (y * 15) as u8, ((x * y) % 256) as u8])
     });
+    let expected = blur_sync(3, 2.0, img.clone());
+
+    let mut names = vec!["threadpool", "simd", "fixed"];
+    if cfg!(feature = "rayon") {
+        names.push("rayon");
+    }
+    if cfg!(feature = "fft") {
+        names.push("fft");
+    }
 
-    assert_eq!(rx.recv_timeout(Duration::from_secs(30)), Ok(()))
+    for name in names {
+        let backend = backend::from_name(name, 3).unwrap().unwrap();
+        let blurred = backend.blur(Kernel::new(3, 2.0), img.clone());
+
+        for (a, b) in blurred.as_raw().iter().zip(expected.as_raw()) {
+            assert!(a.abs_diff(*b) <= 1, "{name} differs: {a} vs {b}");
+        }
+    }
 }
 
 #[test]
-fn big_radius() {
-    let img = image::open("assets/bg.jpg").unwrap().to_rgb8();
+fn outputs_are_identical_across_thread_counts() {
+    // Odd sizes so the bands and tiles never divide the image evenly
+    let img = image::RgbImage::from_fn(37, 29, |x, y| {
+        image::Rgb([
+            ((x * 53 + y * 7) % 256) as u8,
+            ((y * 91) % 256) as u8,
+            ((x ^ y) * 29 % 256) as u8,
+        ])
+    });
+    let denoise = denoise::DenoiseParams {
+        radius: 2,
+        sigma: 1.5,
+        threshold: 40,
+        chroma_sigma: 1.0,
+    };
+    let nl_means = denoise::NlMeansParams {
+        patch_radius: 1,
+        search_radius: 3,
+        noise: 10.0,
+        h: 6.0,
+    };
 
-    let (tx, rx) = mpsc::channel();
+    let kernel = Kernel::new(3, 2.0);
+
+    type Blur<'a> = Box<dyn Fn(usize) -> image::RgbImage + 'a>;
+
+    let blurs: Vec<(&str, Blur)> = vec![
+        (
+            "blur_async",
+            Box::new(|n| blur_async(3, 2.0, n, img.clone())),
+        ),
+        (
+            "blur_async_focused",
+            Box::new(|n| blur_async_focused(kernel, n, &[], img.clone(), 100, (5, 20), |_, _| {})),
+        ),
+        (
+            "blur_async_events",
+            Box::new(|n| blur_async_events(3, 2.0, n, img.clone()).wait().unwrap()),
+        ),
+        (
+            "blur_adaptive",
+            Box::new(|n| throttle::blur_adaptive(kernel, n, &[], img.clone())),
+        ),
+        (
+            "threadpool",
+            Box::new(|n| {
+                let backend = backend::from_name("threadpool", n).unwrap().unwrap();
+                backend.blur(Kernel::new(3, 2.0), img.clone())
+            }),
+        ),
+        (
+            "auto",
+            Box::new(|n| <dyn backend::Backend>::auto(3, 37, 29, n).blur(kernel, img.clone())),
+        ),
+        (
+            "blurrer",
+            Box::new(|n| blurrer::Blurrer::new(3, 2.0, n).run(&img)),
+        ),
+        (
+            "surface_blur",
+            Box::new(|n| surface_blur(kernel, 30, n, img.clone())),
+        ),
+        ("denoise", Box::new(|n| denoise::denoise(&img, denoise, n))),
+        (
+            "nl_means",
+            Box::new(|n| denoise::nl_means(&img, nl_means, n)),
+        ),
+    ];
+
+    for (name, blur) in &blurs {
+        let expected = blur(1);
+
+        for n_threads in [2, 3, 4, 7, 16, 64] {
+            assert!(
+                blur(n_threads) == expected,
+                "{name} differs with {n_threads} threads"
+            );
+        }
+    }
+}
+
+#[test]
+fn derived_radius_grows_without_steps() {
+    let img = image::RgbImage::from_fn(41, 23, |x, y| {
+        image::Rgb([if (x / 4 + y / 3) % 2 == 0 { 0 } else { 255 }; 3])
+    });
+    let simd = backend::from_name("simd", 1).unwrap().unwrap();
+    let blur = |sigma: f64| simd.blur(Kernel::derived(sigma), img.clone());
 
-    thread::spawn(move || {
-        let img_blurred = blur_sync(10, 10.0, img);
-        img_blurred.save("assets/blurred_test_2.jpg").unwrap();
+    // Sigmas where 3 sigma crosses a whole number and the radius grows by one
+    for k in 2..=30 {
+        let sigma = k as f64 / 3.0;
+        let (before, after) = (blur(sigma - 1e-6), blur(sigma + 1e-6));
+
+        for (a, b) in before.as_raw().iter().zip(after.as_raw()) {
+            assert_eq!(a, b, "sigma {sigma}");
+        }
+    }
+}
 
-        tx.send(()).unwrap();
+#[test]
+fn explicit_radius_keeps_outer_taps_whole() {
+    let img = image::RgbImage::from_fn(41, 23, |x, y| {
+        image::Rgb([if (x / 4 + y / 3) % 2 == 0 { 0 } else { 255 }; 3])
     });
 
+    // 3 sigma only reaches a quarter into the taps 4 away, which fade only when derived
+    assert_eq!(metrics::radius_for(1.1), 4);
+    let sync = |k, img| backend::Backend::blur(&backend::SyncCpu, k, img);
+    assert!(Kernel::new(4, 1.1).verify(sync).is_ok());
+    assert!(Kernel::derived(1.1).verify(sync).is_ok());
+
+    let explicit = sync(Kernel::new(4, 1.1), img.clone());
+    let derived = sync(Kernel::derived(1.1), img.clone());
+    assert_ne!(explicit, derived);
+    assert_eq!(explicit, blur_sync(4, 1.1, img));
+}
+
+/// A stage that runs `gaussian` once `until` has passed, recording whether the blur left the
+/// image as it was
+struct Late {
+    gaussian: pipeline::Gaussian,
+    until: Instant,
+    untouched: Arc<AtomicBool>,
+}
+
+impl pipeline::Filter for Late {
+    fn name(&self) -> &str {
+        "late"
+    }
+
+    fn apply(&self, img: pipeline::FloatImage) -> Result<pipeline::FloatImage, Error> {
+        thread::sleep(self.until.saturating_duration_since(Instant::now()));
+
+        let blurred = self.gaussian.apply(img.clone())?;
+        self.untouched.store(blurred == img, Ordering::SeqCst);
+
+        Ok(blurred)
+    }
+}
+
+#[test]
+fn separable_blurs_stop_at_the_deadline() {
+    let img = image::RgbImage::from_fn(60, 40, |x, y| image::Rgb([(x ^ y) as u8; 3]));
+    let untouched = Arc::new(AtomicBool::new(false));
+    let deadline = Instant::now() + Duration::from_millis(20);
+    let pipeline = pipeline::Pipeline::new().then(Late {
+        gaussian: pipeline::Gaussian {
+            kernel: Kernel::new(6, 2.0),
+            passes: 2,
+            direction: Direction::Both,
+        },
+        until: deadline,
+        untouched: Arc::clone(&untouched),
+    });
+
+    assert_eq!(pipeline.run_until(&img, deadline), Err(Error::Timeout));
+    assert!(untouched.load(Ordering::SeqCst));
+
+    assert!(pipeline.run(&img).is_ok());
+    assert!(!untouched.load(Ordering::SeqCst));
+}
+
+#[test]
+fn fixed_point_stays_within_one_of_float_for_wide_kernels() {
+    let img = image::RgbImage::from_fn(53, 31, |x, y| {
+        image::Rgb([
+            ((x * 37) % 256) as u8,
+            ((y * 91) % 256) as u8,
+            ((x ^ y) * 13 % 256) as u8,
+        ])
+    });
+    let fixed = backend::from_name("fixed", 1).unwrap().unwrap();
+    let simd = backend::from_name("simd", 1).unwrap().unwrap();
+
+    for (radius, sigma) in [(1, 0.5), (24, 9.0), (80, 30.0)] {
+        let a = fixed.blur(Kernel::new(radius, sigma), img.clone());
+        let b = simd.blur(Kernel::new(radius, sigma), img.clone());
+
+        for (a, b) in a.as_raw().iter().zip(b.as_raw()) {
+            assert!(a.abs_diff(*b) <= 1, "radius {radius}: {a} vs {b}");
+        }
+    }
+}
+
+#[test]
+fn edge_protection_keeps_outlines() {
+    let img = image::RgbImage::from_fn(20, 6, |x, _| image::Rgb([if x < 10 { 0 } else { 255 }; 3]));
+    let mask = mask::edge_protection(&img, 0.8);
+
+    assert!((mask.get(10, 3) - 0.2).abs() < 0.05);
+    assert_eq!(mask.get(0, 3), 1.0);
+}
+
+#[test]
+fn chroma_key_masks_matching_pixels() {
+    let img = image::RgbImage::from_fn(4, 1, |x, _| match x {
+        0 => image::Rgb([0, 255, 0]),
+        1 => image::Rgb([10, 240, 10]),
+        _ => image::Rgb([200, 30, 40]),
+    });
+    let mask = mask::chroma_key(&img, image::Rgb([0, 255, 0]), 30.0, 15.0);
+
+    assert_eq!(mask.get(0, 0), 1.0);
+    assert_eq!(mask.get(1, 0), 1.0);
+    assert_eq!(mask.get(2, 0), 0.0);
+}
+
+#[test]
+fn tileable_blur_commutes_with_wrapping_shifts() {
+    let img = image::RgbImage::from_fn(20, 14, |x, y| {
+        image::Rgb([(x * 12) as u8, (y * 18) as u8, ((x + y) % 3 * 120) as u8])
+    });
+    let shift = |img: &image::RgbImage| {
+        image::RgbImage::from_fn(20, 14, |x, y| *img.get_pixel((x + 7) % 20, (y + 5) % 14))
+    };
+
+    let blurred = blur_tileable(Kernel::new(4, 2.5), 1, Direction::Both, &img);
+    let shifted = blur_tileable(Kernel::new(4, 2.5), 1, Direction::Both, &shift(&img));
+
+    assert_eq!(shift(&blurred), shifted);
+}
+
+#[cfg(feature = "redact")]
+#[test]
+fn text_regions_cover_glyph_rows() {
+    // A line of 3px wide strokes on a white page, with a flat block underneath
+    let img = image::RgbImage::from_fn(96, 64, |x, y| match (x, y) {
+        (8..=80, 8..=23) if x % 6 < 3 => image::Rgb([0; 3]),
+        (8..=80, 40..=55) => image::Rgb([90; 3]),
+        _ => image::Rgb([255; 3]),
+    });
+
+    let regions = detect::text_regions(&img);
+
+    assert!(regions.iter().any(|r| r.contains(40, 16)));
+    assert!(!regions.iter().any(|r| r.contains(40, 48)));
+}
+
+#[cfg(feature = "redact")]
+#[test]
+fn code_regions_find_qr_codes_and_barcodes() {
+    let code = qrcode::QrCode::new(b"https://example.com/join/abc123").unwrap();
+    let (modules, scale, offset) = (code.width() as u32, 4, 40);
+    let colors = code.to_colors();
+
+    let img = image::RgbImage::from_fn(400, 260, |x, y| {
+        let (mx, my) = (
+            (x / scale).wrapping_sub(offset / scale),
+            (y / scale).wrapping_sub(offset / scale),
+        );
+
+        if mx < modules && my < modules {
+            return match colors[(my * modules + mx) as usize] {
+                qrcode::Color::Dark => image::Rgb([0; 3]),
+                qrcode::Color::Light => image::Rgb([255; 3]),
+            };
+        }
+
+        // A barcode of uneven bars off to the right
+        match (x, y) {
+            (260..=360, 60..=140) if (x * 7 / 3) % 5 < 2 => image::Rgb([0; 3]),
+            _ => image::Rgb([255; 3]),
+        }
+    });
+
+    let regions = detect::code_regions(&img);
+    let center = offset + modules * scale / 2;
+
+    assert!(regions.iter().any(|r| r.contains(center, center)));
+    assert!(regions.iter().any(|r| r.contains(310, 100)));
+    assert!(!regions.iter().any(|r| r.contains(300, 220)));
+}
+
+#[cfg(feature = "redact")]
+#[test]
+fn plate_regions_skip_long_text_lines() {
+    let img = image::RgbImage::from_fn(240, 120, |x, y| match (x, y) {
+        // A plate: dark characters on a white panel over a grey car
+        (20..=99, 20..=43) if (28..=91).contains(&x) && (26..=37).contains(&y) && x % 6 < 3 => {
+            image::Rgb([10; 3])
+        }
+        (20..=99, 20..=43) => image::Rgb([245; 3]),
+        // A long line of text
+        (8..=230, 80..=95) if x % 6 < 3 => image::Rgb([0; 3]),
+        _ => image::Rgb([120; 3]),
+    });
+
+    let plates = detect::plate_regions(&img);
+
+    assert!(plates.iter().any(|r| r.contains(60, 32)));
+    assert!(!plates.iter().any(|r| r.contains(120, 88)));
+}
+
+#[test]
+fn pipeline_chains_stages_without_rounding() {
+    let img = image::RgbImage::from_fn(30, 20, |x, y| {
+        image::Rgb([(x * 8) as u8, (y * 12) as u8, ((x * y) % 251) as u8])
+    });
+    let gaussian = pipeline::Gaussian {
+        kernel: Kernel::new(4, 2.0),
+        passes: 1,
+        direction: Direction::Both,
+    };
+
+    let chained = pipeline::Pipeline::new()
+        .then(gaussian)
+        .then(gaussian)
+        .run(&img)
+        .unwrap();
+
     assert_eq!(
-        rx.recv_timeout(Duration::from_secs(30)),
-        Err(mpsc::RecvTimeoutError::Timeout)
+        chained,
+        blur_passes(Kernel::new(4, 2.0), 2, Direction::Both, &img)
     );
 }
 
 #[test]
-fn async_small_radius() {
-    let img = image::open("assets/bg.jpg").unwrap().to_rgb8();
+fn ops_match_the_equivalent_pipeline() {
+    let img = image::RgbImage::from_fn(30, 20, |x, y| {
+        image::Rgb([(x * 8) as u8, (y * 12) as u8, ((x * y) % 251) as u8])
+    });
 
-    let (tx, rx) = mpsc::channel();
+    let parsed = ops::parse("rotate(90) | gaussian(s=2, r=4) | tint(102030, opacity=0.5)")
+        .unwrap()
+        .run(&img)
+        .unwrap();
+    let built = pipeline::Pipeline::new()
+        .then(pipeline::Rotate::Cw90)
+        .then(pipeline::Gaussian {
+            kernel: Kernel::new(4, 2.0),
+            passes: 1,
+            direction: Direction::Both,
+        })
+        .then(pipeline::Tint {
+            color: image::Rgb([0x10, 0x20, 0x30]),
+            opacity: 0.5,
+        })
+        .run(&img)
+        .unwrap();
 
-    thread::spawn(move || {
-        let img_blurred = blur_async(3, 10.0, 10, img);
+    assert_eq!(parsed, built);
+}
 
-        img_blurred.save("assets/blurred_test_3.jpg").unwrap();
+#[test]
+fn pipelines_over_their_memory_budget_run_in_strips() {
+    let img = testgen::generate(testgen::Pattern::Noise, 64, 200, 1);
+    let build = || {
+        pipeline::Pipeline::new()
+            .then(pipeline::Gaussian {
+                kernel: Kernel::new(5, 2.5),
+                passes: 2,
+                direction: Direction::Vertical,
+            })
+            .then(pipeline::Unsharp {
+                amount: 0.5,
+                radius: 2,
+                sigma: 1.0,
+            })
+            .then(pipeline::Grayscale)
+            .dither(dither::Dither::Ordered)
+    };
 
-        tx.send(()).unwrap();
-    });
+    let budget = memory::required(64, 200) / 2;
+    assert!(memory::strip_rows(64, 200, Some(16), budget).unwrap() < 200);
+    assert_eq!(
+        build().memory_budget(Some(budget)).run(&img),
+        build().run(&img)
+    );
 
-    assert_eq!(rx.recv_timeout(Duration::from_secs(30)), Ok(()));
+    let diffused = build()
+        .dither(dither::Dither::Diffusion)
+        .memory_budget(Some(budget));
+    assert!(matches!(diffused.run(&img), Err(Error::TooLarge(_))));
 }
 
 #[test]
-fn async_big_radius() {
-    let img = image::open("assets/bg.jpg").unwrap().to_rgb8();
+fn rects_past_u32_max_are_clipped_instead_of_overflowing() {
+    let img = image::RgbImage::new(12, 12);
+    let far: region::Rect = "4294967290,0,10,10".parse().unwrap();
 
-    let (tx, rx) = mpsc::channel();
+    let cropped = pipeline::Pipeline::new()
+        .then(pipeline::Crop { rect: far })
+        .run(&img);
+    assert!(matches!(cropped, Err(Error::InvalidArgs(_))));
 
-    thread::spawn(move || {
-        let img_blurred = blur_async(10, 10.0, 10, img);
+    let corner = region::Rect::new(u32::MAX - 2, u32::MAX - 2, 1, 1).pad(5);
+    assert_eq!((corner.right(), corner.bottom()), (u32::MAX, u32::MAX));
 
-        img_blurred.save("assets/blurred_test_3.jpg").unwrap();
+    let geometry: region::Rect = "10x10+4294967290+4294967290".parse().unwrap();
+    assert_eq!(geometry.clamp_to(12, 12), None);
+    assert_eq!(mask::rects(12, 12, &[far, geometry]).get(11, 0), 0.0);
+}
 
-        tx.send(()).unwrap();
+#[cfg(feature = "wasm")]
+#[test]
+fn wasm_filters_match_pixel_and_region_entry_points() {
+    use vincent_blur::pipeline::Pipeline;
+    use vincent_blur::script::WasmFilter;
+
+    let img = image::RgbImage::from_fn(13, 9, |x, y| {
+        image::Rgb([(x * 19) as u8, (y * 28) as u8, ((x * y) % 251) as u8])
     });
 
-    assert_eq!(rx.recv_timeout(Duration::from_secs(30)), Ok(()));
+    let pixel = wat::parse_str(
+        r#"(module
+            (func (export "map_pixel") (param f32 f32 f32 i32 i32) (result f32 f32 f32)
+                (f32.sub (f32.const 255) (local.get 0))
+                (f32.sub (f32.const 255) (local.get 1))
+                (f32.sub (f32.const 255) (local.get 2))))"#,
+    )
+    .unwrap();
+
+    let region = wat::parse_str(
+        r#"(module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) (i32.const 16))
+            (func (export "map_region") (param $ptr i32) (param $w i32) (param $h i32) (param i32 i32)
+                (local $end i32)
+                (local.set $end (i32.add (local.get $ptr)
+                    (i32.mul (i32.const 12) (i32.mul (local.get $w) (local.get $h)))))
+                (block $done
+                    (loop $next
+                        (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+                        (f32.store (local.get $ptr)
+                            (f32.sub (f32.const 255) (f32.load (local.get $ptr))))
+                        (local.set $ptr (i32.add (local.get $ptr) (i32.const 4)))
+                        (br $next)))))"#,
+    )
+    .unwrap();
+
+    let expected = image::RgbImage::from_fn(13, 9, |x, y| {
+        let p = img.get_pixel(x, y);
+        image::Rgb([255 - p[0], 255 - p[1], 255 - p[2]])
+    });
+
+    for (name, wasm) in [("pixel", pixel), ("region", region)] {
+        let filter = WasmFilter::new(name, &wasm, 4).unwrap();
+        let out = Pipeline::new().then(filter).run(&img).unwrap();
+
+        assert_eq!(out, expected, "{name}");
+    }
+}
+
+#[cfg(feature = "fuzz")]
+#[test]
+fn fuzz_targets_return_errors_for_garbage() {
+    let mut state = 0x2545_f491_u32;
+    let mut bytes = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    };
+
+    for len in [0, 1, 7, 64, 500] {
+        let data = bytes(len);
+        let text = String::from_utf8_lossy(&data);
+
+        assert!(fuzz_targets::decode(&data).is_err());
+        let _ = fuzz_targets::regions(&text);
+        let _ = fuzz_targets::ops(fuzz_targets::OpsInput {
+            expr: &text,
+            width: data.first().copied().unwrap_or(0),
+            height: 9,
+        });
+    }
+
+    for (radius, sigma) in [(0, 1.0), (3, f64::NAN), (3, -2.0), (255, 1e300)] {
+        let input = fuzz_targets::BlurInput {
+            radius,
+            sigma,
+            backend: 0,
+            image: b"P3 2 2 255 0 0 0 9 9 9 50 50 50 255 255 255",
+        };
+
+        assert!(matches!(
+            fuzz_targets::blur(input),
+            Err(Error::InvalidArgs(_)) | Ok(_)
+        ));
+    }
+
+    for backend in 0..8 {
+        let input = fuzz_targets::BlurInput {
+            radius: 2,
+            sigma: 1.0,
+            backend,
+            image: b"P3 2 2 255 0 0 0 9 9 9 50 50 50 255 255 255",
+        };
+
+        assert_eq!(fuzz_targets::blur(input).unwrap().dimensions(), (2, 2));
+    }
+
+    assert!(fuzz_targets::regions("10%,10%,50x20+1+1").is_err());
+    assert!(fuzz_targets::regions("50x20+1+1").is_ok());
+    assert!(fuzz_targets::regions("[[1, 2, 3.5, 4]]").is_ok());
 }